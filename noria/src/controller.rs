@@ -2,12 +2,12 @@ use crate::consensus::{self, Authority};
 use crate::debug::stats;
 use crate::table::{Table, TableBuilder, TableRpc};
 use crate::view::{View, ViewBuilder, ViewRpc};
-use crate::ActivationResult;
+use crate::{ActivationResult, DataType, SerializedConverterState};
 use failure::{self, ResultExt};
 use futures_util::future;
 use petgraph::graph::NodeIndex;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
@@ -18,6 +18,21 @@ use std::{
 use tower_buffer::Buffer;
 use tower_service::Service;
 
+/// A failed attempt to pre-populate a view with a set of keys.
+#[derive(Debug, Fail)]
+pub enum PrewarmError {
+    /// There is no view with the given name.
+    #[fail(display = "no view named {}", _0)]
+    NoSuchView(String),
+
+    /// A key had the wrong number of columns for the view's key.
+    #[fail(
+        display = "wrong number of key columns used: expected {}, got {}",
+        _0, _1
+    )]
+    WrongKeyColumnCount(usize, usize),
+}
+
 /// Describes a running controller instance.
 ///
 /// A serialized version of this struct is stored in ZooKeeper so that clients can reach the
@@ -424,6 +439,289 @@ impl<A: Authority + 'static> ControllerHandle<A> {
         )
     }
 
+    /// Pre-populate the partially materialized view `view_name` with the results for `keys`, so
+    /// that the first reads of those keys do not have to wait for an upquery.
+    ///
+    /// The keys are filled in asynchronously; this method returns once the upqueries have been
+    /// issued. Fails if a key does not match the arity of the view's key.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn prewarm_keys(
+        &mut self,
+        view_name: &str,
+        keys: Vec<Vec<DataType>>,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc("prewarm_keys", (view_name, keys), "failed to prewarm keys")
+    }
+
+    /// Obtain the names of the base tables that the view `view_name` reads from.
+    ///
+    /// Returns `None` if there is no view called `view_name`.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn base_dependencies(
+        &mut self,
+        view_name: &str,
+    ) -> impl Future<Output = Result<Option<HashSet<String>>, failure::Error>> {
+        self.rpc(
+            "base_dependencies",
+            view_name,
+            "failed to fetch base dependencies",
+        )
+    }
+
+    /// Obtain a textual plan of the view `view_name`, with one line per operator, starting at
+    /// the view's leaf and going up to the base tables it reads from.
+    ///
+    /// Returns `None` if there is no view called `view_name`.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn explain(
+        &mut self,
+        view_name: &str,
+    ) -> impl Future<Output = Result<Option<String>, failure::Error>> {
+        self.rpc("explain", view_name, "failed to explain view")
+    }
+
+    /// Key the view `view_name` on `key_columns` instead of on the parameters of its query, e.g.,
+    /// to look up a view whose query has no parameters by one of its columns. Only the view's
+    /// reader is replaced; the operators that feed it are kept. `View` handles obtained for the
+    /// view before must be obtained again.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn rekey_view(
+        &mut self,
+        view_name: &str,
+        key_columns: &[&str],
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "rekey_view",
+            (view_name, key_columns),
+            "failed to re-key view",
+        )
+    }
+
+    /// Replace the `ORDER BY` and `LIMIT` of the view `view_name` with `order` and `limit`
+    /// without building the whole view again: only its TopK operator and the nodes above it are
+    /// replaced. The view must already have an `ORDER BY` or `LIMIT`, and `limit` must not have
+    /// an offset. `View` handles obtained for the view before must be obtained again.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn update_topk(
+        &mut self,
+        view_name: &str,
+        order: Option<nom_sql::OrderClause>,
+        limit: nom_sql::LimitClause,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "update_topk",
+            (view_name, order, limit),
+            "failed to update view's ORDER BY and LIMIT",
+        )
+    }
+
+    /// Add the view `name`, which reads the view (or base table) `base_view` sorted by `order` and
+    /// cut down to the first `page.limit` rows, such as to page through a view whose query has no
+    /// `ORDER BY` or `LIMIT`. Views are paged within each of their keys, and the new view is keyed
+    /// the same way; base tables are paged as a whole and read through the bogokey. `page` must
+    /// not have an offset.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn add_sorted_view(
+        &mut self,
+        base_view: &str,
+        name: &str,
+        order: nom_sql::OrderClause,
+        page: nom_sql::LimitClause,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "add_sorted_view",
+            (base_view, name, order, page),
+            "failed to add sorted view",
+        )
+    }
+
+    /// Add a view that returns the rows of the base table `base_name` looked up by `key_columns`
+    /// instead of by its primary key, and return the name of the view (`<base>_by_<columns>`).
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn add_secondary_index(
+        &mut self,
+        base_name: &str,
+        key_columns: &[&str],
+    ) -> impl Future<Output = Result<String, failure::Error>> {
+        self.rpc(
+            "add_secondary_index",
+            (base_name, key_columns),
+            "failed to add secondary index",
+        )
+    }
+
+    /// Obtain the schema evolution state of the controller's SQL-to-MIR converter, so that it can
+    /// be restored with `import_converter_state`, e.g., after a restart.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn export_converter_state(
+        &mut self,
+    ) -> impl Future<Output = Result<SerializedConverterState, failure::Error>> {
+        self.rpc(
+            "export_converter_state",
+            (),
+            "failed to export converter state",
+        )
+    }
+
+    /// Replace the schema evolution state of the controller's SQL-to-MIR converter with `state`,
+    /// as obtained from `export_converter_state`.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn import_converter_state(
+        &mut self,
+        state: SerializedConverterState,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "import_converter_state",
+            state,
+            "failed to import converter state",
+        )
+    }
+
+    /// Estimate how many dataflow nodes adding the `SELECT` query `query` would create, for
+    /// instance to turn away queries that are too complex before installing them. Nodes the
+    /// query could share with existing views are counted too, so this is an upper bound.
+    ///
+    /// Fails if the query could not be added to the recipe.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn estimate_node_count(
+        &mut self,
+        query: &str,
+    ) -> impl Future<Output = Result<usize, failure::Error>> {
+        self.rpc(
+            "estimate_node_count",
+            query,
+            "failed to estimate node count",
+        )
+    }
+
+    /// Set estimates of the number of rows in each table or view, by name. Queries added from
+    /// now on join the smallest relations first, which keeps the intermediate join state small.
+    /// Relations without an estimate are joined last.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn set_cardinality_hints(
+        &mut self,
+        hints: HashMap<String, usize>,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "set_cardinality_hints",
+            hints,
+            "failed to set cardinality hints",
+        )
+    }
+
+    /// Key the view of the query `query_name`, once it is added, on the SQL function `function`
+    /// (`LOWER` or `UPPER`) of its parameter column `param` rather than on the column itself, as
+    /// if the query compared `function(param)` with the parameter. The view is then looked up by
+    /// the function's value, e.g., by the lower-cased email for case-insensitive lookups, and
+    /// keeps that value in a column called `name`. Set this before installing the query.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn set_computed_parameter(
+        &mut self,
+        query_name: &str,
+        param: &str,
+        name: &str,
+        function: &str,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "set_computed_parameter",
+            (query_name, param, name, function),
+            "failed to set computed parameter",
+        )
+    }
+
+    /// Bind the parameter `column` (as in `table.column = ?`) of the query `query_name`, once
+    /// it is added, to the literal `value`. The view then only holds rows whose `column` is
+    /// `value`, and is keyed on the query's remaining parameters, or on the bogokey if there are
+    /// none left. Bind parameters before installing the query they belong to.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn bind_parameter(
+        &mut self,
+        query_name: &str,
+        column: &str,
+        value: nom_sql::Literal,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc(
+            "bind_parameter",
+            (query_name, column, value),
+            "failed to bind parameter",
+        )
+    }
+
+    /// Prepend `prefix` to the names of the internal nodes generated for queries added from now
+    /// on, so that independent schemas loaded into one instance do not produce clashing node
+    /// names. Base tables and views keep the names they are given.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn set_name_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> impl Future<Output = Result<(), failure::Error>> {
+        self.rpc("set_name_prefix", prefix, "failed to set node name prefix")
+    }
+
+    /// Obtain the names of the columns of the view `view_name`, in the order its rows hold them,
+    /// without reading from it. Columns are named after their aliases, and views whose query
+    /// has no parameters also have a `bogokey` column.
+    ///
+    /// Returns `None` if there is no view called `view_name`.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn view_columns(
+        &mut self,
+        view_name: &str,
+    ) -> impl Future<Output = Result<Option<Vec<String>>, failure::Error>> {
+        self.rpc("view_columns", view_name, "failed to fetch view columns")
+    }
+
+    /// Obtain the names of the operators in the view `view_name` of the kind `operator`, as
+    /// named in the output of `explain` (e.g., "Join" or "Aggregation"), such as to flag views
+    /// that use expensive operators. Operators that the view shares with other views are
+    /// included.
+    ///
+    /// Returns `None` if there is no view called `view_name`.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn find_nodes(
+        &mut self,
+        view_name: &str,
+        operator: &str,
+    ) -> impl Future<Output = Result<Option<Vec<String>>, failure::Error>> {
+        self.rpc("find_nodes", (view_name, operator), "failed to find nodes")
+    }
+
+    /// Add the view `view_name` to the security universe described by `context`, such as a view
+    /// that was added to the recipe after the universe was created. `context` identifies the
+    /// universe as it did when the universe was created.
+    ///
+    /// Returns the name of the universe's copy of the view, or `None` if there is no view called
+    /// `view_name`, no such universe, or if the universe already has a copy of the view.
+    ///
+    /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
+    pub fn reinstantiate_for_universe(
+        &mut self,
+        view_name: &str,
+        context: HashMap<String, DataType>,
+    ) -> impl Future<Output = Result<Option<String>, failure::Error>> {
+        self.rpc(
+            "reinstantiate_for_universe",
+            (view_name, context),
+            "failed to reinstantiate view for universe",
+        )
+    }
+
     /// Remove the given external view from the graph.
     ///
     /// `Self::poll_ready` must have returned `Async::Ready` before you call this method.
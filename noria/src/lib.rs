@@ -245,6 +245,7 @@ pub mod results {
 
 /// Noria errors.
 pub mod error {
+    pub use crate::controller::PrewarmError;
     pub use crate::table::TableError;
     pub use crate::view::ViewError;
 }
@@ -328,6 +329,19 @@ pub struct ActivationResult {
     pub expressions_removed: usize,
 }
 
+/// The schema evolution state of the controller's SQL-to-MIR converter: every schema version of
+/// each base table, the version of each named node that is current, and the converter's own
+/// schema version. Dataflow nodes are not included, as they can be rebuilt from the recipe.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SerializedConverterState {
+    /// Columns of each schema version of each base table, keyed by the base's name.
+    pub base_schemas: HashMap<String, Vec<(usize, Vec<nom_sql::ColumnSpecification>)>>,
+    /// Current schema version of each named node.
+    pub current: HashMap<String, usize>,
+    /// Schema version the converter is at.
+    pub schema_version: usize,
+}
+
 #[doc(hidden)]
 #[inline]
 pub fn shard_by(dt: &DataType, shards: usize) -> usize {
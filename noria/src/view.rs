@@ -124,6 +124,29 @@ pub enum ReadQuery {
         /// Whether to block if a partial replay is triggered
         block: bool,
     },
+    /// Read all rows whose key lies within a range from a leaf view
+    Range {
+        /// Where to read from
+        target: (NodeIndex, usize),
+        /// Inclusive lower bound on the key
+        lower: DataType,
+        /// Inclusive upper bound on the key
+        upper: DataType,
+    },
+    /// Read the first `k` rows for a key from a leaf view that ranks its rows
+    Top {
+        /// Where to read from
+        target: (NodeIndex, usize),
+        /// Key to read with
+        key: Vec<DataType>,
+        /// How many rows to read
+        k: usize,
+    },
+    /// Read every row of a leaf view, regardless of its key
+    All {
+        /// Where to read from
+        target: (NodeIndex, usize),
+    },
     /// Read the size of a leaf view
     Size {
         /// Where to read from
@@ -414,6 +437,141 @@ impl View {
         self.call((keys, block)).await
     }
 
+    /// Retrieve the query results for any of the given values of the view's single parameter.
+    ///
+    /// This is meant for views whose parameter is compared against a list of placeholders (e.g.,
+    /// `WHERE x IN (?, ?)`), and returns the rows matching any of `keys`, each at most once.
+    pub async fn lookup_any(
+        &mut self,
+        keys: &[DataType],
+        block: bool,
+    ) -> Result<Results, ViewError> {
+        let mut keys = keys.to_vec();
+        keys.sort();
+        keys.dedup();
+        let keys = keys.into_iter().map(|k| vec![k]).collect();
+        let rows = self
+            .multi_lookup(keys, block)
+            .await?
+            .into_iter()
+            .flat_map(Into::<Vec<Vec<DataType>>>::into)
+            .collect();
+        Ok(Results::new(rows, Arc::from(&self.columns[..])))
+    }
+
+    /// Retrieve the query results for all parameter values between `lower` and `upper` (both
+    /// inclusive).
+    ///
+    /// This is only supported on views whose single parameter is compared by range (e.g.,
+    /// `WHERE score >= ? AND score <= ?`). Within each shard, rows are returned in key order.
+    pub async fn lookup_range(
+        &mut self,
+        lower: DataType,
+        upper: DataType,
+    ) -> Result<Results, ViewError> {
+        future::poll_fn(|cx| self.poll_ready(cx)).await?;
+
+        let node = self.node;
+        let mut rsps = self
+            .shards
+            .iter_mut()
+            .enumerate()
+            .map(|(shardi, shard)| {
+                shard.call(Tagged::from(ReadQuery::Range {
+                    target: (node, shardi),
+                    lower: lower.clone(),
+                    upper: upper.clone(),
+                }))
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut rows = Vec::new();
+        while let Some(reply) = rsps.next().await.transpose()? {
+            match reply.v {
+                ReadReply::Normal(Ok(batches)) => {
+                    for batch in batches {
+                        rows.extend(batch);
+                    }
+                }
+                ReadReply::Normal(Err(())) => return Err(ViewError::NotYetAvailable),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(Results::new(rows, Arc::from(&self.columns[..])))
+    }
+
+    /// Retrieve the first `k` query results for the given parameter value.
+    ///
+    /// This is only supported on views of queries whose limit is given at read time (marked `TOP`
+    /// in the recipe), and returns at most as many rows as the query's limit allows. Rows are
+    /// returned in the query's order.
+    pub async fn lookup_top(&mut self, key: &[DataType], k: usize) -> Result<Results, ViewError> {
+        future::poll_fn(|cx| self.poll_ready(cx)).await?;
+
+        let shardi = if self.shards.len() == 1 {
+            0
+        } else {
+            crate::shard_by(&key[0], self.shards.len())
+        };
+        for (i, shard) in self.shards.iter_mut().enumerate() {
+            if i != shardi {
+                // poll_ready reserves a sender slot which we have to release
+                *shard = shard.clone();
+            }
+        }
+
+        let reply = self.shards[shardi]
+            .call(Tagged::from(ReadQuery::Top {
+                target: (self.node, shardi),
+                key: Vec::from(key),
+                k,
+            }))
+            .await?;
+        let rows = match reply.v {
+            ReadReply::Normal(Ok(batches)) => batches.into_iter().flatten().collect(),
+            ReadReply::Normal(Err(())) => return Err(ViewError::NotYetAvailable),
+            _ => unreachable!(),
+        };
+
+        Ok(Results::new(rows, Arc::from(&self.columns[..])))
+    }
+
+    /// Retrieve all rows of this view.
+    ///
+    /// This is only supported on fully materialized views, such as those of parameterless queries
+    /// installed without a bogokey.
+    pub async fn lookup_all(&mut self) -> Result<Results, ViewError> {
+        future::poll_fn(|cx| self.poll_ready(cx)).await?;
+
+        let node = self.node;
+        let mut rsps = self
+            .shards
+            .iter_mut()
+            .enumerate()
+            .map(|(shardi, shard)| {
+                shard.call(Tagged::from(ReadQuery::All {
+                    target: (node, shardi),
+                }))
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let mut rows = Vec::new();
+        while let Some(reply) = rsps.next().await.transpose()? {
+            match reply.v {
+                ReadReply::Normal(Ok(batches)) => {
+                    for batch in batches {
+                        rows.extend(batch);
+                    }
+                }
+                ReadReply::Normal(Err(())) => return Err(ViewError::NotYetAvailable),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(Results::new(rows, Arc::from(&self.columns[..])))
+    }
+
     /// Retrieve the query results for the given parameter value.
     ///
     /// The method will block if the results are not yet available only when `block` is `true`.
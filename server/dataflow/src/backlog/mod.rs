@@ -1,13 +1,26 @@
+use crate::ops::topk::{self, Collation, NullOrder};
 use crate::prelude::*;
 use ahash::RandomState;
 use common::SizeOf;
+use nom_sql::{Operator, OrderType};
 use rand::prelude::*;
 use std::borrow::Cow;
-use std::sync::Arc;
+use std::collections::BTreeSet;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
 
 /// Allocate a new end-user facing result table.
 pub(crate) fn new(cols: usize, key: &[usize]) -> (SingleReadHandle, WriteHandle) {
-    new_inner(cols, key, None)
+    new_inner(cols, key, None, false)
+}
+
+/// Allocate a new end-user facing result table that also keeps its keys in order, so that it can
+/// be looked up by ranges of key values.
+///
+/// Only single-column keys can be kept in order. Tables with any other key are allocated as by
+/// `new`, and range lookups on them fail.
+pub(crate) fn new_ordered(cols: usize, key: &[usize]) -> (SingleReadHandle, WriteHandle) {
+    new_inner(cols, key, None, true)
 }
 
 /// Allocate a new partially materialized end-user facing result table.
@@ -21,13 +34,14 @@ pub(crate) fn new_partial<F>(
 where
     F: Fn(&mut dyn Iterator<Item = &[DataType]>) -> bool + 'static + Send + Sync,
 {
-    new_inner(cols, key, Some(Arc::new(trigger)))
+    new_inner(cols, key, Some(Arc::new(trigger)), false)
 }
 
 fn new_inner(
     cols: usize,
     key: &[usize],
     trigger: Option<Arc<dyn Fn(&mut dyn Iterator<Item = &[DataType]>) -> bool + Send + Sync>>,
+    ordered: bool,
 ) -> (SingleReadHandle, WriteHandle) {
     let contiguous = {
         let mut contiguous = true;
@@ -58,6 +72,18 @@ fn new_inner(
 
     let (r, w) = match key.len() {
         0 => unreachable!(),
+        1 if ordered => {
+            let (r, w) = evmap::Options::default()
+                .with_meta(-1)
+                .with_hasher(RandomState::default())
+                .construct();
+            let keys = Arc::new(RwLock::new(BTreeSet::new()));
+
+            (
+                multir::Handle::Ordered(r, keys.clone()),
+                multiw::Handle::Ordered(w, keys, Vec::new()),
+            )
+        }
         1 => make!(Single),
         2 => make!(Double),
         _ => make!(Many),
@@ -75,6 +101,8 @@ fn new_inner(
         handle: r,
         trigger,
         key: Vec::from(key),
+        order: None,
+        open_range: None,
     };
 
     (r, w)
@@ -300,6 +328,8 @@ pub struct SingleReadHandle {
     handle: multir::Handle,
     trigger: Option<Arc<dyn Fn(&mut dyn Iterator<Item = &[DataType]>) -> bool + Send + Sync>>,
     key: Vec<usize>,
+    order: Option<Vec<(usize, OrderType, NullOrder, Collation)>>,
+    open_range: Option<Operator>,
 }
 
 impl std::fmt::Debug for SingleReadHandle {
@@ -308,6 +338,8 @@ impl std::fmt::Debug for SingleReadHandle {
             .field("handle", &self.handle)
             .field("has_trigger", &self.trigger.is_some())
             .field("key", &self.key)
+            .field("order", &self.order)
+            .field("open_range", &self.open_range)
             .finish()
     }
 }
@@ -352,6 +384,122 @@ impl SingleReadHandle {
             })
     }
 
+    /// Find all entries whose key lies within `lower` and `upper` (both inclusive), in key order.
+    ///
+    /// Returned records are passed to `then` before being returned. Range lookups are only
+    /// supported on state allocated with `new_ordered`; on any other state they return `Err(())`,
+    /// as they do before the state is ready.
+    pub fn try_find_range_and<F, T>(
+        &self,
+        lower: &DataType,
+        upper: &DataType,
+        then: F,
+    ) -> Result<T, ()>
+    where
+        F: FnOnce(&mut dyn Iterator<Item = &Vec<DataType>>) -> T,
+    {
+        self.handle
+            .range_and(Bound::Included(lower), Bound::Included(upper), then)
+            .ok_or(())
+    }
+
+    /// Find all entries, regardless of their key.
+    ///
+    /// Returned records are passed to `then` before being returned. Only supported on fully
+    /// materialized state, since a partial view does not know which keys it is missing.
+    pub fn try_find_all_and<F, T>(&self, then: F) -> Result<T, ()>
+    where
+        F: FnOnce(&mut dyn Iterator<Item = &Vec<DataType>>) -> T,
+    {
+        assert!(
+            self.trigger.is_none(),
+            "tried to read all rows of a partially materialized view"
+        );
+        self.handle.all_and(then).ok_or(())
+    }
+
+    /// Find the first `k` entries that matched the given key, ranked in the order set with
+    /// `set_order`.
+    ///
+    /// Returned records are passed to `then` before being returned. Holes in partially
+    /// materialized state are returned as `Ok((None, _))`.
+    pub fn try_find_top_and<F, T>(
+        &self,
+        key: &[DataType],
+        k: usize,
+        mut then: F,
+    ) -> Result<(Option<T>, i64), ()>
+    where
+        F: FnMut(Vec<&Vec<DataType>>) -> T,
+    {
+        let order = self
+            .order
+            .as_ref()
+            .expect("tried to read the first rows of a view that has no order");
+        self.try_find_and(key, |rs| {
+            let mut rows: Vec<_> = rs.iter().collect();
+            topk::sort_by_rank(&mut rows, order);
+            rows.truncate(k);
+            then(rows)
+        })
+    }
+
+    /// Find all entries that matched the given key, ranked in the order set with `set_order` if
+    /// there is one. If an open range is set with `set_open_range`, all entries whose key lies
+    /// beyond the given one match instead, in key order.
+    ///
+    /// Returned records are passed to `then` before being returned. Holes in partially
+    /// materialized state are returned as `Ok((None, _))`.
+    pub fn try_find_sorted_and<F, T>(
+        &self,
+        key: &[DataType],
+        mut then: F,
+    ) -> Result<(Option<T>, i64), ()>
+    where
+        F: FnMut(Vec<&Vec<DataType>>) -> T,
+    {
+        if let Some(ref op) = self.open_range {
+            if key.len() != 1 {
+                return Err(());
+            }
+            let (lower, upper) = match *op {
+                Operator::Greater => (Bound::Excluded(&key[0]), Bound::Unbounded),
+                Operator::GreaterOrEqual => (Bound::Included(&key[0]), Bound::Unbounded),
+                Operator::Less => (Bound::Unbounded, Bound::Excluded(&key[0])),
+                Operator::LessOrEqual => (Bound::Unbounded, Bound::Included(&key[0])),
+                _ => return Err(()),
+            };
+            // range reads do not consult the map's meta value
+            return self
+                .handle
+                .range_and(lower, upper, |rs| then(rs.collect()))
+                .map(|rs| (Some(rs), 0))
+                .ok_or(());
+        }
+
+        self.try_find_and(key, |rs| {
+            let mut rows: Vec<_> = rs.iter().collect();
+            if let Some(ref order) = self.order {
+                topk::sort_by_rank(&mut rows, order);
+            }
+            then(rows)
+        })
+    }
+
+    /// Rank each key's rows in `order`, as a `TopK` would, for reads of only the first rows.
+    pub(crate) fn set_order(
+        &mut self,
+        order: Option<Vec<(usize, OrderType, NullOrder, Collation)>>,
+    ) {
+        self.order = order;
+    }
+
+    /// Match each lookup key against all keys that compare to it with `op`, for readers of
+    /// open-ended range queries such as `x > ?`.
+    pub(crate) fn set_open_range(&mut self, op: Option<Operator>) {
+        self.open_range = op;
+    }
+
     pub fn len(&self) -> usize {
         self.handle.len()
     }
@@ -397,6 +545,126 @@ mod tests {
             .unwrap());
     }
 
+    #[test]
+    fn range_lookup_works() {
+        let (r, mut w) = new_ordered(2, &[0]);
+        w.swap();
+
+        w.add(vec![
+            Record::Positive(vec![3.into(), "c".into()]),
+            Record::Positive(vec![1.into(), "a".into()]),
+            Record::Positive(vec![2.into(), "b".into()]),
+            Record::Positive(vec![4.into(), "d".into()]),
+        ]);
+        w.swap();
+
+        let found = r
+            .try_find_range_and(&2.into(), &3.into(), |rs| {
+                rs.map(|r| r[1].clone()).collect::<Vec<_>>()
+            })
+            .unwrap();
+        assert_eq!(found, vec![DataType::from("b"), DataType::from("c")]);
+
+        // keys that lose their last row drop out of the range
+        w.add(vec![Record::Negative(vec![2.into(), "b".into()])]);
+        w.swap();
+        let found = r
+            .try_find_range_and(&1.into(), &4.into(), |rs| {
+                rs.map(|r| r[1].clone()).collect::<Vec<_>>()
+            })
+            .unwrap();
+        assert_eq!(
+            found,
+            vec![
+                DataType::from("a"),
+                DataType::from("c"),
+                DataType::from("d")
+            ]
+        );
+
+        // a range that ends before it starts holds nothing
+        assert_eq!(
+            r.try_find_range_and(&3.into(), &1.into(), |rs| rs.count()),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn open_range_lookup_works() {
+        let (mut r, mut w) = new_ordered(2, &[0]);
+        r.set_open_range(Some(Operator::Greater));
+        w.swap();
+
+        w.add(vec![
+            Record::Positive(vec![3.into(), "c".into()]),
+            Record::Positive(vec![1.into(), "a".into()]),
+            Record::Positive(vec![2.into(), "b".into()]),
+        ]);
+        w.swap();
+
+        let found = r
+            .try_find_sorted_and(&[1.into()], |rs| {
+                rs.into_iter().map(|r| r[1].clone()).collect::<Vec<_>>()
+            })
+            .unwrap()
+            .0
+            .unwrap();
+        assert_eq!(found, vec![DataType::from("b"), DataType::from("c")]);
+
+        // an open range is bounded by a single value
+        assert_eq!(
+            r.try_find_sorted_and(&[1.into(), 2.into()], |rs| rs.len()),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn range_lookup_needs_ordered_state() {
+        let (r, mut w) = new(2, &[0]);
+        w.add(vec![Record::Positive(vec![1.into(), "a".into()])]);
+        w.swap();
+
+        assert_eq!(
+            r.try_find_range_and(&0.into(), &2.into(), |rs| rs.count()),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn top_lookup_works() {
+        let (mut r, mut w) = new(2, &[0]);
+        // rank by the second column, largest first, as a TopK in SQL descending order would
+        r.set_order(Some(vec![(
+            1,
+            OrderType::OrderAscending,
+            NullOrder::NullsLast,
+            Collation::Binary,
+        )]));
+        w.swap();
+
+        w.add(vec![
+            Record::Positive(vec![1.into(), 5.into()]),
+            Record::Positive(vec![1.into(), 9.into()]),
+            Record::Positive(vec![1.into(), 7.into()]),
+            Record::Positive(vec![2.into(), 8.into()]),
+        ]);
+        w.swap();
+
+        let top = |k| {
+            r.try_find_top_and(&[1.into()], k, |rs| {
+                rs.into_iter().map(|r| r[1].clone()).collect::<Vec<_>>()
+            })
+            .unwrap()
+            .0
+            .unwrap()
+        };
+        assert_eq!(top(2), vec![DataType::from(9), DataType::from(7)]);
+        assert_eq!(
+            top(5),
+            vec![DataType::from(9), DataType::from(7), DataType::from(5)]
+        );
+    }
+
     #[test]
     fn busybusybusy() {
         use std::thread;
@@ -1,10 +1,18 @@
 use ahash::RandomState;
 use common::DataType;
 use evmap;
+use std::collections::BTreeSet;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
 
 #[derive(Clone, Debug)]
 pub(super) enum Handle {
     Single(evmap::ReadHandle<DataType, Vec<DataType>, i64, RandomState>),
+    /// A single-column key whose keys are also kept in order, for range lookups.
+    Ordered(
+        evmap::ReadHandle<DataType, Vec<DataType>, i64, RandomState>,
+        Arc<RwLock<BTreeSet<DataType>>>,
+    ),
     Double(evmap::ReadHandle<(DataType, DataType), Vec<DataType>, i64, RandomState>),
     Many(evmap::ReadHandle<Vec<DataType>, Vec<DataType>, i64, RandomState>),
 }
@@ -12,18 +20,78 @@ pub(super) enum Handle {
 impl Handle {
     pub(super) fn len(&self) -> usize {
         match *self {
-            Handle::Single(ref h) => h.len(),
+            Handle::Single(ref h) | Handle::Ordered(ref h, _) => h.len(),
             Handle::Double(ref h) => h.len(),
             Handle::Many(ref h) => h.len(),
         }
     }
 
+    /// Collect the records of all keys between `lower` and `upper`, ordered by key.
+    ///
+    /// Only ordered handles support range lookups; all others return `None`, as does a map that
+    /// is not yet ready.
+    pub(super) fn range_and<F, T>(
+        &self,
+        lower: Bound<&DataType>,
+        upper: Bound<&DataType>,
+        then: F,
+    ) -> Option<T>
+    where
+        F: FnOnce(&mut dyn Iterator<Item = &Vec<DataType>>) -> T,
+    {
+        match *self {
+            Handle::Ordered(ref h, ref keys) => {
+                let map = h.read()?;
+                // `BTreeSet::range` panics on ranges that end before they start
+                let empty = match (lower, upper) {
+                    (Bound::Included(l), Bound::Included(u)) => l > u,
+                    (Bound::Included(l), Bound::Excluded(u))
+                    | (Bound::Excluded(l), Bound::Included(u))
+                    | (Bound::Excluded(l), Bound::Excluded(u)) => l >= u,
+                    _ => false,
+                };
+                if empty {
+                    return Some(then(&mut std::iter::empty::<&Vec<DataType>>()));
+                }
+                // the key set may briefly hold keys that the map no longer has; those are skipped
+                let keys = keys.read().unwrap();
+                let mut rs = keys
+                    .range((lower, upper))
+                    .filter_map(|k| map.get(k))
+                    .flat_map(|rs| rs.iter());
+                Some(then(&mut rs))
+            }
+            _ => None,
+        }
+    }
+
+    /// Collect the records of all keys.
+    pub(super) fn all_and<F, T>(&self, then: F) -> Option<T>
+    where
+        F: FnOnce(&mut dyn Iterator<Item = &Vec<DataType>>) -> T,
+    {
+        match *self {
+            Handle::Single(ref h) | Handle::Ordered(ref h, _) => {
+                let map = h.read()?;
+                Some(then(&mut map.iter().flat_map(|(_, rs)| rs.iter())))
+            }
+            Handle::Double(ref h) => {
+                let map = h.read()?;
+                Some(then(&mut map.iter().flat_map(|(_, rs)| rs.iter())))
+            }
+            Handle::Many(ref h) => {
+                let map = h.read()?;
+                Some(then(&mut map.iter().flat_map(|(_, rs)| rs.iter())))
+            }
+        }
+    }
+
     pub(super) fn meta_get_and<F, T>(&self, key: &[DataType], then: F) -> Option<(Option<T>, i64)>
     where
         F: FnOnce(&evmap::Values<Vec<DataType>, RandomState>) -> T,
     {
         match *self {
-            Handle::Single(ref h) => {
+            Handle::Single(ref h) | Handle::Ordered(ref h, _) => {
                 assert_eq!(key.len(), 1);
                 let map = h.read()?;
                 let v = map.get(&key[0]).map(then);
@@ -2,9 +2,19 @@ use super::{key_to_double, key_to_single, Key};
 use crate::prelude::*;
 use ahash::RandomState;
 use evmap;
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
 
 pub(super) enum Handle {
     Single(evmap::WriteHandle<DataType, Vec<DataType>, i64, RandomState>),
+    /// A single-column key whose keys are also kept in order, for range lookups. Keys are added
+    /// to the ordered set as soon as they are written, and keys that may have lost their last
+    /// record are held back until the next refresh, so that readers never miss a key.
+    Ordered(
+        evmap::WriteHandle<DataType, Vec<DataType>, i64, RandomState>,
+        Arc<RwLock<BTreeSet<DataType>>>,
+        Vec<DataType>,
+    ),
     Double(evmap::WriteHandle<(DataType, DataType), Vec<DataType>, i64, RandomState>),
     Many(evmap::WriteHandle<Vec<DataType>, Vec<DataType>, i64, RandomState>),
 }
@@ -12,7 +22,7 @@ pub(super) enum Handle {
 impl Handle {
     pub fn is_empty(&self) -> bool {
         match *self {
-            Handle::Single(ref h) => h.is_empty(),
+            Handle::Single(ref h) | Handle::Ordered(ref h, ..) => h.is_empty(),
             Handle::Double(ref h) => h.is_empty(),
             Handle::Many(ref h) => h.is_empty(),
         }
@@ -20,7 +30,7 @@ impl Handle {
 
     pub fn clear(&mut self, k: Key) {
         match *self {
-            Handle::Single(ref mut h) => {
+            Handle::Single(ref mut h) | Handle::Ordered(ref mut h, ..) => {
                 h.clear(key_to_single(k).into_owned());
             }
             Handle::Double(ref mut h) => {
@@ -37,6 +47,11 @@ impl Handle {
             Handle::Single(ref mut h) => {
                 h.empty(key_to_single(k).into_owned());
             }
+            Handle::Ordered(ref mut h, _, ref mut gone) => {
+                let k = key_to_single(k).into_owned();
+                gone.push(k.clone());
+                h.empty(k);
+            }
             Handle::Double(ref mut h) => {
                 h.empty(key_to_double(k).into_owned());
             }
@@ -56,6 +71,10 @@ impl Handle {
     ) {
         match *self {
             Handle::Single(ref mut h) => h.empty_random(rng, n).for_each(|r| f(r.1)),
+            Handle::Ordered(ref mut h, _, ref mut gone) => h.empty_random(rng, n).for_each(|r| {
+                gone.push(r.0.clone());
+                f(r.1)
+            }),
             Handle::Double(ref mut h) => h.empty_random(rng, n).for_each(|r| f(r.1)),
             Handle::Many(ref mut h) => h.empty_random(rng, n).for_each(|r| f(r.1)),
         }
@@ -66,6 +85,17 @@ impl Handle {
             Handle::Single(ref mut h) => {
                 h.refresh();
             }
+            Handle::Ordered(ref mut h, ref keys, ref mut gone) => {
+                h.refresh();
+                if let Some(map) = h.read() {
+                    let mut keys = keys.write().unwrap();
+                    for k in gone.drain(..) {
+                        if map.get(&k).map(|rs| rs.is_empty()).unwrap_or(true) {
+                            keys.remove(&k);
+                        }
+                    }
+                }
+            }
             Handle::Double(ref mut h) => {
                 h.refresh();
             }
@@ -80,7 +110,7 @@ impl Handle {
         F: FnOnce(&evmap::Values<Vec<DataType>, RandomState>) -> T,
     {
         match *self {
-            Handle::Single(ref h) => {
+            Handle::Single(ref h) | Handle::Ordered(ref h, ..) => {
                 assert_eq!(key.len(), 1);
                 let map = h.read()?;
                 let v = map.get(&key[0]).map(then);
@@ -152,6 +182,25 @@ impl Handle {
                     }
                 }
             }
+            Handle::Ordered(ref mut h, ref keys, ref mut gone) => {
+                assert_eq!(key.len(), 1);
+                let mut keys = keys.write().unwrap();
+                for r in rs {
+                    debug_assert!(r.len() >= cols);
+                    match r {
+                        Record::Positive(r) => {
+                            memory_delta += r.deep_size_of() as isize;
+                            keys.insert(r[key[0]].clone());
+                            h.insert(r[key[0]].clone(), r);
+                        }
+                        Record::Negative(r) => {
+                            memory_delta -= r.deep_size_of() as isize;
+                            gone.push(r[key[0]].clone());
+                            h.remove(r[key[0]].clone(), r);
+                        }
+                    }
+                }
+            }
             Handle::Double(ref mut h) => {
                 assert_eq!(key.len(), 2);
                 for r in rs {
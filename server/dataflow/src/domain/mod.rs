@@ -834,6 +834,18 @@ impl Domain {
                             .send(ControlReplyPacket::ack())
                             .unwrap();
                     }
+                    Packet::RenameBaseColumn {
+                        node,
+                        column,
+                        field,
+                    } => {
+                        let mut n = self.nodes[node].borrow_mut();
+                        assert!(n.is_base(), "told to rename column of non-base node");
+                        n.rename_column(column, &field);
+                        self.control_reply_tx
+                            .send(ControlReplyPacket::ack())
+                            .unwrap();
+                    }
                     Packet::UpdateEgress {
                         node,
                         new_tx,
@@ -932,7 +944,7 @@ impl Domain {
                                         tx
                                     })
                                     .collect::<Vec<_>>();
-                                let (r_part, w_part) = backlog::new_partial(
+                                let (mut r_part, w_part) = backlog::new_partial(
                                     cols,
                                     &k[..],
                                     move |misses: &mut dyn Iterator<Item = &[DataType]>| {
@@ -968,6 +980,8 @@ impl Domain {
                                 let mut n = self.nodes[node].borrow_mut();
                                 tokio::task::block_in_place(|| {
                                     n.with_reader_mut(|r| {
+                                        r_part.set_order(r.order().map(<[_]>::to_vec));
+                                        r_part.set_open_range(r.open_range());
                                         assert!(self
                                             .readers
                                             .lock()
@@ -986,11 +1000,21 @@ impl Domain {
                             }
                             InitialState::Global { gid, cols, key } => {
                                 use crate::backlog;
-                                let (r_part, w_part) = backlog::new(cols, &key[..]);
+                                let ranged = self.nodes[node]
+                                    .borrow()
+                                    .with_reader(|r| r.is_ranged())
+                                    .unwrap();
+                                let (mut r_part, w_part) = if ranged {
+                                    backlog::new_ordered(cols, &key[..])
+                                } else {
+                                    backlog::new(cols, &key[..])
+                                };
 
                                 let mut n = self.nodes[node].borrow_mut();
                                 tokio::task::block_in_place(|| {
                                     n.with_reader_mut(|r| {
+                                        r_part.set_order(r.order().map(<[_]>::to_vec));
+                                        r_part.set_open_range(r.open_range());
                                         assert!(self
                                             .readers
                                             .lock()
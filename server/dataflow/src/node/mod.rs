@@ -340,6 +340,10 @@ impl Node {
         self.fields.len() - 1
     }
 
+    pub fn rename_column(&mut self, column: usize, field: &str) {
+        self.fields[column] = field.to_string();
+    }
+
     pub fn has_domain(&self) -> bool {
         self.domain.is_some()
     }
@@ -36,7 +36,7 @@ impl Node {
                         inner, mut senders, ..
                     }) => {
                         let Input { dst, data } = unsafe { inner.take() };
-                        let mut rs = b.process(addr, data, &*state);
+                        let mut rs = b.process(addr, data, &*state, log);
 
                         // When a replay originates at a base node, we replay the data *through* that
                         // same base node because its column set may have changed. However, this replay
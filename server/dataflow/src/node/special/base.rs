@@ -1,5 +1,6 @@
 use crate::prelude::*;
 use noria::{Modification, Operation, TableOperation};
+use slog::Logger;
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::HashMap;
@@ -13,6 +14,11 @@ use vec_map::VecMap;
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Base {
     primary_key: Option<Vec<usize>>,
+    unique_keys: Vec<Vec<usize>>,
+    rowid: Option<usize>,
+    next_rowid: i64,
+    not_null: Vec<usize>,
+    real_columns: Vec<usize>,
 
     defaults: Vec<DataType>,
     dropped: Vec<usize>,
@@ -37,6 +43,136 @@ impl Base {
         self.primary_key.as_ref().map(|cols| &cols[..])
     }
 
+    /// Builder with unique secondary keys, which the base maintains lookup indices for.
+    pub fn with_unique_keys(mut self, unique_keys: Vec<Vec<usize>>) -> Base {
+        self.unique_keys = unique_keys;
+        self
+    }
+
+    pub fn unique_keys(&self) -> &[Vec<usize>] {
+        &self.unique_keys[..]
+    }
+
+    /// Builder with a column that the base fills with increasing row identifiers for inserts
+    /// that do not provide a value for it.
+    pub fn with_rowid(mut self, column: usize) -> Base {
+        self.rowid = Some(column);
+        self
+    }
+
+    /// Builder with columns that must not be `NULL`. Inserts that set any of them to `NULL` are
+    /// dropped.
+    pub fn with_not_null(mut self, columns: Vec<usize>) -> Base {
+        self.not_null = columns;
+        self
+    }
+
+    pub fn not_null(&self) -> &[usize] {
+        &self.not_null[..]
+    }
+
+    /// Builder with columns that hold floating point values. Integers written to them, or used to
+    /// look up keys in them, are stored and compared as floats.
+    pub fn with_real_columns(mut self, columns: Vec<usize>) -> Base {
+        for &col in &columns {
+            if let Some(d) = self.defaults.get_mut(col) {
+                *d = as_real(d.clone());
+            }
+        }
+        self.real_columns = columns;
+        self
+    }
+
+    /// Converts the integers that `ops` write to, or key on, floating point columns to floats, so
+    /// that they compare equal to the floats already stored there.
+    fn coerce_reals(&self, ops: &mut [TableOperation]) {
+        if self.real_columns.is_empty() {
+            return;
+        }
+        let real_columns = &self.real_columns;
+        let coerce_row = |row: &mut Vec<DataType>| {
+            for &col in real_columns {
+                if let Some(d) = row.get_mut(col) {
+                    *d = as_real(d.clone());
+                }
+            }
+        };
+        let coerce_update = |update: &mut Vec<Modification>| {
+            for &col in real_columns {
+                if let Some(Modification::Set(ref mut d)) = update.get_mut(col) {
+                    *d = as_real(d.clone());
+                }
+            }
+        };
+        let key_cols = self.primary_key.as_ref();
+        let coerce_key = |key: &mut Vec<DataType>| {
+            for (i, col) in key_cols.into_iter().flatten().enumerate() {
+                if real_columns.contains(col) {
+                    if let Some(d) = key.get_mut(i) {
+                        *d = as_real(d.clone());
+                    }
+                }
+            }
+        };
+        for op in ops {
+            match *op {
+                TableOperation::Insert(ref mut row) => coerce_row(row),
+                TableOperation::Delete { ref mut key } => coerce_key(key),
+                TableOperation::Update {
+                    ref mut key,
+                    ref mut set,
+                } => {
+                    coerce_key(key);
+                    coerce_update(set);
+                }
+                TableOperation::InsertOrUpdate {
+                    ref mut row,
+                    ref mut update,
+                } => {
+                    coerce_row(row);
+                    coerce_update(update);
+                }
+            }
+        }
+    }
+
+    /// Drops inserted rows that have a `NULL` value in a `NOT NULL` column, and logs each one to
+    /// `log`. Columns the row does not provide take their default values later, and are not
+    /// checked here.
+    fn reject_nulls(&self, ops: &mut Vec<TableOperation>, log: &Logger) {
+        if self.not_null.is_empty() {
+            return;
+        }
+        let not_null = &self.not_null;
+        ops.retain(|op| match *op {
+            TableOperation::Insert(ref row) | TableOperation::InsertOrUpdate { ref row, .. } => {
+                let ok = not_null
+                    .iter()
+                    .all(|&col| row.get(col).map_or(true, |v| !v.is_none()));
+                if !ok {
+                    warn!(log, "base ignoring row with NULL in a NOT NULL column"; "row" => ?row);
+                }
+                ok
+            }
+            _ => true,
+        });
+    }
+
+    fn assign_rowids(&mut self, ops: &mut [TableOperation]) {
+        let col = match self.rowid {
+            Some(col) => col,
+            None => return,
+        };
+        for op in ops {
+            if let TableOperation::Insert(ref mut row) = *op {
+                if row.len() > col && row[col].is_none() {
+                    self.next_rowid += 1;
+                    row[col] = self.next_rowid.into();
+                }
+            }
+        }
+    }
+
     /// Add a new column to this base node.
     pub fn add_column(&mut self, default: DataType) -> usize {
         assert!(
@@ -91,6 +227,11 @@ impl Clone for Base {
     fn clone(&self) -> Base {
         Base {
             primary_key: self.primary_key.clone(),
+            unique_keys: self.unique_keys.clone(),
+            rowid: self.rowid,
+            next_rowid: self.next_rowid,
+            not_null: self.not_null.clone(),
+            real_columns: self.real_columns.clone(),
 
             defaults: self.defaults.clone(),
             dropped: self.dropped.clone(),
@@ -103,6 +244,11 @@ impl Default for Base {
     fn default() -> Self {
         Base {
             primary_key: None,
+            unique_keys: Vec::new(),
+            rowid: None,
+            next_rowid: 0,
+            not_null: Vec::new(),
+            real_columns: Vec::new(),
 
             defaults: Vec::new(),
             dropped: Vec::new(),
@@ -111,6 +257,17 @@ impl Default for Base {
     }
 }
 
+/// `d` as a float, if it is an integer.
+fn as_real(d: DataType) -> DataType {
+    match d {
+        DataType::Int(_)
+        | DataType::UnsignedInt(_)
+        | DataType::BigInt(_)
+        | DataType::UnsignedBigInt(_) => DataType::from(i128::from(&d) as f64),
+        d => d,
+    }
+}
+
 fn key_val(i: usize, col: usize, r: &TableOperation) -> &DataType {
     match *r {
         TableOperation::Insert(ref row) => &row[col],
@@ -137,7 +294,12 @@ impl Base {
         us: LocalNodeIndex,
         mut ops: Vec<TableOperation>,
         state: &StateMap,
+        log: &Logger,
     ) -> Records {
+        self.assign_rowids(&mut ops);
+        self.coerce_reals(&mut ops);
+        self.reject_nulls(&mut ops, log);
+
         if self.primary_key.is_none() || ops.is_empty() {
             return ops
                 .into_iter()
@@ -307,6 +469,53 @@ mod tests {
         assert_eq!(b.unmodified, true);
     }
 
+    #[test]
+    fn it_drops_nulls_in_not_null_columns() {
+        let mut b = Base::new(vec![]).with_not_null(vec![1]);
+        let local = unsafe { LocalNodeIndex::make(0 as u32) };
+
+        let rs = b.process(
+            local,
+            vec![
+                TableOperation::Insert(vec![1.into(), "a".into()]),
+                TableOperation::Insert(vec![2.into(), DataType::None]),
+                TableOperation::Insert(vec![DataType::None, "c".into()]),
+            ],
+            &StateMap::new(),
+            &Logger::root(slog::Discard, o!()),
+        );
+        assert_eq!(
+            rs,
+            vec![vec![1.into(), "a".into()], vec![DataType::None, "c".into()],].into()
+        );
+    }
+
+    #[test]
+    fn it_stores_integers_in_real_columns_as_floats() {
+        let mut b = Base::new(vec![]).with_real_columns(vec![1]);
+        let local = unsafe { LocalNodeIndex::make(0 as u32) };
+
+        let rs = b.process(
+            local,
+            vec![
+                TableOperation::Insert(vec![1.into(), 3.into()]),
+                TableOperation::Insert(vec![2.into(), 2.5.into()]),
+                TableOperation::Insert(vec![3.into(), DataType::None]),
+            ],
+            &StateMap::new(),
+            &Logger::root(slog::Discard, o!()),
+        );
+        assert_eq!(
+            rs,
+            vec![
+                vec![1.into(), 3.0.into()],
+                vec![2.into(), 2.5.into()],
+                vec![3.into(), DataType::None],
+            ]
+            .into()
+        );
+    }
+
     fn test_lots_of_changes_in_same_batch(mut state: Box<dyn State>) {
         use crate::node;
         use crate::prelude::*;
@@ -344,8 +553,9 @@ mod tests {
         let n = graph[global].take();
         let mut n = n.finalize(&graph);
 
+        let log = Logger::root(slog::Discard, o!());
         let mut one = move |u: Vec<TableOperation>| {
-            let mut m = n.get_base_mut().unwrap().process(local, u, &states);
+            let mut m = n.get_base_mut().unwrap().process(local, u, &states, &log);
             node::materialize(&mut m, None, states.get_mut(local));
             m
         };
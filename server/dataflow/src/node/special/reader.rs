@@ -1,5 +1,7 @@
 use crate::backlog;
+use crate::ops::topk::{Collation, NullOrder};
 use crate::prelude::*;
+use nom_sql::{Operator, OrderType};
 
 #[derive(Serialize, Deserialize)]
 pub struct Reader {
@@ -8,6 +10,10 @@ pub struct Reader {
 
     for_node: NodeIndex,
     state: Option<Vec<usize>>,
+    ranged: bool,
+    full: bool,
+    order: Option<Vec<(usize, OrderType, NullOrder, Collation)>>,
+    open_range: Option<Operator>,
 }
 
 impl Clone for Reader {
@@ -17,6 +23,10 @@ impl Clone for Reader {
             writer: None,
             state: self.state.clone(),
             for_node: self.for_node,
+            ranged: self.ranged,
+            full: self.full,
+            order: self.order.clone(),
+            open_range: self.open_range.clone(),
         }
     }
 }
@@ -27,6 +37,10 @@ impl Reader {
             writer: None,
             state: None,
             for_node,
+            ranged: false,
+            full: false,
+            order: None,
+            open_range: None,
         }
     }
 
@@ -50,6 +64,10 @@ impl Reader {
             writer: self.writer.take(),
             state: self.state.clone(),
             for_node: self.for_node,
+            ranged: self.ranged,
+            full: self.full,
+            order: self.order.clone(),
+            open_range: self.open_range.clone(),
         }
     }
 
@@ -81,6 +99,85 @@ impl Reader {
         }
     }
 
+    /// Whether this reader is looked up by ranges of key values rather than by exact keys.
+    pub fn is_ranged(&self) -> bool {
+        self.ranged
+    }
+
+    /// Keep the reader's keys in order, so that it can be looked up by ranges of key values. Only
+    /// readers keyed on a single column can be ranged.
+    ///
+    /// Range lookups can't tell which parts of a range are missing from partial state, so this
+    /// also forces the reader to be fully materialized.
+    pub fn set_ranged(&mut self) -> Result<(), String> {
+        match self.key().map(<[usize]>::len) {
+            Some(1) => {}
+            Some(n) => {
+                return Err(format!(
+                    "range lookups need a single key column, but the reader is keyed on {}",
+                    n
+                ))
+            }
+            None => return Err("range lookups need a keyed reader".to_owned()),
+        }
+        self.ranged = true;
+        self.full = true;
+        Ok(())
+    }
+
+    /// The operator that an open-ended range reader compares its keys with against the single
+    /// value it is looked up with, if this is such a reader.
+    pub fn open_range(&self) -> Option<Operator> {
+        self.open_range.clone()
+    }
+
+    /// Have lookups return the rows of all keys that compare to the looked-up value with `op`
+    /// (one of `<`, `<=`, `>`, `>=`), rather than those of an inclusive range given by the
+    /// reader. The reader must already be ranged.
+    pub fn set_open_range(&mut self, op: Operator) -> Result<(), String> {
+        if !self.ranged {
+            return Err("open range lookups need a ranged reader".to_owned());
+        }
+        match op {
+            Operator::Less
+            | Operator::LessOrEqual
+            | Operator::Greater
+            | Operator::GreaterOrEqual => {}
+            _ => return Err(format!("{} does not bound a range", op)),
+        }
+        self.open_range = Some(op);
+        Ok(())
+    }
+
+    /// Whether this reader must keep all of its state, rather than being partially materialized.
+    pub fn requires_full_materialization(&self) -> bool {
+        self.full
+    }
+
+    pub fn force_full_materialization(&mut self) {
+        self.full = true;
+    }
+
+    /// The order in which reads that only want the first rows of each key rank that key's rows,
+    /// if the reader supports such reads.
+    pub fn order(&self) -> Option<&[(usize, OrderType, NullOrder, Collation)]> {
+        self.order.as_ref().map(|o| &o[..])
+    }
+
+    /// Lets reads ask for only the first rows of each key, ranked in `order` as by a `TopK`. Such
+    /// reads cannot tell whether partial state holds all of a key's rows, so this also forces the
+    /// reader to be fully materialized.
+    pub fn set_order(&mut self, order: Vec<(usize, OrderType, NullOrder, Collation)>) {
+        self.set_sorted(order);
+        self.full = true;
+    }
+
+    /// Rank each key's rows in `order` on every read, without allowing reads of only the first
+    /// rows. Unlike `set_order`, this works with partial materialization.
+    pub fn set_sorted(&mut self, order: Vec<(usize, OrderType, NullOrder, Collation)>) {
+        self.order = Some(order);
+    }
+
     pub(crate) fn is_empty(&self) -> bool {
         self.writer.as_ref().map(|w| w.is_empty()).unwrap_or(true)
     }
@@ -1,8 +1,11 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt::{self, Display};
 use std::sync;
 
+use crate::ops::topk::Collation;
 use crate::prelude::*;
 pub use nom_sql::Operator;
 
@@ -11,6 +14,10 @@ pub use nom_sql::Operator;
 pub struct Filter {
     src: IndexPair,
     filter: sync::Arc<Vec<(usize, FilterCondition)>>,
+    /// The rows this filter has let through, and how many times, if it compares against the
+    /// clock. Whether such a row matches may have changed since, so its negatives are let through
+    /// if and only if it is in here.
+    admitted: HashMap<Vec<DataType>, usize>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -38,6 +45,322 @@ impl Display for Value {
 pub enum FilterCondition {
     Comparison(Operator, Value),
     In(Vec<DataType>),
+    /// Compares against the wall clock time, shifted by `offset` seconds (e.g., `-60` for "one
+    /// minute ago").
+    ///
+    /// The clock is read when a record passes through the filter or when a lookup is answered by
+    /// querying through it; it is not re-evaluated as time advances. Materialized state below the
+    /// filter therefore keeps rows that no longer match, and does not pick up rows that only
+    /// start matching later. Views that need up-to-date results must not materialize below such
+    /// a filter. Deletions of rows the filter let through are always forwarded, even once those
+    /// rows no longer match, so that they leave that state.
+    TimeRelative {
+        operator: Operator,
+        offset: i64,
+    },
+    /// Matches text against a SQL `LIKE` pattern, in which `%` stands for any sequence of
+    /// characters and `_` for any single character. In the pattern, the `escape` character makes
+    /// the character after it match only itself; without one, no character is special beyond the
+    /// wildcards. `NULL` and non-text values never match, whether or not the condition is
+    /// `negated`.
+    Like {
+        pattern: String,
+        escape: Option<char>,
+        negated: bool,
+    },
+    /// Matches sets that contain the given element. A set is stored as text listing its elements
+    /// separated by `SET_SEPARATOR`, with whitespace around each element ignored (e.g.,
+    /// `"red, green"`); the empty string is the empty set. Elements are compared by their text,
+    /// so the element `3` is in the set `"1,2,3"`. `NULL` neither is nor contains an element.
+    Contains(DataType),
+    /// Matches text against a POSIX-style regular expression, as SQL's `~` (or `~*`, if
+    /// `case_insensitive`) does. The pattern matches anywhere in the text unless anchored with
+    /// `^` or `$`. As with `Like`, `NULL` and non-text values never match, whether or not the
+    /// condition is `negated`.
+    Regex {
+        pattern: String,
+        case_insensitive: bool,
+        negated: bool,
+    },
+    /// Matches values equal to the given one, as MySQL's `<=>` does: unlike in standard SQL,
+    /// `NULL` is equal to `NULL` (and to nothing else).
+    NullSafeEquality(DataType),
+    /// Compares against the given value as `Comparison` does, except that text compares under
+    /// `collation` rather than by its bytes. Values that are not text compare as in `Comparison`.
+    CollatedComparison {
+        operator: Operator,
+        value: DataType,
+        collation: Collation,
+    },
+    /// Matches text holding a JSON object whose field at `path` (a key of each nested object in
+    /// turn) equals `value`, as SQL's `->> 'a.b' = value` does. Fields that are strings compare by
+    /// their contents, and all others by their JSON text. `NULL`, text that is not JSON, and
+    /// objects without the field (or with a `null` one) never match.
+    JsonPathEquality {
+        path: Vec<String>,
+        value: DataType,
+    },
+}
+
+/// Separates the elements of a set-valued text column, as used by `FilterCondition::Contains`.
+pub const SET_SEPARATOR: char = ',';
+
+/// Whether the set encoded in `set` contains `element`.
+pub(crate) fn set_contains(set: &str, element: &DataType) -> bool {
+    let element = match *element {
+        DataType::None => return false,
+        DataType::Text(..) | DataType::TinyText(..) => {
+            let text: &str = element.into();
+            text.trim().to_owned()
+        }
+        ref d => d.to_string(),
+    };
+    !set.trim().is_empty() && set.split(SET_SEPARATOR).any(|e| e.trim() == element)
+}
+
+/// The field at `path` of the JSON object in `json`, if there is a non-`null` one.
+pub(crate) fn json_path_field(json: &str, path: &[String]) -> Option<serde_json::Value> {
+    let mut field: serde_json::Value = serde_json::from_str(json).ok()?;
+    for key in path {
+        field = field.get_mut(key.as_str())?.take();
+    }
+    if field.is_null() {
+        None
+    } else {
+        Some(field)
+    }
+}
+
+/// The field at `path` of the JSON object in `json`, as text, if there is a non-`null` one.
+pub(crate) fn json_path_value(json: &str, path: &[String]) -> Option<String> {
+    match json_path_field(json, path)? {
+        serde_json::Value::String(s) => Some(s),
+        v => Some(v.to_string()),
+    }
+}
+
+/// Compares `d` against the current wall clock time shifted by `offset` seconds.
+///
+/// Only the ordering and (in)equality operators compare against a time; values never match any
+/// other operator. The SQL layer rejects such conditions before they reach a filter.
+pub(crate) fn compare_to_now(d: &DataType, op: &Operator, offset: i64) -> bool {
+    let now = chrono::Local::now().naive_local() + chrono::Duration::seconds(offset);
+    let v = &DataType::from(now);
+    match *op {
+        Operator::Equal => d == v,
+        Operator::NotEqual => d != v,
+        Operator::Greater => d > v,
+        Operator::GreaterOrEqual => d >= v,
+        Operator::Less => d < v,
+        Operator::LessOrEqual => d <= v,
+        _ => false,
+    }
+}
+
+/// Whether `value` matches the `LIKE` pattern `pattern`, honoring the `escape` character.
+pub(crate) fn like_matches(value: &str, pattern: &str, escape: Option<char>) -> bool {
+    enum Token {
+        Any,
+        One,
+        Char(char),
+    }
+
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            // a trailing escape character has nothing to escape, and so matches itself
+            c if Some(c) == escape => Token::Char(chars.next().unwrap_or(c)),
+            '%' => Token::Any,
+            '_' => Token::One,
+            c => Token::Char(c),
+        });
+    }
+
+    // match greedily, and on a mismatch let the most recent `%` swallow one more character
+    let value: Vec<char> = value.chars().collect();
+    let (mut t, mut v) = (0, 0);
+    let mut retry: Option<(usize, usize)> = None;
+    while v < value.len() {
+        match tokens.get(t) {
+            Some(Token::Any) => {
+                retry = Some((t, v));
+                t += 1;
+            }
+            Some(Token::One) => {
+                t += 1;
+                v += 1;
+            }
+            Some(Token::Char(c)) if *c == value[v] => {
+                t += 1;
+                v += 1;
+            }
+            _ => match retry {
+                Some((rt, rv)) => {
+                    retry = Some((rt, rv + 1));
+                    t = rt + 1;
+                    v = rv + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    tokens[t..].iter().all(|token| match *token {
+        Token::Any => true,
+        _ => false,
+    })
+}
+
+thread_local! {
+    /// Compiled `FilterCondition::Regex` patterns, so that each is only compiled once per thread.
+    static REGEXES: RefCell<HashMap<(String, bool), Option<regex::Regex>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Compiles the regular expression `pattern` as a `FilterCondition::Regex` interprets it.
+pub fn compile_regex(pattern: &str, case_insensitive: bool) -> Result<regex::Regex, regex::Error> {
+    regex::RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+}
+
+/// Whether `value` matches the regular expression `pattern`. An invalid pattern matches nothing.
+pub(crate) fn regex_matches(value: &str, pattern: &str, case_insensitive: bool) -> bool {
+    REGEXES.with(|regexes| {
+        regexes
+            .borrow_mut()
+            .entry((pattern.to_owned(), case_insensitive))
+            .or_insert_with(|| compile_regex(pattern, case_insensitive).ok())
+            .as_ref()
+            .map_or(false, |re| re.is_match(value))
+    })
+}
+
+/// Whether the value in column `i` of record `r` satisfies `cond`.
+pub(crate) fn condition_holds(r: &[DataType], i: usize, cond: &FilterCondition) -> bool {
+    let d = &r[i];
+    match *cond {
+        FilterCondition::Comparison(ref op, ref f) => {
+            let v = match *f {
+                Value::Constant(ref dt) => dt,
+                Value::Column(c) => &r[c],
+            };
+            match *op {
+                Operator::Equal => d == v,
+                Operator::NotEqual => d != v,
+                Operator::Greater => d > v,
+                Operator::GreaterOrEqual => d >= v,
+                Operator::Less => d < v,
+                Operator::LessOrEqual => d <= v,
+                Operator::In => unreachable!(),
+                _ => unimplemented!(),
+            }
+        }
+        FilterCondition::In(ref fs) => fs.contains(d),
+        FilterCondition::TimeRelative {
+            ref operator,
+            offset,
+        } => compare_to_now(d, operator, offset),
+        FilterCondition::Like {
+            ref pattern,
+            escape,
+            negated,
+        } => d.is_string() && like_matches(d.into(), pattern, escape) != negated,
+        FilterCondition::Contains(ref e) => d.is_string() && set_contains(d.into(), e),
+        FilterCondition::Regex {
+            ref pattern,
+            case_insensitive,
+            negated,
+        } => d.is_string() && regex_matches(d.into(), pattern, case_insensitive) != negated,
+        FilterCondition::NullSafeEquality(ref v) => d == v,
+        FilterCondition::CollatedComparison {
+            ref operator,
+            ref value,
+            collation,
+        } => {
+            let ordering = collation.compare(d, value);
+            match *operator {
+                Operator::Equal => ordering == Ordering::Equal,
+                Operator::NotEqual => ordering != Ordering::Equal,
+                Operator::Greater => ordering == Ordering::Greater,
+                Operator::GreaterOrEqual => ordering != Ordering::Less,
+                Operator::Less => ordering == Ordering::Less,
+                Operator::LessOrEqual => ordering != Ordering::Greater,
+                _ => unimplemented!(),
+            }
+        }
+        FilterCondition::JsonPathEquality {
+            ref path,
+            ref value,
+        } => {
+            d.is_string()
+                && json_path_value(d.into(), path).map_or(false, |f| DataType::from(f) == *value)
+        }
+    }
+}
+
+impl Display for FilterCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FilterCondition::Comparison(ref op, ref v) => write!(f, "{} {}", op, v),
+            FilterCondition::In(ref xs) => write!(
+                f,
+                "IN ({})",
+                xs.iter()
+                    .map(|d| format!("{}", d))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            FilterCondition::TimeRelative {
+                ref operator,
+                offset,
+            } => write!(f, "{} {}", operator, describe_now(offset)),
+            FilterCondition::Like {
+                ref pattern,
+                escape,
+                negated,
+            } => {
+                write!(f, "{}LIKE '{}'", if negated { "NOT " } else { "" }, pattern)?;
+                match escape {
+                    Some(e) => write!(f, " ESCAPE '{}'", e),
+                    None => Ok(()),
+                }
+            }
+            FilterCondition::Contains(ref e) => write!(f, "CONTAINS {}", e),
+            FilterCondition::Regex {
+                ref pattern,
+                case_insensitive,
+                negated,
+            } => write!(
+                f,
+                "{}~{} '{}'",
+                if negated { "!" } else { "" },
+                if case_insensitive { "*" } else { "" },
+                pattern
+            ),
+            FilterCondition::NullSafeEquality(ref v) => write!(f, "<=> {}", v),
+            FilterCondition::CollatedComparison {
+                ref operator,
+                ref value,
+                collation,
+            } => write!(f, "{} {} COLLATE {}", operator, value, collation),
+            FilterCondition::JsonPathEquality {
+                ref path,
+                ref value,
+            } => write!(f, "->> '{}' = {}", path.join("."), value),
+        }
+    }
+}
+
+/// Describes the (shifted) current time that a `FilterCondition::TimeRelative` compares against.
+pub fn describe_now(offset: i64) -> String {
+    if offset < 0 {
+        format!("NOW() - {}s", -offset)
+    } else if offset > 0 {
+        format!("NOW() + {}s", offset)
+    } else {
+        String::from("NOW()")
+    }
 }
 
 impl Filter {
@@ -48,8 +371,16 @@ impl Filter {
         Filter {
             src: src.into(),
             filter: sync::Arc::new(Vec::from(filter)),
+            admitted: HashMap::new(),
         }
     }
+
+    fn is_time_relative(&self) -> bool {
+        self.filter.iter().any(|(_, cond)| match *cond {
+            FilterCondition::TimeRelative { .. } => true,
+            _ => false,
+        })
+    }
 }
 
 impl Ingredient for Filter {
@@ -81,31 +412,40 @@ impl Ingredient for Filter {
         _: &DomainNodes,
         _: &StateMap,
     ) -> ProcessingResult {
-        rs.retain(|r| {
-            self.filter.iter().all(|(i, cond)| {
-                // check if this filter matches
-                let d = &r[*i];
-                match cond {
-                    FilterCondition::Comparison(ref op, ref f) => {
-                        let v = match *f {
-                            Value::Constant(ref dt) => dt,
-                            Value::Column(c) => &r[c],
-                        };
-                        match *op {
-                            Operator::Equal => d == v,
-                            Operator::NotEqual => d != v,
-                            Operator::Greater => d > v,
-                            Operator::GreaterOrEqual => d >= v,
-                            Operator::Less => d < v,
-                            Operator::LessOrEqual => d <= v,
-                            Operator::In => unreachable!(),
-                            _ => unimplemented!(),
+        if !self.is_time_relative() {
+            rs.retain(|r| {
+                self.filter
+                    .iter()
+                    .all(|(i, cond)| condition_holds(r, *i, cond))
+            });
+        } else {
+            // the clock has moved on since a row was let through, so negatives are forwarded if
+            // their positive was, rather than if they match now
+            let filter = &self.filter;
+            let admitted = &mut self.admitted;
+            rs.retain(|r| match *r {
+                Record::Positive(ref row) => {
+                    let holds = filter.iter().all(|(i, cond)| condition_holds(r, *i, cond));
+                    if holds {
+                        *admitted.entry(row.clone()).or_insert(0) += 1;
+                    }
+                    holds
+                }
+                Record::Negative(ref row) => {
+                    let gone = match admitted.get_mut(row) {
+                        Some(n) => {
+                            *n -= 1;
+                            *n == 0
                         }
+                        None => return false,
+                    };
+                    if gone {
+                        admitted.remove(row);
                     }
-                    FilterCondition::In(ref fs) => fs.contains(d),
+                    true
                 }
-            })
-        });
+            });
+        }
 
         ProcessingResult {
             results: rs,
@@ -150,6 +490,23 @@ impl Ingredient for Filter {
                             .collect::<Vec<_>>()
                             .join(", ")
                     )),
+                    FilterCondition::TimeRelative {
+                        ref operator,
+                        offset,
+                    } => Some(format!(
+                        "f{} {} {}",
+                        i,
+                        escape(&format!("{}", operator)),
+                        describe_now(offset)
+                    )),
+                    FilterCondition::Like { .. }
+                    | FilterCondition::Contains(_)
+                    | FilterCondition::Regex { .. }
+                    | FilterCondition::NullSafeEquality(_)
+                    | FilterCondition::CollatedComparison { .. }
+                    | FilterCondition::JsonPathEquality { .. } => {
+                        Some(format!("f{} {}", i, escape(&format!("{}", cond))))
+                    }
                 })
                 .collect::<Vec<_>>()
                 .as_slice()
@@ -173,28 +530,7 @@ impl Ingredient for Filter {
             .and_then(|result| {
                 let f = self.filter.clone();
                 let filter = move |r: &[DataType]| {
-                    f.iter().all(|(i, ref cond)| {
-                        // check if this filter matches
-                        let d = &r[*i];
-                        match *cond {
-                            FilterCondition::Comparison(ref op, ref f) => {
-                                let v = match *f {
-                                    Value::Constant(ref dt) => dt,
-                                    Value::Column(c) => &r[c],
-                                };
-                                match *op {
-                                    Operator::Equal => d == v,
-                                    Operator::NotEqual => d != v,
-                                    Operator::Greater => d > v,
-                                    Operator::GreaterOrEqual => d >= v,
-                                    Operator::Less => d < v,
-                                    Operator::LessOrEqual => d <= v,
-                                    _ => unimplemented!(),
-                                }
-                            }
-                            FilterCondition::In(ref fs) => fs.contains(d),
-                        }
-                    })
+                    f.iter().all(|(i, ref cond)| condition_holds(r, *i, cond))
                 };
 
                 match result {
@@ -420,4 +756,315 @@ mod tests {
         left = vec![42.into(), "b".into()];
         assert_eq!(g.narrow_one_row(left.clone(), false), vec![left].into());
     }
+
+    #[test]
+    fn it_works_with_empty_in_list() {
+        let mut g = setup(false, Some(&[(0, FilterCondition::In(vec![]))]));
+
+        // nothing is a member of an empty list, not even NULL
+        for left in vec![vec![2.into(), "b".into()], vec![DataType::None, "a".into()]] {
+            assert!(g.narrow_one_row(left, false).is_empty());
+        }
+    }
+
+    #[test]
+    fn it_works_with_time_relative() {
+        // only pass rows whose timestamp is within the last minute
+        let mut g = setup(
+            false,
+            Some(&[(
+                0,
+                FilterCondition::TimeRelative {
+                    operator: Operator::GreaterOrEqual,
+                    offset: -60,
+                },
+            )]),
+        );
+
+        let now = chrono::Local::now().naive_local();
+        let mut left: Vec<DataType>;
+
+        // ten seconds ago
+        left = vec![(now - chrono::Duration::seconds(10)).into(), "a".into()];
+        assert_eq!(g.narrow_one_row(left.clone(), false), vec![left].into());
+
+        // two minutes ago
+        left = vec![(now - chrono::Duration::seconds(120)).into(), "a".into()];
+        assert!(g.narrow_one_row(left.clone(), false).is_empty());
+
+        // operators that do not compare against a time never match
+        assert!(!compare_to_now(&now.into(), &Operator::Like, 0));
+    }
+
+    #[test]
+    fn it_forwards_deletions_of_rows_that_aged_out() {
+        // only pass rows whose timestamp is within the last second
+        let mut g = setup(
+            false,
+            Some(&[(
+                0,
+                FilterCondition::TimeRelative {
+                    operator: Operator::GreaterOrEqual,
+                    offset: -1,
+                },
+            )]),
+        );
+
+        let now = chrono::Local::now().naive_local();
+        let fresh: Vec<DataType> = vec![now.into(), "a".into()];
+        let stale: Vec<DataType> = vec![(now - chrono::Duration::seconds(120)).into(), "b".into()];
+        assert_eq!(
+            g.narrow_one_row(fresh.clone(), false),
+            vec![fresh.clone()].into()
+        );
+        assert!(g.narrow_one_row(stale.clone(), false).is_empty());
+
+        // let the row age out
+        std::thread::sleep(std::time::Duration::from_secs(2));
+        assert!(g.narrow_one_row(fresh.clone(), false).is_empty());
+
+        // it was let through once, so exactly one deletion of it is
+        assert_eq!(
+            g.narrow_one_row((fresh.clone(), false), false),
+            vec![(fresh.clone(), false)].into()
+        );
+        assert!(g.narrow_one_row((fresh, false), false).is_empty());
+
+        // rows that were never let through are not deleted either
+        assert!(g.narrow_one_row((stale, false), false).is_empty());
+    }
+
+    #[test]
+    fn it_works_with_like() {
+        let like = |pattern: &str, escape: Option<char>| FilterCondition::Like {
+            pattern: pattern.into(),
+            escape,
+            negated: false,
+        };
+        let matches = |cond: FilterCondition, value: &str| {
+            let mut g = setup(false, Some(&[(1, cond)]));
+            !g.narrow_one_row(vec![1.into(), value.into()], false)
+                .is_empty()
+        };
+
+        // without an escape character, `_` is always a wildcard
+        assert!(matches(like("a_b%", None), "axbyz"));
+        assert!(matches(like("a\\_b%", None), "a\\xbyz"));
+
+        // an escaped `_` only matches an underscore
+        assert!(matches(like("a\\_b%", Some('\\')), "a_byz"));
+        assert!(!matches(like("a\\_b%", Some('\\')), "axbyz"));
+        assert!(matches(like("100!%", Some('!')), "100%"));
+        assert!(!matches(like("100!%", Some('!')), "1000"));
+
+        // `%` may swallow any number of characters, including none
+        assert!(matches(like("%a%b", None), "ab"));
+        assert!(matches(like("%a%b", None), "xxaxxab"));
+        assert!(!matches(like("%a%b", None), "xxaxxba"));
+
+        // neither LIKE nor NOT LIKE holds for NULL
+        for negated in &[false, true] {
+            let mut g = setup(
+                false,
+                Some(&[(
+                    1,
+                    FilterCondition::Like {
+                        pattern: "%".into(),
+                        escape: None,
+                        negated: *negated,
+                    },
+                )]),
+            );
+            assert!(g
+                .narrow_one_row(vec![1.into(), DataType::None], false)
+                .is_empty());
+        }
+        assert_eq!(
+            format!("{}", like("a\\_b%", Some('\\'))),
+            "LIKE 'a\\_b%' ESCAPE '\\'"
+        );
+    }
+
+    #[test]
+    fn it_works_with_set_membership() {
+        let contains = |element: DataType, set: DataType| {
+            let mut g = setup(false, Some(&[(1, FilterCondition::Contains(element))]));
+            !g.narrow_one_row(vec![1.into(), set], false).is_empty()
+        };
+
+        assert!(contains("green".into(), "red,green,blue".into()));
+        assert!(contains("green".into(), "red, green ,blue".into()));
+        assert!(!contains("gree".into(), "red,green,blue".into()));
+        assert!(!contains("red,green".into(), "red,green,blue".into()));
+
+        // elements compare by their text, so numbers find their textual form
+        assert!(contains(3.into(), "1,2,3".into()));
+        assert!(!contains(4.into(), "1,2,3".into()));
+
+        // the empty set contains nothing, not even the empty element
+        assert!(!contains("".into(), "".into()));
+        assert!(contains("".into(), "a,,b".into()));
+
+        // NULL neither contains nor is contained in anything
+        assert!(!contains("a".into(), DataType::None));
+        assert!(!contains(DataType::None, "a".into()));
+
+        assert_eq!(
+            format!("{}", FilterCondition::Contains("x".into())),
+            "CONTAINS \"x\""
+        );
+    }
+
+    #[test]
+    fn it_works_with_regex() {
+        let regex = |pattern: &str, case_insensitive: bool, negated: bool| FilterCondition::Regex {
+            pattern: pattern.into(),
+            case_insensitive,
+            negated,
+        };
+        let matches = |cond: FilterCondition, value: DataType| {
+            let mut g = setup(false, Some(&[(1, cond)]));
+            !g.narrow_one_row(vec![1.into(), value], false).is_empty()
+        };
+
+        assert!(matches(
+            regex("^foo.*bar$", false, false),
+            "foo and bar".into()
+        ));
+        assert!(!matches(
+            regex("^foo.*bar$", false, false),
+            "foo and baz".into()
+        ));
+        // unanchored patterns match anywhere in the text
+        assert!(matches(regex("o+ b", false, false), "foo bar".into()));
+        assert!(!matches(regex("^FOO", false, false), "foo".into()));
+        assert!(matches(regex("^FOO", true, false), "foo".into()));
+
+        // the negated form keeps exactly the rows the pattern does not match
+        assert!(!matches(
+            regex("^foo.*bar$", false, true),
+            "foo and bar".into()
+        ));
+        assert!(matches(
+            regex("^foo.*bar$", false, true),
+            "foo and baz".into()
+        ));
+
+        // neither form holds for NULL, and an invalid pattern matches nothing
+        assert!(!matches(regex(".*", false, false), DataType::None));
+        assert!(!matches(regex(".*", false, true), DataType::None));
+        assert!(!matches(regex("(", false, false), "(".into()));
+
+        assert_eq!(format!("{}", regex("^a", true, true)), "!~* '^a'");
+    }
+
+    #[test]
+    fn it_works_with_null_safe_equality() {
+        let matches = |cond: FilterCondition, value: DataType| {
+            let mut g = setup(false, Some(&[(1, cond)]));
+            !g.narrow_one_row(vec![1.into(), value], false).is_empty()
+        };
+
+        let is_null = FilterCondition::NullSafeEquality(DataType::None);
+        assert!(matches(is_null.clone(), DataType::None));
+        assert!(!matches(is_null.clone(), "a".into()));
+
+        let is_a = FilterCondition::NullSafeEquality("a".into());
+        assert!(matches(is_a.clone(), "a".into()));
+        assert!(!matches(is_a.clone(), "b".into()));
+        assert!(!matches(is_a, DataType::None));
+
+        assert_eq!(format!("{}", is_null), "<=> NULL");
+    }
+
+    #[test]
+    fn it_works_with_case_insensitive_collation() {
+        let matches = |cond: FilterCondition, value: DataType| {
+            let mut g = setup(false, Some(&[(1, cond)]));
+            !g.narrow_one_row(vec![1.into(), value], false).is_empty()
+        };
+        let collated = |operator: Operator, value: &str, collation: Collation| {
+            FilterCondition::CollatedComparison {
+                operator,
+                value: value.into(),
+                collation,
+            }
+        };
+
+        let is_jose = collated(Operator::Equal, "José", Collation::CaseInsensitive);
+        assert!(matches(is_jose.clone(), "José".into()));
+        assert!(matches(is_jose.clone(), "JOSÉ".into()));
+        assert!(matches(is_jose.clone(), "josé".into()));
+        assert!(!matches(is_jose.clone(), "Jose".into()));
+        assert!(!matches(is_jose.clone(), DataType::None));
+        assert_eq!(format!("{}", is_jose), "= José COLLATE NOCASE");
+
+        // by bytes, only the exact spelling matches
+        let is_jose = collated(Operator::Equal, "José", Collation::Binary);
+        assert!(matches(is_jose.clone(), "José".into()));
+        assert!(!matches(is_jose, "JOSÉ".into()));
+
+        // "B" sorts before "a" by bytes, but after it when case is ignored
+        assert!(matches(
+            collated(Operator::Less, "a", Collation::Binary),
+            "B".into()
+        ));
+        assert!(matches(
+            collated(Operator::Greater, "a", Collation::CaseInsensitive),
+            "B".into()
+        ));
+    }
+
+    #[test]
+    fn it_works_with_json_paths() {
+        let matches = |path: &[&str], value: DataType, json: DataType| {
+            let cond = FilterCondition::JsonPathEquality {
+                path: path.iter().map(|&k| String::from(k)).collect(),
+                value,
+            };
+            let mut g = setup(false, Some(&[(1, cond)]));
+            !g.narrow_one_row(vec![1.into(), json], false).is_empty()
+        };
+        let json: DataType = r#"{"status": "active", "owner": {"name": "ann", "age": 7}}"#.into();
+
+        assert!(matches(&["status"], "active".into(), json.clone()));
+        assert!(!matches(&["status"], "done".into(), json.clone()));
+        // nested fields, including ones that are not strings
+        assert!(matches(&["owner", "name"], "ann".into(), json.clone()));
+        assert!(!matches(&["owner", "name"], "bob".into(), json.clone()));
+        assert!(matches(&["owner", "age"], "7".into(), json.clone()));
+        // missing fields, NULL, and text that is not JSON never match
+        assert!(!matches(&["owner", "email"], "ann".into(), json.clone()));
+        assert!(!matches(&["status", "name"], "active".into(), json));
+        assert!(!matches(&["status"], "active".into(), DataType::None));
+        assert!(!matches(&["status"], "active".into(), "active".into()));
+
+        let cond = FilterCondition::JsonPathEquality {
+            path: vec!["owner".into(), "name".into()],
+            value: "ann".into(),
+        };
+        assert_eq!(format!("{}", cond), "->> 'owner.name' = ann");
+    }
+
+    #[test]
+    fn it_escapes_descriptions() {
+        let g = setup(
+            false,
+            Some(&[
+                (
+                    0,
+                    FilterCondition::Like {
+                        pattern: "<a>%".into(),
+                        escape: None,
+                        negated: false,
+                    },
+                ),
+                (1, FilterCondition::Contains("<b>".into())),
+            ]),
+        );
+        assert_eq!(
+            g.node().description(true),
+            "σ[f0 LIKE '\\<a\\>%', f1 CONTAINS \\<b\\>]"
+        );
+    }
 }
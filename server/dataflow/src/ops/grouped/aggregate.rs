@@ -37,6 +37,46 @@ impl Aggregation {
             },
         )
     }
+
+    /// Extract the change this operation makes to a group's value when `r` is added (or removed,
+    /// if `!pos`), aggregating over column `over`.
+    pub(crate) fn to_diff(&self, r: &[DataType], over: usize, pos: bool) -> i128 {
+        match *self {
+            Aggregation::COUNT if pos => 1,
+            Aggregation::COUNT => -1,
+            Aggregation::SUM => {
+                let v = match r[over] {
+                    DataType::Int(n) => i128::from(n),
+                    DataType::UnsignedInt(n) => i128::from(n),
+                    DataType::BigInt(n) => i128::from(n),
+                    DataType::UnsignedBigInt(n) => i128::from(n),
+                    DataType::None => 0,
+                    ref x => unreachable!("tried to aggregate over {:?} on {:?}", x, r),
+                };
+                if pos {
+                    v
+                } else {
+                    0i128 - v
+                }
+            }
+        }
+    }
+
+    /// Add up the `diffs` for a group whose value is `current` (or that has no value yet).
+    pub(crate) fn apply(
+        current: Option<&DataType>,
+        diffs: &mut dyn Iterator<Item = i128>,
+    ) -> DataType {
+        let n = match current {
+            Some(&DataType::Int(n)) => i128::from(n),
+            Some(&DataType::UnsignedInt(n)) => i128::from(n),
+            Some(&DataType::BigInt(n)) => i128::from(n),
+            Some(&DataType::UnsignedBigInt(n)) => i128::from(n),
+            None => 0,
+            _ => unreachable!(),
+        };
+        diffs.fold(n, |n, d| n + d).into()
+    }
 }
 
 /// Aggregator implementas a Soup node that performans common aggregation operations such as counts
@@ -59,6 +99,13 @@ pub struct Aggregator {
     group: Vec<usize>,
 }
 
+impl Aggregator {
+    /// The aggregation this operator computes.
+    pub fn kind(&self) -> &Aggregation {
+        &self.op
+    }
+}
+
 impl GroupedOperation for Aggregator {
     type Diff = i128;
 
@@ -74,41 +121,15 @@ impl GroupedOperation for Aggregator {
     }
 
     fn to_diff(&self, r: &[DataType], pos: bool) -> Self::Diff {
-        match self.op {
-            Aggregation::COUNT if pos => 1,
-            Aggregation::COUNT => -1,
-            Aggregation::SUM => {
-                let v = match r[self.over] {
-                    DataType::Int(n) => i128::from(n),
-                    DataType::UnsignedInt(n) => i128::from(n),
-                    DataType::BigInt(n) => i128::from(n),
-                    DataType::UnsignedBigInt(n) => i128::from(n),
-                    DataType::None => 0,
-                    ref x => unreachable!("tried to aggregate over {:?} on {:?}", x, r),
-                };
-                if pos {
-                    v
-                } else {
-                    0i128 - v
-                }
-            }
-        }
+        self.op.to_diff(r, self.over, pos)
     }
 
     fn apply(
         &self,
-        current: Option<&DataType>,
+        current: Option<&[DataType]>,
         diffs: &mut dyn Iterator<Item = Self::Diff>,
-    ) -> DataType {
-        let n = match current {
-            Some(&DataType::Int(n)) => i128::from(n),
-            Some(&DataType::UnsignedInt(n)) => i128::from(n),
-            Some(&DataType::BigInt(n)) => i128::from(n),
-            Some(&DataType::UnsignedBigInt(n)) => i128::from(n),
-            None => 0,
-            _ => unreachable!(),
-        };
-        diffs.fold(n, |n, d| n + d).into()
+    ) -> Vec<DataType> {
+        vec![Aggregation::apply(current.map(|c| &c[0]), diffs)]
     }
 
     fn description(&self, detailed: bool) -> String {
@@ -152,9 +152,9 @@ impl GroupedOperation for GroupConcat {
 
     fn apply(
         &self,
-        current: Option<&DataType>,
+        current: Option<&[DataType]>,
         diffs: &mut dyn Iterator<Item = Self::Diff>,
-    ) -> DataType {
+    ) -> Vec<DataType> {
         use std::collections::BTreeSet;
         use std::iter::FromIterator;
 
@@ -165,7 +165,7 @@ impl GroupedOperation for GroupConcat {
         // supporting efficient add/remove.
 
         use std::borrow::Cow;
-        let current: &str = match current {
+        let current: &str = match current.map(|c| &c[0]) {
             Some(dt @ &DataType::Text(..)) | Some(dt @ &DataType::TinyText(..)) => dt.into(),
             None => "",
             _ => unreachable!(),
@@ -200,7 +200,7 @@ impl GroupedOperation for GroupConcat {
         // we pushed one separator too many above
         let real_len = new.len() - self.separator.len();
         new.truncate(real_len);
-        new.into()
+        vec![new.into()]
     }
 
     fn description(&self, detailed: bool) -> String {
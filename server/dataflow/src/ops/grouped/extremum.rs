@@ -63,6 +63,13 @@ pub enum DiffType {
     Remove(i128),
 }
 
+impl ExtremumOperator {
+    /// The extremum this operator computes.
+    pub fn kind(&self) -> &Extremum {
+        &self.op
+    }
+}
+
 impl GroupedOperation for ExtremumOperator {
     type Diff = DiffType;
 
@@ -106,9 +113,11 @@ impl GroupedOperation for ExtremumOperator {
 
     fn apply(
         &self,
-        current: Option<&DataType>,
+        current: Option<&[DataType]>,
         diffs: &mut dyn Iterator<Item = Self::Diff>,
-    ) -> DataType {
+    ) -> Vec<DataType> {
+        let current = current.map(|c| &c[0]);
+
         // Extreme values are those that are at least as extreme as the current min/max (if any).
         // let mut is_extreme_value : Box<dyn Fn(i64) -> bool> = Box::new(|_|true);
         let mut extreme_values: Vec<i128> = vec![];
@@ -158,7 +167,7 @@ impl GroupedOperation for ExtremumOperator {
         };
 
         if let Some(extreme) = extreme {
-            return extreme.into();
+            return vec![extreme.into()];
         }
 
         // TODO: handle this case by querying into the parent.
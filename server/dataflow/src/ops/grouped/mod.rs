@@ -1,8 +1,9 @@
-use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
 
+use slog::Logger;
+
 use crate::prelude::*;
 
 // pub mod latest;
@@ -10,6 +11,7 @@ pub mod aggregate;
 pub mod concat;
 pub mod extremum;
 pub mod filteraggregate;
+pub mod multiaggregate;
 
 /// Trait for implementing operations that collapse a group of records into a single record.
 ///
@@ -51,13 +53,18 @@ pub trait GroupedOperation: fmt::Debug + Clone {
     /// Extract the aggregation value from a single record.
     fn to_diff(&self, record: &[DataType], is_positive: bool) -> Self::Diff;
 
-    /// Given the given `current` value, and a number of changes for a group (`diffs`), compute the
-    /// updated group value.
+    /// The number of values computed for each group. They follow the group columns in the output.
+    fn values(&self) -> usize {
+        1
+    }
+
+    /// Given the given `current` values, and a number of changes for a group (`diffs`), compute the
+    /// updated group values.
     fn apply(
         &self,
-        current: Option<&DataType>,
+        current: Option<&[DataType]>,
         diffs: &mut dyn Iterator<Item = Self::Diff>,
-    ) -> DataType;
+    ) -> Vec<DataType>;
 
     fn description(&self, detailed: bool) -> String;
     fn over_columns(&self) -> Vec<usize>;
@@ -76,6 +83,9 @@ pub struct GroupedOperator<T: GroupedOperation> {
     group_by: Vec<usize>,
     out_key: Vec<usize>,
     colfix: Vec<usize>,
+
+    /// A group that always has a row, even if no record has ever arrived for it
+    default_group: Option<Vec<DataType>>,
 }
 
 impl<T: GroupedOperation> GroupedOperator<T> {
@@ -89,12 +99,29 @@ impl<T: GroupedOperation> GroupedOperator<T> {
             group_by: Vec::new(),
             out_key: Vec::new(),
             colfix: Vec::new(),
+            default_group: None,
         }
     }
 
+    /// Makes `group` (the values of the group columns, in column order) always present in the
+    /// output, with the value of an empty group until records arrive for it. Aggregations without
+    /// a GROUP BY clause group on a constant, and must produce a row even over no input.
+    ///
+    /// The default group's row is produced when the operator's state is first reconstructed, so
+    /// such operators are always fully materialized and never sharded.
+    pub fn with_default_group(mut self, group: Vec<DataType>) -> Self {
+        self.default_group = Some(group);
+        self
+    }
+
     pub fn over_columns(&self) -> Vec<usize> {
         self.inner.over_columns()
     }
+
+    /// The grouped operation this operator performs.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
 }
 
 /// Extract a copy of all values in the record being targeted by the group
@@ -237,29 +264,25 @@ where
                     };
 
                     let old = rs.into_iter().next();
-                    // current value is in the last output column
-                    // or "" if there is no current group
-                    let current = old.as_ref().map(|rows| match rows {
-                        Cow::Borrowed(rs) => Cow::Borrowed(&rs[rs.len() - 1]),
-                        Cow::Owned(rs) => Cow::Owned(rs[rs.len() - 1].clone()),
-                    });
-
-                    // new is the result of applying all diffs for the group to the current value
-                    let new = inner.apply(current.as_ref().map(|v| &**v), &mut diffs as &mut _);
+                    // current values are in the last output columns
+                    // or None if there is no current group
+                    let current = old.as_ref().map(|row| &row[group.len()..]);
+
+                    // new is the result of applying all diffs for the group to the current values
+                    let new = inner.apply(current, &mut diffs as &mut _);
                     match current {
-                        Some(ref current) if new == **current => {
+                        Some(current) if new[..] == *current => {
                             // no change
                         }
                         _ => {
                             if let Some(old) = old {
-                                // revoke old value
-                                debug_assert!(current.is_some());
+                                // revoke old values
                                 out.push(Record::Negative(old.into_owned()));
                             }
 
                             // emit positive, which is group + new.
                             let mut rec = group;
-                            rec.push(new);
+                            rec.extend(new);
                             out.push(Record::Positive(rec));
                         }
                     }
@@ -286,13 +309,59 @@ where
         }
     }
 
+    fn on_input_raw(
+        &mut self,
+        executor: &mut dyn Executor,
+        from: LocalNodeIndex,
+        data: Records,
+        replay: ReplayContext,
+        domain: &DomainNodes,
+        states: &StateMap,
+        _: &Logger,
+    ) -> RawProcessingResult {
+        let complete = match replay {
+            ReplayContext::Full { last } => last,
+            _ => false,
+        };
+        let mut result = self.on_input(executor, from, data, replay.key(), domain, states);
+
+        // once our state has been fully reconstructed, the default group must exist, whether or
+        // not any records were replayed for it
+        if let (true, Some(group)) = (complete, self.default_group.as_ref()) {
+            let us = self.us.unwrap();
+            let in_state = match states
+                .get(*us)
+                .expect("grouped operators must have their own state materialized")
+                .lookup(&self.out_key[..], &KeyType::from(&group[..]))
+            {
+                LookupResult::Some(rs) => !rs.is_empty(),
+                LookupResult::Missing => unreachable!("default group in partial state"),
+            };
+            let emitted = result
+                .results
+                .iter()
+                .any(|r| r.is_positive() && r[..group.len()] == group[..]);
+            if !in_state && !emitted {
+                let mut row = group.clone();
+                row.extend(self.inner.apply(None, &mut std::iter::empty()));
+                result.results.push(Record::Positive(row));
+            }
+        }
+
+        RawProcessingResult::Regular(result)
+    }
+
     fn suggest_indexes(&self, this: NodeIndex) -> HashMap<NodeIndex, Vec<usize>> {
         // index by our primary key
         Some((this, self.out_key.clone())).into_iter().collect()
     }
 
     fn resolve(&self, col: usize) -> Option<Vec<(NodeIndex, usize)>> {
-        if col == self.colfix.len() {
+        if col >= self.colfix.len() {
+            return None;
+        }
+        if self.default_group.is_some() {
+            // the default group's row is generated here, rather than derived from our parent
             return None;
         }
         Some(vec![(self.src.as_global(), self.colfix[col])])
@@ -303,7 +372,7 @@ where
     }
 
     fn parent_columns(&self, column: usize) -> Vec<(NodeIndex, Option<usize>)> {
-        if column == self.colfix.len() {
+        if column >= self.colfix.len() {
             return vec![(self.src.as_global(), None)];
         }
         vec![(self.src.as_global(), Some(self.colfix[column]))]
@@ -312,4 +381,8 @@ where
     fn is_selective(&self) -> bool {
         true
     }
+
+    fn requires_full_materialization(&self) -> bool {
+        self.default_group.is_some()
+    }
 }
@@ -0,0 +1,224 @@
+use crate::ops::grouped::aggregate::Aggregation;
+use crate::ops::grouped::GroupedOperation;
+use crate::ops::grouped::GroupedOperator;
+
+use crate::prelude::*;
+
+/// MultiAggregator computes several counts and sums over the same groups in a single pass.
+///
+/// It behaves like a set of `Aggregator` nodes that share their input and group columns, except
+/// that each group's values are kept in a single row: the output record is constructed by
+/// concatenating the columns identifying the group, followed by one column per aggregation, in the
+/// order the aggregations were given. For example, with `COUNT` and `SUM` over column 1, and the
+/// group `[a]` currently at `[a, 1, 3]`, an incoming record `[a, 4]` produces `-[a, 1, 3]` and
+/// `+[a, 2, 7]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiAggregator {
+    aggregates: Vec<(Aggregation, usize)>,
+    group: Vec<usize>,
+}
+
+impl MultiAggregator {
+    /// Construct a new `MultiAggregator` that computes each of `aggregates` (an operation and the
+    /// column it aggregates over) for every group identified by the columns in `group_by`.
+    pub fn new(
+        src: NodeIndex,
+        aggregates: Vec<(Aggregation, usize)>,
+        group_by: &[usize],
+    ) -> GroupedOperator<MultiAggregator> {
+        assert!(!aggregates.is_empty(), "no aggregations to compute");
+        assert!(
+            !aggregates
+                .iter()
+                .any(|&(_, over)| group_by.iter().any(|&i| i == over)),
+            "cannot group by aggregation column"
+        );
+        GroupedOperator::new(
+            src,
+            MultiAggregator {
+                aggregates,
+                group: group_by.into(),
+            },
+        )
+    }
+
+    /// The aggregations this operator computes, in output order, with the column each is over.
+    pub fn aggregates(&self) -> &[(Aggregation, usize)] {
+        &self.aggregates[..]
+    }
+}
+
+impl GroupedOperation for MultiAggregator {
+    type Diff = Vec<i128>;
+
+    fn setup(&mut self, parent: &Node) {
+        assert!(
+            self.aggregates
+                .iter()
+                .all(|&(_, over)| over < parent.fields().len()),
+            "cannot aggregate over non-existing column"
+        );
+    }
+
+    fn group_by(&self) -> &[usize] {
+        &self.group[..]
+    }
+
+    fn to_diff(&self, r: &[DataType], pos: bool) -> Self::Diff {
+        self.aggregates
+            .iter()
+            .map(|&(ref op, over)| op.to_diff(r, over, pos))
+            .collect()
+    }
+
+    fn values(&self) -> usize {
+        self.aggregates.len()
+    }
+
+    fn apply(
+        &self,
+        current: Option<&[DataType]>,
+        diffs: &mut dyn Iterator<Item = Self::Diff>,
+    ) -> Vec<DataType> {
+        // the diffs hold one change per aggregation, so add them up column by column
+        let diffs: Vec<_> = diffs.collect();
+        (0..self.aggregates.len())
+            .map(|i| Aggregation::apply(current.map(|c| &c[i]), &mut diffs.iter().map(|d| d[i])))
+            .collect()
+    }
+
+    fn description(&self, detailed: bool) -> String {
+        if !detailed {
+            return self
+                .aggregates
+                .iter()
+                .map(|&(ref op, _)| match *op {
+                    Aggregation::COUNT => "+",
+                    Aggregation::SUM => "𝛴",
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+        }
+
+        let op_strings = self
+            .aggregates
+            .iter()
+            .map(|&(ref op, over)| match *op {
+                Aggregation::COUNT => "|*|".to_owned(),
+                Aggregation::SUM => format!("𝛴({})", over),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let group_cols = self
+            .group
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{} γ[{}]", op_strings, group_cols)
+    }
+
+    fn over_columns(&self) -> Vec<usize> {
+        self.aggregates.iter().map(|&(_, over)| over).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ops;
+
+    fn setup(mat: bool) -> ops::test::MockGraph {
+        let mut g = ops::test::MockGraph::new();
+        let s = g.add_base("source", &["x", "y"]);
+        g.set_op(
+            "multi",
+            &["x", "count", "sum"],
+            MultiAggregator::new(
+                s.as_global(),
+                vec![(Aggregation::COUNT, 1), (Aggregation::SUM, 1)],
+                &[0],
+            ),
+            mat,
+        );
+        g
+    }
+
+    #[test]
+    fn it_describes() {
+        let c = MultiAggregator::new(
+            0.into(),
+            vec![(Aggregation::COUNT, 1), (Aggregation::SUM, 1)],
+            &[0, 2],
+        );
+        assert_eq!(c.description(true), "|*|, 𝛴(1) γ[0, 2]");
+    }
+
+    #[test]
+    fn it_forwards() {
+        let mut c = setup(true);
+
+        // first row for a group should emit all values for that group
+        let rs = c.narrow_one_row(vec![1.into(), 3.into()], true);
+        assert_eq!(rs, vec![vec![1.into(), 1.into(), 3.into()]].into());
+
+        // second row for a group should revoke the old values and emit the new ones
+        let rs = c.narrow_one_row(vec![1.into(), 4.into()], true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), 1.into(), 3.into()], false),
+                (vec![1.into(), 2.into(), 7.into()], true),
+            ]
+            .into()
+        );
+
+        // negative row for a group should update every value
+        let rs = c.narrow_one_row((vec![1.into(), 3.into()], false), true);
+        assert_eq!(
+            rs,
+            vec![
+                (vec![1.into(), 2.into(), 7.into()], false),
+                (vec![1.into(), 1.into(), 4.into()], true),
+            ]
+            .into()
+        );
+
+        // a batch touching several groups emits each group's values once
+        let rs = c.narrow_one(
+            vec![
+                (vec![2.into(), 5.into()], true),
+                (vec![1.into(), 1.into()], true),
+                (vec![2.into(), 6.into()], true),
+            ],
+            true,
+        );
+        assert_eq!(rs.len(), 3);
+        assert!(rs.has_negative(&[1.into(), 1.into(), 4.into()][..]));
+        assert!(rs.has_positive(&[1.into(), 2.into(), 5.into()][..]));
+        assert!(rs.has_positive(&[2.into(), 2.into(), 11.into()][..]));
+    }
+
+    #[test]
+    fn it_suggests_indices() {
+        let me = 1.into();
+        let c = setup(false);
+        let idx = c.node().suggest_indexes(me);
+
+        // should only index on the group-by column
+        assert_eq!(idx.len(), 1);
+        assert_eq!(idx[&me], vec![0]);
+    }
+
+    #[test]
+    fn it_resolves() {
+        let c = setup(false);
+        assert_eq!(
+            c.node().resolve(0),
+            Some(vec![(c.narrow_base_id().as_global(), 0)])
+        );
+        assert_eq!(c.node().resolve(1), None);
+        assert_eq!(c.node().resolve(2), None);
+    }
+}
@@ -737,6 +737,22 @@ mod tests {
         assert_eq!(rs.len(), 0);
     }
 
+    #[test]
+    fn it_pairs_null_keys() {
+        // unlike in standard SQL, NULL join keys match each other, as with MySQL's `<=>`
+        let (mut j, l, r) = setup();
+        let r_x = vec![DataType::None, "x".into()];
+        j.seed(r, r_x.clone());
+        j.one_row(r, r_x, false);
+
+        let l_a = vec![DataType::None, "a".into()];
+        j.seed(l, l_a.clone());
+        assert_eq!(
+            j.one_row(l, l_a, false),
+            vec![(vec![DataType::None, "a".into(), "x".into()], true)].into()
+        );
+    }
+
     #[test]
     fn it_suggests_indices() {
         use std::collections::HashMap;
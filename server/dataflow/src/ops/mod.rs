@@ -23,6 +23,7 @@ pub enum NodeOperator {
     Extremum(grouped::GroupedOperator<grouped::extremum::ExtremumOperator>),
     Concat(grouped::GroupedOperator<grouped::concat::GroupConcat>),
     FilterSum(grouped::GroupedOperator<grouped::filteraggregate::FilterAggregator>),
+    MultiSum(grouped::GroupedOperator<grouped::multiaggregate::MultiAggregator>),
     Join(join::Join),
     Latest(latest::Latest),
     Project(project::Project),
@@ -61,6 +62,10 @@ nodeop_from_impl!(
     NodeOperator::FilterSum,
     grouped::GroupedOperator<grouped::filteraggregate::FilterAggregator>
 );
+nodeop_from_impl!(
+    NodeOperator::MultiSum,
+    grouped::GroupedOperator<grouped::multiaggregate::MultiAggregator>
+);
 nodeop_from_impl!(NodeOperator::Join, join::Join);
 nodeop_from_impl!(NodeOperator::Latest, latest::Latest);
 nodeop_from_impl!(NodeOperator::Project, project::Project);
@@ -79,6 +84,7 @@ macro_rules! impl_ingredient_fn_mut {
             NodeOperator::Extremum(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Concat(ref mut i) => i.$fn($($arg),*),
             NodeOperator::FilterSum(ref mut i) => i.$fn($($arg),*),
+            NodeOperator::MultiSum(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Join(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Latest(ref mut i) => i.$fn($($arg),*),
             NodeOperator::Project(ref mut i) => i.$fn($($arg),*),
@@ -100,6 +106,7 @@ macro_rules! impl_ingredient_fn_ref {
             NodeOperator::Extremum(ref i) => i.$fn($($arg),*),
             NodeOperator::Concat(ref i) => i.$fn($($arg),*),
             NodeOperator::FilterSum(ref i) => i.$fn($($arg),*),
+            NodeOperator::MultiSum(ref i) => i.$fn($($arg),*),
             NodeOperator::Join(ref i) => i.$fn($($arg),*),
             NodeOperator::Latest(ref i) => i.$fn($($arg),*),
             NodeOperator::Project(ref i) => i.$fn($($arg),*),
@@ -4,9 +4,10 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
 
+use crate::ops::filter;
 use crate::prelude::*;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ProjectExpressionBase {
     Column(usize),
     Literal(DataType),
@@ -51,6 +52,43 @@ impl fmt::Display for ProjectExpression {
     }
 }
 
+/// A scalar function over several columns or literals of a record.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ProjectFunction {
+    /// The argument's text in lower case. Values other than text are passed through unchanged.
+    Lower(ProjectExpressionBase),
+    /// The argument's text in upper case. Values other than text are passed through unchanged.
+    Upper(ProjectExpressionBase),
+    /// The field at the given path (a key of each nested object in turn) of the JSON object in the
+    /// argument's text, as in MySQL's `->>`. Numbers yield numbers, strings their contents, and
+    /// all other values their JSON text. Missing fields, `null`, and text that is not JSON yield
+    /// `NULL`.
+    JsonExtract(ProjectExpressionBase, Vec<String>),
+}
+
+impl ProjectFunction {
+    /// The function's first argument.
+    pub fn first_argument(&self) -> Option<&ProjectExpressionBase> {
+        match *self {
+            ProjectFunction::Lower(ref first)
+            | ProjectFunction::Upper(ref first)
+            | ProjectFunction::JsonExtract(ref first, _) => Some(first),
+        }
+    }
+}
+
+impl fmt::Display for ProjectFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProjectFunction::Lower(ref a) => write!(f, "LOWER({})", a),
+            ProjectFunction::Upper(ref a) => write!(f, "UPPER({})", a),
+            ProjectFunction::JsonExtract(ref a, ref path) => {
+                write!(f, "{}->>'{}'", a, path.join("."))
+            }
+        }
+    }
+}
+
 /// Permutes or omits columns from its source node, or adds additional literal value columns.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Project {
@@ -58,6 +96,7 @@ pub struct Project {
     emit: Option<Vec<usize>>,
     additional: Option<Vec<DataType>>,
     expressions: Option<Vec<ProjectExpression>>,
+    functions: Option<Vec<ProjectFunction>>,
     src: IndexPair,
     cols: usize,
 }
@@ -74,12 +113,22 @@ impl Project {
             emit: Some(emit.into()),
             additional,
             expressions,
+            functions: None,
             src: src.into(),
             cols: 0,
             us: None,
         }
     }
 
+    /// Additionally emit the value of each of the given scalar functions, after all other
+    /// columns.
+    pub fn with_functions(mut self, functions: Vec<ProjectFunction>) -> Self {
+        if !functions.is_empty() {
+            self.functions = Some(functions);
+        }
+        self
+    }
+
     fn resolve_col(&self, col: usize) -> usize {
         if self.emit.is_some() && col >= self.emit.as_ref().unwrap().len() {
             panic!(
@@ -98,6 +147,10 @@ impl Project {
             self.expressions.as_ref().map(Vec::as_slice).unwrap_or(&[]),
         )
     }
+
+    pub fn functions(&self) -> &[ProjectFunction] {
+        self.functions.as_ref().map(Vec::as_slice).unwrap_or(&[])
+    }
 }
 
 fn eval_expression(expression: &ProjectExpression, record: &[DataType]) -> DataType {
@@ -119,6 +172,39 @@ fn eval_expression(expression: &ProjectExpression, record: &[DataType]) -> DataT
     }
 }
 
+fn eval_function(function: &ProjectFunction, record: &[DataType]) -> DataType {
+    let value = |arg: &ProjectExpressionBase| match *arg {
+        ProjectExpressionBase::Column(i) => record[i].clone(),
+        ProjectExpressionBase::Literal(ref data) => data.clone(),
+    };
+
+    match *function {
+        ProjectFunction::Lower(ref a) | ProjectFunction::Upper(ref a) => match value(a) {
+            ref d if d.is_string() => {
+                let text: &str = d.into();
+                DataType::from(match *function {
+                    ProjectFunction::Lower(_) => text.to_lowercase(),
+                    _ => text.to_uppercase(),
+                })
+            }
+            d => d,
+        },
+        ProjectFunction::JsonExtract(ref a, ref path) => match value(a) {
+            ref d if d.is_string() => match filter::json_path_field(d.into(), path) {
+                Some(serde_json::Value::Number(n)) => n
+                    .as_i64()
+                    .map(DataType::from)
+                    .or_else(|| n.as_f64().map(DataType::from))
+                    .unwrap_or(DataType::None),
+                Some(serde_json::Value::String(s)) => DataType::from(s),
+                Some(v) => DataType::from(v.to_string()),
+                None => DataType::None,
+            },
+            _ => DataType::None,
+        },
+    }
+}
+
 impl Ingredient for Project {
     fn take(&mut self) -> NodeOperator {
         Clone::clone(self).into()
@@ -143,6 +229,7 @@ impl Ingredient for Project {
         let emit = self.emit.clone();
         let additional = self.additional.clone();
         let expressions = self.expressions.clone();
+        let functions = self.functions.clone();
 
         // translate output columns to input columns
         let mut in_cols = Cow::Borrowed(columns);
@@ -172,6 +259,11 @@ impl Ingredient for Project {
                             } else {
                                 vec![]
                             };
+                            let mut function_values: Vec<DataType> = functions
+                                .iter()
+                                .flatten()
+                                .map(|f| eval_function(f, &r[..]))
+                                .collect();
 
                             new_r.extend(
                                 r.into_owned()
@@ -185,6 +277,7 @@ impl Ingredient for Project {
                             if let Some(ref a) = additional {
                                 new_r.append(&mut a.clone());
                             }
+                            new_r.append(&mut function_values);
 
                             Cow::from(new_r)
                         })) as Box<_>,
@@ -209,8 +302,10 @@ impl Ingredient for Project {
         // the inputs, so we don't needlessly perform extra work on each
         // update.
         self.emit = self.emit.take().and_then(|emit| {
-            let complete =
-                emit.len() == self.cols && self.additional.is_none() && self.expressions.is_none();
+            let complete = emit.len() == self.cols
+                && self.additional.is_none()
+                && self.expressions.is_none()
+                && self.functions.is_none();
             let sequential = emit.iter().enumerate().all(|(i, &j)| i == j);
             if complete && sequential {
                 None
@@ -246,6 +341,10 @@ impl Ingredient for Project {
                     new_r.append(&mut a.clone());
                 }
 
+                if let Some(ref f) = self.functions {
+                    new_r.extend(f.iter().map(|f| eval_function(f, &r[..])));
+                }
+
                 **r = new_r;
             }
         }
@@ -291,6 +390,10 @@ impl Ingredient for Project {
                             .collect::<Vec<_>>(),
                     );
                 }
+
+                if let Some(ref functions) = self.functions {
+                    emit_cols.extend(functions.iter().map(|f| format!("{}", f)));
+                }
             }
         };
         format!("π[{}]", emit_cols.join(", "))
@@ -693,4 +796,86 @@ mod tests {
         let p = setup(false, false, true);
         p.node().resolve(2);
     }
+
+    fn setup_function(function: ProjectFunction) -> ops::test::MockGraph {
+        let mut g = ops::test::MockGraph::new();
+        let s = g.add_base("source", &["a", "b", "c"]);
+        g.set_op(
+            "pick",
+            &["a", "b", "c", "pick"],
+            Project::new(s.as_global(), &[0, 1, 2], None, None).with_functions(vec![function]),
+            false,
+        );
+        g
+    }
+
+    #[test]
+    fn it_forwards_lower_and_upper() {
+        let mut p = setup_function(ProjectFunction::Lower(ProjectExpressionBase::Column(1)));
+        assert_eq!(p.node().description(true), "π[0, 1, 2, LOWER(1)]");
+        let rec = vec![1.into(), "MiXeD".into(), 2.into()];
+        assert_eq!(
+            p.narrow_one_row(rec, false),
+            vec![vec![1.into(), "MiXeD".into(), 2.into(), "mixed".into()]].into()
+        );
+
+        // values other than text, including NULL, pass through unchanged
+        let mut p = setup_function(ProjectFunction::Upper(ProjectExpressionBase::Column(0)));
+        let rec = vec![DataType::None, "x".into(), 2.into()];
+        assert_eq!(
+            p.narrow_one_row(rec, false),
+            vec![vec![DataType::None, "x".into(), 2.into(), DataType::None]].into()
+        );
+        let rec = vec![7.into(), "x".into(), 2.into()];
+        assert_eq!(
+            p.narrow_one_row(rec, false),
+            vec![vec![7.into(), "x".into(), 2.into(), 7.into()]].into()
+        );
+    }
+
+    #[test]
+    fn it_forwards_json_fields() {
+        let extract = |path: &[&str]| {
+            ProjectFunction::JsonExtract(
+                ProjectExpressionBase::Column(1),
+                path.iter().map(|k| String::from(*k)).collect(),
+            )
+        };
+        let json: DataType = r#"{"name": "ann", "owner": {"age": 7, "score": 1.5}}"#.into();
+
+        let mut p = setup_function(extract(&["name"]));
+        assert_eq!(p.node().description(true), "π[0, 1, 2, 1->>'name']");
+        let rec = vec![1.into(), json.clone(), 2.into()];
+        assert_eq!(
+            p.narrow_one_row(rec, false),
+            vec![vec![1.into(), json.clone(), 2.into(), "ann".into()]].into()
+        );
+
+        // numbers keep their type
+        let mut p = setup_function(extract(&["owner", "age"]));
+        let rec = vec![1.into(), json.clone(), 2.into()];
+        assert_eq!(
+            p.narrow_one_row(rec, false),
+            vec![vec![1.into(), json.clone(), 2.into(), 7.into()]].into()
+        );
+        let mut p = setup_function(extract(&["owner", "score"]));
+        let rec = vec![1.into(), json.clone(), 2.into()];
+        assert_eq!(
+            p.narrow_one_row(rec, false),
+            vec![vec![1.into(), json.clone(), 2.into(), 1.5.into()]].into()
+        );
+
+        // rows lacking the field, and ones that are not JSON, yield NULL
+        let mut p = setup_function(extract(&["email"]));
+        let rec = vec![1.into(), json.clone(), 2.into()];
+        assert_eq!(
+            p.narrow_one_row(rec, false),
+            vec![vec![1.into(), json, 2.into(), DataType::None]].into()
+        );
+        let rec = vec![2.into(), "ann".into(), 2.into()];
+        assert_eq!(
+            p.narrow_one_row(rec, false),
+            vec![vec![2.into(), "ann".into(), 2.into(), DataType::None]].into()
+        );
+    }
 }
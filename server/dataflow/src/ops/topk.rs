@@ -1,19 +1,106 @@
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt;
 
 use crate::prelude::*;
 
 use nom_sql::OrderType;
 
+/// Where `NULL` values of an ordering column are placed, independently of the column's order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NullOrder {
+    /// `NULL`s rank ahead of all other values
+    NullsFirst,
+    /// `NULL`s rank behind all other values
+    NullsLast,
+}
+
+impl NullOrder {
+    /// The placement used when a SQL `ORDER BY` column does not specify one. `NULL` is treated as
+    /// larger than any other value, as `DataType` orders it, so it comes last in ascending order
+    /// and first in descending order.
+    pub fn default_for(order_type: &OrderType) -> Self {
+        match *order_type {
+            OrderType::OrderAscending => NullOrder::NullsLast,
+            OrderType::OrderDescending => NullOrder::NullsFirst,
+        }
+    }
+}
+
+impl fmt::Display for NullOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NullOrder::NullsFirst => write!(f, "NULLS FIRST"),
+            NullOrder::NullsLast => write!(f, "NULLS LAST"),
+        }
+    }
+}
+
+/// How text values of a column compare, both in filters and when ordering rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Collation {
+    /// Text compares by its bytes, so `"B"` sorts before `"a"`
+    Binary,
+    /// Text compares as if it were all lowercase, so `"José"` equals `"JOSÉ"`
+    CaseInsensitive,
+}
+
+impl Collation {
+    /// The collation named by a SQL `COLLATE` clause. As in MySQL, names ending in `_ci` (e.g.,
+    /// `utf8mb4_general_ci`) are case-insensitive, as is SQLite's `NOCASE`; all others compare
+    /// text by its bytes.
+    pub fn from_name(name: &str) -> Self {
+        let name = name.to_lowercase();
+        if name.ends_with("_ci") || name == "nocase" {
+            Collation::CaseInsensitive
+        } else {
+            Collation::Binary
+        }
+    }
+
+    /// Compares `a` and `b` under this collation. Values that are not both text compare as they
+    /// always do.
+    pub fn compare(self, a: &DataType, b: &DataType) -> Ordering {
+        match self {
+            Collation::CaseInsensitive if a.is_string() && b.is_string() => {
+                let a: &str = a.into();
+                let b: &str = b.into();
+                a.chars()
+                    .flat_map(char::to_lowercase)
+                    .cmp(b.chars().flat_map(char::to_lowercase))
+            }
+            _ => a.cmp(b),
+        }
+    }
+}
+
+impl fmt::Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Collation::Binary => write!(f, "BINARY"),
+            Collation::CaseInsensitive => write!(f, "NOCASE"),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
-struct Order(Vec<(usize, OrderType)>);
+struct Order(Vec<(usize, OrderType, NullOrder, Collation)>);
 impl Order {
     fn cmp(&self, a: &[DataType], b: &[DataType]) -> Ordering {
-        for &(c, ref order_type) in &self.0 {
-            let result = match *order_type {
-                OrderType::OrderAscending => a[c].cmp(&b[c]),
-                OrderType::OrderDescending => b[c].cmp(&a[c]),
+        for &(c, ref order_type, null_order, collation) in &self.0 {
+            // rows that compare greater are the ones that are kept, so a NULL that should come
+            // first must compare greater than any non-NULL value, whatever the column's order.
+            let result = match (&a[c], &b[c], null_order) {
+                (&DataType::None, &DataType::None, _) => Ordering::Equal,
+                (&DataType::None, _, NullOrder::NullsFirst)
+                | (_, &DataType::None, NullOrder::NullsLast) => Ordering::Greater,
+                (&DataType::None, _, NullOrder::NullsLast)
+                | (_, &DataType::None, NullOrder::NullsFirst) => Ordering::Less,
+                _ => match *order_type {
+                    OrderType::OrderAscending => collation.compare(&a[c], &b[c]),
+                    OrderType::OrderDescending => collation.compare(&b[c], &a[c]),
+                },
             };
             if result != Ordering::Equal {
                 return result;
@@ -23,12 +110,22 @@ impl Order {
     }
 }
 
-impl From<Vec<(usize, OrderType)>> for Order {
-    fn from(other: Vec<(usize, OrderType)>) -> Self {
+impl From<Vec<(usize, OrderType, NullOrder, Collation)>> for Order {
+    fn from(other: Vec<(usize, OrderType, NullOrder, Collation)>) -> Self {
         Order(other)
     }
 }
 
+/// Sorts `rows` so that the rows a `TopK` with the given `order` would keep come first, e.g., for
+/// readers that return only the first rows of each group.
+pub(crate) fn sort_by_rank(
+    rows: &mut [&Vec<DataType>],
+    order: &[(usize, OrderType, NullOrder, Collation)],
+) {
+    let order = Order(order.to_vec());
+    rows.sort_by(|a, b| order.cmp(b, a));
+}
+
 /// TopK provides an operator that will produce the top k elements for each group.
 ///
 /// Positives are generally fast to process, while negative records can trigger expensive backwards
@@ -52,12 +149,12 @@ pub struct TopK {
 impl TopK {
     /// Construct a new TopK operator.
     ///
-    /// `src` is this operator's ancestor, `over` is the column to compute the top K over,
-    /// `group_by` indicates the columns that this operator is keyed on, and k is the maximum number
-    /// of results per group.
+    /// `src` is this operator's ancestor, `order` lists the columns to compute the top K over
+    /// along with their order, `NULL` placement, and collation, `group_by` indicates the columns
+    /// that this operator is keyed on, and k is the maximum number of results per group.
     pub fn new(
         src: NodeIndex,
-        order: Vec<(usize, OrderType)>,
+        order: Vec<(usize, OrderType, NullOrder, Collation)>,
         group_by: Vec<usize>,
         k: usize,
     ) -> Self {
@@ -325,9 +422,19 @@ mod tests {
 
     fn setup(reversed: bool) -> (ops::test::MockGraph, IndexPair) {
         let cmp_rows = if reversed {
-            vec![(2, OrderType::OrderDescending)]
+            vec![(
+                2,
+                OrderType::OrderDescending,
+                NullOrder::NullsLast,
+                Collation::Binary,
+            )]
         } else {
-            vec![(2, OrderType::OrderAscending)]
+            vec![(
+                2,
+                OrderType::OrderAscending,
+                NullOrder::NullsLast,
+                Collation::Binary,
+            )]
         };
 
         let mut g = ops::test::MockGraph::new();
@@ -366,6 +473,48 @@ mod tests {
         assert_eq!(g.states[ni].rows(), 3);
     }
 
+    #[test]
+    fn it_places_nulls() {
+        let rows: Vec<Vec<DataType>> = vec![
+            vec![1.into(), "z".into(), 12.into()],
+            vec![2.into(), "z".into(), DataType::None],
+            vec![3.into(), "z".into(), 5.into()],
+            vec![4.into(), "z".into(), 10.into()],
+        ];
+
+        for &(null_order, has_null) in
+            &[(NullOrder::NullsFirst, true), (NullOrder::NullsLast, false)]
+        {
+            // keep the two largest values, with NULLs placed either ahead of or behind them
+            let mut g = ops::test::MockGraph::new();
+            let s = g.add_base("source", &["x", "y", "z"]);
+            g.set_op(
+                "topk",
+                &["x", "y", "z"],
+                TopK::new(
+                    s.as_global(),
+                    vec![(2, OrderType::OrderAscending, null_order, Collation::Binary)],
+                    vec![1],
+                    2,
+                ),
+                true,
+            );
+            let ni = g.node().local_addr();
+
+            for r in &rows {
+                g.narrow_one_row(r.clone(), true);
+            }
+            assert_eq!(g.states[ni].rows(), 2);
+            assert_eq!(
+                g.states[ni]
+                    .cloned_records()
+                    .iter()
+                    .any(|r| r[2] == DataType::None),
+                has_null
+            );
+        }
+    }
+
     #[test]
     fn it_forwards() {
         let (mut g, _) = setup(false);
@@ -561,4 +710,83 @@ mod tests {
         assert!(emit.iter().any(|r| !r.is_positive() && r[2] == 10.into()));
         assert!(emit.iter().any(|r| r.is_positive() && r[2] == 11.into()));
     }
+
+    #[test]
+    fn it_orders_case_insensitively() {
+        let rows: Vec<Vec<DataType>> = vec![
+            vec![1.into(), "z".into(), "b".into()],
+            vec![2.into(), "z".into(), "C".into()],
+            vec![3.into(), "z".into(), "a".into()],
+            vec![4.into(), "z".into(), "D".into()],
+        ];
+
+        // keep the two smallest values; by bytes, those are the uppercase ones
+        for &(collation, kept) in &[
+            (Collation::Binary, ["C", "D"]),
+            (Collation::CaseInsensitive, ["a", "b"]),
+        ] {
+            let mut g = ops::test::MockGraph::new();
+            let s = g.add_base("source", &["x", "y", "z"]);
+            g.set_op(
+                "topk",
+                &["x", "y", "z"],
+                TopK::new(
+                    s.as_global(),
+                    vec![(
+                        2,
+                        OrderType::OrderDescending,
+                        NullOrder::NullsLast,
+                        collation,
+                    )],
+                    vec![1],
+                    2,
+                ),
+                true,
+            );
+            let ni = g.node().local_addr();
+
+            for r in &rows {
+                g.narrow_one_row(r.clone(), true);
+            }
+            let mut kept_rows: Vec<_> = g.states[ni]
+                .cloned_records()
+                .into_iter()
+                .map(|r| r[2].clone())
+                .collect();
+            kept_rows.sort();
+            assert_eq!(kept_rows, vec![kept[0].into(), kept[1].into()]);
+        }
+    }
+
+    #[test]
+    fn it_sorts_by_rank() {
+        let r1: Vec<DataType> = vec![1.into(), 10.into()];
+        let r2: Vec<DataType> = vec![2.into(), 30.into()];
+        let r3: Vec<DataType> = vec![3.into(), DataType::None];
+        let r4: Vec<DataType> = vec![4.into(), 20.into()];
+
+        // like TopK, this ranks the rows that compare greater first
+        let mut rows = vec![&r1, &r2, &r3, &r4];
+        sort_by_rank(
+            &mut rows,
+            &[(
+                1,
+                OrderType::OrderAscending,
+                NullOrder::NullsLast,
+                Collation::Binary,
+            )],
+        );
+        assert_eq!(rows, vec![&r2, &r4, &r1, &r3]);
+
+        sort_by_rank(
+            &mut rows,
+            &[(
+                1,
+                OrderType::OrderDescending,
+                NullOrder::NullsFirst,
+                Collation::Binary,
+            )],
+        );
+        assert_eq!(rows, vec![&r3, &r1, &r4, &r2]);
+    }
 }
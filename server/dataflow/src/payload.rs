@@ -147,6 +147,13 @@ pub enum Packet {
         column: usize,
     },
 
+    /// Renames an existing column of a `Base` node, keeping its data.
+    RenameBaseColumn {
+        node: LocalNodeIndex,
+        column: usize,
+        field: String,
+    },
+
     /// Update Egress node.
     UpdateEgress {
         node: LocalNodeIndex,
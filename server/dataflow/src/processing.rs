@@ -95,7 +95,7 @@ pub(crate) enum ReplayContext<'a> {
 }
 
 impl<'a> ReplayContext<'a> {
-    fn key(&self) -> Option<&'a [usize]> {
+    pub(crate) fn key(&self) -> Option<&'a [usize]> {
         if let ReplayContext::Partial { key_cols, .. } = *self {
             Some(key_cols)
         } else {
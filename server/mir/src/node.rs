@@ -1,4 +1,6 @@
-use nom_sql::{ArithmeticExpression, ColumnSpecification, Literal, OrderType};
+use nom_sql::{
+    ArithmeticExpression, ColumnConstraint, ColumnSpecification, Literal, Operator, OrderType,
+};
 use petgraph::graph::NodeIndex;
 use std::cell::RefCell;
 use std::fmt::{Debug, Display, Error, Formatter};
@@ -8,10 +10,12 @@ use crate::column::Column;
 use crate::{FlowNode, MirNodeRef};
 use common::DataType;
 use dataflow::ops;
-use dataflow::ops::filter::FilterCondition;
+use dataflow::ops::filter::{self, FilterCondition};
 use dataflow::ops::grouped::aggregate::Aggregation as AggregationKind;
 use dataflow::ops::grouped::extremum::Extremum as ExtremumKind;
 use dataflow::ops::grouped::filteraggregate::FilterAggregation as FilterAggregationKind;
+use dataflow::ops::project::ProjectFunction;
+use dataflow::ops::topk::{Collation, NullOrder};
 use std::collections::HashMap;
 
 /// Helper enum to avoid having separate `make_aggregation_node` and `make_extremum_node` functions
@@ -22,6 +26,16 @@ pub enum GroupedNodeType {
     GroupConcat(String),
 }
 
+/// How the reader for a leaf view should be materialized, if not as the planner chooses
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaterializationMode {
+    /// Always keep the entire view in memory
+    Full,
+    /// Keep the entire view in memory and serve it without a key. Only valid for queries
+    /// without parameters, which then do not get a bogokey.
+    Unkeyed,
+}
+
 pub struct MirNode {
     pub name: String,
     pub from_version: usize,
@@ -72,6 +86,8 @@ impl MirNode {
             MirNodeType::Base {
                 ref column_specs,
                 ref keys,
+                ref unique_keys,
+                implicit_rowid,
                 ..
             } => {
                 let new_column_specs: Vec<(ColumnSpecification, Option<usize>)> = column_specs
@@ -89,6 +105,11 @@ impl MirNode {
                     .iter()
                     .map(|&(ref cs, _)| Column::from(&cs.column))
                     .collect();
+                let not_null: Vec<Column> = new_column_specs
+                    .iter()
+                    .filter(|&&(ref cs, _)| cs.constraints.contains(&ColumnConstraint::NotNull))
+                    .map(|&(ref cs, _)| Column::from(&cs.column))
+                    .collect();
 
                 assert_eq!(
                     new_column_specs.len(),
@@ -98,6 +119,9 @@ impl MirNode {
                 let new_inner = MirNodeType::Base {
                     column_specs: new_column_specs,
                     keys: keys.clone(),
+                    unique_keys: unique_keys.clone(),
+                    implicit_rowid,
+                    not_null,
                     adapted_over: Some(BaseNodeAdaptation {
                         over: node.clone(),
                         columns_added: added_cols.into_iter().cloned().collect(),
@@ -191,6 +215,11 @@ impl MirNode {
                 let pos = self.columns.len() - 1;
                 self.columns.insert(pos, c.clone());
             }
+            // ... and so must the columns of a multi-aggregation
+            MirNodeType::MultiAggregation { ref aggregates, .. } => {
+                let pos = self.columns.len() - aggregates.len();
+                self.columns.insert(pos, c.clone());
+            }
             _ => self.columns.push(c.clone()),
         }
         self.inner.add_column(c);
@@ -302,6 +331,14 @@ impl MirNode {
         }
     }
 
+    /// Columns of a base node that were declared `NOT NULL`.
+    pub fn not_null_columns(&self) -> &[Column] {
+        match self.inner {
+            MirNodeType::Base { ref not_null, .. } => not_null.as_slice(),
+            _ => panic!("non-base MIR nodes don't have NOT NULL constraints!"),
+        }
+    }
+
     pub fn flow_node_addr(&self) -> Result<NodeIndex, String> {
         match self.flow_node {
             Some(FlowNode::New(na)) | Some(FlowNode::Existing(na)) => Ok(na),
@@ -338,6 +375,14 @@ impl MirNode {
                     columns.push(on.clone());
                 }
             }
+            MirNodeType::MultiAggregation { ref aggregates, .. } => {
+                // need all "over" columns
+                for &(ref on, _) in aggregates {
+                    if !columns.contains(on) {
+                        columns.push(on.clone());
+                    }
+                }
+            }
             MirNodeType::Filter { .. } => {
                 let parent = self.ancestors.iter().next().unwrap();
                 // need all parent columns
@@ -403,10 +448,19 @@ pub enum MirNodeType {
         group_by: Vec<Column>,
         kind: AggregationKind,
     },
-    /// column specifications, keys (non-compound), tx flag, adapted base
+    /// group_by columns, and the over column of each aggregation computed per group
+    MultiAggregation {
+        group_by: Vec<Column>,
+        aggregates: Vec<(Column, AggregationKind)>,
+    },
+    /// column specifications, keys (non-compound), unique secondary keys, whether the key is a
+    /// synthesized rowid column, columns declared `NOT NULL`, adapted base
     Base {
         column_specs: Vec<(ColumnSpecification, Option<usize>)>,
         keys: Vec<Column>,
+        unique_keys: Vec<Vec<Column>>,
+        implicit_rowid: bool,
+        not_null: Vec<Column>,
         adapted_over: Option<BaseNodeAdaptation>,
     },
     /// over column, group_by columns
@@ -428,10 +482,7 @@ pub enum MirNodeType {
         conditions: Vec<(usize, FilterCondition)>,
     },
     /// over column, separator
-    GroupConcat {
-        on: Column,
-        separator: String,
-    },
+    GroupConcat { on: Column, separator: String },
     /// no extra info required
     Identity,
     /// left node, right node, on left columns, on right columns, emit columns
@@ -449,38 +500,48 @@ pub enum MirNodeType {
     /// group columns
     // currently unused
     #[allow(dead_code)]
-    Latest {
-        group_by: Vec<Column>,
-    },
-    /// emit columns
+    Latest { group_by: Vec<Column> },
+    /// emit columns, computed columns, literal columns, and scalar function columns (whose
+    /// arguments refer to parent column indices)
     Project {
         emit: Vec<Column>,
         arithmetic: Vec<(String, ArithmeticExpression)>,
         literals: Vec<(String, DataType)>,
+        functions: Vec<(String, ProjectFunction)>,
     },
     /// emit columns
-    Union {
-        emit: Vec<Vec<Column>>,
-    },
-    /// order function, group columns, k
+    Union { emit: Vec<Vec<Column>> },
+    /// order function (with NULL placement and collation), group columns, k
+    ///
+    /// If `parameterized_k` is set, `k` is only an upper bound, and readers pick the number of
+    /// rows per group they want when looking up a group.
     TopK {
-        order: Option<Vec<(Column, OrderType)>>,
+        order: Option<Vec<(Column, OrderType, NullOrder, Collation)>>,
         group_by: Vec<Column>,
         k: usize,
         offset: usize,
+        parameterized_k: bool,
     },
-    // Get the distinct element sorted by a specific column
+    /// group columns
+    ///
+    /// If `ordered` is set, readers of the view return each key's rows ordered by the `group_by`
+    /// columns.
     Distinct {
         group_by: Vec<Column>,
+        ordered: bool,
     },
     /// reuse another node
-    Reuse {
-        node: MirNodeRef,
-    },
-    /// leaf (reader) node, keys
+    Reuse { node: MirNodeRef },
+    /// leaf (reader) node, keys; a `ranged` leaf is looked up by a range of key values rather
+    /// than by exact key. A ranged leaf with an `open_range` operator is looked up by a single
+    /// value, and returns the rows of all keys that compare to it with that operator. Without a
+    /// `materialization` mode, the planner decides.
     Leaf {
         node: MirNodeRef,
         keys: Vec<Column>,
+        ranged: bool,
+        open_range: Option<Operator>,
+        materialization: Option<MaterializationMode>,
     },
     /// Rewrite node
     Rewrite {
@@ -495,6 +556,30 @@ impl MirNodeType {
         format!("{:?}", self)
     }
 
+    /// The name of the kind of operator this node is, e.g. `Join` or `TopK`.
+    pub fn operator_name(&self) -> &'static str {
+        match *self {
+            MirNodeType::Aggregation { .. } => "Aggregation",
+            MirNodeType::Base { .. } => "Base",
+            MirNodeType::Extremum { .. } => "Extremum",
+            MirNodeType::Filter { .. } => "Filter",
+            MirNodeType::FilterAggregation { .. } => "FilterAggregation",
+            MirNodeType::MultiAggregation { .. } => "MultiAggregation",
+            MirNodeType::GroupConcat { .. } => "GroupConcat",
+            MirNodeType::Identity => "Identity",
+            MirNodeType::Join { .. } => "Join",
+            MirNodeType::LeftJoin { .. } => "LeftJoin",
+            MirNodeType::Latest { .. } => "Latest",
+            MirNodeType::Project { .. } => "Project",
+            MirNodeType::Union { .. } => "Union",
+            MirNodeType::TopK { .. } => "TopK",
+            MirNodeType::Distinct { .. } => "Distinct",
+            MirNodeType::Reuse { .. } => "Reuse",
+            MirNodeType::Leaf { .. } => "Leaf",
+            MirNodeType::Rewrite { .. } => "Rewrite",
+        }
+    }
+
     fn add_column(&mut self, c: Column) {
         match *self {
             MirNodeType::Aggregation {
@@ -514,6 +599,11 @@ impl MirNodeType {
             } => {
                 group_by.push(c);
             }
+            MirNodeType::MultiAggregation {
+                ref mut group_by, ..
+            } => {
+                group_by.push(c);
+            }
             MirNodeType::Join {
                 ref mut project, ..
             }
@@ -590,12 +680,17 @@ impl MirNodeType {
             MirNodeType::Base {
                 column_specs: ref our_column_specs,
                 keys: ref our_keys,
+                unique_keys: ref our_unique_keys,
+                implicit_rowid: our_implicit_rowid,
                 adapted_over: ref our_adapted_over,
+                ..
             } => {
                 match *other {
                     MirNodeType::Base {
                         ref column_specs,
                         ref keys,
+                        ref unique_keys,
+                        implicit_rowid,
                         ..
                     } => {
                         // if we are instructed to adapt an earlier base node, we cannot reuse
@@ -611,7 +706,10 @@ impl MirNodeType {
                         // note that as long as we are not adapting a previous base node,
                         // we do *not* need `adapted_over` to *match*, since current reuse
                         // does not depend on how base node was created from an earlier one
-                        our_column_specs == column_specs && our_keys == keys
+                        our_column_specs == column_specs
+                            && our_keys == keys
+                            && our_unique_keys == unique_keys
+                            && our_implicit_rowid == implicit_rowid
                     }
                     _ => false,
                 }
@@ -656,6 +754,16 @@ impl MirNodeType {
                 }
                 _ => false,
             },
+            MirNodeType::MultiAggregation {
+                group_by: ref our_group_by,
+                aggregates: ref our_aggregates,
+            } => match *other {
+                MirNodeType::MultiAggregation {
+                    ref group_by,
+                    ref aggregates,
+                } => our_group_by == group_by && our_aggregates == aggregates,
+                _ => false,
+            },
             MirNodeType::Join {
                 on_left: ref our_on_left,
                 on_right: ref our_on_right,
@@ -696,18 +804,29 @@ impl MirNodeType {
                 emit: ref our_emit,
                 literals: ref our_literals,
                 arithmetic: ref our_arithmetic,
+                functions: ref our_functions,
             } => match *other {
                 MirNodeType::Project {
                     ref emit,
                     ref literals,
                     ref arithmetic,
-                } => our_emit == emit && our_literals == literals && our_arithmetic == arithmetic,
+                    ref functions,
+                } => {
+                    our_emit == emit
+                        && our_literals == literals
+                        && our_arithmetic == arithmetic
+                        && our_functions == functions
+                }
                 _ => false,
             },
             MirNodeType::Distinct {
                 group_by: ref our_group_by,
+                ordered: our_ordered,
             } => match *other {
-                MirNodeType::Distinct { ref group_by } => group_by == our_group_by,
+                MirNodeType::Distinct {
+                    ref group_by,
+                    ordered,
+                } => group_by == our_group_by && ordered == our_ordered,
                 _ => false,
             },
             MirNodeType::Reuse { node: ref us } => {
@@ -727,24 +846,42 @@ impl MirNodeType {
                 group_by: ref our_group_by,
                 k: our_k,
                 offset: our_offset,
+                parameterized_k: our_parameterized_k,
             } => match *other {
                 MirNodeType::TopK {
                     ref order,
                     ref group_by,
                     k,
                     offset,
+                    parameterized_k,
                 } => {
                     order == our_order
                         && group_by == our_group_by
                         && k == our_k
                         && offset == our_offset
+                        && parameterized_k == our_parameterized_k
                 }
                 _ => false,
             },
             MirNodeType::Leaf {
-                keys: ref our_keys, ..
+                keys: ref our_keys,
+                ranged: our_ranged,
+                open_range: ref our_open_range,
+                materialization: our_materialization,
+                ..
             } => match *other {
-                MirNodeType::Leaf { ref keys, .. } => keys == our_keys,
+                MirNodeType::Leaf {
+                    ref keys,
+                    ranged,
+                    ref open_range,
+                    materialization,
+                    ..
+                } => {
+                    keys == our_keys
+                        && ranged == our_ranged
+                        && open_range == our_open_range
+                        && materialization == our_materialization
+                }
                 _ => false,
             },
             MirNodeType::Union { emit: ref our_emit } => match *other {
@@ -874,6 +1011,23 @@ impl Debug for MirNodeType {
                                     .collect::<Vec<_>>()
                                     .join(", ")
                             )),
+                            FilterCondition::TimeRelative {
+                                ref operator,
+                                offset,
+                            } => Some(format!(
+                                "f{} {} {}",
+                                i,
+                                escape(&format!("{}", operator)),
+                                filter::describe_now(offset)
+                            )),
+                            FilterCondition::Like { .. }
+                            | FilterCondition::Contains(_)
+                            | FilterCondition::Regex { .. }
+                            | FilterCondition::NullSafeEquality(_)
+                            | FilterCondition::CollatedComparison { .. }
+                            | FilterCondition::JsonPathEquality { .. } => {
+                                Some(format!("f{} {}", i, escape(&format!("{}", cond))))
+                            }
                         })
                         .collect::<Vec<_>>()
                         .as_slice()
@@ -898,6 +1052,25 @@ impl Debug for MirNodeType {
                     .join(", ");
                 write!(f, "{} γ[{}]", op_string, group_cols)
             }
+            MirNodeType::MultiAggregation {
+                ref group_by,
+                ref aggregates,
+            } => {
+                let op_strings = aggregates
+                    .iter()
+                    .map(|&(ref on, ref kind)| match *kind {
+                        AggregationKind::COUNT => format!("|*|({})", on.name.as_str()),
+                        AggregationKind::SUM => format!("𝛴({})", on.name.as_str()),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let group_cols = group_by
+                    .iter()
+                    .map(|c| c.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{} γ[{}]", op_strings, group_cols)
+            }
             MirNodeType::GroupConcat {
                 ref on,
                 ref separator,
@@ -925,13 +1098,23 @@ impl Debug for MirNodeType {
                     jc
                 )
             }
-            MirNodeType::Leaf { ref keys, .. } => {
+            MirNodeType::Leaf {
+                ref keys,
+                ranged,
+                ref open_range,
+                ..
+            } => {
                 let key_cols = keys
                     .iter()
                     .map(|k| k.name.clone())
                     .collect::<Vec<_>>()
                     .join(", ");
-                write!(f, "Leaf [⚷: {}]", key_cols)
+                let range = match *open_range {
+                    Some(ref op) => format!(" (range {})", op),
+                    None if ranged => String::from(" (range)"),
+                    None => String::new(),
+                };
+                write!(f, "Leaf [⚷: {}{}]", key_cols, range)
             }
             MirNodeType::LeftJoin {
                 ref on_left,
@@ -967,9 +1150,10 @@ impl Debug for MirNodeType {
                 ref emit,
                 ref literals,
                 ref arithmetic,
+                ref functions,
             } => write!(
                 f,
-                "π [{}{}{}]",
+                "π [{}{}{}{}]",
                 emit.iter()
                     .map(|c| c.name.as_str())
                     .collect::<Vec<_>>()
@@ -998,6 +1182,10 @@ impl Debug for MirNodeType {
                             .join(", ")
                     )
                 },
+                functions
+                    .iter()
+                    .map(|&(ref n, ref fun)| format!(", {}: {}", n, fun))
+                    .collect::<String>(),
             ),
             MirNodeType::Reuse { ref node } => write!(
                 f,
@@ -1005,17 +1193,34 @@ impl Debug for MirNodeType {
                 node.borrow().versioned_name(),
                 node.borrow()
             ),
-            MirNodeType::Distinct { ref group_by } => {
+            MirNodeType::Distinct {
+                ref group_by,
+                ordered,
+            } => {
                 let key_cols = group_by
                     .iter()
                     .map(|k| k.name.clone())
                     .collect::<Vec<_>>()
                     .join(", ");
-                write!(f, "Distinct [γ: {}]", key_cols)
+                write!(
+                    f,
+                    "Distinct [γ: {}{}]",
+                    key_cols,
+                    if ordered { ", ordered" } else { "" }
+                )
             }
             MirNodeType::TopK {
-                ref order, ref k, ..
-            } => write!(f, "TopK [k: {}, {:?}]", k, order),
+                ref order,
+                ref k,
+                parameterized_k,
+                ..
+            } => write!(
+                f,
+                "TopK [k: {}{}, {:?}]",
+                if parameterized_k { "? <= " } else { "" },
+                k,
+                order
+            ),
             MirNodeType::Union { ref emit } => {
                 let cols = emit
                     .iter()
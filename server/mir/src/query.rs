@@ -1,6 +1,9 @@
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Error, Formatter};
+use std::hash::{Hash, Hasher};
 
+use crate::node::MirNodeType;
 use crate::MirNodeRef;
 use petgraph::graph::NodeIndex;
 
@@ -96,6 +99,116 @@ impl MirQuery {
     }
 }
 
+/// The first point at which two MIR queries diverge structurally, as found by `mir_query_diff`.
+#[derive(Clone, Debug)]
+pub struct MirQueryDiff {
+    /// diverging node in the first query
+    pub left: MirNodeRef,
+    /// diverging node in the second query
+    pub right: MirNodeRef,
+    /// how the two nodes differ
+    pub reason: String,
+}
+
+/// Follows `Reuse` nodes to the node they reuse.
+pub fn reuse_target(n: &MirNodeRef) -> MirNodeRef {
+    match n.borrow().inner {
+        MirNodeType::Reuse { ref node } => reuse_target(node),
+        _ => n.clone(),
+    }
+}
+
+fn diff_nodes(
+    a: &MirNodeRef,
+    b: &MirNodeRef,
+    visited: &mut HashSet<(String, String)>,
+) -> Option<MirQueryDiff> {
+    if !visited.insert((a.borrow().versioned_name(), b.borrow().versioned_name())) {
+        return None;
+    }
+
+    let diff = |reason: String| {
+        Some(MirQueryDiff {
+            left: a.clone(),
+            right: b.clone(),
+            reason,
+        })
+    };
+
+    // compare the operators (and their parameters) of the nodes that actually do the work, so
+    // that a reused node is equivalent to a newly created copy of it
+    let (ta, tb) = (reuse_target(a), reuse_target(b));
+    let (da, db) = (
+        format!("{:?}", ta.borrow().inner),
+        format!("{:?}", tb.borrow().inner),
+    );
+    if da != db {
+        return diff(format!("operator {} != {}", da, db));
+    }
+
+    let column_names = |n: &MirNodeRef| -> Vec<String> {
+        n.borrow().columns.iter().map(|c| c.name.clone()).collect()
+    };
+    let (ca, cb) = (column_names(a), column_names(b));
+    if ca != cb {
+        return diff(format!("columns {:?} != {:?}", ca, cb));
+    }
+
+    let (aa, ab) = (a.borrow().ancestors.clone(), b.borrow().ancestors.clone());
+    if aa.len() != ab.len() {
+        return diff(format!("{} ancestors != {} ancestors", aa.len(), ab.len()));
+    }
+    aa.iter()
+        .zip(ab.iter())
+        .filter_map(|(x, y)| diff_nodes(x, y, visited))
+        .next()
+}
+
+/// Returns the first node at which two MIR queries differ structurally, or `None` if they are
+/// equivalent. Starting at the leaves, nodes are compared by operator type and parameters, by
+/// their column names, and by their ancestors (in order). Node names and schema versions are
+/// ignored, and a `Reuse` node is treated like the node it reuses.
+pub fn mir_query_diff(a: &MirQuery, b: &MirQuery) -> Option<MirQueryDiff> {
+    diff_nodes(&a.leaf, &b.leaf, &mut HashSet::new())
+}
+
+/// Returns true if two MIR queries are structurally equivalent (see `mir_query_diff`).
+pub fn mir_queries_equivalent(a: &MirQuery, b: &MirQuery) -> bool {
+    mir_query_diff(a, b).is_none()
+}
+
+fn hash_node(n: &MirNodeRef, hashed: &mut HashMap<String, u64>) -> u64 {
+    let name = n.borrow().versioned_name();
+    if let Some(&h) = hashed.get(&name) {
+        return h;
+    }
+
+    // cover exactly what `diff_nodes` compares, so equivalent queries hash equal
+    let mut s = DefaultHasher::new();
+    format!("{:?}", reuse_target(n).borrow().inner).hash(&mut s);
+    for c in n.borrow().columns.iter() {
+        c.name.hash(&mut s);
+    }
+    let ancestors = n.borrow().ancestors.clone();
+    ancestors.len().hash(&mut s);
+    for a in &ancestors {
+        hash_node(a, hashed).hash(&mut s);
+    }
+
+    let h = s.finish();
+    hashed.insert(name, h);
+    h
+}
+
+/// Returns a fingerprint of the structure of a MIR query, for use as a key in caches of compiled
+/// queries. Queries that `mir_query_diff` finds equivalent hash equal; in particular, node names
+/// and schema versions do not affect the hash.
+///
+/// The hash is stable within a build, but may change with the Rust standard library's hasher.
+pub fn structural_hash(query: &MirQuery) -> u64 {
+    hash_node(&query.leaf, &mut HashMap::new())
+}
+
 impl Display for MirQuery {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         use std::collections::VecDeque;
@@ -133,3 +246,113 @@ impl Display for MirQuery {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::MirNode;
+    use crate::Column;
+    use dataflow::ops::filter::{FilterCondition, Operator, Value};
+    use nom_sql::{self, ColumnSpecification, SqlType};
+
+    // base -> [filter ->] leaf, with node names suffixed by `suffix`
+    fn make_query(suffix: &str, filter: Option<i32>) -> MirQuery {
+        let cols = vec![Column::from("a"), Column::from("b")];
+        let base = MirNode::new(
+            &format!("base{}", suffix),
+            0,
+            cols.clone(),
+            MirNodeType::Base {
+                column_specs: vec![
+                    (
+                        ColumnSpecification::new(nom_sql::Column::from("a"), SqlType::Text),
+                        None,
+                    ),
+                    (
+                        ColumnSpecification::new(nom_sql::Column::from("b"), SqlType::Text),
+                        None,
+                    ),
+                ],
+                keys: vec![Column::from("a")],
+                unique_keys: vec![],
+                implicit_rowid: false,
+                not_null: vec![],
+                adapted_over: None,
+            },
+            vec![],
+            vec![],
+        );
+        let parent = match filter {
+            Some(v) => MirNode::new(
+                &format!("filter{}", suffix),
+                0,
+                cols.clone(),
+                MirNodeType::Filter {
+                    conditions: vec![(
+                        1,
+                        FilterCondition::Comparison(Operator::Equal, Value::Constant(v.into())),
+                    )],
+                },
+                vec![base.clone()],
+                vec![],
+            ),
+            None => base.clone(),
+        };
+        let leaf = MirNode::new(
+            &format!("leaf{}", suffix),
+            0,
+            cols,
+            MirNodeType::Leaf {
+                node: parent.clone(),
+                keys: vec![Column::from("a")],
+                ranged: false,
+                open_range: None,
+                materialization: None,
+            },
+            vec![parent],
+            vec![],
+        );
+        MirQuery {
+            name: format!("q{}", suffix),
+            roots: vec![base],
+            leaf,
+        }
+    }
+
+    #[test]
+    fn it_finds_identical_queries_equivalent() {
+        let a = make_query("_u1", Some(42));
+        let b = make_query("_u2", Some(42));
+        assert!(mir_queries_equivalent(&a, &b));
+    }
+
+    #[test]
+    fn it_reports_diverging_filter() {
+        let a = make_query("_u1", Some(42));
+        let b = make_query("_u2", Some(21));
+        assert!(!mir_queries_equivalent(&a, &b));
+
+        let diff = mir_query_diff(&a, &b).unwrap();
+        assert_eq!(diff.left.borrow().name, "filter_u1");
+        assert_eq!(diff.right.borrow().name, "filter_u2");
+
+        // a missing filter shows up on the leaf's ancestor
+        let c = make_query("_u3", None);
+        let diff = mir_query_diff(&a, &c).unwrap();
+        assert_eq!(diff.left.borrow().name, "filter_u1");
+        assert_eq!(diff.right.borrow().name, "base_u3");
+    }
+
+    #[test]
+    fn it_hashes_equivalent_queries_equal() {
+        let a = make_query("_u1", Some(42));
+        let b = make_query("_u2", Some(42));
+        b.leaf.borrow_mut().from_version = 3;
+        assert_eq!(structural_hash(&a), structural_hash(&b));
+
+        let c = make_query("_u3", Some(21));
+        assert_ne!(structural_hash(&a), structural_hash(&c));
+        let d = make_query("_u4", None);
+        assert_ne!(structural_hash(&a), structural_hash(&d));
+    }
+}
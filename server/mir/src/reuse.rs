@@ -240,6 +240,9 @@ mod tests {
             MirNodeType::Base {
                 column_specs: vec![cspec("aa"), cspec("ab")],
                 keys: vec![Column::from("aa")],
+                unique_keys: vec![],
+                implicit_rowid: false,
+                not_null: vec![],
                 adapted_over: None,
             },
             vec![],
@@ -252,6 +255,9 @@ mod tests {
             MirNodeType::Base {
                 column_specs: vec![cspec("ba"), cspec("bb")],
                 keys: vec![Column::from("ba")],
+                unique_keys: vec![],
+                implicit_rowid: false,
+                not_null: vec![],
                 adapted_over: None,
             },
             vec![],
@@ -276,6 +282,9 @@ mod tests {
             MirNodeType::Leaf {
                 node: c.clone(),
                 keys: vec![Column::from("ba")],
+                ranged: false,
+                open_range: None,
+                materialization: None,
             },
             vec![],
             vec![],
@@ -325,6 +334,7 @@ mod tests {
                 emit: vec![Column::from("aa")],
                 arithmetic: vec![],
                 literals: vec![],
+                functions: vec![],
             },
             vec![c.clone()],
             vec![d.clone()],
@@ -3,10 +3,11 @@ use std::fmt::{self, Write};
 
 use crate::node::{MirNode, MirNodeType};
 use crate::query::MirQuery;
-use dataflow::ops::filter::FilterCondition;
+use dataflow::ops::filter::{self, FilterCondition};
 use dataflow::ops::grouped::aggregate::Aggregation as AggregationKind;
 use dataflow::ops::grouped::extremum::Extremum as ExtremumKind;
 use dataflow::ops::grouped::filteraggregate::FilterAggregation as FilterAggregationKind;
+use dataflow::ops::topk::Collation;
 
 pub trait GraphViz {
     fn to_graphviz(&self) -> Result<String, fmt::Error>;
@@ -193,12 +194,48 @@ impl GraphViz for MirNodeType {
                                     .collect::<Vec<_>>()
                                     .join(", ")
                             )),
+                            FilterCondition::TimeRelative {
+                                ref operator,
+                                offset,
+                            } => Some(format!(
+                                "f{} {} {}",
+                                i,
+                                escape(&format!("{}", operator)),
+                                filter::describe_now(offset)
+                            )),
+                            FilterCondition::Like { .. }
+                            | FilterCondition::Contains(_)
+                            | FilterCondition::Regex { .. }
+                            | FilterCondition::NullSafeEquality(_)
+                            | FilterCondition::CollatedComparison { .. }
+                            | FilterCondition::JsonPathEquality { .. } => {
+                                Some(format!("f{} {}", i, escape(&format!("{}", cond))))
+                            }
                         })
                         .collect::<Vec<_>>()
                         .as_slice()
                         .join(", ")
                 )?;
             }
+            MirNodeType::MultiAggregation {
+                ref group_by,
+                ref aggregates,
+            } => {
+                let op_strings = aggregates
+                    .iter()
+                    .map(|&(ref on, ref kind)| match *kind {
+                        AggregationKind::COUNT => format!("\\|*\\|({})", print_col(on)),
+                        AggregationKind::SUM => format!("𝛴({})", print_col(on)),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let group_cols = group_by
+                    .iter()
+                    .map(|c| print_col(c))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(out, "{} | γ: {}", op_strings, group_cols)?;
+            }
             MirNodeType::GroupConcat {
                 ref on,
                 ref separator,
@@ -221,13 +258,23 @@ impl GraphViz for MirNodeType {
                     .join(", ");
                 write!(out, "⋈  | on: {}", jc)?;
             }
-            MirNodeType::Leaf { ref keys, .. } => {
+            MirNodeType::Leaf {
+                ref keys,
+                ranged,
+                ref open_range,
+                ..
+            } => {
                 let key_cols = keys
                     .iter()
                     .map(|k| print_col(k))
                     .collect::<Vec<_>>()
                     .join(", ");
-                write!(out, "Leaf | ⚷: {}", key_cols)?;
+                let range = match *open_range {
+                    Some(ref op) => format!(" (range {})", op),
+                    None if ranged => String::from(" (range)"),
+                    None => String::new(),
+                };
+                write!(out, "Leaf | ⚷: {}{}", key_cols, range)?;
             }
             MirNodeType::LeftJoin {
                 ref on_left,
@@ -254,10 +301,11 @@ impl GraphViz for MirNodeType {
                 ref emit,
                 ref literals,
                 ref arithmetic,
+                ref functions,
             } => {
                 write!(
                     out,
-                    "π: {}{}{}",
+                    "π: {}{}{}{}",
                     emit.iter()
                         .map(|c| print_col(c))
                         .collect::<Vec<_>>()
@@ -285,32 +333,55 @@ impl GraphViz for MirNodeType {
                                 .collect::<Vec<_>>()
                                 .join(", ")
                         )
-                    }
+                    },
+                    functions
+                        .iter()
+                        .map(|&(ref n, ref fun)| format!(", {}: {}", n, fun))
+                        .collect::<String>(),
                 )?;
             }
             MirNodeType::Reuse { ref node } => {
                 write!(out, "Reuse | using: {}", node.borrow().versioned_name(),)?;
             }
-            MirNodeType::Distinct { ref group_by } => {
+            MirNodeType::Distinct {
+                ref group_by,
+                ordered,
+            } => {
                 let key_cols = group_by
                     .iter()
                     .map(|k| print_col(k))
                     .collect::<Vec<_>>()
                     .join(", ");
                 write!(out, "Distinct | γ: {}", key_cols)?;
+                if ordered {
+                    write!(out, " | ordered")?;
+                }
             }
             MirNodeType::TopK {
-                ref order, ref k, ..
+                ref order,
+                ref k,
+                parameterized_k,
+                ..
             } => {
                 write!(
                     out,
-                    "TopK [k: {}; {}]",
+                    "TopK [k: {}{}; {}]",
+                    if parameterized_k { "? \\<= " } else { "" },
                     k,
                     order
                         .as_ref()
                         .map(|v| v
                             .iter()
-                            .map(|(c, o)| format!("{}: {}", c.name.as_str(), o))
+                            .map(|(c, o, n, collation)| match *collation {
+                                Collation::Binary => format!("{}: {} {}", c.name.as_str(), o, n),
+                                _ => format!(
+                                    "{}: {} {} COLLATE {}",
+                                    c.name.as_str(),
+                                    o,
+                                    n,
+                                    collation
+                                ),
+                            })
                             .collect::<Vec<_>>()
                             .join(", "))
                         .unwrap_or_else(|| "".into())
@@ -1,8 +1,10 @@
 use crate::handle::Handle;
 use crate::Config;
 use crate::FrontierStrategy;
+use crate::PostLoweringHook;
 use crate::ReuseConfigType;
 use dataflow::PersistenceParameters;
+use mir::MirNodeRef;
 use noria::consensus::{Authority, LocalAuthority};
 use std::future::Future;
 use std::net::IpAddr;
@@ -16,6 +18,7 @@ pub struct Builder {
     memory_check_frequency: Option<time::Duration>,
     listen_addr: IpAddr,
     log: slog::Logger,
+    post_lowering_hook: Option<PostLoweringHook>,
 }
 impl Default for Builder {
     fn default() -> Self {
@@ -25,6 +28,7 @@ impl Default for Builder {
             log: slog::Logger::root(slog::Discard, o!()),
             memory_limit: None,
             memory_check_frequency: None,
+            post_lowering_hook: None,
         }
     }
 }
@@ -93,6 +97,29 @@ impl Builder {
         self.config.reuse = reuse_type;
     }
 
+    /// Give tables created without a primary key an implicit, auto-incrementing `rowid` key
+    /// column.
+    pub fn enable_implicit_rowid(&mut self) {
+        self.config.implicit_rowid = true;
+    }
+
+    /// Reject queries with a `GROUP BY` clause that select columns they neither group by nor
+    /// aggregate, as standard SQL does. By default, such queries are accepted, and each group
+    /// shows the value of an arbitrary one of its rows for these columns.
+    pub fn enable_strict_group_by(&mut self) {
+        self.config.strict_group_by = true;
+    }
+
+    /// Run `hook` over the MIR nodes generated for each query, including the query's leaf, before
+    /// they are added to the data-flow graph. The hook may rewrite the nodes, for example to fuse
+    /// adjacent filters, as long as it leaves a well-formed query behind.
+    pub fn set_post_lowering_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&mut Vec<MirNodeRef>) + Send + Sync + 'static,
+    {
+        self.post_lowering_hook = Some(Arc::new(hook));
+    }
+
     /// Set the number of pool threads to use (default is #cores)
     pub fn set_threads(&mut self, threads: usize) {
         self.config.threads = Some(threads);
@@ -114,10 +141,12 @@ impl Builder {
             memory_limit,
             memory_check_frequency,
             ref log,
+            ref post_lowering_hook,
         } = *self;
 
         let config = config.clone();
         let log = log.clone();
+        let post_lowering_hook = post_lowering_hook.clone();
 
         crate::startup::start_instance(
             authority,
@@ -126,6 +155,7 @@ impl Builder {
             memory_limit,
             memory_check_frequency,
             log,
+            post_lowering_hook,
         )
     }
 
@@ -5,16 +5,18 @@ use crate::controller::schema;
 use crate::controller::{ControllerState, Migration, Recipe};
 use crate::controller::{Worker, WorkerIdentifier};
 use crate::coordination::{CoordinationMessage, CoordinationPayload, DomainDescriptor};
+use crate::PostLoweringHook;
 use dataflow::prelude::*;
 use dataflow::{node, payload::ControlReplyPacket, prelude::Packet, DomainBuilder, DomainConfig};
 use futures_util::stream::StreamExt;
 use hyper::{self, Method, StatusCode};
-use nom_sql::ColumnSpecification;
+use nom_sql::{ColumnSpecification, LimitClause, Literal, OrderClause};
 use noria::builders::*;
 use noria::channel::tcp::{SendError, TcpSender};
 use noria::consensus::{Authority, Epoch, STATE_KEY};
 use noria::debug::stats::{DomainStats, GraphStats, NodeStats};
-use noria::ActivationResult;
+use noria::error::PrewarmError;
+use noria::{ActivationResult, SerializedConverterState};
 use petgraph::visit::Bfs;
 use slog::Logger;
 use std::collections::{BTreeMap, HashMap, HashSet};
@@ -265,6 +267,92 @@ impl ControllerInner {
             (Method::POST, "/view_builder") => json::from_slice(&body)
                 .map_err(|_| StatusCode::BAD_REQUEST)
                 .map(|args| Ok(json::to_string(&self.view_builder(args)).unwrap())),
+            (Method::POST, "/base_dependencies") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| Ok(json::to_string(&self.base_dependencies(args)).unwrap())),
+            (Method::POST, "/explain") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| Ok(json::to_string(&self.explain(args)).unwrap())),
+            (Method::POST, "/export_converter_state") => {
+                Ok(Ok(json::to_string(&self.export_converter_state()).unwrap()))
+            }
+            (Method::POST, "/import_converter_state") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| Ok(json::to_string(&self.import_converter_state(args)).unwrap())),
+            (Method::POST, "/rekey_view") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|(view, key_columns): (String, Vec<String>)| {
+                    self.rekey_view(&view, key_columns)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/update_topk") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(
+                    |(view, order, limit): (String, Option<OrderClause>, LimitClause)| {
+                        self.update_topk(&view, order, limit)
+                            .map(|r| json::to_string(&r).unwrap())
+                    },
+                ),
+            (Method::POST, "/add_sorted_view") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(
+                    |(base_view, name, order, page): (String, String, OrderClause, LimitClause)| {
+                        self.add_sorted_view(&base_view, &name, order, page)
+                            .map(|r| json::to_string(&r).unwrap())
+                    },
+                ),
+            (Method::POST, "/add_secondary_index") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|(base, key_columns): (String, Vec<String>)| {
+                    self.add_secondary_index(&base, key_columns)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/estimate_node_count") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| {
+                    self.estimate_node_count(args)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/set_cardinality_hints") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| Ok(json::to_string(&self.set_cardinality_hints(args)).unwrap())),
+            (Method::POST, "/view_columns") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| Ok(json::to_string(&self.view_columns(args)).unwrap())),
+            (Method::POST, "/find_nodes") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|(view, operator): (String, String)| {
+                    Ok(json::to_string(&self.find_nodes(&view, &operator)).unwrap())
+                }),
+            (Method::POST, "/set_computed_parameter") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(
+                    |(query, param, name, function): (String, String, String, String)| {
+                        self.set_computed_parameter(&query, &param, &name, &function)
+                            .map(|r| json::to_string(&r).unwrap())
+                    },
+                ),
+            (Method::POST, "/bind_parameter") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|(query, column, value): (String, String, Literal)| {
+                    Ok(json::to_string(&self.bind_parameter(&query, &column, value)).unwrap())
+                }),
+            (Method::POST, "/set_name_prefix") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|args| Ok(json::to_string(&self.set_name_prefix(args)).unwrap())),
+            (Method::POST, "/reinstantiate_for_universe") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|(view, context): (String, HashMap<String, DataType>)| {
+                    self.reinstantiate_for_universe(&view, context)
+                        .map(|r| json::to_string(&r).unwrap())
+                }),
+            (Method::POST, "/prewarm_keys") => json::from_slice(&body)
+                .map_err(|_| StatusCode::BAD_REQUEST)
+                .map(|(view, keys): (String, Vec<Vec<DataType>>)| {
+                    self.prewarm_keys(&view, keys)
+                        .map(|r| json::to_string(&r).unwrap())
+                        .map_err(|e| e.to_string())
+                }),
             (Method::POST, "/extend_recipe") => json::from_slice(&body)
                 .map_err(|_| StatusCode::BAD_REQUEST)
                 .map(|args| {
@@ -421,6 +509,7 @@ impl ControllerInner {
         log: slog::Logger,
         state: ControllerState,
         drx: tokio::sync::mpsc::UnboundedReceiver<ControlReplyPacket>,
+        post_lowering_hook: Option<PostLoweringHook>,
     ) -> Self {
         let mut g = petgraph::Graph::new();
         let source = g.add_node(node::Node::new(
@@ -446,6 +535,15 @@ impl ControllerInner {
 
         let mut recipe = Recipe::blank(Some(log.clone()));
         recipe.enable_reuse(state.config.reuse);
+        if state.config.implicit_rowid {
+            recipe.enable_implicit_rowid();
+        }
+        if state.config.strict_group_by {
+            recipe.enable_strict_group_by();
+        }
+        if let Some(hook) = post_lowering_hook {
+            recipe.set_post_lowering_hook(hook);
+        }
 
         ControllerInner {
             ingredients: g,
@@ -749,9 +847,8 @@ impl ControllerInner {
         None
     }
 
-    /// Obtain a `ViewBuilder` that can be sent to a client and then used to query a given
-    /// (already maintained) reader node called `name`.
-    fn view_builder(&self, name: &str) -> Option<ViewBuilder> {
+    /// Find the reader node of the (already maintained) view called `name`.
+    fn find_reader(&self, name: &str) -> Option<NodeIndex> {
         // first try to resolve the node via the recipe, which handles aliasing between identical
         // queries.
         let node = match self.recipe.node_addr_for(name) {
@@ -767,7 +864,13 @@ impl ControllerInner {
             None => name,
             Some(alias) => alias,
         };
-        self.find_view_for(node, name).map(|r| {
+        self.find_view_for(node, name)
+    }
+
+    /// Obtain a `ViewBuilder` that can be sent to a client and then used to query a given
+    /// (already maintained) reader node called `name`.
+    fn view_builder(&self, name: &str) -> Option<ViewBuilder> {
+        self.find_reader(name).map(|r| {
             let domain = self.ingredients[r].domain();
             let columns = self.ingredients[r].fields().to_vec();
             let schema = self.view_schema(r);
@@ -784,6 +887,228 @@ impl ControllerInner {
         })
     }
 
+    /// Returns the names of the base tables that the view called `view_name` reads from, or
+    /// `None` if there is no such view.
+    fn base_dependencies(&self, view_name: &str) -> Option<HashSet<String>> {
+        self.recipe.node_addr_for(view_name).ok()?;
+        Some(self.recipe.base_dependencies(view_name))
+    }
+
+    /// Returns a textual plan of the view called `view_name`, with one line per MIR node, or
+    /// `None` if there is no such view.
+    fn explain(&self, view_name: &str) -> Option<String> {
+        self.recipe.node_addr_for(view_name).ok()?;
+        self.recipe.explain(view_name)
+    }
+
+    /// Key the view called `view_name` on `key_columns` instead of on the parameters of its
+    /// query, e.g., to look up a view without parameters by one of its columns. The new reader
+    /// reads from the node that fed the old one, so none of the view's other nodes are built
+    /// again; the old reader is removed once the new one is in place.
+    fn rekey_view(&mut self, view_name: &str, key_columns: Vec<String>) -> Result<(), String> {
+        let old_reader = self
+            .find_reader(view_name)
+            .ok_or_else(|| format!("no view named \"{}\"", view_name))?;
+        let mut r = self.recipe.clone();
+        self.migrate(|mig| r.rekey_view(view_name, &key_columns, mig))?;
+        self.recipe = r;
+        self.remove_leaf(old_reader)
+    }
+
+    /// Replace the `ORDER BY` and `LIMIT` of the view `view_name` with `order` and `limit`. Only
+    /// the view's TopK and the nodes above it are built again, so everything upstream of it is
+    /// kept, and the view's old reader is removed.
+    fn update_topk(
+        &mut self,
+        view_name: &str,
+        order: Option<OrderClause>,
+        limit: LimitClause,
+    ) -> Result<(), String> {
+        let old_reader = self
+            .find_reader(view_name)
+            .ok_or_else(|| format!("no view named \"{}\"", view_name))?;
+        let mut r = self.recipe.clone();
+        self.migrate(|mig| r.update_topk(view_name, order, limit, mig))?;
+        self.recipe = r;
+        self.remove_leaf(old_reader)
+    }
+
+    /// Add the view `name`, which reads the view (or base table) `base_view` sorted by `order` and
+    /// cut down to the first `page.limit` rows for each of the view's keys. Views without a key,
+    /// such as base tables, are paged as a whole and read through the bogokey.
+    fn add_sorted_view(
+        &mut self,
+        base_view: &str,
+        name: &str,
+        order: OrderClause,
+        page: LimitClause,
+    ) -> Result<(), String> {
+        let mut r = self.recipe.clone();
+        self.migrate(|mig| r.add_sorted_view(base_view, name, order, page, mig))?;
+        self.recipe = r;
+        Ok(())
+    }
+
+    /// Add a view that returns the rows of the base table `base_name` looked up by `key_columns`
+    /// rather than by the base's primary key. Returns the name of the new view, which is
+    /// `<base>_by_<columns>`.
+    fn add_secondary_index(
+        &mut self,
+        base_name: &str,
+        key_columns: Vec<String>,
+    ) -> Result<String, String> {
+        let mut r = self.recipe.clone();
+        let name = self.migrate(|mig| r.add_secondary_index(base_name, &key_columns, mig))?;
+        self.recipe = r;
+        Ok(name)
+    }
+
+    /// Returns the schema evolution state of the SQL-to-MIR converter: the schema history of
+    /// every base table and the current version of each named node.
+    fn export_converter_state(&self) -> SerializedConverterState {
+        self.recipe.export_converter_state()
+    }
+
+    /// Replaces the schema evolution state of the SQL-to-MIR converter with `state`, as returned
+    /// by `export_converter_state` before a restart.
+    fn import_converter_state(&mut self, state: SerializedConverterState) {
+        self.recipe.import_converter_state(state);
+    }
+
+    /// Estimates how many dataflow nodes adding the `SELECT` query `query` would create, without
+    /// changing the graph. Fails if the query could not be added.
+    fn estimate_node_count(&self, query: &str) -> Result<usize, String> {
+        self.recipe.estimate_node_count(query)
+    }
+
+    /// Set estimates of the number of rows in each table or view, by name. Queries added from
+    /// now on build their join chains starting with the smallest relations, which keeps the
+    /// intermediate join state small. Queries with outer joins keep the order the query gives.
+    fn set_cardinality_hints(&mut self, hints: HashMap<String, usize>) {
+        self.recipe.set_cardinality_hints(hints);
+    }
+
+    /// Key the view of the query `query_name`, once added, on `function(param)` rather than on
+    /// its parameter column `param`, as if the query compared the function with the parameter
+    /// (e.g., `WHERE LOWER(users.email) = ?`). The function's value is kept in a column called
+    /// `name`. Fails if `function` is not one of `LOWER` and `UPPER`.
+    fn set_computed_parameter(
+        &mut self,
+        query_name: &str,
+        param: &str,
+        name: &str,
+        function: &str,
+    ) -> Result<(), String> {
+        self.recipe
+            .set_computed_parameter(query_name, param, name, function)
+    }
+
+    /// Bind the parameter `column` (as in `table.column = ?`) of the query `query_name`, once
+    /// added, to `value`. Its view then only holds rows whose `column` is `value`, and readers
+    /// supply the query's other parameters as the key.
+    fn bind_parameter(&mut self, query_name: &str, column: &str, value: Literal) {
+        self.recipe.bind_parameter(query_name, column, value);
+    }
+
+    /// Prepend `prefix` to the names of the internal nodes generated for queries added from now
+    /// on, so that independent schemas loaded into the same instance do not produce clashing
+    /// node names. Base tables and views keep the names they are given.
+    fn set_name_prefix(&mut self, prefix: &str) {
+        self.recipe.set_name_prefix(prefix);
+    }
+
+    /// Returns the names of the columns that the view called `view_name` exposes, in the order
+    /// its rows hold them, or `None` if there is no such view.
+    fn view_columns(&self, view_name: &str) -> Option<Vec<String>> {
+        self.recipe.node_addr_for(view_name).ok()?;
+        self.recipe.view_columns(view_name)
+    }
+
+    /// Returns the names of the nodes of the view called `view_name` whose operator is
+    /// `operator` (as named in `explain`, e.g., "Join"), or `None` if there is no such view.
+    fn find_nodes(&self, view_name: &str, operator: &str) -> Option<Vec<String>> {
+        self.recipe.node_addr_for(view_name).ok()?;
+        Some(self.recipe.find_nodes(view_name, operator))
+    }
+
+    /// Add the view called `view_name` to the universe described by `context` (as passed to
+    /// `create_universe`), for instance because the view was added to the recipe after the
+    /// universe was created. Returns the name of the universe's copy of the view, or `None` if
+    /// there is no such view, the universe does not exist, or the view already exists in it.
+    fn reinstantiate_for_universe(
+        &mut self,
+        view_name: &str,
+        context: HashMap<String, DataType>,
+    ) -> Result<Option<String>, String> {
+        if self.recipe.node_addr_for(view_name).is_err() {
+            return Ok(None);
+        }
+        let mut r = self.recipe.clone();
+        let name =
+            self.add_universe(context, |mig| r.reinstantiate_for_universe(view_name, mig))?;
+        self.recipe = r;
+        Ok(name)
+    }
+
+    /// Ask the domain of the view called `view_name` to fill its reader with the results for
+    /// `keys`, so that the first reads of those keys hit materialized state.
+    ///
+    /// The replays happen asynchronously. Fully materialized views already hold every key, so
+    /// they need no upqueries.
+    pub(super) fn prewarm_keys(
+        &mut self,
+        view_name: &str,
+        keys: Vec<Vec<DataType>>,
+    ) -> Result<(), PrewarmError> {
+        let r = self
+            .find_reader(view_name)
+            .ok_or_else(|| PrewarmError::NoSuchView(view_name.to_owned()))?;
+        let cols = self.ingredients[r]
+            .with_reader(|r| r.key().map(Vec::from).unwrap_or_else(Vec::new))
+            .unwrap();
+        if let Some(key) = keys.iter().find(|k| k.len() != cols.len()) {
+            return Err(PrewarmError::WrongKeyColumnCount(cols.len(), key.len()));
+        }
+
+        match self.materializations.get_status(r, &self.ingredients[r]) {
+            MaterializationStatus::Partial { .. } => {}
+            _ => return Ok(()),
+        }
+
+        // readers are sharded by the first column of their key
+        let domain = self.ingredients[r].domain();
+        let node = self.ingredients[r].local_addr();
+        let shards = self.domains[&domain].shards();
+        let mut shard_keys = vec![Vec::new(); shards];
+        for key in keys {
+            let shard = if shards == 1 {
+                0
+            } else {
+                noria::shard_by(&key[0], shards)
+            };
+            shard_keys[shard].push(key);
+        }
+
+        let workers = &self.workers;
+        let dh = self.domains.get_mut(&domain).unwrap();
+        for (shard, keys) in shard_keys.into_iter().enumerate() {
+            if keys.is_empty() {
+                continue;
+            }
+            dh.send_to_healthy_shard(
+                shard,
+                Box::new(Packet::RequestReaderReplay {
+                    node,
+                    cols: cols.clone(),
+                    keys,
+                }),
+                workers,
+            )
+            .unwrap();
+        }
+        Ok(())
+    }
+
     fn view_schema(&self, view_ni: NodeIndex) -> Option<Vec<ColumnSpecification>> {
         let n = &self.ingredients[view_ni];
         let schema: Vec<_> = (0..n.fields().len())
@@ -162,6 +162,16 @@ impl Materializations {
                     .collect()
             };
 
+            // unique secondary keys on base nodes must be indexed so that they can be looked up
+            if let Some(b) = n.get_base() {
+                for key in b.unique_keys() {
+                    lookup_obligations
+                        .entry(ni)
+                        .or_insert_with(HashSet::new)
+                        .insert(key.clone());
+                }
+            }
+
             if indices.is_empty() && n.is_base() {
                 // we must *always* materialize base nodes
                 // so, just make up some column to index on
@@ -335,6 +345,14 @@ impl Materializations {
                 able = false;
             }
 
+            if graph[ni]
+                .with_reader(|r| r.requires_full_materialization())
+                .unwrap_or(false)
+            {
+                warn!(self.log, "full because reader requires it"; "node" => ni.index());
+                able = false;
+            }
+
             // we are already fully materialized, so can't be made partial
             if !new.contains(&ni)
                 && self.added.get(&ni).map(|i| i.len()).unwrap_or(0)
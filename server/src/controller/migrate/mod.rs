@@ -21,8 +21,10 @@
 //! Beware, Here be dragons™
 
 use crate::controller::ControllerInner;
+use dataflow::ops::topk::{Collation, NullOrder};
 use dataflow::prelude::*;
 use dataflow::{node, prelude::Packet};
+use nom_sql::{Operator, OrderType};
 use std::collections::{HashMap, HashSet};
 use std::time::Instant;
 
@@ -39,6 +41,7 @@ mod sharding;
 pub(super) enum ColumnChange {
     Add(String, DataType),
     Drop(usize),
+    Rename(usize, String),
 }
 
 /// A `Migration` encapsulates a number of changes to the Soup data flow graph.
@@ -225,6 +228,23 @@ impl<'a> Migration<'a> {
         self.columns.push((node, ColumnChange::Drop(column)));
     }
 
+    /// Rename a column of a base node, keeping the data stored in it.
+    pub fn rename_column<S: ToString>(&mut self, node: NodeIndex, column: usize, field: S) {
+        // not allowed to rename columns of new nodes
+        assert!(!self.added.contains(&node));
+
+        let field = field.to_string();
+        let base = &mut self.mainline.ingredients[node];
+        assert!(base.is_base());
+
+        // records are positional, so only the field name needs to change
+        base.rename_column(column, &field);
+
+        // also eventually propagate to domain clone
+        self.columns
+            .push((node, ColumnChange::Rename(column, field)));
+    }
+
     #[cfg(test)]
     pub(crate) fn graph(&self) -> &Graph {
         self.mainline.graph()
@@ -277,6 +297,80 @@ impl<'a> Migration<'a> {
             .unwrap();
     }
 
+    /// Keep all of the state of the reader for the given node, even if it could be partially
+    /// materialized. The node must already be maintained.
+    pub fn maintain_fully(&mut self, n: NodeIndex) {
+        let ri = self.readers[&n];
+
+        self.mainline.ingredients[ri]
+            .with_reader_mut(|r| r.force_full_materialization())
+            .unwrap();
+    }
+
+    /// Set up the given node such that its output can be queried for all rows whose `key` column
+    /// falls within a given range.
+    ///
+    /// Range lookups cannot be served from partial state, so the reader is always fully
+    /// materialized.
+    ///
+    /// Only a single key column can be looked up by range; other keys are rejected.
+    pub fn maintain_range(
+        &mut self,
+        name: String,
+        n: NodeIndex,
+        key: &[usize],
+    ) -> Result<(), String> {
+        self.maintain(name, n, key);
+
+        let ri = self.readers[&n];
+
+        self.mainline.ingredients[ri]
+            .with_reader_mut(|r| r.set_ranged())
+            .unwrap()
+    }
+
+    /// Have lookups of a single value in the reader for the given node return the rows of all keys
+    /// that compare to that value with `op`, as for a query such as `WHERE x > ?`. The node must
+    /// already be maintained with `maintain_range`.
+    pub fn maintain_open_range(&mut self, n: NodeIndex, op: Operator) -> Result<(), String> {
+        let ri = self.readers[&n];
+
+        self.mainline.ingredients[ri]
+            .with_reader_mut(|r| r.set_open_range(op))
+            .unwrap()
+    }
+
+    /// Let reads from the reader for the given node ask for only the first rows of each key,
+    /// ranked in `order` as by a `TopK` with that order. The node must already be maintained.
+    ///
+    /// Such reads cannot tell whether partial state holds all of a key's rows, so the reader is
+    /// always fully materialized.
+    pub fn maintain_ranked(
+        &mut self,
+        n: NodeIndex,
+        order: Vec<(usize, OrderType, NullOrder, Collation)>,
+    ) {
+        let ri = self.readers[&n];
+
+        self.mainline.ingredients[ri]
+            .with_reader_mut(|r| r.set_order(order))
+            .unwrap();
+    }
+
+    /// Have reads from the reader for the given node return each key's rows ranked in `order`,
+    /// as by a `TopK` with that order. The node must already be maintained.
+    pub fn maintain_sorted(
+        &mut self,
+        n: NodeIndex,
+        order: Vec<(usize, OrderType, NullOrder, Collation)>,
+    ) {
+        let ri = self.readers[&n];
+
+        self.mainline.ingredients[ri]
+            .with_reader_mut(|r| r.set_sorted(order))
+            .unwrap();
+    }
+
     /// Commit the changes introduced by this `Migration` to the master `Soup`.
     ///
     /// This will spin up an execution thread for each new thread domain, and hook those new
@@ -533,8 +627,8 @@ impl<'a> Migration<'a> {
                     })
                     .collect()
             } else {
-                // ingress nodes don't need to know about deleted columns, because those are only
-                // relevant when new writes enter the graph.
+                // ingress nodes don't need to know about deleted or renamed columns, because those
+                // are only relevant when new writes enter the graph.
                 Vec::new()
             };
             inform.push(ni);
@@ -551,6 +645,11 @@ impl<'a> Migration<'a> {
                         node: n.local_addr(),
                         column,
                     }),
+                    ColumnChange::Rename(column, field) => Box::new(Packet::RenameBaseColumn {
+                        node: n.local_addr(),
+                        column,
+                        field,
+                    }),
                 };
 
                 let domain = mainline.domains.get_mut(&n.domain()).unwrap();
@@ -1,16 +1,20 @@
 use nom_sql::{
-    ArithmeticBase, ArithmeticExpression, ColumnConstraint, ColumnSpecification, Literal, OrderType,
+    ArithmeticBase, ArithmeticExpression, ColumnConstraint, ColumnSpecification, Literal,
+    OrderType, SqlType,
 };
 use std::collections::HashMap;
 
 use crate::controller::Migration;
 use common::DataType;
 use dataflow::ops::filter::FilterCondition;
+use dataflow::ops::grouped::aggregate::Aggregation;
+use dataflow::ops::grouped::multiaggregate::MultiAggregator;
 use dataflow::ops::join::{Join, JoinType};
 use dataflow::ops::latest::Latest;
-use dataflow::ops::project::{Project, ProjectExpression, ProjectExpressionBase};
+use dataflow::ops::project::{Project, ProjectExpression, ProjectExpressionBase, ProjectFunction};
+use dataflow::ops::topk::{Collation, NullOrder};
 use dataflow::{node, ops};
-use mir::node::{GroupedNodeType, MirNode, MirNodeType};
+use mir::node::{GroupedNodeType, MaterializationMode, MirNode, MirNodeType};
 use mir::query::{MirQuery, QueryFlowParts};
 use mir::{Column, FlowNode, MirNodeRef};
 use petgraph::graph::NodeIndex;
@@ -19,7 +23,7 @@ pub(super) fn mir_query_to_flow_parts(
     mir_query: &mut MirQuery,
     mig: &mut Migration,
     table_mapping: Option<&HashMap<(String, Option<String>), String>>,
-) -> QueryFlowParts {
+) -> Result<QueryFlowParts, String> {
     use std::collections::VecDeque;
 
     let mut new_nodes = Vec::new();
@@ -35,7 +39,7 @@ pub(super) fn mir_query_to_flow_parts(
     while !node_queue.is_empty() {
         let n = node_queue.pop_front().unwrap();
         assert_eq!(in_edge_counts[&n.borrow().versioned_name()], 0);
-        let flow_node = mir_node_to_flow_parts(&mut n.borrow_mut(), mig, table_mapping);
+        let flow_node = mir_node_to_flow_parts(&mut n.borrow_mut(), mig, table_mapping)?;
         match flow_node {
             FlowNode::New(na) => new_nodes.push(na),
             FlowNode::Existing(na) => reused_nodes.push(na),
@@ -63,19 +67,19 @@ pub(super) fn mir_query_to_flow_parts(
         .expect("Leaf must have FlowNode by now")
         .address();
 
-    QueryFlowParts {
+    Ok(QueryFlowParts {
         name: mir_query.name.clone(),
         new_nodes,
         reused_nodes,
         query_leaf: leaf_na,
-    }
+    })
 }
 
 fn mir_node_to_flow_parts(
     mir_node: &mut MirNode,
     mig: &mut Migration,
     table_mapping: Option<&HashMap<(String, Option<String>), String>>,
-) -> FlowNode {
+) -> Result<FlowNode, String> {
     let name = mir_node.name.clone();
     match mir_node.flow_node {
         None => {
@@ -101,12 +105,39 @@ fn mir_node_to_flow_parts(
                         None,
                     )
                 }
+                MirNodeType::MultiAggregation {
+                    ref group_by,
+                    ref aggregates,
+                } => {
+                    assert_eq!(mir_node.ancestors.len(), 1);
+                    let parent = mir_node.ancestors[0].clone();
+                    make_multi_grouped_node(
+                        &name,
+                        parent,
+                        mir_node.columns.as_slice(),
+                        group_by,
+                        aggregates,
+                        mig,
+                        table_mapping,
+                    )
+                }
                 MirNodeType::Base {
                     ref mut column_specs,
                     ref keys,
+                    ref unique_keys,
+                    implicit_rowid,
+                    ref not_null,
                     ref adapted_over,
                 } => match *adapted_over {
-                    None => make_base_node(&name, column_specs.as_mut_slice(), keys, mig),
+                    None => make_base_node(
+                        &name,
+                        column_specs.as_mut_slice(),
+                        keys,
+                        unique_keys,
+                        implicit_rowid,
+                        not_null,
+                        mig,
+                    ),
                     Some(ref bna) => adapt_base_node(
                         bna.over.clone(),
                         mig,
@@ -212,10 +243,33 @@ fn mir_node_to_flow_parts(
                     let parent = mir_node.ancestors[0].clone();
                     make_latest_node(&name, parent, mir_node.columns.as_slice(), group_by, mig)
                 }
-                MirNodeType::Leaf { ref keys, .. } => {
+                MirNodeType::Leaf {
+                    ref keys,
+                    ranged,
+                    ref open_range,
+                    materialization,
+                    ..
+                } => {
                     assert_eq!(mir_node.ancestors.len(), 1);
                     let parent = mir_node.ancestors[0].clone();
-                    materialize_leaf_node(&parent, name, keys, mig);
+                    materialize_leaf_node(&parent, name, keys, ranged, mig)?;
+                    if let Some(ref op) = *open_range {
+                        let na = parent.borrow().flow_node_addr().unwrap();
+                        mig.maintain_open_range(na, op.clone())?;
+                    }
+                    if let Some(order) = read_time_topk_order(&parent) {
+                        let na = parent.borrow().flow_node_addr().unwrap();
+                        mig.maintain_ranked(na, order);
+                    } else if let Some(order) = ordered_distinct_order(&parent) {
+                        let na = parent.borrow().flow_node_addr().unwrap();
+                        mig.maintain_sorted(na, order);
+                    }
+                    if materialization == Some(MaterializationMode::Full)
+                        || materialization == Some(MaterializationMode::Unkeyed)
+                    {
+                        let na = parent.borrow().flow_node_addr().unwrap();
+                        mig.maintain_fully(na);
+                    }
                     // TODO(malte): below is yucky, but required to satisfy the type system:
                     // each match arm must return a `FlowNode`, so we use the parent's one
                     // here.
@@ -249,6 +303,7 @@ fn mir_node_to_flow_parts(
                     ref emit,
                     ref literals,
                     ref arithmetic,
+                    ref functions,
                 } => {
                     assert_eq!(mir_node.ancestors.len(), 1);
                     let parent = mir_node.ancestors[0].clone();
@@ -259,6 +314,7 @@ fn mir_node_to_flow_parts(
                         emit,
                         arithmetic,
                         literals,
+                        functions,
                         mig,
                         table_mapping,
                     )
@@ -287,7 +343,7 @@ fn mir_node_to_flow_parts(
                         table_mapping,
                     )
                 }
-                MirNodeType::Distinct { ref group_by } => {
+                MirNodeType::Distinct { ref group_by, .. } => {
                     assert_eq!(mir_node.ancestors.len(), 1);
                     let parent = mir_node.ancestors[0].clone();
                     make_distinct_node(&name, parent, mir_node.columns.as_slice(), group_by, mig)
@@ -297,6 +353,7 @@ fn mir_node_to_flow_parts(
                     ref group_by,
                     ref k,
                     ref offset,
+                    ..
                 } => {
                     assert_eq!(mir_node.ancestors.len(), 1);
                     let parent = mir_node.ancestors[0].clone();
@@ -339,12 +396,27 @@ fn mir_node_to_flow_parts(
                 FlowNode::New(na) => Some(FlowNode::Existing(na)),
                 ref n @ FlowNode::Existing(..) => Some(n.clone()),
             };
-            flow_node
+            Ok(flow_node)
         }
-        Some(ref flow_node) => flow_node.clone(),
+        Some(ref flow_node) => Ok(flow_node.clone()),
     }
 }
 
+/// Returns the value that a base column takes on for rows that were written without it.
+///
+/// Note that this defaults to a "None" (= NULL) default value for columns that do not have one
+/// specified; we don't currently handle a "NOT NULL" SQL constraint for defaults.
+fn column_default(cs: &ColumnSpecification) -> DataType {
+    cs.constraints
+        .iter()
+        .filter_map(|c| match *c {
+            ColumnConstraint::DefaultValue(ref dv) => Some(dv.into()),
+            _ => None,
+        })
+        .next()
+        .unwrap_or(DataType::None)
+}
+
 fn adapt_base_node(
     over_node: MirNodeRef,
     mig: &mut Migration,
@@ -358,19 +430,8 @@ fn adapt_base_node(
     };
 
     for a in add.iter() {
-        let default_value = match a
-            .constraints
-            .iter()
-            .filter_map(|c| match *c {
-                ColumnConstraint::DefaultValue(ref dv) => Some(dv.into()),
-                _ => None,
-            })
-            .next()
-        {
-            None => DataType::None,
-            Some(dv) => dv,
-        };
-        let column_id = mig.add_column(na, &a.column.name, default_value);
+        // existing rows get the declared default for the new column
+        let column_id = mig.add_column(na, &a.column.name, column_default(a));
 
         // store the new column ID in the column specs for this node
         for &mut (ref cs, ref mut cid) in column_specs.iter_mut() {
@@ -404,6 +465,9 @@ fn make_base_node(
     name: &str,
     column_specs: &mut [(ColumnSpecification, Option<usize>)],
     pkey_columns: &[Column],
+    unique_keys: &[Vec<Column>],
+    implicit_rowid: bool,
+    not_null: &[Column],
     mig: &mut Migration,
 ) -> FlowNode {
     // remember the absolute base column ID for potential later removal
@@ -417,35 +481,51 @@ fn make_base_node(
         .collect();
     let column_names = column_names(columns.as_slice());
 
-    // note that this defaults to a "None" (= NULL) default value for columns that do not have one
-    // specified; we don't currently handle a "NOT NULL" SQL constraint for defaults
     let default_values = column_specs
         .iter()
-        .map(|&(ref cs, _)| {
-            for c in &cs.constraints {
-                if let ColumnConstraint::DefaultValue(ref dv) = *c {
-                    return dv.into();
-                }
-            }
-            DataType::None
-        })
+        .map(|&(ref cs, _)| column_default(cs))
         .collect::<Vec<DataType>>();
 
-    let base = if !pkey_columns.is_empty() {
-        let pkey_column_ids = pkey_columns
+    let column_id = |kc: &Column| {
+        column_specs
             .iter()
-            .map(|pkc| {
-                //assert_eq!(pkc.table.as_ref().unwrap(), name);
-                column_specs
-                    .iter()
-                    .position(|&(ref cs, _)| Column::from(&cs.column) == *pkc)
-                    .unwrap()
-            })
-            .collect();
+            .position(|&(ref cs, _)| Column::from(&cs.column) == *kc)
+            .unwrap()
+    };
+
+    let base = if implicit_rowid {
+        // the synthesized rowid column is the key, and the base assigns its values
+        assert_eq!(pkey_columns.len(), 1);
+        let rowid = column_id(&pkey_columns[0]);
+        node::special::Base::new(default_values)
+            .with_key(vec![rowid])
+            .with_rowid(rowid)
+    } else if !pkey_columns.is_empty() {
+        let pkey_column_ids = pkey_columns.iter().map(&column_id).collect();
         node::special::Base::new(default_values).with_key(pkey_column_ids)
     } else {
         node::special::Base::new(default_values)
     };
+    let base = base.with_unique_keys(
+        unique_keys
+            .iter()
+            .map(|uk| uk.iter().map(&column_id).collect())
+            .collect(),
+    );
+    let base = base.with_not_null(not_null.iter().map(&column_id).collect());
+    // integers written to floating point columns are stored as floats, which is what the
+    // literals that queries compare these columns with are converted to
+    let base = base.with_real_columns(
+        column_specs
+            .iter()
+            .enumerate()
+            .filter(|&(_, &(ref cs, _))| match cs.sql_type {
+                SqlType::Float | SqlType::Double | SqlType::Real | SqlType::Decimal(..) => true,
+                _ => false,
+            })
+            .map(|(i, _)| i)
+            .collect(),
+    );
 
     FlowNode::New(mig.add_base(name, column_names.as_slice(), base))
 }
@@ -569,11 +649,16 @@ fn make_grouped_node(
     assert!(!group_col_indx.is_empty());
 
     let na = match kind {
-        GroupedNodeType::Aggregation(agg) => mig.add_ingredient(
-            String::from(name),
-            column_names.as_slice(),
-            agg.over(parent_na, over_col_indx, group_col_indx.as_slice()),
-        ),
+        GroupedNodeType::Aggregation(agg) => {
+            let op = agg.over(parent_na, over_col_indx, group_col_indx.as_slice());
+            // grouping on constants only (as aggregations without GROUP BY do) yields a single
+            // group, which has a row even if there are no input records
+            let op = match constant_group(&parent, group_by, &group_col_indx) {
+                Some(group) => op.with_default_group(group),
+                None => op,
+            };
+            mig.add_ingredient(String::from(name), column_names.as_slice(), op)
+        }
         GroupedNodeType::Extremum(extr) => mig.add_ingredient(
             String::from(name),
             column_names.as_slice(),
@@ -602,6 +687,80 @@ fn make_grouped_node(
     FlowNode::New(na)
 }
 
+fn make_multi_grouped_node(
+    name: &str,
+    parent: MirNodeRef,
+    columns: &[Column],
+    group_by: &[Column],
+    aggregates: &[(Column, Aggregation)],
+    mig: &mut Migration,
+    table_mapping: Option<&HashMap<(String, Option<String>), String>>,
+) -> FlowNode {
+    assert!(!group_by.is_empty());
+    assert!(
+        group_by.len() <= 6,
+        format!(
+            "can't have >6 group columns due to compound key restrictions, {} needs {}",
+            name,
+            group_by.len()
+        )
+    );
+
+    let parent_na = parent.borrow().flow_node_addr().unwrap();
+    let column_names = column_names(columns);
+
+    let aggregates = aggregates
+        .iter()
+        .map(|&(ref on, ref kind)| {
+            (
+                kind.clone(),
+                parent.borrow().column_id_for_column(on, table_mapping),
+            )
+        })
+        .collect();
+    let group_col_indx = group_by
+        .iter()
+        .map(|c| parent.borrow().column_id_for_column(c, table_mapping))
+        .collect::<Vec<_>>();
+
+    let op = MultiAggregator::new(parent_na, aggregates, group_col_indx.as_slice());
+    // as for a single aggregation, grouping on constants only yields a group that always has a row
+    let op = match constant_group(&parent, group_by, &group_col_indx) {
+        Some(group) => op.with_default_group(group),
+        None => op,
+    };
+    let na = mig.add_ingredient(String::from(name), column_names.as_slice(), op);
+    FlowNode::New(na)
+}
+
+/// Returns the values of the `group_by` columns (at `group_col_indx` in `parent`) if `parent`
+/// projects all of them as literals, ordered by their column index.
+fn constant_group(
+    parent: &MirNodeRef,
+    group_by: &[Column],
+    group_col_indx: &[usize],
+) -> Option<Vec<DataType>> {
+    let parent = parent.borrow();
+    let literals = match parent.inner {
+        MirNodeType::Project { ref literals, .. } => literals,
+        MirNodeType::Reuse { ref node } => return constant_group(node, group_by, group_col_indx),
+        _ => return None,
+    };
+
+    let mut group = group_by
+        .iter()
+        .zip(group_col_indx)
+        .map(|(c, &i)| {
+            literals
+                .iter()
+                .find(|&&(ref n, _)| c.table.is_none() && *n == c.name)
+                .map(|&(_, ref v)| (i, v.clone()))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    group.sort_by_key(|&(i, _)| i);
+    Some(group.into_iter().map(|(_, v)| v).collect())
+}
+
 fn make_identity_node(
     name: &str,
     parent: MirNodeRef,
@@ -788,6 +947,7 @@ fn make_project_node(
     emit: &[Column],
     arithmetic: &[(String, ArithmeticExpression)],
     literals: &[(String, DataType)],
+    functions: &[(String, ProjectFunction)],
     mig: &mut Migration,
     table_mapping: Option<&HashMap<(String, Option<String>), String>>,
 ) -> FlowNode {
@@ -820,7 +980,8 @@ fn make_project_node(
             projected_column_ids.as_slice(),
             Some(literal_values),
             Some(projected_arithmetic),
-        ),
+        )
+        .with_functions(functions.iter().map(|&(_, ref f)| f.clone()).collect()),
     );
     FlowNode::New(n)
 }
@@ -861,7 +1022,7 @@ fn make_topk_node(
     name: &str,
     parent: MirNodeRef,
     columns: &[Column],
-    order: &Option<Vec<(Column, OrderType)>>,
+    order: &Option<Vec<(Column, OrderType, NullOrder, Collation)>>,
     group_by: &[Column],
     k: usize,
     offset: usize,
@@ -883,24 +1044,7 @@ fn make_topk_node(
     let cmp_rows = match *order {
         Some(ref o) => {
             assert_eq!(offset, 0); // Non-zero offset not supported
-
-            let columns: Vec<_> = o
-                .iter()
-                .map(|&(ref c, ref order_type)| {
-                    // SQL and Soup disagree on what ascending and descending order means, so do the
-                    // conversion here.
-                    let reversed_order_type = match *order_type {
-                        OrderType::OrderAscending => OrderType::OrderDescending,
-                        OrderType::OrderDescending => OrderType::OrderAscending,
-                    };
-                    (
-                        parent.borrow().column_id_for_column(c, None),
-                        reversed_order_type,
-                    )
-                })
-                .collect();
-
-            columns
+            flow_order(&parent, o)
         }
         None => Vec::new(),
     };
@@ -914,12 +1058,92 @@ fn make_topk_node(
     FlowNode::New(na)
 }
 
+/// Converts a SQL order over columns of `parent` into the order a `TopK` below `parent` takes.
+fn flow_order(
+    parent: &MirNodeRef,
+    order: &[(Column, OrderType, NullOrder, Collation)],
+) -> Vec<(usize, OrderType, NullOrder, Collation)> {
+    order
+        .iter()
+        .map(|&(ref c, ref order_type, null_order, collation)| {
+            // SQL and Soup disagree on what ascending and descending order means, so do the
+            // conversion here.
+            let reversed_order_type = match *order_type {
+                OrderType::OrderAscending => OrderType::OrderDescending,
+                OrderType::OrderDescending => OrderType::OrderAscending,
+            };
+            // NULL placement refers to the output order, so it needs no conversion, and neither
+            // does the collation.
+            (
+                parent.borrow().column_id_for_column(c, None),
+                reversed_order_type,
+                null_order,
+                collation,
+            )
+        })
+        .collect()
+}
+
+/// If the leaf below `parent` reads from a `TopK` whose `k` readers pick, returns the order in
+/// which the reader must rank each key's rows, over the columns of `parent`. Only projections
+/// and identities may lie between the `TopK` and the leaf.
+fn read_time_topk_order(
+    parent: &MirNodeRef,
+) -> Option<Vec<(usize, OrderType, NullOrder, Collation)>> {
+    let mut n = parent.clone();
+    loop {
+        let next = match n.borrow().inner {
+            MirNodeType::TopK {
+                order: Some(ref order),
+                parameterized_k: true,
+                ..
+            } => return Some(flow_order(parent, order)),
+            MirNodeType::Reuse { ref node } => node.clone(),
+            MirNodeType::Project { .. } | MirNodeType::Identity => n.borrow().ancestors[0].clone(),
+            _ => return None,
+        };
+        n = next;
+    }
+}
+
+/// If the leaf below `parent` reads from a `Distinct` whose readers return rows ordered by its
+/// columns, returns that order over the columns of `parent`. Only projections and identities may
+/// lie between the `Distinct` and the leaf.
+fn ordered_distinct_order(
+    parent: &MirNodeRef,
+) -> Option<Vec<(usize, OrderType, NullOrder, Collation)>> {
+    let mut n = parent.clone();
+    loop {
+        let next = match n.borrow().inner {
+            MirNodeType::Distinct {
+                ref group_by,
+                ordered: true,
+            } => {
+                let order: Vec<_> = group_by
+                    .iter()
+                    .map(|c| {
+                        let ot = OrderType::OrderAscending;
+                        let no = NullOrder::default_for(&ot);
+                        (c.clone(), ot, no, Collation::Binary)
+                    })
+                    .collect();
+                return Some(flow_order(parent, &order));
+            }
+            MirNodeType::Reuse { ref node } => node.clone(),
+            MirNodeType::Project { .. } | MirNodeType::Identity => n.borrow().ancestors[0].clone(),
+            _ => return None,
+        };
+        n = next;
+    }
+}
+
 fn materialize_leaf_node(
     parent: &MirNodeRef,
     name: String,
     key_cols: &[Column],
+    ranged: bool,
     mig: &mut Migration,
-) {
+) -> Result<(), String> {
     let na = parent.borrow().flow_node_addr().unwrap();
 
     // we must add a new reader for this query. This also requires adding an identity node (at
@@ -934,9 +1158,14 @@ fn materialize_leaf_node(
             .iter()
             .map(|c| parent.borrow().column_id_for_column(c, None))
             .collect();
-        mig.maintain(name, na, &key_cols[..]);
+        if ranged {
+            mig.maintain_range(name, na, &key_cols[..])?;
+        } else {
+            mig.maintain(name, na, &key_cols[..]);
+        }
     } else {
         // if no key specified, default to the first column
         mig.maintain(name, na, &[0]);
     }
+    Ok(())
 }
@@ -5,6 +5,7 @@ use crate::coordination::CoordinationMessage;
 use crate::coordination::CoordinationPayload;
 use crate::startup::Event;
 use crate::Config;
+use crate::PostLoweringHook;
 use async_bincode::AsyncBincodeReader;
 use dataflow::payload::ControlReplyPacket;
 use futures_util::{
@@ -71,6 +72,7 @@ pub(super) async fn main<A: Authority + 'static>(
     log: slog::Logger,
     authority: Arc<A>,
     tx: tokio::sync::mpsc::UnboundedSender<Event>,
+    post_lowering_hook: Option<PostLoweringHook>,
 ) {
     let (dtx, drx) = tokio::sync::mpsc::unbounded_channel();
 
@@ -159,7 +161,12 @@ pub(super) async fn main<A: Authority + 'static>(
                 let c = campaign.take().unwrap();
                 tokio::task::block_in_place(move || c.join().unwrap());
                 let drx = drx.take().unwrap();
-                controller = Some(ControllerInner::new(log.clone(), state, drx));
+                controller = Some(ControllerInner::new(
+                    log.clone(),
+                    state,
+                    drx,
+                    post_lowering_hook.clone(),
+                ));
             }
             Event::CampaignError(e) => {
                 panic!("{:?}", e);
@@ -1,18 +1,19 @@
 use crate::controller::security::SecurityConfig;
-use crate::controller::sql::SqlIncorporator;
+use crate::controller::sql::{parse_inline_views, SqlIncorporator};
 use crate::controller::Migration;
-use crate::ReuseConfigType;
+use crate::{PostLoweringHook, ReuseConfigType};
 use dataflow::ops::trigger::Trigger;
 use dataflow::ops::trigger::TriggerEvent;
 use dataflow::prelude::DataType;
+use mir::node::MaterializationMode;
 use nom_sql::parser as sql_parser;
-use nom_sql::SqlQuery;
-use noria::ActivationResult;
+use nom_sql::{LimitClause, Literal, OrderClause, SqlQuery};
+use noria::{ActivationResult, SerializedConverterState};
 use petgraph::graph::NodeIndex;
 
 use nom_sql::CreateTableStatement;
 use slog;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str;
 use std::vec::Vec;
 
@@ -28,6 +29,8 @@ pub(crate) struct Recipe {
     expression_order: Vec<QueryID>,
     /// Named read/write expression aliases, mapping to queries in `expressions`.
     aliases: HashMap<String, QueryID>,
+    /// Options that named queries ask for, such as how their views are materialized.
+    options: HashMap<String, Vec<QueryOption>>,
     /// Security configuration
     security_config: Option<SecurityConfig>,
 
@@ -50,6 +53,7 @@ impl PartialEq for Recipe {
         self.expressions == other.expressions
             && self.expression_order == other.expression_order
             && self.aliases == other.aliases
+            && self.options == other.options
             && self.version == other.version
             && self.prior == other.prior
     }
@@ -78,25 +82,97 @@ fn ident(input: &str) -> nom::IResult<&str, &str> {
     })
 }
 
-fn query_prefix(input: &str) -> nom::IResult<&str, (bool, Option<&str>)> {
+/// An option that a named query can ask for in a recipe, as in `QUERY FULL TOP name: ...`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum QueryOption {
+    /// Materialize the query's view as given rather than as the planner chooses (`FULL` or
+    /// `UNKEYED`)
+    Materialize(MaterializationMode),
+    /// Let readers ask for any number of the query's first rows per key, up to its LIMIT (`TOP`)
+    ReadTimeLimit,
+    /// Have readers of the `SELECT DISTINCT` query return each key's rows ordered by the selected
+    /// columns (`ORDERED`)
+    OrderedDistinct,
+    /// Let the branches of the `UNION` query select fewer columns than its widest branch, padding
+    /// them with `NULL`s (`PADDED`)
+    PaddedUnion,
+    /// Roll the query's `GROUP BY` columns up into subtotal and grand total rows, as in
+    /// `GROUP BY ROLLUP(...)` (`ROLLUP`)
+    Rollup,
+    /// Have the grouped query select an additional `group_size` column with the number of rows in
+    /// each group (`GROUP_SIZE`)
+    GroupSize,
+    /// Add subtotal rows for every subset of the query's `GROUP BY` columns, as in
+    /// `GROUP BY CUBE(...)` (`CUBE`)
+    Cube,
+}
+
+impl QueryOption {
+    /// Tells `inc` that the query `name`, once added, asks for this option.
+    fn apply(self, inc: &mut SqlIncorporator, name: &str) {
+        match self {
+            QueryOption::Materialize(mode) => inc.set_materialization(name, mode),
+            QueryOption::ReadTimeLimit => inc.set_read_time_limit(name),
+            QueryOption::OrderedDistinct => inc.set_ordered_distinct(name),
+            QueryOption::PaddedUnion => inc.set_padded_union(name),
+            QueryOption::Rollup => inc.set_rollup(name),
+            QueryOption::GroupSize => inc.set_group_size(name),
+            QueryOption::Cube => inc.set_cube(name),
+        }
+    }
+}
+
+fn query_option(input: &str) -> nom::IResult<&str, QueryOption> {
     use nom::branch::alt;
     use nom::bytes::complete::tag_no_case;
+    use nom::combinator::value;
+    alt((
+        value(
+            QueryOption::Materialize(MaterializationMode::Full),
+            tag_no_case("full"),
+        ),
+        value(
+            QueryOption::Materialize(MaterializationMode::Unkeyed),
+            tag_no_case("unkeyed"),
+        ),
+        value(QueryOption::ReadTimeLimit, tag_no_case("top")),
+        value(QueryOption::OrderedDistinct, tag_no_case("ordered")),
+        value(QueryOption::PaddedUnion, tag_no_case("padded")),
+        value(QueryOption::Rollup, tag_no_case("rollup")),
+        value(QueryOption::GroupSize, tag_no_case("group_size")),
+        value(QueryOption::Cube, tag_no_case("cube")),
+    ))(input)
+}
+
+fn query_prefix(input: &str) -> nom::IResult<&str, (bool, Vec<QueryOption>, Option<&str>)> {
+    use nom::branch::alt;
+    use nom::bytes::complete::{tag_no_case, take_while1};
     use nom::character::complete::{char, multispace0, space1};
-    use nom::combinator::opt;
+    use nom::combinator::{opt, peek};
+    use nom::multi::many0;
     use nom::sequence::{pair, terminated};
     let (input, public) = opt(pair(
         alt((tag_no_case("query"), tag_no_case("view"))),
         space1,
     ))(input)?;
+    // a named view may ask for options before its name, as in `QUERY FULL name: ...`
+    let (input, options) = if public.is_some() {
+        let name_start = take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_');
+        many0(terminated(query_option, pair(space1, peek(name_start))))(input)?
+    } else {
+        (input, Vec::new())
+    };
     let (input, _) = multispace0(input)?;
     let (input, name) = opt(terminated(ident, multispace0))(input)?;
     let (input, _) = multispace0(input)?;
     let (input, _) = char(':')(input)?;
     let (input, _) = multispace0(input)?;
-    Ok((input, (public.is_some(), name)))
+    Ok((input, (public.is_some(), options, name)))
 }
 
-fn query_expr(input: &str) -> nom::IResult<&str, (bool, Option<&str>, SqlQuery)> {
+type QueryExpr<'a> = (bool, Vec<QueryOption>, Option<&'a str>, SqlQuery);
+
+fn query_expr(input: &str) -> nom::IResult<&str, QueryExpr> {
     use nom::character::complete::multispace0;
     use nom::combinator::opt;
     let (input, prefix) = opt(query_prefix)(input)?;
@@ -113,16 +189,37 @@ fn query_expr(input: &str) -> nom::IResult<&str, (bool, Option<&str>, SqlQuery)>
     Ok((
         input,
         match prefix {
-            None => (false, None, expr),
-            Some((public, name)) => (public, name, expr),
+            None => (false, Vec::new(), None, expr),
+            Some((public, options, name)) => (public, options, name, expr),
         },
     ))
 }
 
-fn query_exprs(input: &str) -> nom::IResult<&str, Vec<(bool, Option<&str>, SqlQuery)>> {
+fn query_exprs(input: &str) -> nom::IResult<&str, Vec<QueryExpr>> {
     nom::multi::many1(query_expr)(input)
 }
 
+type ParsedExpr = (bool, Vec<QueryOption>, Option<String>, SqlQuery);
+
+/// Parses a query that defines views inline into those views, which are not public, followed by
+/// the query itself, or returns `None` if the query has no inline views.
+fn inline_views_expr(input: &str) -> Result<Option<Vec<ParsedExpr>>, String> {
+    let (input, (public, options, name)) =
+        query_prefix(input).unwrap_or((input, (false, Vec::new(), None)));
+    Ok(parse_inline_views(name, input)?.map(|(views, q)| {
+        views
+            .into_iter()
+            .map(|(view, v)| (false, Vec::new(), Some(view), v))
+            .chain(std::iter::once((
+                public,
+                options,
+                name.map(String::from),
+                q,
+            )))
+            .collect()
+    }))
+}
+
 #[allow(unused)]
 impl Recipe {
     /// Return security groups in the recipe
@@ -146,6 +243,7 @@ impl Recipe {
             expressions: HashMap::default(),
             expression_order: Vec::default(),
             aliases: HashMap::default(),
+            options: HashMap::default(),
             version: 0,
             prior: None,
             inc: match log {
@@ -185,6 +283,57 @@ impl Recipe {
         self.inc.as_mut().unwrap().enable_reuse(reuse_type)
     }
 
+    /// Give tables without a primary key an implicit rowid key column
+    pub(super) fn enable_implicit_rowid(&mut self) {
+        self.inc.as_mut().unwrap().enable_implicit_rowid()
+    }
+
+    /// Reject queries that select columns they neither group by nor aggregate
+    pub(super) fn enable_strict_group_by(&mut self) {
+        self.inc.as_mut().unwrap().enable_strict_group_by()
+    }
+
+    /// Prepend `prefix` to the names of the nodes generated for queries added from now on
+    pub(super) fn set_name_prefix(&mut self, prefix: &str) {
+        self.inc.as_mut().unwrap().set_name_prefix(prefix)
+    }
+
+    /// Join smaller relations first in queries added from now on, going by the estimated row
+    /// counts in `hints`
+    pub(super) fn set_cardinality_hints(&mut self, hints: HashMap<String, usize>) {
+        self.inc.as_mut().unwrap().set_cardinality_hints(hints)
+    }
+
+    /// Key the view of the query `name`, once added, on the SQL function `function` of its
+    /// parameter column `param`, computed into a column called `column_name`
+    pub(super) fn set_computed_parameter(
+        &mut self,
+        name: &str,
+        param: &str,
+        column_name: &str,
+        function: &str,
+    ) -> Result<(), String> {
+        let name = self.resolve_alias(name).unwrap_or(name).to_owned();
+        self.inc
+            .as_mut()
+            .unwrap()
+            .set_computed_parameter(&name, param, column_name, function)
+    }
+
+    /// Bind the parameter `column` of the query `name`, once added, to `value`
+    pub(super) fn bind_parameter(&mut self, name: &str, column: &str, value: Literal) {
+        let name = self.resolve_alias(name).unwrap_or(name).to_owned();
+        self.inc
+            .as_mut()
+            .unwrap()
+            .bind_parameter(&name, column, value)
+    }
+
+    /// Run `hook` over the MIR nodes generated for each query added from now on
+    pub(super) fn set_post_lowering_hook(&mut self, hook: PostLoweringHook) {
+        self.inc.as_mut().unwrap().set_post_lowering_hook(hook)
+    }
+
     pub(in crate::controller) fn resolve_alias(&self, alias: &str) -> Option<&str> {
         self.aliases.get(alias).map(|ref qid| {
             let (ref internal_qn, _, _) = self.expressions[qid];
@@ -228,6 +377,140 @@ impl Recipe {
         }
     }
 
+    /// Get the names of the base tables that a view in the recipe reads from.
+    pub(super) fn base_dependencies(&self, name: &str) -> HashSet<String> {
+        let inc = self.inc.as_ref().expect("Recipe not applied");
+        match self.resolve_alias(name) {
+            None => inc.base_dependencies(name),
+            Some(internal_qn) => inc.base_dependencies(internal_qn),
+        }
+    }
+
+    /// Get a textual plan of a view in the recipe.
+    pub(super) fn explain(&self, name: &str) -> Option<String> {
+        let inc = self.inc.as_ref().expect("Recipe not applied");
+        match self.resolve_alias(name) {
+            None => inc.explain(name),
+            Some(internal_qn) => inc.explain(internal_qn),
+        }
+    }
+
+    /// Get the names of the columns that a view in the recipe exposes.
+    pub(super) fn view_columns(&self, name: &str) -> Option<Vec<String>> {
+        let inc = self.inc.as_ref().expect("Recipe not applied");
+        match self.resolve_alias(name) {
+            None => inc.view_columns(name),
+            Some(internal_qn) => inc.view_columns(internal_qn),
+        }
+    }
+
+    /// Get the names of the nodes of a view in the recipe whose operator is `operator`.
+    pub(super) fn find_nodes(&self, name: &str, operator: &str) -> Vec<String> {
+        let inc = self.inc.as_ref().expect("Recipe not applied");
+        match self.resolve_alias(name) {
+            None => inc.find_nodes(name, operator),
+            Some(internal_qn) => inc.find_nodes(internal_qn, operator),
+        }
+    }
+
+    /// Estimate how many nodes adding the `SELECT` query `query` would create.
+    pub(super) fn estimate_node_count(&self, query: &str) -> Result<usize, String> {
+        let inc = self.inc.as_ref().expect("Recipe not applied");
+        inc.estimate_node_count(query)
+    }
+
+    /// Get the schema evolution state of the recipe's SQL-to-MIR converter.
+    pub(super) fn export_converter_state(&self) -> SerializedConverterState {
+        let inc = self.inc.as_ref().expect("Recipe not applied");
+        inc.export_state()
+    }
+
+    /// Replace the schema evolution state of the recipe's SQL-to-MIR converter.
+    pub(super) fn import_converter_state(&mut self, state: SerializedConverterState) {
+        self.inc.as_mut().unwrap().import_state(state)
+    }
+
+    /// Key the reader of a view in the recipe on `key_columns` instead, keeping the view's
+    /// other nodes.
+    pub(super) fn rekey_view(
+        &mut self,
+        name: &str,
+        key_columns: &[String],
+        mig: &mut Migration,
+    ) -> Result<(), String> {
+        let name = self.resolve_alias(name).unwrap_or(name).to_owned();
+        let key_columns: Vec<_> = key_columns
+            .iter()
+            .map(|c| mir::Column::new(None, c))
+            .collect();
+        let inc = self.inc.as_mut().expect("Recipe not applied");
+        inc.rekey_leaf(&name, &key_columns, mig)?;
+        Ok(())
+    }
+
+    /// Replace the `ORDER BY` and `LIMIT` of a view in the recipe with `order` and `limit`
+    pub(super) fn update_topk(
+        &mut self,
+        name: &str,
+        order: Option<OrderClause>,
+        limit: LimitClause,
+        mig: &mut Migration,
+    ) -> Result<(), String> {
+        let name = self.resolve_alias(name).unwrap_or(name).to_owned();
+        let inc = self.inc.as_mut().expect("Recipe not applied");
+        inc.update_topk(&name, order, limit, mig)?;
+        Ok(())
+    }
+
+    /// Add a view `name` that reads a view (or base) in the recipe sorted by `order` and cut down
+    /// to `page`
+    pub(super) fn add_sorted_view(
+        &mut self,
+        base_view: &str,
+        name: &str,
+        order: OrderClause,
+        page: LimitClause,
+        mig: &mut Migration,
+    ) -> Result<(), String> {
+        let base_view = self
+            .resolve_alias(base_view)
+            .unwrap_or(base_view)
+            .to_owned();
+        let inc = self.inc.as_mut().expect("Recipe not applied");
+        inc.add_sorted_leaf(&base_view, name, order, page, mig)?;
+        Ok(())
+    }
+
+    /// Add a view over the base table `base_name` that is keyed on `key_columns`, and return the
+    /// name of the new view.
+    pub(super) fn add_secondary_index(
+        &mut self,
+        base_name: &str,
+        key_columns: &[String],
+        mig: &mut Migration,
+    ) -> Result<String, String> {
+        let key_columns: Vec<_> = key_columns
+            .iter()
+            .map(|c| mir::Column::new(None, c))
+            .collect();
+        let inc = self.inc.as_mut().expect("Recipe not applied");
+        Ok(inc.add_secondary_index(base_name, &key_columns, mig)?.name)
+    }
+
+    /// Add a named query of the recipe to the universe that `mig` operates in, and return the
+    /// name of the new view.
+    pub(super) fn reinstantiate_for_universe(
+        &mut self,
+        name: &str,
+        mig: &mut Migration,
+    ) -> Result<Option<String>, String> {
+        let name = self.resolve_alias(name).unwrap_or(name).to_owned();
+        let inc = self.inc.as_mut().expect("Recipe not applied");
+        Ok(inc
+            .reinstantiate_for_universe(&name, mig)?
+            .map(|qfp| qfp.name))
+    }
+
     /// Set recipe's security configuration
     pub(in crate::controller) fn set_security_config(&mut self, config_text: &str) {
         let mut config = SecurityConfig::parse(config_text);
@@ -249,9 +532,11 @@ impl Recipe {
         let cleaned_recipe_text = lines.join("\n");
 
         // parse and compute differences to current recipe
-        let parsed_queries = Recipe::parse(&cleaned_recipe_text)?;
+        let (parsed_queries, options) = Recipe::parse(&cleaned_recipe_text)?;
 
-        Ok(Recipe::from_queries(parsed_queries, log))
+        let mut recipe = Recipe::from_queries(parsed_queries, log);
+        recipe.options = options;
+        Ok(recipe)
     }
 
     /// Creates a recipe from a set of pre-parsed `SqlQuery` structures.
@@ -304,6 +589,7 @@ impl Recipe {
             expressions,
             expression_order,
             aliases,
+            options: HashMap::default(),
             security_config: None,
             version: 0,
             prior: None,
@@ -361,6 +647,12 @@ impl Recipe {
 
             let is_leaf = if group.is_some() { false } else { is_leaf };
 
+            if let (Some(ref name), Some(ref new_name)) = (&n, &new_name) {
+                for option in self.options.get(name).into_iter().flatten() {
+                    option.apply(self.inc.as_mut().unwrap(), new_name);
+                }
+            }
+
             let qfp = self
                 .inc
                 .as_mut()
@@ -454,6 +746,12 @@ impl Recipe {
         for qid in added {
             let (n, q, is_leaf) = self.expressions[&qid].clone();
 
+            if let Some(ref name) = n {
+                for option in self.options.get(name).into_iter().flatten() {
+                    option.apply(self.inc.as_mut().unwrap(), name);
+                }
+            }
+
             // add the query
             let qfp = self
                 .inc
@@ -557,6 +855,7 @@ impl Recipe {
             expressions: self.expressions.clone(),
             expression_order: self.expression_order.clone(),
             aliases: self.aliases.clone(),
+            options: self.options.clone(),
             version: self.version + 1,
             inc: prior_inc,
             log: self.log.clone(),
@@ -579,7 +878,12 @@ impl Recipe {
                 n
             );
         }
+        // a query that is named again takes the options it is given now, which may be none
+        for n in add_rp.aliases.keys() {
+            new.options.remove(n);
+        }
         new.aliases.extend(add_rp.aliases);
+        new.options.extend(add_rp.options);
 
         // return new recipe as replacement for self
         Ok(new)
@@ -601,7 +905,17 @@ impl Recipe {
         self.inc = Some(new_inc);
     }
 
-    fn parse(recipe_text: &str) -> Result<Vec<(Option<String>, SqlQuery, bool)>, String> {
+    /// Parses the queries in `recipe_text`, along with the options that named queries ask for.
+    #[allow(clippy::type_complexity)]
+    fn parse(
+        recipe_text: &str,
+    ) -> Result<
+        (
+            Vec<(Option<String>, SqlQuery, bool)>,
+            HashMap<String, Vec<QueryOption>>,
+        ),
+        String,
+    > {
         let lines: Vec<&str> = recipe_text
             .lines()
             .filter(|l| !l.is_empty() && !l.starts_with('#'))
@@ -634,7 +948,19 @@ impl Recipe {
 
         let parsed_queries = query_strings.iter().fold(
             Vec::new(),
-            |mut acc: Vec<Result<(bool, Option<&str>, SqlQuery), String>>, q| {
+            |mut acc: Vec<Result<(bool, Vec<QueryOption>, Option<String>, SqlQuery), String>>,
+             q| {
+                match inline_views_expr(q) {
+                    Result::Err(e) => {
+                        acc.push(Err(format!("Query \"{}\", parse error: {}", q, e)));
+                        return acc;
+                    }
+                    Result::Ok(Some(parsed)) => {
+                        acc.extend(parsed.into_iter().map(Ok));
+                        return acc;
+                    }
+                    Result::Ok(None) => (),
+                }
                 match query_exprs(q) {
                     Result::Err(e) => {
                         // we got a parse error
@@ -649,20 +975,27 @@ impl Recipe {
                                 remainder
                             )
                         );
-                        acc.extend(parsed.into_iter().map(|p| Ok(p)).collect::<Vec<_>>());
+                        acc.extend(parsed.into_iter().map(|(public, options, name, q)| {
+                            Ok((public, options, name.map(String::from), q))
+                        }));
                     }
                 }
                 acc
             },
         );
 
-        Ok(parsed_queries
-            .into_iter()
-            .map(|pr| {
-                let pr = pr.unwrap();
-                (pr.1.map(String::from), pr.2, pr.0)
-            })
-            .collect::<Vec<_>>())
+        let mut queries = Vec::new();
+        let mut options = HashMap::new();
+        for pr in parsed_queries {
+            let (public, opts, name, q) = pr.unwrap();
+            if let Some(ref name) = name {
+                if !opts.is_empty() {
+                    options.insert(name.clone(), opts);
+                }
+            }
+            queries.push((name, q, public));
+        }
+        Ok((queries, options))
     }
 
     /// Returns the predecessor from which this `Recipe` was migrated to.
@@ -887,6 +1220,112 @@ mod tests {
         assert_eq!(r1.expressions.len(), 2);
     }
 
+    #[test]
+    fn it_parses_query_options() {
+        let r0 = Recipe::blank(None);
+
+        let r1_txt = "QUERY FULL q_0: SELECT a FROM b WHERE c = ?;\
+                      VIEW unkeyed q_1: SELECT x FROM y;\
+                      QUERY full TOP q_2: SELECT a FROM b WHERE c = ? ORDER BY a LIMIT 3;\
+                      QUERY full: SELECT a FROM b;";
+        let r1_t = Recipe::from_str(r1_txt, None).unwrap();
+        let r1 = r0.replace(r1_t).unwrap();
+        assert_eq!(r1.expressions.len(), 4);
+        assert_eq!(
+            r1.options["q_0"],
+            vec![QueryOption::Materialize(MaterializationMode::Full)]
+        );
+        assert_eq!(
+            r1.options["q_1"],
+            vec![QueryOption::Materialize(MaterializationMode::Unkeyed)]
+        );
+        assert_eq!(
+            r1.options["q_2"],
+            vec![
+                QueryOption::Materialize(MaterializationMode::Full),
+                QueryOption::ReadTimeLimit
+            ]
+        );
+        // an option keyword on its own is the query's name
+        assert!(r1.resolve_alias("full").is_some());
+        assert!(!r1.options.contains_key("full"));
+
+        for (text, option) in &[
+            (
+                "QUERY ORDERED q: SELECT DISTINCT a, c FROM b WHERE c = ?;",
+                QueryOption::OrderedDistinct,
+            ),
+            (
+                "QUERY PADDED q: SELECT a, c FROM b UNION SELECT a FROM b;",
+                QueryOption::PaddedUnion,
+            ),
+            (
+                "QUERY ROLLUP q: SELECT a, c, COUNT(*) AS n FROM b GROUP BY a, c;",
+                QueryOption::Rollup,
+            ),
+            (
+                "QUERY GROUP_SIZE q: SELECT a, SUM(c) AS s FROM b GROUP BY a;",
+                QueryOption::GroupSize,
+            ),
+            (
+                "QUERY CUBE q: SELECT a, c, COUNT(*) AS n FROM b GROUP BY a, c;",
+                QueryOption::Cube,
+            ),
+        ] {
+            let r = Recipe::from_str(text, None).unwrap();
+            assert_eq!(r.options["q"], vec![*option]);
+        }
+    }
+
+    #[test]
+    fn it_replaces_options_of_redefined_queries() {
+        let r0 = Recipe::blank(None);
+
+        let r1_txt = "QUERY FULL q: SELECT a FROM b WHERE c = ?;\
+                      QUERY FULL p: SELECT x FROM y;";
+        let r1_t = Recipe::from_str(r1_txt, None).unwrap();
+        let r1 = r0.replace(r1_t).unwrap();
+        assert_eq!(
+            r1.options["q"],
+            vec![QueryOption::Materialize(MaterializationMode::Full)]
+        );
+
+        // naming the query again without options drops the ones it had
+        let r2 = r1.extend("QUERY q: SELECT a FROM b WHERE c = ?;").unwrap();
+        assert!(!r2.options.contains_key("q"));
+        assert_eq!(
+            r2.options["p"],
+            vec![QueryOption::Materialize(MaterializationMode::Full)]
+        );
+
+        let r3 = r2
+            .extend("VIEW unkeyed q: SELECT a FROM b WHERE c = ?;")
+            .unwrap();
+        assert_eq!(
+            r3.options["q"],
+            vec![QueryOption::Materialize(MaterializationMode::Unkeyed)]
+        );
+    }
+
+    #[test]
+    fn it_scopes_inline_views_to_their_query() {
+        let r0 = Recipe::blank(None);
+
+        let r1_txt = "QUERY names: WITH recent AS (SELECT id, name FROM users WHERE id > 10) \
+                      SELECT recent.name FROM recent WHERE recent.id = ?;\n\
+                      QUERY sizes: SELECT COUNT(per_dept.cnt) AS depts \
+                      FROM (SELECT dept, COUNT(*) AS cnt FROM emp GROUP BY dept) AS per_dept;";
+        let r1_t = Recipe::from_str(r1_txt, None).unwrap();
+        let r1 = r0.replace(r1_t).unwrap();
+        assert_eq!(r1.expressions.len(), 4);
+        assert!(r1.resolve_alias("recent").is_none());
+        assert!(r1.resolve_alias("per_dept").is_none());
+        for view in &["names_recent", "sizes_per_dept"] {
+            let (_, _, is_leaf) = r1.expressions[&r1.aliases[*view]];
+            assert!(!is_leaf);
+        }
+    }
+
     #[test]
     fn it_handles_missing_semicolon() {
         let r0 = Recipe::blank(None);
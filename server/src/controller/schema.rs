@@ -1,6 +1,9 @@
 use super::keys::provenance_of;
 use super::recipe::{Recipe, Schema};
 use dataflow::ops;
+use dataflow::ops::grouped::aggregate::Aggregation;
+use dataflow::ops::grouped::extremum::Extremum;
+use dataflow::ops::grouped::filteraggregate::FilterAggregation;
 use dataflow::prelude::*;
 use nom_sql::{Column, ColumnSpecification, SqlType};
 
@@ -27,6 +30,44 @@ fn to_sql_type(d: &DataType) -> Option<SqlType> {
     }
 }
 
+/// An SQL aggregate function, as far as the type of the values it computes is concerned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AggregateKind {
+    Count,
+    Sum,
+    // currently unused, as AVG is not lowered to dataflow yet
+    #[allow(dead_code)]
+    Avg,
+    Min,
+    Max,
+    GroupConcat,
+}
+
+impl AggregateKind {
+    /// The type of the values this aggregate computes over values of type `over`, if known.
+    fn output_type(self, over: Option<SqlType>) -> Option<SqlType> {
+        let fractional = match over {
+            Some(SqlType::Real)
+            | Some(SqlType::Float)
+            | Some(SqlType::Double)
+            | Some(SqlType::Decimal(..)) => true,
+            _ => false,
+        };
+        match self {
+            // counts are integral, whatever they count
+            AggregateKind::Count => Some(SqlType::Bigint(64)),
+            // sums keep fractional inputs fractional, and widen integral ones
+            AggregateKind::Sum if fractional => over,
+            AggregateKind::Sum => Some(SqlType::Bigint(64)),
+            // averages of integers are fractional
+            AggregateKind::Avg if fractional => over,
+            AggregateKind::Avg => Some(SqlType::Real),
+            AggregateKind::Min | AggregateKind::Max => over,
+            AggregateKind::GroupConcat => Some(SqlType::Text),
+        }
+    }
+}
+
 fn type_for_internal_column(
     node: &dataflow::node::Node,
     column_index: usize,
@@ -46,39 +87,71 @@ fn type_for_internal_column(
                 // TODO(malte): trace the actual column types, since this could be a
                 // real-valued arithmetic operation
                 Some(SqlType::Bigint(64))
-            } else {
+            } else if column_index < emits.0.len() + emits.2.len() + emits.1.len() {
                 // literal
                 let off = column_index - (emits.0.len() + emits.2.len());
                 to_sql_type(&emits.1[off])
+            } else {
+                // scalar function; use the type of its first argument
+                let off = column_index - (emits.0.len() + emits.2.len() + emits.1.len());
+                match o.functions()[off].first_argument() {
+                    Some(ops::project::ProjectExpressionBase::Column(i)) => {
+                        column_schema(graph, next_node_on_path, recipe, *i, log)
+                            .map(|cs| cs.sql_type)
+                    }
+                    Some(ops::project::ProjectExpressionBase::Literal(ref d)) => to_sql_type(d),
+                    None => None,
+                }
             }
         }
-        ops::NodeOperator::Sum(_) | ops::NodeOperator::FilterSum(_) => {
+        ops::NodeOperator::Sum(ref o) => {
             // computed column is always emitted last
-            if column_index == node.fields().len() - 1 {
-                // counts and sums always produce integral columns
-                Some(SqlType::Bigint(64))
-            } else {
-                // no column that isn't the aggregation result column should ever trace
-                // back to an aggregation.
-                unreachable!();
-            }
+            assert_eq!(column_index, node.fields().len() - 1);
+            let kind = match *o.inner().kind() {
+                Aggregation::COUNT => AggregateKind::Count,
+                Aggregation::SUM => AggregateKind::Sum,
+            };
+            let over = column_schema(graph, next_node_on_path, recipe, o.over_columns()[0], log);
+            kind.output_type(over.map(|cs| cs.sql_type))
+        }
+        ops::NodeOperator::FilterSum(ref o) => {
+            // computed column is always emitted last
+            assert_eq!(column_index, node.fields().len() - 1);
+            let kind = match *o.inner().kind() {
+                FilterAggregation::COUNT => AggregateKind::Count,
+                FilterAggregation::SUM => AggregateKind::Sum,
+            };
+            let over = column_schema(graph, next_node_on_path, recipe, o.over_columns()[0], log);
+            kind.output_type(over.map(|cs| cs.sql_type))
+        }
+        ops::NodeOperator::MultiSum(ref o) => {
+            // computed columns are emitted after the group columns, in the order of the
+            // aggregations; no other column should ever trace back to an aggregation.
+            let aggregates = o.inner().aggregates();
+            let first = node.fields().len() - aggregates.len();
+            assert!(column_index >= first);
+            let (ref agg, over) = aggregates[column_index - first];
+            let kind = match *agg {
+                Aggregation::COUNT => AggregateKind::Count,
+                Aggregation::SUM => AggregateKind::Sum,
+            };
+            let over = column_schema(graph, next_node_on_path, recipe, over, log);
+            kind.output_type(over.map(|cs| cs.sql_type))
         }
         ops::NodeOperator::Extremum(ref o) => {
             let over_columns = o.over_columns();
             assert_eq!(over_columns.len(), 1);
-            // use type of the "over" column
-            column_schema(graph, next_node_on_path, recipe, over_columns[0], log)
-                .map(|cs| cs.sql_type)
+            let kind = match *o.inner().kind() {
+                Extremum::MIN => AggregateKind::Min,
+                Extremum::MAX => AggregateKind::Max,
+            };
+            let over = column_schema(graph, next_node_on_path, recipe, over_columns[0], log);
+            kind.output_type(over.map(|cs| cs.sql_type))
         }
         ops::NodeOperator::Concat(_) => {
             // group_concat always outputs a string as the last column
-            if column_index == node.fields().len() - 1 {
-                Some(SqlType::Text)
-            } else {
-                // no column that isn't the concat result column should ever trace
-                // back to a group_concat.
-                unreachable!();
-            }
+            assert_eq!(column_index, node.fields().len() - 1);
+            AggregateKind::GroupConcat.output_type(None)
         }
         ops::NodeOperator::Join(_) => {
             // join doesn't "generate" columns, but they may come from one of the other
@@ -186,3 +259,50 @@ pub(super) fn column_schema(
     );
     Some(cs)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::AggregateKind;
+    use nom_sql::SqlType;
+
+    #[test]
+    fn it_infers_aggregate_types() {
+        let int = || Some(SqlType::Int(32));
+
+        // counting and summing integers yields integers, but averaging them does not
+        assert_eq!(
+            AggregateKind::Count.output_type(int()),
+            Some(SqlType::Bigint(64))
+        );
+        assert_eq!(
+            AggregateKind::Count.output_type(None),
+            Some(SqlType::Bigint(64))
+        );
+        assert_eq!(
+            AggregateKind::Sum.output_type(int()),
+            Some(SqlType::Bigint(64))
+        );
+        assert_eq!(AggregateKind::Avg.output_type(int()), Some(SqlType::Real));
+
+        // fractional inputs stay fractional
+        assert_eq!(
+            AggregateKind::Sum.output_type(Some(SqlType::Double)),
+            Some(SqlType::Double)
+        );
+        assert_eq!(
+            AggregateKind::Avg.output_type(Some(SqlType::Real)),
+            Some(SqlType::Real)
+        );
+
+        // extrema have the type of their input
+        assert_eq!(AggregateKind::Max.output_type(int()), int());
+        assert_eq!(
+            AggregateKind::Min.output_type(Some(SqlType::Text)),
+            Some(SqlType::Text)
+        );
+        assert_eq!(
+            AggregateKind::GroupConcat.output_type(int()),
+            Some(SqlType::Text)
+        );
+    }
+}
@@ -15,6 +15,7 @@ pub enum Policy {
     Rewrite(RewritePolicy),
     Allow(RowPolicy),
     Deny(RowPolicy),
+    Hide(ColumnPolicy),
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Serialize, Deserialize)]
@@ -24,6 +25,14 @@ pub struct RowPolicy {
     pub predicate: SqlQuery,
 }
 
+/// Hides a set of columns of a table from the views of a universe.
+#[derive(Clone, Debug, Hash, PartialEq, Serialize, Deserialize)]
+pub struct ColumnPolicy {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Serialize, Deserialize)]
 pub struct RewritePolicy {
     pub name: String,
@@ -40,6 +49,7 @@ impl Policy {
             Policy::Rewrite(ref p) => p.name.clone(),
             Policy::Allow(ref p) => p.name.clone(),
             Policy::Deny(ref p) => p.name.clone(),
+            Policy::Hide(ref p) => p.name.clone(),
         }
     }
 
@@ -48,6 +58,7 @@ impl Policy {
             Policy::Rewrite(ref p) => p.table.clone(),
             Policy::Allow(ref p) => p.table.clone(),
             Policy::Deny(ref p) => p.table.clone(),
+            Policy::Hide(ref p) => p.table.clone(),
         }
     }
 
@@ -56,6 +67,7 @@ impl Policy {
             Policy::Rewrite(_) => false,
             Policy::Allow(_) => true,
             Policy::Deny(_) => true,
+            Policy::Hide(_) => false,
         }
     }
 
@@ -64,6 +76,7 @@ impl Policy {
             Policy::Rewrite(ref p) => p.rewrite_view.clone(),
             Policy::Allow(ref p) => p.predicate.clone(),
             Policy::Deny(ref p) => p.predicate.clone(),
+            Policy::Hide(_) => panic!("Column policy doesn't have predicate field"),
         }
     }
 
@@ -72,6 +85,7 @@ impl Policy {
             Policy::Rewrite(ref p) => p.value.clone(),
             Policy::Allow(_) => panic!("Row policy doesn't have value field"),
             Policy::Deny(_) => panic!("Row policy doesn't have value field"),
+            Policy::Hide(_) => panic!("Column policy doesn't have value field"),
         }
     }
 
@@ -80,6 +94,7 @@ impl Policy {
             Policy::Rewrite(ref p) => p.column.clone(),
             Policy::Allow(_) => panic!("Row policy doesn't have column field"),
             Policy::Deny(_) => panic!("Row policy doesn't have column field"),
+            Policy::Hide(_) => panic!("Column policy doesn't have column field"),
         }
     }
 
@@ -88,6 +103,7 @@ impl Policy {
             Policy::Rewrite(ref p) => p.key.clone(),
             Policy::Allow(_) => panic!("Row policy doesn't have key field"),
             Policy::Deny(_) => panic!("Row policy doesn't have key field"),
+            Policy::Hide(_) => panic!("Column policy doesn't have key field"),
         }
     }
 
@@ -104,6 +120,7 @@ impl Policy {
                     Some("rewrite") => Policy::parse_rewrite_policy(p),
                     Some("allow") => Policy::parse_row_policy(p, Action::Allow),
                     Some("deny") => Policy::parse_row_policy(p, Action::Deny),
+                    Some("hide") => Policy::parse_column_policy(p),
                     _ => panic!("Unsupported policy action {}", action),
                 },
                 None => Policy::parse_row_policy(p, Action::Allow),
@@ -134,6 +151,27 @@ impl Policy {
         }
     }
 
+    fn parse_column_policy(p: &Value) -> Policy {
+        let name = match p.get("name") {
+            Some(n) => n.as_str().unwrap(),
+            None => "",
+        };
+
+        let table = p["table"].as_str().unwrap();
+        let columns = p["columns"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c.as_str().unwrap().to_string())
+            .collect();
+
+        Policy::Hide(ColumnPolicy {
+            name: name.to_string(),
+            table: table.to_string(),
+            columns,
+        })
+    }
+
     fn parse_rewrite_policy(p: &Value) -> Policy {
         let name = match p.get("name") {
             Some(n) => n.as_str().unwrap(),
@@ -181,4 +219,24 @@ mod tests {
             sql_parser::parse_query(p1).unwrap()
         );
     }
+
+    #[test]
+    fn it_parses_column_policies() {
+        use super::*;
+
+        let policy_text = r#"[{ "action": "hide", "table": "post", "columns": ["notes"] }]"#;
+
+        let policies = Policy::parse(policy_text);
+
+        assert_eq!(policies.len(), 1);
+        assert!(!policies[0].is_row_policy());
+        assert_eq!(
+            policies[0],
+            Policy::Hide(ColumnPolicy {
+                name: String::new(),
+                table: String::from("post"),
+                columns: vec![String::from("notes")],
+            })
+        );
+    }
 }
@@ -1,15 +1,17 @@
-use crate::controller::sql::mir::SqlToMirConverter;
-use crate::controller::sql::query_graph::{QueryGraph, QueryGraphEdge};
+use crate::controller::sql::mir::{MirConversionError, SqlToMirConverter};
+use crate::controller::sql::query_graph::{OutputColumn, QueryGraph, QueryGraphEdge};
 use mir::{Column, MirNodeRef};
 use nom_sql::FunctionExpression::*;
 use nom_sql::{
-    self, CaseWhenExpression, ColumnOrLiteral, ConditionExpression, FunctionArguments,
-    FunctionExpression,
+    self, ArithmeticBase, CaseWhenExpression, ColumnOrLiteral, ConditionExpression,
+    FunctionArguments, FunctionExpression,
 };
 use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 
-fn target_columns_from_computed_column(computed_col: &nom_sql::Column) -> Column {
+fn target_columns_from_computed_column(
+    computed_col: &nom_sql::Column,
+) -> Result<Column, MirConversionError> {
     use nom_sql::FunctionExpression::*;
 
     match *computed_col.function.as_ref().unwrap().deref() {
@@ -32,12 +34,14 @@ fn target_columns_from_computed_column(computed_col: &nom_sql::Column) -> Column
             }),
             _,
         )
-        | Sum(FunctionArguments::Column(ref col), _) => Column::from(col),
+        | Sum(FunctionArguments::Column(ref col), _) => Ok(Column::from(col)),
         CountStar => {
             // see comment re COUNT(*) rewriting in make_aggregation_node
-            panic!("COUNT(*) should have been rewritten earlier!")
+            Err(MirConversionError::Inconsistency(String::from(
+                "COUNT(*) should have been rewritten earlier",
+            )))
         }
-        _ => unreachable!(),
+        ref f => Err(MirConversionError::UnsupportedAggregation(f.to_string())),
     }
 }
 
@@ -50,7 +54,7 @@ pub(super) fn make_predicates_above_grouped<'a>(
     node_count: usize,
     column_to_predicates: &HashMap<Column, Vec<&'a ConditionExpression>>,
     prev_node: &mut Option<MirNodeRef>,
-) -> (Vec<&'a ConditionExpression>, Vec<MirNodeRef>) {
+) -> Result<(Vec<&'a ConditionExpression>, Vec<MirNodeRef>), MirConversionError> {
     let mut created_predicates = Vec::new();
     let mut predicates_above_group_by_nodes = Vec::new();
     let mut node_count = node_count;
@@ -62,7 +66,7 @@ pub(super) fn make_predicates_above_grouped<'a>(
                 // whenever we have a column getting aggregated (i.e. an over column
                 // rather than a group by column) we won't be able to filter on it
                 // later, so any filters involving it need to get moved above
-                let over_col = target_columns_from_computed_column(ccol);
+                let over_col = target_columns_from_computed_column(ccol)?;
                 let over_table = over_col.table.as_ref().unwrap().as_str();
 
                 if column_to_predicates.contains_key(&over_col) {
@@ -77,7 +81,7 @@ pub(super) fn make_predicates_above_grouped<'a>(
                         over_col,
                         parent,
                         &mut created_predicates,
-                    );
+                    )?;
 
                     node_count += predicates_above_group_by_nodes.len();
                     *prev_node = Some(new_mpns.last().unwrap().clone());
@@ -87,7 +91,123 @@ pub(super) fn make_predicates_above_grouped<'a>(
         }
     }
 
-    (created_predicates, predicates_above_group_by_nodes)
+    Ok((created_predicates, predicates_above_group_by_nodes))
+}
+
+/// Returns the columns that aggregations over `parent_node` group by when the query has the GROUP
+/// BY clauses in `gb_edges`: the GROUP BY columns, followed by any parameter columns that are not
+/// among them.
+fn group_by_columns(
+    qg: &QueryGraph,
+    gb_edges: &[&QueryGraphEdge],
+    parent_node: &MirNodeRef,
+) -> Vec<Column> {
+    let mut gb_cols: Vec<&nom_sql::Column> = Vec::new();
+
+    for e in gb_edges {
+        match **e {
+            QueryGraphEdge::GroupBy(ref gbc) => {
+                let table = gbc.first().unwrap().table.as_ref().unwrap();
+                assert!(gbc.iter().all(|c| c.table.as_ref().unwrap() == table));
+                gb_cols.extend(gbc);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // get any parameter columns that aren't also in the group-by
+    // column set
+    let param_cols: Vec<_> = qg.relations.values().fold(vec![], |acc, rel| {
+        acc.into_iter()
+            .chain(rel.parameters.iter().filter(|c| !gb_cols.contains(c)))
+            .collect()
+    });
+    // combine and dedup
+    let dedup_gb_cols: Vec<_> = gb_cols
+        .into_iter()
+        .filter(|gbc| !param_cols.contains(gbc))
+        .collect();
+    let gb_and_param_cols: Vec<Column> = dedup_gb_cols
+        .into_iter()
+        .chain(param_cols.into_iter())
+        .map(Column::from)
+        .collect();
+
+    let mut have_parent_cols = HashSet::new();
+    // we cannot have duplicate columns at the data-flow level, as it confuses our
+    // migration analysis code.
+    gb_and_param_cols
+        .into_iter()
+        .filter_map(|mut c| {
+            let pn = parent_node.borrow();
+            let pc = pn.columns().iter().position(|pc| *pc == c);
+            if pc.is_none() {
+                Some(c)
+            } else if !have_parent_cols.contains(&pc) {
+                have_parent_cols.insert(pc);
+                let pc = pn.columns()[pc.unwrap()].clone();
+                if pc.name != c.name || pc.table != c.table {
+                    // remember the alias with the parent column
+                    c.aliases.push(pc);
+                }
+                Some(c)
+            } else {
+                // we already have this column, so eliminate duplicate
+                None
+            }
+        })
+        .collect()
+}
+
+/// Checks that every column the query selects without aggregating it, directly or in an arithmetic
+/// expression, is one of its GROUP BY columns, as standard SQL requires. Otherwise, each group
+/// would show the value of an arbitrary one of its rows. Parameter columns are exempt, since
+/// views are grouped by them anyway, and so are queries without a GROUP BY clause, which group by
+/// all their non-aggregated columns.
+pub(super) fn check_group_by(qg: &QueryGraph) -> Result<(), MirConversionError> {
+    let group_by_cols: Vec<&nom_sql::Column> = qg
+        .edges
+        .values()
+        .filter_map(|e| match *e {
+            QueryGraphEdge::GroupBy(ref gbc) => Some(gbc),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    if group_by_cols.is_empty() {
+        return Ok(());
+    }
+
+    // selected columns may carry an alias that the GROUP BY clause does not repeat
+    let same = |a: &nom_sql::Column, b: &nom_sql::Column| a.table == b.table && a.name == b.name;
+    let selected = qg.columns.iter().flat_map(|oc| match *oc {
+        OutputColumn::Data(ref c) => vec![c],
+        OutputColumn::Arithmetic(ref ac) => [&ac.expression.left, &ac.expression.right]
+            .iter()
+            .filter_map(|&b| match *b {
+                ArithmeticBase::Column(ref c) => Some(c),
+                ArithmeticBase::Scalar(_) => None,
+            })
+            .collect(),
+        OutputColumn::Literal(_) => vec![],
+    });
+    for c in selected.filter(|c| c.function.is_none()) {
+        let grouped = group_by_cols.iter().any(|gbc| same(gbc, c))
+            || qg
+                .relations
+                .values()
+                .any(|rel| rel.parameters.iter().any(|p| same(p, c)));
+        if !grouped {
+            return Err(MirConversionError::InvalidGroupBy {
+                column: match c.table {
+                    Some(ref t) => format!("{}.{}", t, c.name),
+                    None => c.name.clone(),
+                },
+            });
+        }
+    }
+
+    Ok(())
 }
 
 pub(super) fn make_grouped(
@@ -98,7 +218,7 @@ pub(super) fn make_grouped(
     node_count: usize,
     prev_node: &mut Option<MirNodeRef>,
     is_reconcile: bool,
-) -> Vec<MirNodeRef> {
+) -> Result<Vec<MirNodeRef>, MirConversionError> {
     let mut func_nodes: Vec<MirNodeRef> = Vec::new();
     let mut node_count = node_count;
 
@@ -114,6 +234,60 @@ pub(super) fn make_grouped(
                 })
                 .collect();
 
+            // When several aggregations share a single group column, each of them is computed over
+            // the same input (so that e.g. a filtered COUNT does not affect an unfiltered one), and
+            // their results are then joined on the group column.
+            let group_by_cols: Vec<&nom_sql::Column> = gb_edges
+                .iter()
+                .flat_map(|e| match **e {
+                    QueryGraphEdge::GroupBy(ref gbc) => gbc.iter(),
+                    _ => unreachable!(),
+                })
+                .collect();
+            let join_aggregations = !is_reconcile
+                && computed_cols_cgn.columns.len() > 1
+                && group_by_cols.len() == 1
+                && qg
+                    .relations
+                    .values()
+                    .flat_map(|rel| rel.parameters.iter())
+                    .all(|c| group_by_cols.contains(&c));
+
+            // Plain counts and sums that share their group-by are instead computed together, by a
+            // single node that emits one column per aggregation.
+            let is_plain_aggregation = |c: &nom_sql::Column| match c.function.as_deref() {
+                Some(Count(FunctionArguments::Column(_), false))
+                | Some(Sum(FunctionArguments::Column(_), false)) => true,
+                _ => false,
+            };
+            if !is_reconcile
+                && computed_cols_cgn.columns.len() > 1
+                && !gb_edges.is_empty()
+                && computed_cols_cgn.columns.iter().all(is_plain_aggregation)
+            {
+                let over_col = target_columns_from_computed_column(&computed_cols_cgn.columns[0])?;
+                let parent_node = match *prev_node {
+                    Some(ref node) => node.clone(),
+                    None => node_for_rel[over_col.table.as_ref().unwrap().as_str()].clone(),
+                };
+                let group_cols = group_by_columns(qg, &gb_edges, &parent_node);
+                let computed_cols: Vec<Column> =
+                    computed_cols_cgn.columns.iter().map(Column::from).collect();
+
+                let node = mir_converter.make_multi_grouped_node(
+                    &format!("{}_n{}", name, node_count),
+                    &computed_cols,
+                    group_cols.iter().collect(),
+                    parent_node,
+                )?;
+                func_nodes.push(node.clone());
+                *prev_node = Some(node);
+                return Ok(func_nodes);
+            }
+
+            let aggregation_input = prev_node.clone();
+            let mut joined_aggregations: Option<MirNodeRef> = None;
+
             for computed_col in computed_cols_cgn.columns.iter() {
                 let computed_col = if is_reconcile {
                     let func = computed_col.function.as_ref().unwrap();
@@ -148,7 +322,12 @@ pub(super) fn make_grouped(
                                 nom_sql::Column::from(colname.as_ref()),
                             ))
                         }
-                        _ => unimplemented!(),
+                        ref f => {
+                            return Err(MirConversionError::UnsupportedAggregation(format!(
+                                "{} across universes",
+                                f
+                            )))
+                        }
                     };
 
                     nom_sql::Column {
@@ -162,92 +341,60 @@ pub(super) fn make_grouped(
                 };
 
                 // We must also push parameter columns through the group by
-                let over_col = target_columns_from_computed_column(&computed_col);
+                let over_col = target_columns_from_computed_column(&computed_col)?;
                 let over_table = over_col.table.as_ref().unwrap().as_str();
 
-                let parent_node = match *prev_node {
+                let input = if join_aggregations {
+                    aggregation_input.clone()
+                } else {
+                    prev_node.clone()
+                };
+                let parent_node = match input {
                     // If no explicit parent node is specified, we extract
                     // the base node from the "over" column's specification
                     None => node_for_rel[over_table].clone(),
                     // We have an explicit parent node (likely a projection
                     // helper), so use that
-                    Some(ref node) => node.clone(),
+                    Some(node) => node,
                 };
 
                 let name = &format!("{}_n{}", name, node_count);
 
                 let (parent_node, group_cols) = if !gb_edges.is_empty() {
                     // Function columns with GROUP BY clause
-                    let mut gb_cols: Vec<&nom_sql::Column> = Vec::new();
-
-                    for e in &gb_edges {
-                        match **e {
-                            QueryGraphEdge::GroupBy(ref gbc) => {
-                                let table = gbc.first().unwrap().table.as_ref().unwrap();
-                                assert!(gbc.iter().all(|c| c.table.as_ref().unwrap() == table));
-                                gb_cols.extend(gbc);
-                            }
-                            _ => unreachable!(),
+                    let group_cols = group_by_columns(qg, &gb_edges, &parent_node);
+                    (parent_node, group_cols)
+                } else {
+                    // Without a GROUP BY clause, the non-aggregated columns projected from *any*
+                    // relation form the implied group. The parent is the accumulated join output,
+                    // so columns of all joined relations resolve there.
+                    let mut rels: Vec<_> = qg
+                        .relations
+                        .iter()
+                        .filter(|&(rel, _)| rel != "computed_columns")
+                        .collect();
+                    rels.sort_by(|a, b| a.0.cmp(b.0));
+                    let mut implied_group_cols: Vec<Column> = Vec::new();
+                    for c in rels.into_iter().flat_map(|(_, qgn)| qgn.columns.iter()) {
+                        let c = Column::from(c);
+                        if !implied_group_cols.contains(&c) {
+                            implied_group_cols.push(c);
                         }
                     }
 
-                    // get any parameter columns that aren't also in the group-by
-                    // column set
-                    let param_cols: Vec<_> = qg.relations.values().fold(vec![], |acc, rel| {
-                        acc.into_iter()
-                            .chain(rel.parameters.iter().filter(|c| !gb_cols.contains(c)))
-                            .collect()
-                    });
-                    // combine and dedup
-                    let dedup_gb_cols: Vec<_> = gb_cols
-                        .into_iter()
-                        .filter(|gbc| !param_cols.contains(gbc))
-                        .collect();
-                    let gb_and_param_cols: Vec<Column> = dedup_gb_cols
-                        .into_iter()
-                        .chain(param_cols.into_iter())
-                        .map(Column::from)
-                        .collect();
-
-                    let mut have_parent_cols = HashSet::new();
-                    // we cannot have duplicate columns at the data-flow level, as it confuses our
-                    // migration analysis code.
-                    let gb_and_param_cols = gb_and_param_cols
-                        .into_iter()
-                        .filter_map(|mut c| {
-                            let pn = parent_node.borrow();
-                            let pc = pn.columns().iter().position(|pc| *pc == c);
-                            if pc.is_none() {
-                                Some(c)
-                            } else if !have_parent_cols.contains(&pc) {
-                                have_parent_cols.insert(pc);
-                                let pc = pn.columns()[pc.unwrap()].clone();
-                                if pc.name != c.name || pc.table != c.table {
-                                    // remember the alias with the parent column
-                                    c.aliases.push(pc);
-                                }
-                                Some(c)
-                            } else {
-                                // we already have this column, so eliminate duplicate
-                                None
-                            }
-                        })
-                        .collect();
-
-                    (parent_node, gb_and_param_cols)
-                } else {
-                    let proj_cols_from_target_table = &qg.relations[over_table].columns;
-
-                    let (group_cols, parent_node) = if proj_cols_from_target_table.is_empty() {
+                    let (group_cols, parent_node) = if implied_group_cols.is_empty() {
                         // slightly messy hack: if there are no group columns and the
                         // table on which we compute has no projected columns in the
                         // output, we make one up a group column by adding an extra
                         // projection node
                         let proj_name = format!("{}_prj_hlpr", name);
-                        let fn_col = target_columns_from_computed_column(&computed_col);
+                        let fn_col = target_columns_from_computed_column(&computed_col)?;
 
-                        let proj =
-                            mir_converter.make_projection_helper(&proj_name, parent_node, &fn_col);
+                        let proj = mir_converter.make_projection_helper(
+                            &proj_name,
+                            parent_node,
+                            &fn_col,
+                        )?;
 
                         func_nodes.push(proj.clone());
                         node_count += 1;
@@ -255,13 +402,7 @@ pub(super) fn make_grouped(
                         let bogo_group_col = Column::new(None, "grp");
                         (vec![bogo_group_col], proj)
                     } else {
-                        (
-                            proj_cols_from_target_table
-                                .iter()
-                                .map(Column::from)
-                                .collect(),
-                            parent_node,
-                        )
+                        (implied_group_cols, parent_node)
                     };
 
                     (parent_node, group_cols)
@@ -272,14 +413,32 @@ pub(super) fn make_grouped(
                     &Column::from(computed_col),
                     group_cols.iter().collect(),
                     parent_node,
-                );
+                )?;
 
-                *prev_node = Some(nodes.last().unwrap().clone());
+                let aggregation = nodes.last().unwrap().clone();
                 node_count += nodes.len();
                 func_nodes.extend(nodes);
+
+                *prev_node = match joined_aggregations.take() {
+                    Some(left) => {
+                        let join = mir_converter.make_grouped_join_node(
+                            &format!("{}_join", name),
+                            left,
+                            aggregation,
+                            &group_cols[0],
+                        );
+                        node_count += 1;
+                        func_nodes.push(join.clone());
+                        Some(join)
+                    }
+                    None => Some(aggregation),
+                };
+                if join_aggregations {
+                    joined_aggregations = prev_node.clone();
+                }
             }
         }
     }
 
-    func_nodes
+    Ok(func_nodes)
 }
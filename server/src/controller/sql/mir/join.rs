@@ -1,6 +1,7 @@
-use crate::controller::sql::mir::SqlToMirConverter;
+use crate::controller::sql::mir::{MirConversionError, SqlToMirConverter};
 use crate::controller::sql::query_graph::{JoinRef, QueryGraph, QueryGraphEdge};
 use dataflow::ops::join::JoinType;
+use mir::node::MirNodeType;
 use mir::MirNodeRef;
 use nom_sql::ConditionTree;
 use std::collections::{HashMap, HashSet};
@@ -36,12 +37,25 @@ pub(super) fn make_joins(
     qg: &QueryGraph,
     node_for_rel: &HashMap<&str, MirNodeRef>,
     node_count: usize,
-) -> Vec<MirNodeRef> {
+) -> Result<Vec<MirNodeRef>, MirConversionError> {
     let mut join_nodes: Vec<MirNodeRef> = Vec::new();
     let mut join_chains = Vec::new();
     let mut node_count = node_count;
 
-    for jref in qg.join_order.iter() {
+    // a LEFT JOIN without a predicate we could extract would not produce a join node at all, and
+    // its relation would end up joined some other way; refuse it rather than drop the left rows
+    for (&(ref src, ref dst), edge) in &qg.edges {
+        if let QueryGraphEdge::LeftJoin(ref jps) = *edge {
+            if jps.is_empty() {
+                return Err(MirConversionError::UnsupportedJoinType(format!(
+                    "LEFT JOIN of {} and {} without a join predicate",
+                    src, dst
+                )));
+            }
+        }
+    }
+
+    for jref in order_joins(qg, &mir_converter.cardinality_hints) {
         let (join_type, jp) = from_join_ref(jref, &qg);
         let (left_chain, right_chain) =
             pick_join_chains(&jref.src, &jref.dst, &mut join_chains, node_for_rel);
@@ -51,8 +65,9 @@ pub(super) fn make_joins(
             jp,
             left_chain.last_node.clone(),
             right_chain.last_node.clone(),
-            join_type,
-        );
+            join_type.clone(),
+        )?;
+        check_join_type(&jn, &join_type)?;
 
         // merge node chains
         let new_chain = left_chain.merge_chain(right_chain, jn.clone());
@@ -63,7 +78,72 @@ pub(super) fn make_joins(
         join_nodes.push(jn);
     }
 
-    join_nodes
+    Ok(join_nodes)
+}
+
+/// Orders the joins of `qg` by the given relation cardinality hints: the join chain starts with
+/// the join whose larger relation is smallest, and then grows by the join that adds the smallest
+/// relation to it. Relations without a hint count as larger than all hinted ones, and ties keep
+/// the query's order. Since the result of an outer join depends on which side is joined first,
+/// queries with outer joins keep their order.
+fn order_joins<'a>(qg: &'a QueryGraph, hints: &HashMap<String, usize>) -> Vec<&'a JoinRef> {
+    let mut remaining: Vec<&JoinRef> = qg.join_order.iter().collect();
+    let is_inner = |jref: &&JoinRef| match qg.edges[&(jref.src.clone(), jref.dst.clone())] {
+        QueryGraphEdge::Join(_) => true,
+        _ => false,
+    };
+    if hints.is_empty() || !remaining.iter().all(is_inner) {
+        return remaining;
+    }
+
+    let cardinality = |rel: &str| hints.get(rel).cloned().unwrap_or(usize::max_value());
+    let mut joined: HashSet<&str> = HashSet::new();
+    let mut ordered = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let (next, _) = remaining
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, jref)| {
+                let rels = [jref.src.as_str(), jref.dst.as_str()];
+                // joins that do not extend the chain so far would start a separate chain
+                let disconnected = !joined.is_empty() && !rels.iter().any(|r| joined.contains(r));
+                let added = rels
+                    .iter()
+                    .filter(|r| !joined.contains(*r))
+                    .map(|r| cardinality(*r))
+                    .max()
+                    .unwrap_or(0);
+                (disconnected, added)
+            })
+            .unwrap();
+        let jref = remaining.remove(next);
+        joined.insert(&jref.src);
+        joined.insert(&jref.dst);
+        ordered.push(jref);
+    }
+    ordered
+}
+
+/// Checks that the join node `jn` implements the join type the query graph declares for it, so
+/// that a declared LEFT JOIN never turns into an inner join that drops unmatched left rows.
+pub(super) fn check_join_type(
+    jn: &MirNodeRef,
+    declared: &JoinType,
+) -> Result<(), MirConversionError> {
+    let produced = match mir::query::reuse_target(jn).borrow().inner {
+        MirNodeType::Join { .. } => Some(JoinType::Inner),
+        MirNodeType::LeftJoin { .. } => Some(JoinType::Left),
+        _ => None,
+    };
+    if produced.as_ref() != Some(declared) {
+        return Err(MirConversionError::Inconsistency(format!(
+            "join node {} implements {:?}, but the query declares {:?}",
+            jn.borrow().name(),
+            produced,
+            declared
+        )));
+    }
+    Ok(())
 }
 
 fn from_join_ref<'a>(jref: &JoinRef, qg: &'a QueryGraph) -> (JoinType, &'a ConditionTree) {
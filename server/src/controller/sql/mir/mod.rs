@@ -1,41 +1,171 @@
-use mir::node::{GroupedNodeType, MirNode, MirNodeType};
+use mir::node::{GroupedNodeType, MaterializationMode, MirNode, MirNodeType};
 use mir::query::MirQuery;
 use mir::{Column, MirNodeRef};
-use noria::DataType;
+use noria::{DataType, SerializedConverterState};
 use petgraph::graph::NodeIndex;
 // TODO(malte): remove if possible
 use dataflow::ops::filter::FilterCondition;
 use dataflow::ops::join::JoinType;
+use dataflow::ops::project::{ProjectExpressionBase, ProjectFunction};
+use dataflow::ops::topk::{Collation, NullOrder};
 
-use crate::controller::sql::query_graph::{OutputColumn, QueryGraph};
+use crate::controller::sql::query_graph::{OutputColumn, QueryGraph, QueryGraphEdge};
 use crate::controller::sql::query_signature::Signature;
 use nom_sql::{
-    ArithmeticExpression, CaseWhenExpression, ColumnOrLiteral, ColumnSpecification,
-    CompoundSelectOperator, ConditionBase, ConditionExpression, ConditionTree, Literal, Operator,
-    SqlQuery, TableKey,
+    ArithmeticBase, ArithmeticExpression, ArithmeticOperator, CaseWhenExpression, ColumnConstraint,
+    ColumnOrLiteral, ColumnSpecification, CompoundSelectOperator, ConditionBase,
+    ConditionExpression, ConditionTree, Literal, Operator, SqlQuery, SqlType, TableKey,
 };
 use nom_sql::{LimitClause, OrderClause, SelectStatement};
 
 use slog;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
-
+use std::fmt;
+use std::mem;
 use std::ops::Deref;
+use std::rc::Rc;
 use std::vec::Vec;
 
 use crate::controller::sql::security::Universe;
 use crate::controller::sql::UniverseId;
+use crate::PostLoweringHook;
 
 mod grouped;
 mod join;
 mod rewrite;
 mod security;
 
+/// Qualifies the columns of `order` with the table of the column of the same name in `columns`,
+/// which the sorted rows have.
+fn resolve_order(
+    order: OrderClause,
+    columns: &[Column],
+) -> Result<OrderClause, MirConversionError> {
+    Ok(OrderClause {
+        columns: order
+            .columns
+            .into_iter()
+            .map(|(c, o)| {
+                match columns
+                    .iter()
+                    .find(|pc| pc.name == c.name && (c.table.is_none() || pc.table == c.table))
+                {
+                    Some(pc) => Ok((
+                        nom_sql::Column {
+                            table: pc.table.clone(),
+                            ..c
+                        },
+                        o,
+                    )),
+                    None => {
+                        let available: Vec<String> =
+                            columns.iter().map(|c| c.name.clone()).collect();
+                        Err(MirConversionError::UnknownColumn {
+                            column: c.name.clone(),
+                            available: close_matches(&c.name, &available),
+                        })
+                    }
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    })
+}
+
 fn sanitize_leaf_column(c: &mut Column, view_name: &str) {
     c.table = Some(view_name.to_string());
     c.function = None;
     c.aliases = vec![];
 }
 
+/// Evaluates an arithmetic expression over two numeric constants at lowering time, so that
+/// projections need not compute it again for every record. Returns `None` for expressions that
+/// refer to columns, or to constants that are not numbers.
+fn fold_constant_arithmetic(
+    ae: &ArithmeticExpression,
+) -> Result<Option<DataType>, MirConversionError> {
+    let numeric = |b: &ArithmeticBase| match *b {
+        ArithmeticBase::Scalar(ref l @ Literal::Integer(_))
+        | ArithmeticBase::Scalar(ref l @ Literal::FixedPoint(_)) => Some(DataType::from(l)),
+        _ => None,
+    };
+    let (left, right) = match (numeric(&ae.left), numeric(&ae.right)) {
+        (Some(left), Some(right)) => (left, right),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(match ae.op {
+        ArithmeticOperator::Add => &left + &right,
+        ArithmeticOperator::Subtract => &left - &right,
+        ArithmeticOperator::Multiply => &left * &right,
+        ArithmeticOperator::Divide => {
+            if f64::from(&right) == 0.0 {
+                return Err(MirConversionError::InvalidExpression(format!(
+                    "division by zero in {:?}",
+                    ae
+                )));
+            }
+            &left / &right
+        }
+    }))
+}
+
+/// Converts the literal `value` to the declared type `ty` of the column it is compared with, so
+/// that it compares equal to the values stored there: e.g., the integer literal `3` then matches
+/// `3.0` in a `FLOAT` column, and `'7'` matches `7` in an `INT` column. Values that do not
+/// represent a value of the column's type are left as they are.
+fn coerce_literal(value: DataType, ty: &SqlType) -> DataType {
+    match (ty, &value) {
+        (SqlType::Float, DataType::Int(_))
+        | (SqlType::Float, DataType::BigInt(_))
+        | (SqlType::Double, DataType::Int(_))
+        | (SqlType::Double, DataType::BigInt(_))
+        | (SqlType::Real, DataType::Int(_))
+        | (SqlType::Real, DataType::BigInt(_))
+        | (SqlType::Decimal(..), DataType::Int(_))
+        | (SqlType::Decimal(..), DataType::BigInt(_)) => DataType::from(i64::from(&value) as f64),
+        (SqlType::Float, DataType::Text(_))
+        | (SqlType::Float, DataType::TinyText(_))
+        | (SqlType::Double, DataType::Text(_))
+        | (SqlType::Double, DataType::TinyText(_))
+        | (SqlType::Real, DataType::Text(_))
+        | (SqlType::Real, DataType::TinyText(_))
+        | (SqlType::Decimal(..), DataType::Text(_))
+        | (SqlType::Decimal(..), DataType::TinyText(_)) => {
+            let s: &str = (&value).into();
+            match s.trim().parse::<f64>() {
+                Ok(f) if f.is_finite() => DataType::from(f),
+                _ => value,
+            }
+        }
+        (SqlType::Int(_), DataType::Text(_))
+        | (SqlType::Int(_), DataType::TinyText(_))
+        | (SqlType::Bigint(_), DataType::Text(_))
+        | (SqlType::Bigint(_), DataType::TinyText(_))
+        | (SqlType::Tinyint(_), DataType::Text(_))
+        | (SqlType::Tinyint(_), DataType::TinyText(_)) => {
+            let s: &str = (&value).into();
+            match s.trim().parse::<i64>() {
+                Ok(i) => DataType::from(i),
+                Err(_) => value,
+            }
+        }
+        (SqlType::Char(_), DataType::Int(_))
+        | (SqlType::Char(_), DataType::BigInt(_))
+        | (SqlType::Varchar(_), DataType::Int(_))
+        | (SqlType::Varchar(_), DataType::BigInt(_))
+        | (SqlType::Tinytext, DataType::Int(_))
+        | (SqlType::Tinytext, DataType::BigInt(_))
+        | (SqlType::Mediumtext, DataType::Int(_))
+        | (SqlType::Mediumtext, DataType::BigInt(_))
+        | (SqlType::Longtext, DataType::Int(_))
+        | (SqlType::Longtext, DataType::BigInt(_))
+        | (SqlType::Text, DataType::Int(_))
+        | (SqlType::Text, DataType::BigInt(_)) => DataType::from(i64::from(&value).to_string()),
+        _ => value,
+    }
+}
+
 /// Returns all collumns used in a predicate
 fn predicate_columns(ce: &ConditionExpression) -> HashSet<Column> {
     use nom_sql::ConditionExpression::*;
@@ -59,6 +189,207 @@ fn predicate_columns(ce: &ConditionExpression) -> HashSet<Column> {
     cols
 }
 
+/// The number of single-character insertions, deletions, and substitutions needed to turn `a`
+/// into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let subst = prev[j] + if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = subst.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// The names in `available` that are closest to `name`, to suggest in place of a misspelled
+/// column. If none is close, all of `available` is returned.
+fn close_matches(name: &str, available: &[String]) -> Vec<String> {
+    let threshold = std::cmp::max(2, name.len() / 3);
+    let mut close: Vec<(usize, &String)> = available
+        .iter()
+        .map(|a| (edit_distance(name, a), a))
+        .filter(|&(d, _)| d <= threshold)
+        .collect();
+    if close.is_empty() {
+        return available.to_vec();
+    }
+    close.sort();
+    close.into_iter().map(|(_, a)| a.clone()).collect()
+}
+
+/// Returns true if the predicate is a comparison of a column against a literal, or a conjunction
+/// of such comparisons. All of these can be evaluated by a single filter node.
+fn is_simple_conjunction(ce: &ConditionExpression) -> bool {
+    use nom_sql::ConditionExpression::*;
+
+    match *ce {
+        ComparisonOp(ref ct) => match (ct.left.as_ref(), ct.right.as_ref()) {
+            (Base(ConditionBase::Field(_)), Base(ConditionBase::Literal(Literal::Integer(_))))
+            | (Base(ConditionBase::Field(_)), Base(ConditionBase::Literal(Literal::String(_))))
+            | (Base(ConditionBase::Field(_)), Base(ConditionBase::Literal(Literal::Null)))
+            | (
+                Base(ConditionBase::Field(_)),
+                Base(ConditionBase::Literal(Literal::CurrentTimestamp)),
+            )
+            | (Base(ConditionBase::Field(_)), Base(ConditionBase::LiteralList(_))) => true,
+            _ => false,
+        },
+        LogicalOp(ref ct) if ct.operator == Operator::And => {
+            is_simple_conjunction(&ct.left) && is_simple_conjunction(&ct.right)
+        }
+        Bracketed(ref inner) => is_simple_conjunction(inner),
+        _ => false,
+    }
+}
+
+/// Folds all simple column/literal comparisons among `predicates` into a single conjunction, so
+/// that they end up in the same filter node. Other predicates are returned unchanged.
+fn fuse_simple_conjunctions(predicates: Vec<&ConditionExpression>) -> Vec<ConditionExpression> {
+    let (simple, rest): (Vec<_>, Vec<_>) = predicates
+        .into_iter()
+        .cloned()
+        .partition(is_simple_conjunction);
+
+    let fused = simple.into_iter().fold(None, |acc, p| match acc {
+        None => Some(p),
+        Some(l) => Some(ConditionExpression::LogicalOp(ConditionTree {
+            operator: Operator::And,
+            left: Box::new(l),
+            right: Box::new(p),
+        })),
+    });
+
+    fused.into_iter().chain(rest).collect()
+}
+
+/// Returns true if a conjunction of filter conditions can obviously never be satisfied, e.g.,
+/// because it requires a column to equal two different constants. The check is conservative: it
+/// only considers comparisons against non-NULL constants and returns false whenever in doubt.
+fn conditions_unsatisfiable(conditions: &[(usize, FilterCondition)]) -> bool {
+    use dataflow::ops::filter::Value;
+
+    let constant = |cond: &FilterCondition| match *cond {
+        FilterCondition::Comparison(ref op, Value::Constant(ref v)) => match *v {
+            DataType::None => None,
+            ref v => Some((op.clone(), v.clone())),
+        },
+        _ => None,
+    };
+
+    // whether `d` passes `cond`, if we can tell
+    let holds = |d: &DataType, cond: &FilterCondition| match *cond {
+        FilterCondition::In(ref vs) => Some(vs.contains(d)),
+        _ => match constant(cond) {
+            Some((Operator::Equal, ref v)) => Some(d == v),
+            Some((Operator::NotEqual, ref v)) => Some(d != v),
+            Some((Operator::Greater, ref v)) => Some(d > v),
+            Some((Operator::GreaterOrEqual, ref v)) => Some(d >= v),
+            Some((Operator::Less, ref v)) => Some(d < v),
+            Some((Operator::LessOrEqual, ref v)) => Some(d <= v),
+            _ => None,
+        },
+    };
+
+    for (i, cond) in conditions {
+        match constant(cond) {
+            // a column that must equal a constant has to pass all other conditions on it, too
+            Some((Operator::Equal, ref v)) => {
+                if conditions
+                    .iter()
+                    .any(|(j, other)| j == i && holds(v, other) == Some(false))
+                {
+                    return true;
+                }
+            }
+            // a column cannot be above a lower bound and below a smaller upper bound
+            Some((Operator::Greater, ref lower)) | Some((Operator::GreaterOrEqual, ref lower)) => {
+                let strict_lower = constant(cond).unwrap().0 == Operator::Greater;
+                for (j, other) in conditions {
+                    if j != i {
+                        continue;
+                    }
+                    let (strict_upper, upper) = match constant(other) {
+                        Some((Operator::Less, upper)) => (true, upper),
+                        Some((Operator::LessOrEqual, upper)) => (false, upper),
+                        _ => continue,
+                    };
+                    if *lower > upper || (*lower == upper && (strict_lower || strict_upper)) {
+                        return true;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    false
+}
+
+/// Keeps only the tightest of the lower bounds and the tightest of the upper bounds that a
+/// conjunction of filter conditions puts on each column, e.g., turning `x > 5 AND x >= 10` into
+/// just `x >= 10`. Only comparisons against non-NULL constants of the same type are combined;
+/// all other conditions are kept as they are, and the remaining ones keep their order.
+fn tighten_ranges(conditions: Vec<(usize, FilterCondition)>) -> Vec<(usize, FilterCondition)> {
+    use dataflow::ops::filter::Value;
+    use std::cmp::Ordering;
+    use std::mem::discriminant;
+
+    // the bound a condition puts on its column: whether it is a lower bound, whether it is
+    // strict, and the constant it compares against
+    let bound = |cond: &FilterCondition| match *cond {
+        FilterCondition::Comparison(ref op, Value::Constant(ref v)) if *v != DataType::None => {
+            match *op {
+                Operator::Greater => Some((true, true, v.clone())),
+                Operator::GreaterOrEqual => Some((true, false, v.clone())),
+                Operator::Less => Some((false, true, v.clone())),
+                Operator::LessOrEqual => Some((false, false, v.clone())),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    // whether bound `a` admits fewer values than bound `b` of the same kind
+    let tighter = |a: &(bool, bool, DataType), b: &(bool, bool, DataType)| {
+        let (lower, strict_a, ref va) = *a;
+        let (_, strict_b, ref vb) = *b;
+        match va.cmp(vb) {
+            Ordering::Equal => strict_a && !strict_b,
+            Ordering::Greater => lower,
+            Ordering::Less => !lower,
+        }
+    };
+
+    let bounds: Vec<_> = conditions
+        .iter()
+        .map(|&(i, ref c)| bound(c).map(|b| (i, b)))
+        .collect();
+    // whether another bound on the same column is tighter than bound `k`, or just as tight and
+    // comes first
+    let superseded = |k: usize| match bounds[k] {
+        Some((i, ref b)) => bounds.iter().enumerate().any(|(l, other)| match *other {
+            Some((j, ref o))
+                if l != k && j == i && o.0 == b.0 && discriminant(&o.2) == discriminant(&b.2) =>
+            {
+                tighter(o, b) || (l < k && !tighter(b, o))
+            }
+            _ => false,
+        }),
+        None => false,
+    };
+
+    conditions
+        .into_iter()
+        .enumerate()
+        .filter(|&(k, _)| !superseded(k))
+        .map(|(_, c)| c)
+        .collect()
+}
+
 fn value_columns_needed_for_predicates(
     value_columns: &[OutputColumn],
     predicates: &[ConditionExpression],
@@ -95,16 +426,180 @@ fn value_columns_needed_for_predicates(
         .collect()
 }
 
+/// The column synthesized as the key of base tables without a primary key.
+fn implicit_rowid_spec(table: &str) -> ColumnSpecification {
+    ColumnSpecification::with_constraints(
+        nom_sql::Column {
+            name: String::from("rowid"),
+            table: Some(String::from(table)),
+            alias: None,
+            function: None,
+        },
+        SqlType::Bigint(64),
+        vec![ColumnConstraint::NotNull, ColumnConstraint::AutoIncrement],
+    )
+}
+
+/// The columns in `cols` that are declared `NOT NULL`.
+fn not_null_columns(cols: &[ColumnSpecification]) -> Vec<Column> {
+    cols.iter()
+        .filter(|cs| cs.constraints.contains(&ColumnConstraint::NotNull))
+        .map(|cs| Column::from(&cs.column))
+        .collect()
+}
+
+/// A scalar function over several columns or literals of a query, evaluated for each row by a
+/// projection. nom-sql does not parse these, so callers currently build them themselves.
+#[derive(Clone, Debug)]
+pub(super) enum ScalarFunction {
+    /// `LOWER(..)`
+    Lower(ColumnOrLiteral),
+    /// `UPPER(..)`
+    Upper(ColumnOrLiteral),
+}
+
+/// The reasons for which a query cannot be converted to MIR. Clients can tell them apart using the
+/// stable code that each variant reports, e.g., to hand unsupported queries to another engine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum MirConversionError {
+    /// The query refers to a table or view that does not exist.
+    UnknownView(String),
+    /// The query refers to a column that its relation does not have. `available` lists the
+    /// relation's columns whose names are closest to the one referenced.
+    UnknownColumn {
+        column: String,
+        available: Vec<String>,
+    },
+    /// The named aggregation or function is not supported.
+    UnsupportedAggregation(String),
+    /// The join is not an equi-join between two columns.
+    UnsupportedJoinType(String),
+    /// The compound operator is not supported (only `UNION` is), or its branches do not line up.
+    UnsupportedCompoundOp(String),
+    /// The condition nests expressions that filters cannot evaluate.
+    NestedCondition(String),
+    /// The query's parameters cannot be looked up together.
+    UnsupportedParameters(String),
+    /// The named node exposes no columns, so there is nothing for a filter below it to filter on.
+    EmptyFilterInput(String),
+    /// The query selects `column` without aggregating it, but does not group by it.
+    InvalidGroupBy { column: String },
+    /// A constant expression in the query cannot be evaluated.
+    InvalidExpression(String),
+    /// The converter's own state is inconsistent.
+    Inconsistency(String),
+}
+
+impl MirConversionError {
+    pub(super) fn code(&self) -> &'static str {
+        match *self {
+            MirConversionError::UnknownView(_) => "UNKNOWN_VIEW",
+            MirConversionError::UnknownColumn { .. } => "UNKNOWN_COLUMN",
+            MirConversionError::UnsupportedAggregation(_) => "UNSUPPORTED_AGGREGATION",
+            MirConversionError::UnsupportedJoinType(_) => "UNSUPPORTED_JOIN_TYPE",
+            MirConversionError::UnsupportedCompoundOp(_) => "UNSUPPORTED_COMPOUND_OP",
+            MirConversionError::NestedCondition(_) => "NESTED_CONDITION",
+            MirConversionError::UnsupportedParameters(_) => "UNSUPPORTED_PARAMETERS",
+            MirConversionError::EmptyFilterInput(_) => "EMPTY_FILTER_INPUT",
+            MirConversionError::InvalidGroupBy { .. } => "INVALID_GROUP_BY",
+            MirConversionError::InvalidExpression(_) => "INVALID_EXPRESSION",
+            MirConversionError::Inconsistency(_) => "INCONSISTENCY",
+        }
+    }
+}
+
+impl fmt::Display for MirConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let detail = match *self {
+            MirConversionError::UnknownColumn {
+                ref column,
+                ref available,
+            } => {
+                return write!(
+                    f,
+                    "{}: column \"{}\" does not exist; did you mean one of: {}?",
+                    self.code(),
+                    column,
+                    available.join(", ")
+                )
+            }
+            MirConversionError::InvalidGroupBy { ref column } => {
+                return write!(
+                    f,
+                    "{}: column \"{}\" must appear in the GROUP BY clause or be aggregated",
+                    self.code(),
+                    column
+                )
+            }
+            MirConversionError::UnknownView(ref d)
+            | MirConversionError::UnsupportedAggregation(ref d)
+            | MirConversionError::UnsupportedJoinType(ref d)
+            | MirConversionError::UnsupportedCompoundOp(ref d)
+            | MirConversionError::NestedCondition(ref d)
+            | MirConversionError::UnsupportedParameters(ref d)
+            | MirConversionError::EmptyFilterInput(ref d)
+            | MirConversionError::InvalidExpression(ref d)
+            | MirConversionError::Inconsistency(ref d) => d,
+        };
+        write!(f, "{}: {}", self.code(), detail)
+    }
+}
+
+impl From<MirConversionError> for String {
+    fn from(e: MirConversionError) -> String {
+        e.to_string()
+    }
+}
+
+/// A function run over the MIR nodes generated for each query, after lowering has finished.
+#[derive(Clone)]
+struct LoweringHook(PostLoweringHook);
+
+impl fmt::Debug for LoweringHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoweringHook")
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(super) struct SqlToMirConverter {
     base_schemas: HashMap<String, Vec<(usize, Vec<ColumnSpecification>)>>,
     current: HashMap<String, usize>,
+    /// Filter nodes created while lowering the current query, so that structurally identical
+    /// filters over the same parent can share a node
+    filter_nodes: RefCell<Vec<MirNodeRef>>,
+    /// Whether queries may share the join and grouped nodes of other queries that compute the
+    /// same thing
+    share_nodes: bool,
+    /// Prepended to the names of all nodes that the converter generates names for
+    name_prefix: String,
+    /// Whether base tables without a primary key get a synthesized rowid key column
+    implicit_rowid: bool,
+    /// Whether queries that select columns they neither group by nor aggregate are rejected
+    strict_group_by: bool,
+    /// Estimated number of rows of each relation, used to join smaller relations first
+    cardinality_hints: HashMap<String, usize>,
+    /// Queries whose LIMIT is only an upper bound on the rows per key that readers ask for
+    read_time_limits: HashSet<String>,
+    /// `SELECT DISTINCT` queries whose readers return rows ordered by the distinct columns
+    ordered_distincts: HashSet<String>,
+    /// `UNION` queries whose narrower branches are padded with `NULL` columns
+    padded_unions: HashSet<String>,
+    /// Parameter columns of queries that are compared against a function of the column, with
+    /// the name of the column that computes the function for the leaf to be keyed on
+    computed_parameters: HashMap<String, Vec<(Column, String, ScalarFunction)>>,
     log: slog::Logger,
     nodes: HashMap<(String, usize), MirNodeRef>,
+    /// Parsed statement and query graph of each converted query, kept around so that the query
+    /// can be lowered again for another universe
+    queries: HashMap<String, (SelectStatement, QueryGraph, Option<MaterializationMode>)>,
+    post_lowering_hook: Option<LoweringHook>,
     schema_version: usize,
 
     /// Universe in which the conversion is happening
     universe: Universe,
+    /// Every universe passed to `set_universe`, so that queries can be lowered again for it
+    universes: HashMap<UniverseId, Universe>,
 }
 
 impl Default for SqlToMirConverter {
@@ -112,10 +607,23 @@ impl Default for SqlToMirConverter {
         SqlToMirConverter {
             base_schemas: HashMap::default(),
             current: HashMap::default(),
+            filter_nodes: RefCell::default(),
+            share_nodes: true,
+            name_prefix: String::new(),
+            implicit_rowid: false,
+            strict_group_by: false,
+            cardinality_hints: HashMap::default(),
+            read_time_limits: HashSet::default(),
+            ordered_distincts: HashSet::default(),
+            padded_unions: HashSet::default(),
+            computed_parameters: HashMap::default(),
             log: slog::Logger::root(slog::Discard, o!()),
             nodes: HashMap::default(),
+            queries: HashMap::default(),
+            post_lowering_hook: None,
             schema_version: 0,
             universe: Universe::default(),
+            universes: HashMap::default(),
         }
     }
 }
@@ -133,6 +641,7 @@ impl SqlToMirConverter {
     /// security policies and therefore different nodes that are not
     /// represent in the the query graph
     pub(super) fn set_universe(&mut self, universe: Universe) {
+        self.universes.insert(universe.id(), universe.clone());
         self.universe = universe;
     }
 
@@ -141,15 +650,103 @@ impl SqlToMirConverter {
         self.universe = Universe::default();
     }
 
-    fn get_view(&self, view_name: &str) -> Result<MirNodeRef, String> {
+    /// Set a prefix for the names of all nodes generated from now on, so that converters for
+    /// independent schemas do not produce clashing node names. Base tables and query leaves
+    /// keep the names they are given, since other queries refer to them by these names.
+    pub(super) fn set_name_prefix(&mut self, prefix: &str) {
+        self.name_prefix = String::from(prefix);
+    }
+
+    /// Give base tables created from now on that have no primary key an auto-incrementing
+    /// `rowid` key column, so that they can still be indexed and looked up by key.
+    pub(super) fn enable_implicit_rowid(&mut self) {
+        self.implicit_rowid = true;
+    }
+
+    /// Reject queries converted from now on that have a GROUP BY clause but select columns that
+    /// they neither group by nor aggregate, as standard SQL does. Without this, such queries are
+    /// accepted, as MySQL does without `ONLY_FULL_GROUP_BY`, and each group shows the value of an
+    /// arbitrary one of its rows for these columns.
+    pub(super) fn enable_strict_group_by(&mut self) {
+        self.strict_group_by = true;
+    }
+
+    /// Allow or forbid queries converted from now on to share join and grouped nodes with other
+    /// queries.
+    pub(super) fn set_node_sharing(&mut self, share: bool) {
+        self.share_nodes = share;
+    }
+
+    /// Set estimates of the number of rows in each relation. Queries converted from now on join
+    /// smaller relations first, which keeps the intermediate join state small.
+    pub(super) fn set_cardinality_hints(&mut self, hints: HashMap<String, usize>) {
+        self.cardinality_hints = hints;
+    }
+
+    /// Let readers of the query `query_name`, once converted, pick how many rows per key they
+    /// want at read time, up to the query's LIMIT. nom-sql cannot parse `LIMIT ?`, so the query
+    /// states the largest number of rows readers may ask for instead.
+    pub(super) fn set_read_time_limit(&mut self, query_name: &str) {
+        self.read_time_limits.insert(String::from(query_name));
+    }
+
+    /// Have readers of the `SELECT DISTINCT` query `query_name`, once converted, return each key's
+    /// rows ordered by the selected columns (in SQL ascending order), rather than in the order in
+    /// which they were first seen. Reads then do not depend on the order of earlier writes.
+    pub(super) fn set_ordered_distinct(&mut self, query_name: &str) {
+        self.ordered_distincts.insert(String::from(query_name));
+    }
+
+    /// Let the branches of the `UNION` query `query_name`, once converted, select fewer columns
+    /// than its widest branch. Each column a branch lacks (by name) is then `NULL` in its rows,
+    /// rather than the conversion failing.
+    pub(super) fn set_padded_union(&mut self, query_name: &str) {
+        self.padded_unions.insert(String::from(query_name));
+    }
+
+    /// Key the view of the query `query_name`, once converted, on `function` (computed into a
+    /// column named `name`) wherever it would be keyed on the parameter column `param`. This
+    /// stands in for comparing a function with a parameter (as in `WHERE LOWER(email) = ?`),
+    /// which nom-sql does not parse: the query compares the plain column instead, and readers
+    /// then look up the view by the function's value.
+    pub(super) fn set_computed_parameter(
+        &mut self,
+        query_name: &str,
+        param: Column,
+        name: &str,
+        function: ScalarFunction,
+    ) {
+        self.computed_parameters
+            .entry(String::from(query_name))
+            .or_insert_with(Vec::new)
+            .push((param, String::from(name), function));
+    }
+
+    /// Whether the view of the query `query_name` is keyed on a function of a parameter column.
+    pub(super) fn has_computed_parameters(&self, query_name: &str) -> bool {
+        self.computed_parameters.contains_key(query_name)
+    }
+
+    /// Set a function that gets to inspect and rewrite the MIR nodes generated for every query
+    /// (including its leaf) before they are returned from the conversion.
+    pub(super) fn set_post_lowering_hook(&mut self, hook: PostLoweringHook) {
+        self.post_lowering_hook = Some(LoweringHook(hook));
+    }
+
+    fn get_view(&self, view_name: &str) -> Result<MirNodeRef, MirConversionError> {
         self.current
             .get(view_name)
-            .ok_or_else(|| format!("Query refers to unknown view \"{}\"", view_name))
+            .ok_or_else(|| {
+                MirConversionError::UnknownView(format!(
+                    "Query refers to unknown view \"{}\"",
+                    view_name
+                ))
+            })
             .and_then(|v| match self.nodes.get(&(String::from(view_name), *v)) {
-                None => Err(format!(
-                    "Inconsistency: view \"{}\" does not exist at v{}",
+                None => Err(MirConversionError::Inconsistency(format!(
+                    "view \"{}\" does not exist at v{}",
                     view_name, v
-                )),
+                ))),
                 Some(bmn) => Ok(MirNode::reuse(bmn.clone(), self.schema_version)),
             })
     }
@@ -168,62 +765,133 @@ impl SqlToMirConverter {
         ct: &ConditionTree,
         columns: &mut Vec<Column>,
         n: &MirNodeRef,
-    ) -> Vec<(usize, FilterCondition)> {
+    ) -> Result<Vec<(usize, FilterCondition)>, MirConversionError> {
+        fn side_to_conditions(
+            conv: &SqlToMirConverter,
+            ce: &ConditionExpression,
+            columns: &mut Vec<Column>,
+            n: &MirNodeRef,
+        ) -> Result<Vec<(usize, FilterCondition)>, MirConversionError> {
+            match *ce {
+                ConditionExpression::LogicalOp(ref ct2) => {
+                    conv.logical_op_to_conditions(ct2, columns, n)
+                }
+                ConditionExpression::ComparisonOp(ref ct2) => conv.to_conditions(ct2, columns, n),
+                // nested conjunctions may be bracketed
+                ConditionExpression::Bracketed(ref inner) => {
+                    side_to_conditions(conv, inner, columns, n)
+                }
+                _ => Err(MirConversionError::NestedCondition(format!(
+                    "cannot filter on {:?}",
+                    ce
+                ))),
+            }
+        }
+
         match ct.operator {
             Operator::And => {
-                let mut left_filter = match ct.left.as_ref() {
-                    ConditionExpression::LogicalOp(ref ct2) => {
-                        self.logical_op_to_conditions(ct2, columns, n)
-                    }
-                    ConditionExpression::ComparisonOp(ref ct2) => {
-                        self.to_conditions(ct2, columns, n)
-                    }
-                    _ => unimplemented!(),
-                };
-                let mut right_filter = match ct.right.as_ref() {
-                    ConditionExpression::LogicalOp(ref ct2) => {
-                        self.logical_op_to_conditions(ct2, columns, n)
-                    }
-                    ConditionExpression::ComparisonOp(ref ct2) => {
-                        self.to_conditions(ct2, columns, n)
-                    }
-                    _ => unimplemented!(),
-                };
+                let mut left_filter = side_to_conditions(self, &ct.left, columns, n)?;
+                let mut right_filter = side_to_conditions(self, &ct.right, columns, n)?;
                 left_filter.append(&mut right_filter);
-                left_filter
+                Ok(left_filter)
             }
-            _ => unimplemented!(),
+            _ => Err(MirConversionError::NestedCondition(format!(
+                "cannot combine conditions with {:?} in a single filter",
+                ct.operator
+            ))),
         }
     }
 
+    /// The declaration of the column `c`, if it is a column of a base table.
+    fn column_spec(&self, c: &nom_sql::Column) -> Option<&ColumnSpecification> {
+        let table = c.table.as_ref()?;
+        let (_, specs) = self.base_schemas.get(table)?.last()?;
+        specs.iter().find(|cs| cs.column.name == c.name)
+    }
+
+    /// The declared type of the column `c`, if it is a column of a base table.
+    fn column_type(&self, c: &nom_sql::Column) -> Option<&SqlType> {
+        self.column_spec(c).map(|cs| &cs.sql_type)
+    }
+
+    /// The collation that text in the column `c` compares under, as declared by a `COLLATE`
+    /// clause on a base table column. All other columns compare text by its bytes.
+    fn column_collation(&self, c: &nom_sql::Column) -> Collation {
+        self.column_spec(c)
+            .and_then(|cs| {
+                cs.constraints.iter().find_map(|cc| match *cc {
+                    ColumnConstraint::Collation(ref name) => Some(Collation::from_name(name)),
+                    _ => None,
+                })
+            })
+            .unwrap_or(Collation::Binary)
+    }
+
     /// Converts a condition tree stored in the `ConditionExpr` returned by the SQL parser
     /// and adds its to a vector of conditions.
+    ///
+    /// Note that the parser already maps both spellings of inequality (`<>` and `!=`) to
+    /// `Operator::NotEqual`, so they produce the same condition here.
     fn to_conditions(
         &self,
         ct: &ConditionTree,
         columns: &mut Vec<Column>,
         n: &MirNodeRef,
-    ) -> Vec<(usize, FilterCondition)> {
-        use std::cmp::max;
+    ) -> Result<Vec<(usize, FilterCondition)>, MirConversionError> {
+        // column positions below are relative to the input's columns, so there must be some
+        if columns.is_empty() {
+            return Err(MirConversionError::EmptyFilterInput(format!(
+                "cannot filter the output of \"{}\", which has no columns",
+                n.borrow().name()
+            )));
+        }
 
         // TODO(malte): we only support one level of condition nesting at this point :(
         let l = match *ct.left.as_ref() {
             ConditionExpression::Base(ConditionBase::Field(ref f)) => f.clone(),
-            _ => unimplemented!(),
+            ref e => {
+                return Err(MirConversionError::NestedCondition(format!(
+                    "left-hand side of comparison is not a column: {:?}",
+                    e
+                )))
+            }
         };
         use dataflow::ops::filter;
+        // literals are compared as values of the column's type, if we know it
+        let coerce = |value: DataType| match self.column_type(&l) {
+            Some(ty) => coerce_literal(value, ty),
+            None => value,
+        };
         let f = match *ct.right.as_ref() {
             ConditionExpression::Base(ConditionBase::Literal(Literal::Integer(ref i))) => {
                 FilterCondition::Comparison(
                     ct.operator.clone(),
-                    filter::Value::Constant(DataType::from(*i)),
+                    filter::Value::Constant(coerce(DataType::from(*i))),
                 )
             }
+            // nom-sql does not parse an ESCAPE clause, so patterns have no escape character
+            ConditionExpression::Base(ConditionBase::Literal(Literal::String(ref s)))
+                if ct.operator == Operator::Like || ct.operator == Operator::NotLike =>
+            {
+                FilterCondition::Like {
+                    pattern: s.clone(),
+                    escape: None,
+                    negated: ct.operator == Operator::NotLike,
+                }
+            }
             ConditionExpression::Base(ConditionBase::Literal(Literal::String(ref s))) => {
-                FilterCondition::Comparison(
-                    ct.operator.clone(),
-                    filter::Value::Constant(DataType::from(s.clone())),
-                )
+                let value = coerce(DataType::from(s.clone()));
+                match self.column_collation(&l) {
+                    Collation::Binary => FilterCondition::Comparison(
+                        ct.operator.clone(),
+                        filter::Value::Constant(value),
+                    ),
+                    collation => FilterCondition::CollatedComparison {
+                        operator: ct.operator.clone(),
+                        value,
+                        collation,
+                    },
+                }
             }
             ConditionExpression::Base(ConditionBase::Literal(Literal::Null)) => {
                 FilterCondition::Comparison(
@@ -231,9 +899,47 @@ impl SqlToMirConverter {
                     filter::Value::Constant(DataType::None),
                 )
             }
-            ConditionExpression::Base(ConditionBase::LiteralList(ref ll)) => {
-                FilterCondition::In(ll.iter().map(|l| DataType::from(l.clone())).collect())
+            // evaluated against the clock whenever the filter runs, rather than once here
+            ConditionExpression::Base(ConditionBase::Literal(Literal::CurrentTimestamp)) => {
+                match ct.operator {
+                    Operator::Equal
+                    | Operator::NotEqual
+                    | Operator::Greater
+                    | Operator::GreaterOrEqual
+                    | Operator::Less
+                    | Operator::LessOrEqual => {}
+                    ref op => {
+                        return Err(MirConversionError::InvalidExpression(format!(
+                            "{} cannot be compared with CURRENT_TIMESTAMP using {}",
+                            l.name, op
+                        )))
+                    }
+                }
+                FilterCondition::TimeRelative {
+                    operator: ct.operator.clone(),
+                    offset: 0,
+                }
+            }
+            // no value (not even NULL) is a member of an empty list, so `x IN ()` must reject
+            // every row, whatever operator the parser attached to it
+            ConditionExpression::Base(ConditionBase::LiteralList(ref ll)) if ll.is_empty() => {
+                FilterCondition::In(vec![])
             }
+            // a list of only placeholders becomes a parameter instead, so this one mixes them
+            // with literals; those values would have to be filtered on at read time
+            ConditionExpression::Base(ConditionBase::LiteralList(ref ll))
+                if ll.contains(&Literal::Placeholder) =>
+            {
+                return Err(MirConversionError::UnsupportedParameters(format!(
+                    "IN list on {} mixes placeholders and literals",
+                    l.name
+                )));
+            }
+            ConditionExpression::Base(ConditionBase::LiteralList(ref ll)) => FilterCondition::In(
+                ll.iter()
+                    .map(|l| coerce(DataType::from(l.clone())))
+                    .collect(),
+            ),
             ConditionExpression::Base(ConditionBase::Field(ref f)) => {
                 // NOTE(jon): the uwnrap here is almost certainly wrong given the business
                 // that goes on further down where it appens a column in magical circumstances.
@@ -241,70 +947,167 @@ impl SqlToMirConverter {
                 let fi = columns.iter().rposition(|c| *c.name == f.name).unwrap();
                 FilterCondition::Comparison(ct.operator.clone(), filter::Value::Column(fi))
             }
-            _ => unimplemented!(),
+            ref e => {
+                return Err(MirConversionError::NestedCondition(format!(
+                    "cannot compare against {:?}",
+                    e
+                )))
+            }
         };
 
-        let absolute_column_ids: Vec<usize> = columns
-            .iter()
-            .map(|c| n.borrow().column_id_for_column(c, None))
-            .collect();
-        let max_column_id = *absolute_column_ids.iter().max().unwrap();
-        let num_columns = max(columns.len(), max_column_id + 1);
-        let mut filters = Vec::new();
+        // Records from a base node carry the columns it has since dropped, so its columns are
+        // addressed by absolute column ID. Everywhere else, a column's index is its position in
+        // `columns`, which also covers columns appended for earlier conditions that `n` itself
+        // does not have (and which we therefore cannot look up on it).
+        let on_base = match mir::query::reuse_target(n).borrow().inner {
+            MirNodeType::Base { .. } => true,
+            _ => false,
+        };
+        let column_index = |columns: &[Column], pos: usize| {
+            if on_base {
+                n.borrow().column_id_for_column(&columns[pos], None)
+            } else {
+                pos
+            }
+        };
+        let f = match f {
+            FilterCondition::Comparison(op, filter::Value::Column(fi)) => {
+                FilterCondition::Comparison(op, filter::Value::Column(column_index(columns, fi)))
+            }
+            f => f,
+        };
 
-        match columns.iter().rposition(|c| *c.name == l.name) {
+        let index = match columns.iter().rposition(|c| *c.name == l.name) {
+            Some(pos) => column_index(columns, pos),
             None => {
                 // Might occur if the column doesn't exist in the parent; e.g., for aggregations.
                 // We assume that the column is appended at the end, unless we have an aggregation,
                 // in which case it needs to go before the computed column, which is last.
-                match n.borrow().inner {
-                    MirNodeType::Aggregation { .. } => {
-                        columns.insert(columns.len() - 1, Column::from(l));
-                        filters.push((num_columns - 1, f));
+                let pos = match n.borrow().inner {
+                    MirNodeType::Aggregation { .. } => columns.len() - 1,
+                    MirNodeType::MultiAggregation { ref aggregates, .. } => {
+                        columns.len() - aggregates.len()
                     }
-                    _ => {
-                        columns.push(Column::from(l));
-                        filters.push((num_columns, f));
-                    }
-                }
+                    _ => columns.len(),
+                };
+                let index = if on_base {
+                    // past the highest absolute ID, which may exceed the number of columns
+                    (0..columns.len())
+                        .map(|pos| column_index(columns, pos) + 1)
+                        .max()
+                        .unwrap_or(0)
+                } else {
+                    pos
+                };
+                columns.insert(pos, Column::from(l));
+                index
             }
-            Some(pos) => {
-                let index = absolute_column_ids[pos];
-                filters.push((index, f));
+        };
+
+        Ok(vec![(index, f)])
+    }
+
+    /// Whether any registered leaf reads from `node`, either directly or through reuse nodes.
+    fn has_reader(&self, node: &MirNodeRef) -> bool {
+        fn resolve(n: &MirNodeRef) -> MirNodeRef {
+            match n.borrow().inner {
+                MirNodeType::Reuse { node: ref reused } => resolve(reused),
+                _ => n.clone(),
             }
         }
 
-        filters
+        let node = resolve(node);
+        self.nodes.values().any(|n| match n.borrow().inner {
+            MirNodeType::Leaf {
+                node: ref parent, ..
+            } => Rc::ptr_eq(&resolve(parent), &node),
+            _ => false,
+        })
     }
 
+    /// Follows `node` up past identity nodes, which pass their parent's rows through unchanged, to
+    /// the first node that does something. A node hung off that one instead of the identity saves
+    /// a pointless hop. The identity itself stays in place, as its reader (or other nodes that
+    /// reuse it) still read from it.
+    fn skip_identities(&self, node: MirNodeRef) -> MirNodeRef {
+        let mut node = node;
+        loop {
+            let target = mir::query::reuse_target(&node);
+            let parent = match target.borrow().inner {
+                MirNodeType::Identity => target.borrow().ancestors()[0].clone(),
+                _ => return node,
+            };
+            if parent.borrow().columns() != target.borrow().columns() {
+                return node;
+            }
+            node = parent;
+        }
+    }
+
+    /// Adds a new leaf `name` below `prior_leaf`, keyed on `params`. The leaf may additionally be
+    /// keyed on expressions computed from the parent's columns, given as `computed_params`; these
+    /// are materialized in a reprojection under the given names. If `project_columns` are given,
+    /// the leaf exposes only those (and its keys), which must all be columns of `prior_leaf`.
     pub(super) fn add_leaf_below(
         &mut self,
         prior_leaf: MirNodeRef,
         name: &str,
         params: &[Column],
+        computed_params: Vec<(String, ArithmeticExpression)>,
         project_columns: Option<Vec<Column>>,
-    ) -> MirQuery {
-        // hang off the previous logical leaf node
+        materialization: Option<MaterializationMode>,
+    ) -> Result<MirQuery, MirConversionError> {
+        // hang off the previous logical leaf node, or rather the node it merely passes through
+        let prior_leaf = self.skip_identities(prior_leaf);
         let parent_columns: Vec<Column> = prior_leaf.borrow().columns().to_vec();
+        if let Some(ref pc) = project_columns {
+            if let Some(c) = pc.iter().find(|c| !parent_columns.contains(c)) {
+                let available: Vec<String> =
+                    parent_columns.iter().map(|c| c.name.clone()).collect();
+                return Err(MirConversionError::UnknownColumn {
+                    column: c.name.clone(),
+                    available: close_matches(&c.name, &available),
+                });
+            }
+        }
+        // a node only has a single reader, so if the parent already has one, the new leaf needs a
+        // node of its own
+        let parent_has_reader = self.has_reader(&prior_leaf);
         let parent = MirNode::reuse(prior_leaf, self.schema_version);
 
-        let (reproject, columns): (bool, Vec<Column>) = match project_columns {
-            // parent is a projection already, so no need to reproject; just reuse its columns
-            None => (false, parent_columns),
+        let (reproject, emit): (bool, Vec<Column>) = match project_columns {
+            // parent is a projection already, so no need to reproject; just reuse its columns,
+            // unless we need to compute key columns
+            None => (!computed_params.is_empty(), parent_columns.clone()),
             // parent is not a projection, so we need to reproject to the columns passed to us
             Some(pc) => (true, pc.into_iter().chain(params.iter().cloned()).collect()),
         };
+        let computed_columns: Vec<Column> = computed_params
+            .iter()
+            .map(|&(ref n, _)| Column::new(None, n))
+            .collect();
+        let columns: Vec<Column> = emit
+            .iter()
+            .cloned()
+            .chain(computed_columns.iter().cloned())
+            .collect();
+        let params: Vec<Column> = params.iter().cloned().chain(computed_columns).collect();
 
-        let n = if reproject {
+        let n = if !parent_has_reader && columns == parent_columns {
+            // the parent already exposes exactly the columns we need, so the leaf can go right
+            // below it
+            parent.clone()
+        } else if reproject {
             // add a (re-)projection and then another leaf
             MirNode::new(
-                &format!("{}_reproject", name),
+                &format!("{}{}_reproject", self.name_prefix, name),
                 self.schema_version,
                 columns.clone(),
                 MirNodeType::Project {
-                    emit: columns.clone(),
+                    emit,
                     literals: vec![],
-                    arithmetic: vec![],
+                    arithmetic: computed_params,
+                    functions: vec![],
                 },
                 vec![parent.clone()],
                 vec![],
@@ -312,7 +1115,7 @@ impl SqlToMirConverter {
         } else {
             // add an identity node and then another leaf
             MirNode::new(
-                &format!("{}_id", name),
+                &format!("{}{}_id", self.name_prefix, name),
                 self.schema_version,
                 columns.clone(),
                 MirNodeType::Identity,
@@ -334,7 +1137,10 @@ impl SqlToMirConverter {
                 .collect(),
             MirNodeType::Leaf {
                 node: parent.clone(),
-                keys: Vec::from(params),
+                keys: params,
+                ranged: false,
+                open_range: None,
+                materialization,
             },
             vec![n],
             vec![],
@@ -346,11 +1152,466 @@ impl SqlToMirConverter {
             .insert((String::from(name), self.schema_version), new_leaf.clone());
 
         // wrap in a (very short) query to return
-        MirQuery {
+        Ok(MirQuery {
             name: String::from(name),
             roots: vec![parent],
             leaf: new_leaf,
+        })
+    }
+
+    /// Adds a leaf `name` that reads the existing view (or base) `base_view` sorted by `order` and
+    /// cut down to the page `page`, for views whose query has no `ORDER BY` or `LIMIT` of its own.
+    /// The page is taken within each key of the view, so the new leaf is keyed like the view's
+    /// leaf; views without a key (such as bases) are paged as a whole, through a bogokey. TopK
+    /// operators cannot skip rows yet, so `page` must not have an offset.
+    pub(super) fn add_sorted_leaf(
+        &mut self,
+        base_view: &str,
+        name: &str,
+        order: OrderClause,
+        page: LimitClause,
+    ) -> Result<MirQuery, MirConversionError> {
+        if page.offset != 0 {
+            return Err(MirConversionError::UnsupportedParameters(format!(
+                "cannot page \"{}\" at offset {}: TopK operators do not support offsets",
+                base_view, page.offset
+            )));
+        }
+        if self.current.contains_key(name) {
+            return Err(MirConversionError::UnsupportedParameters(format!(
+                "cannot add sorted view \"{}\": a view of that name already exists",
+                name
+            )));
         }
+
+        let view = match self.current.get(base_view) {
+            Some(v) => self.nodes.get(&(String::from(base_view), *v)).cloned(),
+            None => None,
+        }
+        .ok_or_else(|| {
+            MirConversionError::UnknownView(format!("No view named \"{}\"", base_view))
+        })?;
+        // sort what the view's leaf reads from, and page it within each of the leaf's keys
+        let (above, keys) = match view.borrow().inner {
+            MirNodeType::Leaf { ref keys, .. } => {
+                (view.borrow().ancestors()[0].clone(), keys.clone())
+            }
+            _ => (view.clone(), vec![]),
+        };
+        let columns: Vec<Column> = above.borrow().columns().to_vec();
+        let order = resolve_order(order, &columns)?;
+
+        let parent = MirNode::reuse(above, self.schema_version);
+        let mut over = parent.clone();
+        let keys = if keys.is_empty() {
+            // as for queries without parameters, the TopK needs a bogokey to group by
+            let bogo_project = self.make_project_node(
+                &format!("{}{}_bogokey", self.name_prefix, name),
+                parent.clone(),
+                columns.iter().collect(),
+                vec![],
+                vec![("bogokey".into(), DataType::from(0 as i32))],
+                vec![],
+                false,
+            )?;
+            over = bogo_project;
+            vec![Column::new(None, "bogokey")]
+        } else {
+            keys
+        };
+
+        let topk = self.make_topk_node(
+            &format!("{}{}_topk", self.name_prefix, name),
+            over,
+            keys.iter().collect(),
+            &Some(order),
+            &page,
+            false,
+        );
+        let leaf = MirNode::new(
+            name,
+            self.schema_version,
+            topk.borrow()
+                .columns()
+                .iter()
+                .cloned()
+                .map(|mut c| {
+                    sanitize_leaf_column(&mut c, name);
+                    c
+                })
+                .collect(),
+            MirNodeType::Leaf {
+                node: topk.clone(),
+                keys,
+                ranged: false,
+                open_range: None,
+                materialization: None,
+            },
+            vec![topk],
+            vec![],
+        );
+
+        self.current.insert(String::from(name), self.schema_version);
+        self.nodes
+            .insert((String::from(name), self.schema_version), leaf.clone());
+
+        Ok(MirQuery {
+            name: String::from(name),
+            roots: vec![parent],
+            leaf,
+        })
+    }
+
+    /// Replaces the leaf of the view `name` with one keyed on `new_params`, e.g., to key a view
+    /// that was so far only read through its bogokey on one of its columns instead. The new leaf
+    /// reads from the same node as the old one, so the view's upstream nodes are shared rather
+    /// than built again.
+    pub(super) fn rekey_leaf(
+        &mut self,
+        name: &str,
+        new_params: &[Column],
+    ) -> Result<MirQuery, MirConversionError> {
+        let old_leaf = match self.current.get(name) {
+            Some(v) => self.nodes.get(&(String::from(name), *v)).cloned(),
+            None => None,
+        }
+        .ok_or_else(|| MirConversionError::UnknownView(format!("No view named \"{}\"", name)))?;
+        let (above, materialization) = {
+            let old_leaf = old_leaf.borrow();
+            match old_leaf.inner {
+                MirNodeType::Leaf {
+                    materialization, ..
+                } => (old_leaf.ancestors()[0].clone(), materialization),
+                _ => {
+                    return Err(MirConversionError::Inconsistency(format!(
+                        "view \"{}\" does not end in a leaf",
+                        name
+                    )))
+                }
+            }
+        };
+        // a view that was re-keyed before reads through the identity node of its earlier re-key,
+        // which goes away with the earlier leaf
+        let rekey_id = format!("{}_rekey_id", name);
+        let rekeyed_before = match above.borrow().inner {
+            MirNodeType::Identity => above.borrow().name().ends_with(&rekey_id),
+            _ => false,
+        };
+        let above = if rekeyed_before {
+            let earlier = above.borrow().ancestors()[0].clone();
+            mir::query::reuse_target(&earlier)
+        } else {
+            above
+        };
+        let columns: Vec<Column> = above.borrow().columns().to_vec();
+        // parameters given without a table refer to the view's column of that name
+        let new_params: Vec<Column> = new_params
+            .iter()
+            .map(|p| match p.table {
+                None => columns
+                    .iter()
+                    .find(|c| c.name == p.name)
+                    .cloned()
+                    .unwrap_or_else(|| p.clone()),
+                Some(_) => p.clone(),
+            })
+            .collect();
+        for p in &new_params {
+            if !columns.contains(p) {
+                let available: Vec<String> = columns.iter().map(|c| c.name.clone()).collect();
+                return Err(MirConversionError::UnknownColumn {
+                    column: p.name.clone(),
+                    available: close_matches(&p.name, &available),
+                });
+            }
+        }
+
+        // the old leaf's reader stays in place until the new one is ready, so the new reader
+        // hangs off an identity node of its own
+        let parent = MirNode::reuse(above, self.schema_version);
+        let id = MirNode::new(
+            &format!("{}{}", self.name_prefix, rekey_id),
+            self.schema_version,
+            columns.clone(),
+            MirNodeType::Identity,
+            vec![parent.clone()],
+            vec![],
+        );
+        let leaf = MirNode::new(
+            name,
+            self.schema_version,
+            columns
+                .into_iter()
+                .map(|mut c| {
+                    sanitize_leaf_column(&mut c, name);
+                    c
+                })
+                .collect(),
+            MirNodeType::Leaf {
+                node: id.clone(),
+                keys: new_params,
+                ranged: false,
+                open_range: None,
+                materialization,
+            },
+            vec![id],
+            vec![],
+        );
+
+        self.current.insert(String::from(name), self.schema_version);
+        self.nodes
+            .insert((String::from(name), self.schema_version), leaf.clone());
+
+        Ok(MirQuery {
+            name: String::from(name),
+            roots: vec![parent],
+            leaf,
+        })
+    }
+
+    /// Replaces the `ORDER BY` and `LIMIT` of the view `view_name` with `new_order` and
+    /// `new_limit`. Only the view's TopK and the projections and leaf above it are built again;
+    /// the new TopK reads from the same node as the old one, so everything upstream of it is
+    /// shared rather than lowered anew. TopK operators cannot skip rows yet, so `new_limit` must
+    /// not have an offset.
+    pub(super) fn update_topk(
+        &mut self,
+        view_name: &str,
+        new_order: Option<OrderClause>,
+        new_limit: LimitClause,
+    ) -> Result<MirQuery, MirConversionError> {
+        if new_limit.offset != 0 {
+            return Err(MirConversionError::UnsupportedParameters(format!(
+                "cannot page \"{}\" at offset {}: TopK operators do not support offsets",
+                view_name, new_limit.offset
+            )));
+        }
+
+        let old_leaf = match self.current.get(view_name) {
+            Some(v) => self.nodes.get(&(String::from(view_name), *v)).cloned(),
+            None => None,
+        }
+        .ok_or_else(|| {
+            MirConversionError::UnknownView(format!("No view named \"{}\"", view_name))
+        })?;
+        match old_leaf.borrow().inner {
+            MirNodeType::Leaf { .. } => (),
+            _ => {
+                return Err(MirConversionError::Inconsistency(format!(
+                    "view \"{}\" does not end in a leaf",
+                    view_name
+                )))
+            }
+        }
+
+        // walk down from the leaf through the projections that sit on top of the TopK
+        let mut projections = Vec::new();
+        let mut n = old_leaf.borrow().ancestors()[0].clone();
+        let old_topk = loop {
+            let next = {
+                let node = n.borrow();
+                match node.inner {
+                    MirNodeType::TopK { .. } => None,
+                    MirNodeType::Project { .. } if node.ancestors().len() == 1 => {
+                        Some(node.ancestors()[0].clone())
+                    }
+                    _ => {
+                        return Err(MirConversionError::UnsupportedParameters(format!(
+                            "view \"{}\" has no ORDER BY or LIMIT to update",
+                            view_name
+                        )))
+                    }
+                }
+            };
+            match next {
+                Some(next) => {
+                    projections.push(n);
+                    n = next;
+                }
+                None => break n,
+            }
+        };
+
+        let (group_by, parameterized_k) = match old_topk.borrow().inner {
+            MirNodeType::TopK {
+                ref group_by,
+                parameterized_k,
+                ..
+            } => (group_by.clone(), parameterized_k),
+            _ => unreachable!(),
+        };
+        // readers that take the limit themselves rank the rows of each key by the ORDER BY
+        if parameterized_k && new_order.is_none() {
+            return Err(MirConversionError::UnsupportedParameters(format!(
+                "view \"{}\" takes its limit at read time, which needs an ORDER BY",
+                view_name
+            )));
+        }
+        let above = old_topk.borrow().ancestors()[0].clone();
+        let columns: Vec<Column> = above.borrow().columns().to_vec();
+        let new_order = match new_order {
+            Some(o) => Some(resolve_order(o, &columns)?),
+            None => None,
+        };
+
+        let parent = MirNode::reuse(above, self.schema_version);
+        let mut over = self.make_topk_node(
+            &format!("{}_retopk", view_name),
+            parent.clone(),
+            group_by.iter().collect(),
+            &new_order,
+            &new_limit,
+            parameterized_k,
+        );
+        for (i, p) in projections.iter().rev().enumerate() {
+            let p = p.borrow();
+            let inner = match p.inner {
+                MirNodeType::Project {
+                    ref emit,
+                    ref arithmetic,
+                    ref literals,
+                    ref functions,
+                } => MirNodeType::Project {
+                    emit: emit.clone(),
+                    arithmetic: arithmetic.clone(),
+                    literals: literals.clone(),
+                    functions: functions.clone(),
+                },
+                _ => unreachable!(),
+            };
+            over = MirNode::new(
+                &format!("{}_retopk_p{}", view_name, i),
+                self.schema_version,
+                p.columns().to_vec(),
+                inner,
+                vec![over],
+                vec![],
+            );
+        }
+
+        let leaf = {
+            let old_leaf = old_leaf.borrow();
+            let inner = match old_leaf.inner {
+                MirNodeType::Leaf {
+                    ref keys,
+                    ranged,
+                    ref open_range,
+                    materialization,
+                    ..
+                } => MirNodeType::Leaf {
+                    node: over.clone(),
+                    keys: keys.clone(),
+                    ranged,
+                    open_range: open_range.clone(),
+                    materialization,
+                },
+                _ => unreachable!(),
+            };
+            MirNode::new(
+                view_name,
+                self.schema_version,
+                old_leaf.columns().to_vec(),
+                inner,
+                vec![over],
+                vec![],
+            )
+        };
+
+        self.current
+            .insert(String::from(view_name), self.schema_version);
+        self.nodes
+            .insert((String::from(view_name), self.schema_version), leaf.clone());
+
+        Ok(MirQuery {
+            name: String::from(view_name),
+            roots: vec![parent],
+            leaf,
+        })
+    }
+
+    /// Adds a leaf over base table `base_name` that is keyed on `key_columns` instead of the
+    /// base's primary key, so that the base can be looked up by these columns without a query.
+    /// The leaf is named `<base>_by_<columns>`.
+    pub(super) fn add_secondary_index(
+        &mut self,
+        base_name: &str,
+        key_columns: &[Column],
+    ) -> Result<MirQuery, MirConversionError> {
+        let base = self.get_view(base_name)?;
+        let columns: Vec<Column> = base.borrow().columns().to_vec();
+
+        let keys = key_columns
+            .iter()
+            .map(|c| {
+                let mut c = c.clone();
+                if c.table.is_none() {
+                    c.table = Some(String::from(base_name));
+                }
+                if columns.contains(&c) {
+                    Ok(c)
+                } else {
+                    Err(MirConversionError::InvalidExpression(format!(
+                        "Cannot index base \"{}\" on column \"{}\", which it does not have",
+                        base_name, c.name
+                    )))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let name = format!(
+            "{}_by_{}",
+            base_name,
+            keys.iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+                .join("_")
+        );
+        if self.current.contains_key(&name) {
+            return Err(MirConversionError::UnsupportedParameters(format!(
+                "base \"{}\" already has an index named \"{}\"",
+                base_name, name
+            )));
+        }
+
+        // as for other added leaves, the reader hangs off an identity node below the base
+        let parent = MirNode::reuse(base, self.schema_version);
+        let id = MirNode::new(
+            &format!("{}{}_id", self.name_prefix, name),
+            self.schema_version,
+            columns.clone(),
+            MirNodeType::Identity,
+            vec![parent.clone()],
+            vec![],
+        );
+        let leaf = MirNode::new(
+            &name,
+            self.schema_version,
+            columns
+                .into_iter()
+                .map(|mut c| {
+                    sanitize_leaf_column(&mut c, &name);
+                    c
+                })
+                .collect(),
+            MirNodeType::Leaf {
+                node: id.clone(),
+                keys,
+                ranged: false,
+                open_range: None,
+                materialization: None,
+            },
+            vec![id],
+            vec![],
+        );
+
+        self.current.insert(name.clone(), self.schema_version);
+        self.nodes
+            .insert((name.clone(), self.schema_version), leaf.clone());
+
+        Ok(MirQuery {
+            name,
+            roots: vec![parent],
+            leaf,
+        })
     }
 
     pub(super) fn compound_query_to_mir(
@@ -361,18 +1622,27 @@ impl SqlToMirConverter {
         order: &Option<OrderClause>,
         limit: &Option<LimitClause>,
         has_leaf: bool,
-    ) -> MirQuery {
+    ) -> Result<MirQuery, MirConversionError> {
         let union_name = if !has_leaf && limit.is_none() {
             String::from(name)
         } else {
-            format!("{}_union", name)
+            format!("{}{}_union", self.name_prefix, name)
         };
         let mut final_node = match op {
-            CompoundSelectOperator::Union => self.make_union_node(
-                &union_name,
-                &sqs.iter().map(|mq| mq.leaf.clone()).collect::<Vec<_>>()[..],
-            ),
-            _ => unimplemented!(),
+            CompoundSelectOperator::Union => {
+                if self.padded_unions.contains(name) {
+                    let ancestors: Vec<_> = sqs.iter().map(|mq| mq.leaf.clone()).collect();
+                    self.make_padded_union_node(&union_name, &ancestors)?
+                } else {
+                    self.make_positional_union_node(&union_name, &sqs)?
+                }
+            }
+            _ => {
+                return Err(MirConversionError::UnsupportedCompoundOp(format!(
+                    "{:?}",
+                    op
+                )))
+            }
         };
         let node_id = (union_name, self.schema_version);
         self.nodes
@@ -392,17 +1662,22 @@ impl SqlToMirConverter {
             .collect();
 
         if limit.is_some() {
+            let order = self.resolve_union_order(name, &columns, order)?;
             let (topk_name, topk_columns) = if !has_leaf {
                 (String::from(name), sanitized_columns.iter().collect())
             } else {
-                (format!("{}_topk", name), columns.iter().collect())
+                (
+                    format!("{}{}_topk", self.name_prefix, name),
+                    columns.iter().collect(),
+                )
             };
             let topk_node = self.make_topk_node(
                 &topk_name,
                 final_node,
                 topk_columns,
-                order,
+                &order,
                 limit.as_ref().unwrap(),
+                false,
             );
             let node_id = (topk_name, self.schema_version);
             self.nodes
@@ -419,6 +1694,9 @@ impl SqlToMirConverter {
                 MirNodeType::Leaf {
                     node: final_node.clone(),
                     keys: vec![],
+                    ranged: false,
+                    open_range: None,
+                    materialization: None,
                 },
                 vec![final_node.clone()],
                 vec![],
@@ -428,31 +1706,217 @@ impl SqlToMirConverter {
             final_node
         };
 
-        self.current
-            .insert(String::from(leaf_node.borrow().name()), self.schema_version);
-        let node_id = (String::from(name), self.schema_version);
-        self.nodes
-            .entry(node_id)
-            .or_insert_with(|| leaf_node.clone());
+        self.current
+            .insert(String::from(leaf_node.borrow().name()), self.schema_version);
+        let node_id = (String::from(name), self.schema_version);
+        self.nodes
+            .entry(node_id)
+            .or_insert_with(|| leaf_node.clone());
+
+        Ok(MirQuery {
+            name: String::from(name),
+            roots: sqs.iter().fold(Vec::new(), |mut acc, mq| {
+                acc.extend(mq.roots.iter().cloned());
+                acc
+            }),
+            leaf: leaf_node,
+        })
+    }
+
+    /// Resolve the columns of a compound query's ORDER BY clause against the output columns of
+    /// its union. The union carries the first branch's column names, and every branch's columns
+    /// are renamed to match, so an order column is matched by name alone, whichever branch's
+    /// table it was qualified with.
+    fn resolve_union_order(
+        &self,
+        name: &str,
+        union_columns: &[Column],
+        order: &Option<OrderClause>,
+    ) -> Result<Option<OrderClause>, MirConversionError> {
+        let order = match *order {
+            Some(ref o) => o,
+            None => return Ok(None),
+        };
+        let mut columns = Vec::with_capacity(order.columns.len());
+        for (c, ot) in &order.columns {
+            let uc = union_columns
+                .iter()
+                .find(|uc| uc.name == c.name)
+                .ok_or_else(|| {
+                    MirConversionError::InvalidExpression(format!(
+                        "Query \"{}\" is ordered by column \"{}\", which is not in the output \
+                         of its union (columns: {})",
+                        name,
+                        c.name,
+                        union_columns
+                            .iter()
+                            .map(|uc| uc.name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ))
+                })?;
+            let mut c = c.clone();
+            c.table = uc.table.clone();
+            columns.push((c, ot.clone()));
+        }
+        Ok(Some(OrderClause { columns }))
+    }
+
+    // pub(super) viz for tests
+    pub(super) fn get_flow_node_address(&self, name: &str, version: usize) -> Option<NodeIndex> {
+        match self.nodes.get(&(name.to_string(), version)) {
+            None => None,
+            Some(ref node) => match node.borrow().flow_node {
+                None => None,
+                Some(ref flow_node) => Some(flow_node.address()),
+            },
+        }
+    }
+
+    /// Returns the names of all base tables that the view `name` reads from, or an empty set if
+    /// there is no such view.
+    pub(super) fn base_dependencies(&self, name: &str) -> HashSet<String> {
+        fn walk(n: &MirNodeRef, bases: &mut HashSet<String>) {
+            let n = n.borrow();
+            match n.inner {
+                MirNodeType::Base { .. } => {
+                    bases.insert(n.name.clone());
+                }
+                MirNodeType::Reuse { ref node } => walk(node, bases),
+                _ => (),
+            }
+            for a in n.ancestors() {
+                walk(a, bases);
+            }
+        }
+
+        let mut bases = HashSet::new();
+        if let Some(v) = self.current.get(name) {
+            if let Some(leaf) = self.nodes.get(&(String::from(name), *v)) {
+                walk(leaf, &mut bases);
+            }
+        }
+        bases
+    }
+
+    /// Returns the nodes of the registered query `query_name` whose type satisfies `pred`, in
+    /// the order a walk up from the query's leaf first visits them. Reuse nodes are looked
+    /// through, so the predicate sees (and the result contains) the reused nodes instead. Returns
+    /// nothing if there is no such query.
+    pub(super) fn find_nodes(
+        &self,
+        query_name: &str,
+        pred: impl Fn(&MirNodeType) -> bool,
+    ) -> Vec<MirNodeRef> {
+        let leaf = match self.current.get(query_name) {
+            Some(v) => match self.nodes.get(&(String::from(query_name), *v)) {
+                Some(leaf) => leaf.clone(),
+                None => return vec![],
+            },
+            None => return vec![],
+        };
+
+        let mut found = Vec::new();
+        let mut visited: Vec<MirNodeRef> = Vec::new();
+        let mut stack = vec![leaf];
+        while let Some(mut n) = stack.pop() {
+            loop {
+                let reused = match n.borrow().inner {
+                    MirNodeType::Reuse { ref node } => node.clone(),
+                    _ => break,
+                };
+                n = reused;
+            }
+            // the same node may be reached along several paths, e.g. when joining a view to itself
+            if visited.iter().any(|v| Rc::ptr_eq(v, &n)) {
+                continue;
+            }
+            visited.push(n.clone());
+            if pred(&n.borrow().inner) {
+                found.push(n.clone());
+            }
+            stack.extend(n.borrow().ancestors().iter().rev().cloned());
+        }
+        found
+    }
+
+    /// Renders the view `name` as an indented tree, one line per node, starting from the leaf and
+    /// going up to the roots. Each line gives the node's operator and its key attributes (keys,
+    /// join and group columns, filter conditions, limit and offset). Nodes shared with other
+    /// queries are marked "(reused)", and nodes reached again along another path are not
+    /// expanded twice. Returns `None` if there is no such view.
+    pub(super) fn explain(&self, name: &str) -> Option<String> {
+        fn explain_node(
+            n: &MirNodeRef,
+            depth: usize,
+            reused: bool,
+            visited: &mut Vec<MirNodeRef>,
+            out: &mut String,
+        ) {
+            let target = match n.borrow().inner {
+                MirNodeType::Reuse { ref node } => Some(node.clone()),
+                _ => None,
+            };
+            if let Some(target) = target {
+                return explain_node(&target, depth, true, visited, out);
+            }
 
-        MirQuery {
-            name: String::from(name),
-            roots: sqs.iter().fold(Vec::new(), |mut acc, mq| {
-                acc.extend(mq.roots.iter().cloned());
-                acc
-            }),
-            leaf: leaf_node,
+            let nb = n.borrow();
+            out.push_str(&"  ".repeat(depth));
+            out.push_str(&format!(
+                "{} {}: {:?}",
+                nb.inner.operator_name(),
+                nb.versioned_name(),
+                nb.inner
+            ));
+            if let MirNodeType::TopK { offset, .. } = nb.inner {
+                if offset > 0 {
+                    out.push_str(&format!(" offset {}", offset));
+                }
+            }
+            if reused {
+                out.push_str(" (reused)");
+            }
+            if visited.iter().any(|v| Rc::ptr_eq(v, n)) {
+                out.push_str(" (see above)\n");
+                return;
+            }
+            out.push('\n');
+            visited.push(n.clone());
+            for a in nb.ancestors() {
+                explain_node(a, depth + 1, reused, visited, out);
+            }
         }
+
+        let leaf = self
+            .nodes
+            .get(&(String::from(name), *self.current.get(name)?))?;
+        let mut out = String::new();
+        explain_node(leaf, 0, false, &mut Vec::new(), &mut out);
+        Some(out)
     }
 
-    // pub(super) viz for tests
-    pub(super) fn get_flow_node_address(&self, name: &str, version: usize) -> Option<NodeIndex> {
-        match self.nodes.get(&(name.to_string(), version)) {
-            None => None,
-            Some(ref node) => match node.borrow().flow_node {
-                None => None,
-                Some(ref flow_node) => Some(flow_node.address()),
-            },
+    /// Returns the columns that the view `name` exposes, in order, as clients see them: named
+    /// after their aliases and belonging to the view rather than to the relations they come
+    /// from. Views without parameters also expose the `bogokey` they are keyed on. Returns
+    /// `None` if there is no such view.
+    pub(super) fn view_schema(&self, name: &str) -> Option<Vec<Column>> {
+        let leaf = self
+            .nodes
+            .get(&(String::from(name), *self.current.get(name)?))?;
+        let leaf = leaf.borrow();
+        match leaf.inner {
+            MirNodeType::Leaf { .. } => Some(
+                leaf.columns()
+                    .iter()
+                    .cloned()
+                    .map(|mut c| {
+                        sanitize_leaf_column(&mut c, name);
+                        c
+                    })
+                    .collect(),
+            ),
+            _ => None,
         }
     }
 
@@ -487,13 +1951,20 @@ impl SqlToMirConverter {
             .current
             .remove(name)
             .unwrap_or_else(|| panic!("no query named \"{}\"?", name));
+        self.queries.remove(name);
 
         let nodeid = (name.to_owned(), v);
         let leaf_mn = self.nodes.remove(&nodeid).unwrap();
 
         assert_eq!(leaf_mn.borrow().name, mq.leaf.borrow().name);
 
+        // join and grouped nodes may be shared with other queries, so we must not unregister any
+        // node that a remaining query still reaches
+        let in_use = self.nodes_in_use();
+
         // traverse the MIR query backwards, removing any nodes that we still have registered.
+        // Shared nodes that this query reuses are unregistered too once no other query needs
+        // them any more, as the query that created them may already be gone.
         let mut q = VecDeque::new();
         q.push_back(leaf_mn);
 
@@ -503,12 +1974,41 @@ impl SqlToMirConverter {
             q.extend(n.ancestors.clone());
             // node may not be registered, so don't bother checking return
             match n.inner {
-                MirNodeType::Reuse { .. } | MirNodeType::Base { .. } => (),
+                MirNodeType::Reuse { ref node } => q.push_back(node.clone()),
+                MirNodeType::Base { .. } => (),
+                _ => {
+                    let node_id = (n.name.to_owned(), n.from_version);
+                    if !in_use.contains(&node_id) {
+                        self.nodes.remove(&node_id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the name and version of every node that the leaves of the current queries reach,
+    /// looking through reuse.
+    fn nodes_in_use(&self) -> HashSet<(String, usize)> {
+        let mut in_use = HashSet::new();
+        let mut q: Vec<MirNodeRef> = self
+            .current
+            .iter()
+            .filter_map(|(name, v)| self.nodes.get(&(name.clone(), *v)).cloned())
+            .collect();
+
+        while let Some(mnr) = q.pop() {
+            let n = mnr.borrow();
+            match n.inner {
+                // a reuse node carries the name of the node it reuses
+                MirNodeType::Reuse { ref node } => q.push(node.clone()),
                 _ => {
-                    self.nodes.remove(&(n.name.to_owned(), v));
+                    if in_use.insert((n.name.clone(), n.from_version)) {
+                        q.extend(n.ancestors.iter().cloned());
+                    }
                 }
             }
         }
+        in_use
     }
 
     pub(super) fn remove_base(&mut self, name: &str, mq: &MirQuery) {
@@ -522,6 +2022,31 @@ impl SqlToMirConverter {
         }
     }
 
+    /// Estimates how many dataflow nodes lowering `sq` in the global universe would add, without
+    /// registering anything with this converter. Views and bases that the query merely reuses
+    /// are not counted. Fails like lowering the query would.
+    pub(super) fn estimate_node_count(
+        &self,
+        sq: &SelectStatement,
+        qg: &QueryGraph,
+        has_leaf: bool,
+    ) -> Result<usize, MirConversionError> {
+        // lowering only attaches new nodes below fresh reuse wrappers of existing ones, so
+        // running it on a copy of the converter leaves our own state untouched. The copy does
+        // not run the post-lowering hook, since nothing it produces is ever installed.
+        let mut dry_run = self.clone();
+        dry_run.post_lowering_hook = None;
+        let (_, nodes, _, _) = dry_run.make_nodes_for_selection(
+            "estimate",
+            sq,
+            qg,
+            has_leaf,
+            ("global".into(), None),
+            None,
+        )?;
+        Ok(nodes.iter().filter(|n| !n.borrow().is_reused()).count())
+    }
+
     pub(super) fn named_query_to_mir(
         &mut self,
         name: &str,
@@ -529,6 +2054,7 @@ impl SqlToMirConverter {
         qg: &QueryGraph,
         has_leaf: bool,
         universe: UniverseId,
+        materialization: Option<MaterializationMode>,
     ) -> Result<
         (
             bool,
@@ -536,10 +2062,14 @@ impl SqlToMirConverter {
             Option<HashMap<(String, Option<String>), String>>,
             String,
         ),
-        String,
+        MirConversionError,
     > {
         let (sec, nodes, table_mapping, base_name) =
-            self.make_nodes_for_selection(&name, sq, qg, has_leaf, universe)?;
+            self.make_nodes_for_selection(&name, sq, qg, has_leaf, universe, materialization)?;
+        self.queries.insert(
+            String::from(name),
+            (sq.clone(), qg.clone(), materialization),
+        );
         let mut roots = Vec::new();
         let mut leaves = Vec::new();
         for mn in nodes.into_iter() {
@@ -580,17 +2110,138 @@ impl SqlToMirConverter {
         ))
     }
 
+    /// Lowers the previously converted query `base_query_name` again for `universe`, reusing its
+    /// parsed statement and query graph and applying the security policies that `universe` was
+    /// given in `set_universe`. The new query is named like the recipe names per-universe queries.
+    /// Returns `None` if the query or universe is unknown, if the query already exists in the
+    /// universe, or if it cannot be converted in the universe.
+    pub(super) fn reinstantiate_for_universe(
+        &mut self,
+        base_query_name: &str,
+        universe: UniverseId,
+    ) -> Option<MirQuery> {
+        let (sq, qg, materialization) = self.queries.get(base_query_name)?.clone();
+        let policies = self.universes.get(&universe)?.clone();
+
+        let (ref id, ref group) = universe;
+        let (name, has_leaf) = match *group {
+            Some(ref g) => (format!("{}_{}{}", base_query_name, g, id), false),
+            None => (format!("{}_u{}", base_query_name, id), true),
+        };
+        if self.current.contains_key(&name) {
+            return None;
+        }
+
+        let prior = mem::replace(&mut self.universe, policies);
+        let res =
+            self.named_query_to_mir(&name, &sq, &qg, has_leaf, universe.clone(), materialization);
+        self.universe = prior;
+
+        match res {
+            Ok((true, mq, Some(table_mapping), base_name)) => {
+                Some(mq.make_universe_naming_consistent(&table_mapping, base_name))
+            }
+            Ok((_, mq, _, _)) => Some(mq),
+            Err(e) => {
+                warn!(
+                    self.log,
+                    "failed to reinstantiate {} for universe {:?}: {}",
+                    base_query_name,
+                    universe,
+                    e
+                );
+                None
+            }
+        }
+    }
+
     pub(super) fn upgrade_schema(&mut self, new_version: usize) {
         assert!(new_version > self.schema_version);
         self.schema_version = new_version;
     }
 
+    /// Captures the base schema history of this converter, so that it can be restored with
+    /// `import_state` after a restart.
+    pub(super) fn export_state(&self) -> SerializedConverterState {
+        SerializedConverterState {
+            base_schemas: self.base_schemas.clone(),
+            current: self.current.clone(),
+            schema_version: self.schema_version,
+        }
+    }
+
+    /// Restores base schema history previously captured with `export_state`, replacing the
+    /// converter's own.
+    pub(super) fn import_state(&mut self, state: SerializedConverterState) {
+        self.base_schemas = state.base_schemas;
+        self.current = state.current;
+        self.schema_version = state.schema_version;
+    }
+
     fn make_base_node(
         &mut self,
         name: &str,
         cols: &[ColumnSpecification],
         keys: Option<&Vec<TableKey>>,
     ) -> MirNodeRef {
+        // primary keys can either be specified directly (at the end of CREATE TABLE), or inline
+        // with the definition of a field (i.e., as a ColumnConstraint).
+        // We assume here that an earlier rewrite pass has coalesced all primary key definitions in
+        // the TableKey structure passed in via `keys`.
+        let primary_keys = match keys {
+            None => vec![],
+            Some(keys) => keys
+                .iter()
+                .filter_map(|k| match *k {
+                    ref k @ TableKey::PrimaryKey(..) => Some(k),
+                    _ => None,
+                })
+                .collect(),
+        };
+        assert!(primary_keys.len() <= 1);
+
+        // unique keys are secondary keys that the base node should maintain lookup indices for
+        let unique_keys: Vec<Vec<Column>> = match keys {
+            None => vec![],
+            Some(keys) => keys
+                .iter()
+                .filter_map(|k| match *k {
+                    TableKey::UniqueKey(_, ref key_cols) => {
+                        Some(key_cols.iter().map(Column::from).collect())
+                    }
+                    _ => None,
+                })
+                .collect(),
+        };
+
+        // the keys that a new base would be keyed on; an existing base can only be reused (or
+        // adapted) if it has the same ones, since lookups on it go through its keys
+        let key_columns: Vec<Column> = match primary_keys.first() {
+            Some(&&TableKey::PrimaryKey(ref key_cols)) => {
+                key_cols.iter().map(Column::from).collect()
+            }
+            Some(_) => unreachable!(),
+            None if self.implicit_rowid => vec![Column::from(&implicit_rowid_spec(name).column)],
+            None => vec![],
+        };
+        // key columns are compared by name, as they may or may not be qualified with the table
+        let names = |cols: &[Column]| cols.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+        let same_keys = |n: &MirNodeRef| match mir::query::reuse_target(n).borrow().inner {
+            MirNodeType::Base {
+                keys: ref existing_keys,
+                unique_keys: ref existing_unique_keys,
+                ..
+            } => {
+                names(existing_keys) == names(&key_columns)
+                    && existing_unique_keys.len() == unique_keys.len()
+                    && existing_unique_keys
+                        .iter()
+                        .zip(&unique_keys)
+                        .all(|(a, b)| names(a) == names(b))
+            }
+            _ => false,
+        };
+
         // have we seen a base of this name before?
         if self.base_schemas.contains_key(name) {
             let mut existing_schemas: Vec<(usize, Vec<ColumnSpecification>)> =
@@ -601,8 +2252,19 @@ impl SqlToMirConverter {
 
             #[warn(clippy::never_loop)]
             for (existing_sv, ref schema) in existing_schemas {
-                // TODO(malte): check the keys too
                 if &schema[..] == cols {
+                    let existing_node = self.nodes[&(String::from(name), existing_sv)].clone();
+                    if !same_keys(&existing_node) {
+                        // the same columns, but keyed differently, so we need a new base
+                        info!(
+                            self.log,
+                            "base table for {} already exists with identical schema in version \
+                             {}, but has different keys!",
+                            name,
+                            existing_sv
+                        );
+                        break;
+                    }
                     // exact match, so reuse the existing base node
                     info!(
                         self.log,
@@ -611,7 +2273,6 @@ impl SqlToMirConverter {
                         name,
                         existing_sv
                     );
-                    let existing_node = self.nodes[&(String::from(name), existing_sv)].clone();
                     return MirNode::reuse(existing_node, self.schema_version);
                 } else {
                     // match, but schema is different, so we'll need to either:
@@ -687,11 +2348,25 @@ impl SqlToMirConverter {
                                 - columns_removed.len()
                         );
 
-                        // remember the schema for this version
+                        // the adapted base keeps the existing base's keys, which only works if the
+                        // new schema declares the same ones
+                        let adapted =
+                            MirNode::adapt_base(existing_node, columns_added, columns_removed);
+                        if !same_keys(&adapted) {
+                            info!(self.log, "base table has changed keys");
+                            break;
+                        }
+
+                        // remember the schema for this version; a synthesized rowid column is
+                        // not part of the declared schema
+                        let rowid = implicit_rowid_spec(name);
                         let base_schemas = self.base_schemas.entry(String::from(name)).or_default();
-                        base_schemas.push((self.schema_version, columns.clone()));
+                        base_schemas.push((
+                            self.schema_version,
+                            columns.iter().filter(|cs| **cs != rowid).cloned().collect(),
+                        ));
 
-                        return MirNode::adapt_base(existing_node, columns_added, columns_removed);
+                        return adapted;
                     } else {
                         info!(self.log, "base table has complex schema change");
                         break;
@@ -705,26 +2380,13 @@ impl SqlToMirConverter {
             .iter()
             .all(|c| c.column.table == Some(String::from(name))));
 
-        // primary keys can either be specified directly (at the end of CREATE TABLE), or inline
-        // with the definition of a field (i.e., as a ColumnConstraint).
-        // We assume here that an earlier rewrite pass has coalesced all primary key definitions in
-        // the TableKey structure passed in via `keys`.
-        let primary_keys = match keys {
-            None => vec![],
-            Some(keys) => keys
-                .iter()
-                .filter_map(|k| match *k {
-                    ref k @ TableKey::PrimaryKey(..) => Some(k),
-                    _ => None,
-                })
-                .collect(),
-        };
-        assert!(primary_keys.len() <= 1);
-
         // remember the schema for this version
         let base_schemas = self.base_schemas.entry(String::from(name)).or_default();
         base_schemas.push((self.schema_version, cols.to_vec()));
 
+        // the base rejects writes that would put NULL into these columns
+        let not_null = not_null_columns(cols);
+
         // make node
         if !primary_keys.is_empty() {
             match **primary_keys.iter().next().unwrap() {
@@ -746,6 +2408,9 @@ impl SqlToMirConverter {
                         MirNodeType::Base {
                             column_specs: cols.iter().map(|cs| (cs.clone(), None)).collect(),
                             keys: key_cols.iter().map(Column::from).collect(),
+                            unique_keys,
+                            implicit_rowid: false,
+                            not_null,
                             adapted_over: None,
                         },
                         vec![],
@@ -754,6 +2419,29 @@ impl SqlToMirConverter {
                 }
                 _ => unreachable!(),
             }
+        } else if self.implicit_rowid {
+            // without a primary key, the base would not be keyed at all; instead, we add a rowid
+            // column at the end of the schema that the base fills in for inserted rows
+            let rowid = implicit_rowid_spec(name);
+            debug!(self.log, "Assigning implicit rowid key for base {}", name);
+            let cols: Vec<ColumnSpecification> =
+                cols.iter().cloned().chain(Some(rowid.clone())).collect();
+            let not_null = not_null_columns(&cols);
+            MirNode::new(
+                name,
+                self.schema_version,
+                cols.iter().map(|cs| Column::from(&cs.column)).collect(),
+                MirNodeType::Base {
+                    column_specs: cols.into_iter().map(|cs| (cs, None)).collect(),
+                    keys: vec![Column::from(&rowid.column)],
+                    unique_keys,
+                    implicit_rowid: true,
+                    not_null,
+                    adapted_over: None,
+                },
+                vec![],
+                vec![],
+            )
         } else {
             MirNode::new(
                 name,
@@ -762,6 +2450,9 @@ impl SqlToMirConverter {
                 MirNodeType::Base {
                     column_specs: cols.iter().map(|cs| (cs.clone(), None)).collect(),
                     keys: vec![],
+                    unique_keys,
+                    implicit_rowid: false,
+                    not_null,
                     adapted_over: None,
                 },
                 vec![],
@@ -782,13 +2473,17 @@ impl SqlToMirConverter {
         // which can go wrong if there are multiple columns of the same name in the inputs to the
         // union. Unfortunately, we have to do it by name here because the nested queries in
         // compound SELECT rewrite the table name on their output columns.
-        let mut selected_cols = HashSet::new();
+        // The union's columns come in the order of the first ancestor's columns, and all
+        // ancestors emit their columns in that order.
+        let mut selected_cols: Vec<String> = Vec::new();
         for c in ucols {
             if ancestors
                 .iter()
                 .all(|a| a.borrow().columns().iter().any(|ac| *ac.name == c.name))
             {
-                selected_cols.insert(c.name.clone());
+                if !selected_cols.contains(&c.name) {
+                    selected_cols.push(c.name.clone());
+                }
             } else {
                 panic!(
                     "column with name '{}' not found all union ancestors: all ancestors' \
@@ -804,24 +2499,21 @@ impl SqlToMirConverter {
         );
 
         for ancestor in ancestors.iter() {
-            let mut acols: Vec<Column> = Vec::new();
-            for ac in ancestor.borrow().columns() {
-                if selected_cols.contains(&ac.name)
-                    && acols.iter().find(|c| ac.name == *c.name).is_none()
-                {
-                    acols.push(ac.clone());
-                }
-            }
-            emit.push(acols.clone());
+            let ancestor = ancestor.borrow();
+            let acols: Vec<Column> = selected_cols
+                .iter()
+                .map(|n| {
+                    ancestor
+                        .columns()
+                        .iter()
+                        .find(|ac| ac.name == *n)
+                        .unwrap()
+                        .clone()
+                })
+                .collect();
+            emit.push(acols);
         }
 
-        assert!(
-            emit.iter().all(|e| e.len() == selected_cols.len()),
-            "all ancestors columns must have the same size, but got emit: {:?}, selected: {:?}",
-            emit,
-            selected_cols
-        );
-
         MirNode::new(
             name,
             self.schema_version,
@@ -832,6 +2524,146 @@ impl SqlToMirConverter {
         )
     }
 
+    /// Makes a union over the leaves of the compound query branches `branches` that lines up their
+    /// columns by position in each branch's SELECT list, as SQL does, rather than by name: a
+    /// branch that joins or aggregates may well name its columns differently from the others. The
+    /// union's columns are those of the first branch.
+    fn make_positional_union_node(
+        &self,
+        name: &str,
+        branches: &[&MirQuery],
+    ) -> Result<MirNodeRef, MirConversionError> {
+        assert!(branches.len() > 1, "union must have more than 1 ancestors");
+
+        let emit: Vec<Vec<Column>> = branches
+            .iter()
+            .map(|mq| self.select_list_columns(mq))
+            .collect();
+        for (mq, e) in branches.iter().zip(&emit).skip(1) {
+            if e.len() != emit[0].len() {
+                return Err(MirConversionError::UnsupportedCompoundOp(format!(
+                    "UNION branch \"{}\" selects {} columns, but \"{}\" selects {}",
+                    mq.name,
+                    e.len(),
+                    branches[0].name,
+                    emit[0].len()
+                )));
+            }
+        }
+
+        Ok(MirNode::new(
+            name,
+            self.schema_version,
+            emit[0].clone(),
+            MirNodeType::Union { emit },
+            branches.iter().map(|mq| mq.leaf.clone()).collect(),
+            vec![],
+        ))
+    }
+
+    /// The columns of the leaf of `mq`, in the order in which its query selects them. Falls back
+    /// to the leaf's own column order if the query is unknown or the leaf lacks a selected column.
+    fn select_list_columns(&self, mq: &MirQuery) -> Vec<Column> {
+        let leaf_columns = mq.leaf.borrow().columns().to_vec();
+        let qg = match self.queries.get(&mq.name) {
+            Some(&(_, ref qg, _)) => qg,
+            None => return leaf_columns,
+        };
+
+        let mut taken = Vec::new();
+        for oc in &qg.columns {
+            let name = match *oc {
+                OutputColumn::Data(ref c) => Column::from(c).name,
+                OutputColumn::Arithmetic(ref ac) => ac.name.clone(),
+                OutputColumn::Literal(ref lc) => lc.name.clone(),
+            };
+            // several selected columns may share a name, so each takes the first one left
+            match (0..leaf_columns.len())
+                .find(|i| leaf_columns[*i].name == name && !taken.contains(i))
+            {
+                Some(i) => taken.push(i),
+                None => return leaf_columns,
+            }
+        }
+        taken.into_iter().map(|i| leaf_columns[i].clone()).collect()
+    }
+
+    /// Makes a union over `ancestors` that emits the columns of the widest ancestor. Ancestors
+    /// that lack some of those columns (by name) first pass through a projection that emits
+    /// `NULL` in their place.
+    fn make_padded_union_node(
+        &mut self,
+        name: &str,
+        ancestors: &[MirNodeRef],
+    ) -> Result<MirNodeRef, MirConversionError> {
+        assert!(ancestors.len() > 1, "union must have more than 1 ancestors");
+
+        let mut ucols: Vec<Column> = Vec::new();
+        for a in ancestors {
+            if a.borrow().columns().len() > ucols.len() {
+                ucols = a.borrow().columns().to_vec();
+            }
+        }
+
+        let mut emit = Vec::new();
+        let mut padded = Vec::new();
+        for (i, ancestor) in ancestors.iter().enumerate() {
+            let acols = ancestor.borrow().columns().to_vec();
+            // padding only adds columns, so a branch must not select any the widest one lacks
+            if let Some(c) = acols
+                .iter()
+                .find(|c| !ucols.iter().any(|uc| uc.name == c.name))
+            {
+                return Err(MirConversionError::UnknownColumn {
+                    column: c.name.clone(),
+                    available: ucols.iter().map(|uc| uc.name.clone()).collect(),
+                });
+            }
+
+            let missing: Vec<(String, DataType)> = ucols
+                .iter()
+                .filter(|uc| !acols.iter().any(|c| c.name == uc.name))
+                .map(|uc| (uc.name.clone(), DataType::None))
+                .collect();
+            let ancestor = if missing.is_empty() {
+                ancestor.clone()
+            } else {
+                let pad_name = format!("{}_pad_{}", name, i);
+                let pad = self.make_project_node(
+                    &pad_name,
+                    ancestor.clone(),
+                    acols.iter().collect(),
+                    vec![],
+                    missing,
+                    vec![],
+                    false,
+                )?;
+                self.nodes
+                    .insert((pad_name, self.schema_version), pad.clone());
+                pad
+            };
+
+            // unions emit by position, so every ancestor emits in the widest ancestor's order
+            let acols = ancestor.borrow().columns().to_vec();
+            emit.push(
+                ucols
+                    .iter()
+                    .map(|uc| acols.iter().find(|c| c.name == uc.name).unwrap().clone())
+                    .collect::<Vec<_>>(),
+            );
+            padded.push(ancestor);
+        }
+
+        Ok(MirNode::new(
+            name,
+            self.schema_version,
+            emit.first().unwrap().clone(),
+            MirNodeType::Union { emit },
+            padded,
+            vec![],
+        ))
+    }
+
     // Creates union node for universe creation - returns the resulting node ref and a universe table mapping
     fn make_union_node_sec(
         &self,
@@ -961,24 +2793,78 @@ impl SqlToMirConverter {
         )
     }
 
-    fn make_filter_node(&self, name: &str, parent: MirNodeRef, cond: &ConditionTree) -> MirNodeRef {
+    fn make_filter_node(
+        &self,
+        name: &str,
+        parent: MirNodeRef,
+        cond: &ConditionExpression,
+    ) -> Result<MirNodeRef, MirConversionError> {
         let mut fields = parent.borrow().columns().to_vec();
 
-        let filter = self.to_conditions(cond, &mut fields, &parent);
+        let filter = match *cond {
+            ConditionExpression::ComparisonOp(ref ct) => {
+                self.to_conditions(ct, &mut fields, &parent)?
+            }
+            // conjunctions of simple comparisons share a single filter node
+            ConditionExpression::LogicalOp(ref ct) => {
+                self.logical_op_to_conditions(ct, &mut fields, &parent)?
+            }
+            _ => unreachable!("filter node for unsupported condition {:?}", cond),
+        };
+
+        // contradictory conditions would never let any records through, so we replace them with
+        // a single condition that is just as impossible to meet, but cheaper to check
+        let filter = if conditions_unsatisfiable(&filter) {
+            debug!(
+                self.log,
+                "Filter node {} has unsatisfiable conditions {:?}", name, filter
+            );
+            vec![(0, FilterCondition::In(vec![]))]
+        } else {
+            tighten_ranges(filter)
+        };
+
+        // an identical filter over the same parent may already have been created while lowering
+        // this query (e.g., for a conjunct shared by both sides of an OR), so reuse it if so
+        let existing = self.filter_nodes.borrow().iter().find_map(|f| {
+            let fb = f.borrow();
+            let same_filter = match fb.inner {
+                MirNodeType::Filter { ref conditions } => *conditions == filter,
+                _ => false,
+            };
+            if same_filter && Rc::ptr_eq(&fb.ancestors()[0], &parent) && fb.columns() == &fields[..]
+            {
+                Some(f.clone())
+            } else {
+                None
+            }
+        });
+        if let Some(f) = existing {
+            trace!(
+                self.log,
+                "Reusing filter node {} for {}",
+                f.borrow().name(),
+                name
+            );
+            return Ok(f);
+        }
+
         trace!(
             self.log,
             "Added filter node {} with condition {:?}",
             name,
             filter
         );
-        MirNode::new(
+        let f = MirNode::new(
             name,
             self.schema_version,
             fields,
             MirNodeType::Filter { conditions: filter },
             vec![parent.clone()],
             vec![],
-        )
+        );
+        self.filter_nodes.borrow_mut().push(f.clone());
+        Ok(f)
     }
 
     fn make_function_node(
@@ -987,7 +2873,7 @@ impl SqlToMirConverter {
         func_col: &Column,
         group_cols: Vec<&Column>,
         parent: MirNodeRef,
-    ) -> Vec<MirNodeRef> {
+    ) -> Result<Vec<MirNodeRef>, MirConversionError> {
         use dataflow::ops::grouped::aggregate::Aggregation;
         use dataflow::ops::grouped::extremum::Extremum;
         use dataflow::ops::grouped::filteraggregate::FilterAggregation;
@@ -1000,13 +2886,14 @@ impl SqlToMirConverter {
                       over_else: Option<Literal>,
                       t: GroupedNodeType,
                       distinct: bool,
-                      cond: Option<&ConditionExpression>| {
+                      cond: Option<&ConditionExpression>|
+         -> Result<Vec<MirNodeRef>, MirConversionError> {
             if distinct {
                 let new_name = name.to_owned() + "_distinct";
                 let mut dist_col = Vec::new();
                 dist_col.push(over);
                 dist_col.extend(group_cols.clone());
-                let node = self.make_distinct_node(&new_name, parent, dist_col.clone());
+                let node = self.make_distinct_node(&new_name, parent, dist_col.clone(), false);
                 out_nodes.push(node.clone());
                 out_nodes.push(self.make_grouped_node(
                     name,
@@ -1015,8 +2902,8 @@ impl SqlToMirConverter {
                     group_cols,
                     t,
                     cond,
-                ));
-                out_nodes
+                )?);
+                Ok(out_nodes)
             } else {
                 out_nodes.push(self.make_grouped_node(
                     name,
@@ -1025,8 +2912,8 @@ impl SqlToMirConverter {
                     group_cols,
                     t,
                     cond,
-                ));
-                out_nodes
+                )?);
+                Ok(out_nodes)
             }
         };
 
@@ -1081,7 +2968,9 @@ impl SqlToMirConverter {
                 // faithful to COUNT(*) semantics, because COUNT(*) is supposed to count all
                 // rows including those with NULL values, and we don't have a mechanism to do that
                 // (but we also don't have a NULL value, so maybe we're okay).
-                panic!("COUNT(*) should have been rewritten earlier!")
+                Err(MirConversionError::Inconsistency(String::from(
+                    "COUNT(*) should have been rewritten earlier",
+                )))
             }
             Count(
                 FunctionArguments::Conditional(CaseWhenExpression {
@@ -1132,7 +3021,7 @@ impl SqlToMirConverter {
                 false,
                 None,
             ),
-            _ => unimplemented!(),
+            ref f => Err(MirConversionError::UnsupportedAggregation(f.to_string())),
         }
     }
 
@@ -1144,7 +3033,7 @@ impl SqlToMirConverter {
         group_by: Vec<&Column>,
         node_type: GroupedNodeType,
         condition: Option<&ConditionExpression>,
-    ) -> MirNodeRef {
+    ) -> Result<MirNodeRef, MirConversionError> {
         let parent_node = over.0;
 
         // Resolve column IDs in parent
@@ -1160,7 +3049,7 @@ impl SqlToMirConverter {
         combined_columns.push(computed_col.clone());
 
         // make the new operator
-        match node_type {
+        let node = match node_type {
             GroupedNodeType::Aggregation(agg) => MirNode::new(
                 name,
                 self.schema_version,
@@ -1192,13 +3081,17 @@ impl SqlToMirConverter {
                 let mut fields = parent_node.borrow().columns().to_vec();
                 let filter = match *cond {
                     LogicalOp(ref ct) => {
-                        self.logical_op_to_conditions(ct, &mut fields, &parent_node)
+                        self.logical_op_to_conditions(ct, &mut fields, &parent_node)?
+                    }
+                    ComparisonOp(ref ct) => self.to_conditions(ct, &mut fields, &parent_node)?,
+                    Bracketed(_) | Arithmetic(_) => {
+                        return Err(MirConversionError::NestedCondition(format!(
+                            "cannot filter aggregation input on {:?}",
+                            cond
+                        )))
                     }
-                    ComparisonOp(ref ct) => self.to_conditions(ct, &mut fields, &parent_node),
-                    Bracketed(_) => unimplemented!(),
                     NegationOp(_) => unreachable!("negation should have been removed earlier"),
                     Base(_) => unreachable!("dangling base predicate"),
-                    Arithmetic(_) => unimplemented!(),
                 };
                 MirNode::new(
                     name,
@@ -1226,7 +3119,101 @@ impl SqlToMirConverter {
                 vec![parent_node.clone()],
                 vec![],
             ),
+        };
+
+        // another query may already compute the same aggregation over the same parent at this
+        // schema version (only keying its result differently), in which case we share its node
+        if let Some(existing) = self.find_grouped_node(&node) {
+            trace!(
+                self.log,
+                "Reusing grouped node {} for {}",
+                existing.borrow().name(),
+                name
+            );
+            return Ok(MirNode::reuse(existing, self.schema_version));
+        }
+        Ok(node)
+    }
+
+    /// Finds a grouped node registered at the current schema version that computes the same
+    /// aggregation as `node` (with the same group-by columns, kind and output columns) over the
+    /// same parent, looking through reuse. If there are several, picks the one with the smallest
+    /// name. Finds nothing if node sharing is disabled.
+    fn find_grouped_node(&self, node: &MirNodeRef) -> Option<MirNodeRef> {
+        use mir::query::reuse_target;
+
+        if !self.share_nodes {
+            return None;
         }
+
+        let node = node.borrow();
+        let parent = reuse_target(&node.ancestors()[0]);
+        self.nodes
+            .iter()
+            .filter(|&(&(_, v), _)| v == self.schema_version)
+            .map(|(_, n)| reuse_target(n))
+            .filter(|n| {
+                let nb = n.borrow();
+                let ancestors = nb.ancestors();
+                let grouped = match nb.inner {
+                    MirNodeType::Aggregation { .. }
+                    | MirNodeType::Extremum { .. }
+                    | MirNodeType::FilterAggregation { .. } => true,
+                    _ => false,
+                };
+                grouped
+                    && ancestors.len() == 1
+                    && Rc::ptr_eq(&reuse_target(&ancestors[0]), &parent)
+                    && nb.columns() == node.columns()
+                    && nb.can_reuse_as(&node)
+            })
+            .min_by_key(|n| n.borrow().name().to_owned())
+    }
+
+    /// Makes a single node that computes all of the COUNT and SUM aggregations in
+    /// `computed_cols` for each group of `group_by` columns, emitting one column per aggregation.
+    fn make_multi_grouped_node(
+        &self,
+        name: &str,
+        computed_cols: &[Column],
+        group_by: Vec<&Column>,
+        parent_node: MirNodeRef,
+    ) -> Result<MirNodeRef, MirConversionError> {
+        use dataflow::ops::grouped::aggregate::Aggregation;
+        use nom_sql::FunctionArguments;
+        use nom_sql::FunctionExpression::*;
+
+        let aggregates = computed_cols
+            .iter()
+            .map(|c| match *c.function.as_ref().unwrap().deref() {
+                Count(FunctionArguments::Column(ref col), false) => {
+                    Ok((Column::from(col), Aggregation::COUNT))
+                }
+                Sum(FunctionArguments::Column(ref col), false) => {
+                    Ok((Column::from(col), Aggregation::SUM))
+                }
+                ref f => Err(MirConversionError::UnsupportedAggregation(f.to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // the group columns come first, followed by one column per aggregation
+        let columns = group_by
+            .iter()
+            .map(|c| (*c).clone())
+            .chain(computed_cols.iter().cloned())
+            .collect();
+
+        Ok(MirNode::new(
+            name,
+            self.schema_version,
+            columns,
+            MirNodeType::MultiAggregation {
+                group_by: group_by.into_iter().cloned().collect(),
+                aggregates,
+            },
+            vec![parent_node],
+            vec![],
+        ))
     }
 
     fn make_join_node(
@@ -1236,7 +3223,7 @@ impl SqlToMirConverter {
         left_node: MirNodeRef,
         right_node: MirNodeRef,
         kind: JoinType,
-    ) -> MirNodeRef {
+    ) -> Result<MirNodeRef, MirConversionError> {
         // TODO(malte): this is where we overproject join columns in order to increase reuse
         // opportunities. Technically, we need to only project those columns here that the query
         // actually needs; at a minimum, we could start with just the join colums, relying on the
@@ -1253,15 +3240,33 @@ impl SqlToMirConverter {
         let mut left_join_columns = Vec::new();
         let mut right_join_columns = Vec::new();
 
-        // equi-join only
-        assert!(jp.operator == Operator::Equal || jp.operator == Operator::In);
+        // equi-join only. An `In` predicate between two columns comes from rewriting
+        // `x IN (SELECT y ...)` into a join with the subquery's view; each row of the subquery
+        // contributes a single value, so `x IN y` holds exactly when `x = y` does. The parser has
+        // no syntax for `IN` over a list of columns, so no other `In` join can reach us.
+        if jp.operator != Operator::Equal && jp.operator != Operator::In {
+            return Err(MirConversionError::UnsupportedJoinType(format!(
+                "join on {:?} comparison",
+                jp.operator
+            )));
+        }
         let mut l_col = match *jp.left {
             ConditionExpression::Base(ConditionBase::Field(ref f)) => Column::from(f),
-            _ => unimplemented!(),
+            ref e => {
+                return Err(MirConversionError::NestedCondition(format!(
+                    "join on {:?}",
+                    e
+                )))
+            }
         };
         let r_col = match *jp.right {
             ConditionExpression::Base(ConditionBase::Field(ref f)) => Column::from(f),
-            _ => unimplemented!(),
+            ref e => {
+                return Err(MirConversionError::NestedCondition(format!(
+                    "join on {:?}",
+                    e
+                )))
+            }
         };
 
         // don't duplicate the join column in the output, but instead add aliases to the columns
@@ -1305,14 +3310,120 @@ impl SqlToMirConverter {
                 project: fields.clone(),
             },
         };
+
+        // another query may already join the same nodes in the same way at this schema version,
+        // in which case we share its join node
+        if let Some(existing) = self.find_join_node(&inner, &left_node, &right_node, &fields) {
+            trace!(
+                self.log,
+                "Reusing join node {} for {}",
+                existing.borrow().name(),
+                name
+            );
+            return Ok(MirNode::reuse(existing, self.schema_version));
+        }
+
         trace!(self.log, "Added join node {:?}", inner);
-        MirNode::new(
+        Ok(MirNode::new(
             name,
             self.schema_version,
             fields,
             inner,
             vec![left_node.clone(), right_node.clone()],
             vec![],
+        ))
+    }
+
+    /// Finds a join node registered at the current schema version that computes the join `inner`
+    /// of the nodes `left` and `right` (looking through reuse on both sides) with output columns
+    /// `fields`. If there are several, picks the one with the smallest name. Finds nothing if node
+    /// sharing is disabled.
+    fn find_join_node(
+        &self,
+        inner: &MirNodeType,
+        left: &MirNodeRef,
+        right: &MirNodeRef,
+        fields: &[Column],
+    ) -> Option<MirNodeRef> {
+        use mir::query::reuse_target;
+
+        if !self.share_nodes {
+            return None;
+        }
+
+        let (left, right) = (reuse_target(left), reuse_target(right));
+        let same_join = |a: &MirNodeType| match (a, inner) {
+            (
+                MirNodeType::Join {
+                    on_left: ref al,
+                    on_right: ref ar,
+                    ..
+                },
+                MirNodeType::Join {
+                    ref on_left,
+                    ref on_right,
+                    ..
+                },
+            )
+            | (
+                MirNodeType::LeftJoin {
+                    on_left: ref al,
+                    on_right: ref ar,
+                    ..
+                },
+                MirNodeType::LeftJoin {
+                    ref on_left,
+                    ref on_right,
+                    ..
+                },
+            ) => al == on_left && ar == on_right,
+            _ => false,
+        };
+
+        self.nodes
+            .iter()
+            .filter(|&(&(_, v), _)| v == self.schema_version)
+            .map(|(_, n)| reuse_target(n))
+            .filter(|n| {
+                let nb = n.borrow();
+                let ancestors = nb.ancestors();
+                same_join(&nb.inner)
+                    && ancestors.len() == 2
+                    && Rc::ptr_eq(&reuse_target(&ancestors[0]), &left)
+                    && Rc::ptr_eq(&reuse_target(&ancestors[1]), &right)
+                    && nb.columns() == fields
+            })
+            .min_by_key(|n| n.borrow().name().to_owned())
+    }
+
+    /// Joins the outputs of two grouped nodes over the same input and group column `group_col`,
+    /// yielding one row per group with the computed columns of both.
+    fn make_grouped_join_node(
+        &self,
+        name: &str,
+        left: MirNodeRef,
+        right: MirNodeRef,
+        group_col: &Column,
+    ) -> MirNodeRef {
+        let fields: Vec<Column> = left
+            .borrow()
+            .columns()
+            .iter()
+            .chain(right.borrow().columns().iter().filter(|c| *c != group_col))
+            .cloned()
+            .collect();
+
+        MirNode::new(
+            name,
+            self.schema_version,
+            fields.clone(),
+            MirNodeType::Join {
+                on_left: vec![group_col.clone()],
+                on_right: vec![group_col.clone()],
+                project: fields,
+            },
+            vec![left, right],
+            vec![],
         )
     }
 
@@ -1321,13 +3432,14 @@ impl SqlToMirConverter {
         name: &str,
         parent: MirNodeRef,
         fn_col: &Column,
-    ) -> MirNodeRef {
+    ) -> Result<MirNodeRef, MirConversionError> {
         self.make_project_node(
             name,
             parent,
             vec![fn_col],
             vec![],
             vec![(String::from("grp"), DataType::from(0 as i32))],
+            vec![],
             false,
         )
     }
@@ -1339,14 +3451,34 @@ impl SqlToMirConverter {
         proj_cols: Vec<&Column>,
         arithmetic: Vec<(String, ArithmeticExpression)>,
         literals: Vec<(String, DataType)>,
+        functions: Vec<(String, ScalarFunction)>,
         is_leaf: bool,
-    ) -> MirNodeRef {
+    ) -> Result<MirNodeRef, MirConversionError> {
         //assert!(proj_cols.iter().all(|c| c.table == parent_name));
 
+        // arithmetic over constants yields the same value for every record, so it is emitted as a
+        // literal that is computed once here
+        let mut per_record = Vec::new();
+        let mut folded = Vec::new();
+        for (n, ae) in arithmetic {
+            match fold_constant_arithmetic(&ae)? {
+                Some(v) => folded.push((n, v)),
+                None => per_record.push((n, ae)),
+            }
+        }
+        let arithmetic = per_record;
+        let literals: Vec<(String, DataType)> = folded.into_iter().chain(literals).collect();
+
+        let functions = functions
+            .iter()
+            .map(|&(ref n, ref f)| Ok((n.clone(), self.make_function(&parent_node, f)?)))
+            .collect::<Result<Vec<_>, MirConversionError>>()?;
+
         let names: Vec<String> = arithmetic
             .iter()
             .map(|&(ref n, _)| n.clone())
             .chain(literals.iter().map(|&(ref n, _)| n.clone()))
+            .chain(functions.iter().map(|&(ref n, _)| n.clone()))
             .collect();
 
         let fields = proj_cols
@@ -1355,24 +3487,26 @@ impl SqlToMirConverter {
             .map(|c| {
                 let mut c = c.clone();
                 // if this is the leaf node of a query, it represents a view, so we rewrite the
-                // table name here.
-                if is_leaf {
+                // table name here. A computed column may shadow an emitted one by taking its name
+                // (as in `SELECT t.a + 1 AS a`), though; the view's column then refers to the
+                // computed column, so the emitted one keeps its original table to stay distinct.
+                if is_leaf && !names.contains(&c.name) {
                     sanitize_leaf_column(&mut c, name);
                 }
                 c
             })
-            .chain(names.into_iter().map(|n| {
+            .chain(names.iter().map(|n| {
                 if is_leaf {
-                    Column::new(Some(&name), &n)
+                    Column::new(Some(&name), n)
                 } else {
-                    Column::new(None, &n)
+                    Column::new(None, n)
                 }
             }))
             .collect();
 
         let emit_cols = proj_cols.into_iter().cloned().collect();
 
-        MirNode::new(
+        Ok(MirNode::new(
             name,
             self.schema_version,
             fields,
@@ -1380,10 +3514,42 @@ impl SqlToMirConverter {
                 emit: emit_cols,
                 literals,
                 arithmetic,
+                functions,
             },
             vec![parent_node.clone()],
             vec![],
-        )
+        ))
+    }
+
+    /// Lowers `function` into the form a projection below `parent` evaluates, with its column
+    /// arguments resolved to `parent`'s column indices.
+    fn make_function(
+        &self,
+        parent: &MirNodeRef,
+        function: &ScalarFunction,
+    ) -> Result<ProjectFunction, MirConversionError> {
+        let arg = |arg: &ColumnOrLiteral| match *arg {
+            ColumnOrLiteral::Literal(ref l) => {
+                Ok(ProjectExpressionBase::Literal(DataType::from(l)))
+            }
+            ColumnOrLiteral::Column(ref c) => {
+                let c = Column::from(c);
+                if !parent.borrow().columns().contains(&c) {
+                    return Err(MirConversionError::InvalidExpression(format!(
+                        "{:?} takes column {:?}, which is not in its input",
+                        function, c
+                    )));
+                }
+                Ok(ProjectExpressionBase::Column(
+                    parent.borrow().column_id_for_column(&c, None),
+                ))
+            }
+        };
+
+        Ok(match *function {
+            ScalarFunction::Lower(ref a) => ProjectFunction::Lower(arg(a)?),
+            ScalarFunction::Upper(ref a) => ProjectFunction::Upper(arg(a)?),
+        })
     }
 
     fn make_distinct_node(
@@ -1391,6 +3557,7 @@ impl SqlToMirConverter {
         name: &str,
         parent: MirNodeRef,
         group_by: Vec<&Column>,
+        ordered: bool,
     ) -> MirNodeRef {
         let combined_columns = parent.borrow().columns().to_vec();
 
@@ -1401,6 +3568,7 @@ impl SqlToMirConverter {
             combined_columns,
             MirNodeType::Distinct {
                 group_by: group_by.into_iter().cloned().collect(),
+                ordered,
             },
             vec![parent.clone()],
             vec![],
@@ -1413,14 +3581,23 @@ impl SqlToMirConverter {
         group_by: Vec<&Column>,
         order: &Option<OrderClause>,
         limit: &LimitClause,
+        parameterized_k: bool,
     ) -> MirNodeRef {
         let combined_columns = parent.borrow().columns().to_vec();
 
+        // nom-sql does not parse NULLS FIRST/LAST, so every column gets the default placement
         let order = match *order {
             Some(ref o) => Some(
                 o.columns
                     .iter()
-                    .map(|(c, o)| (Column::from(c), o.clone()))
+                    .map(|(c, o)| {
+                        (
+                            Column::from(c),
+                            o.clone(),
+                            NullOrder::default_for(o),
+                            self.column_collation(c),
+                        )
+                    })
                     .collect(),
             ),
             None => None,
@@ -1438,6 +3615,7 @@ impl SqlToMirConverter {
                 group_by: group_by.into_iter().cloned().collect(),
                 k: limit.limit as usize,
                 offset: 0,
+                parameterized_k,
             },
             vec![parent.clone()],
             vec![],
@@ -1450,7 +3628,7 @@ impl SqlToMirConverter {
         parent: MirNodeRef,
         ce: &ConditionExpression,
         nc: usize,
-    ) -> Vec<MirNodeRef> {
+    ) -> Result<Vec<MirNodeRef>, MirConversionError> {
         use nom_sql::ConditionExpression::*;
 
         let mut pred_nodes: Vec<MirNodeRef> = Vec::new();
@@ -1459,62 +3637,88 @@ impl SqlToMirConverter {
             LogicalOp(ref ct) => {
                 let (left, right);
                 match ct.operator {
+                    Operator::And
+                        if is_simple_conjunction(ce)
+                            && predicate_columns(ce)
+                                .iter()
+                                .all(|c| output_cols.contains(c)) =>
+                    {
+                        // all conditions can be checked by the same filter node
+                        let f = self.make_filter_node(&format!("{}_f{}", name, nc), parent, ce)?;
+
+                        pred_nodes.push(f);
+                    }
                     Operator::And => {
-                        left = self.make_predicate_nodes(name, parent.clone(), &*ct.left, nc);
+                        left = self.make_predicate_nodes(name, parent.clone(), &*ct.left, nc)?;
 
                         right = self.make_predicate_nodes(
                             name,
                             left.last().unwrap().clone(),
                             &*ct.right,
                             nc + left.len(),
-                        );
+                        )?;
 
                         pred_nodes.extend(left.clone());
                         pred_nodes.extend(right.clone());
                     }
                     Operator::Or => {
-                        left = self.make_predicate_nodes(name, parent.clone(), &*ct.left, nc);
+                        left = self.make_predicate_nodes(name, parent.clone(), &*ct.left, nc)?;
 
                         right = self.make_predicate_nodes(
                             name,
                             parent.clone(),
                             &*ct.right,
                             nc + left.len(),
-                        );
-
-                        debug!(self.log, "Creating union node for `or` predicate");
+                        )?;
 
                         let last_left = left.last().unwrap().clone();
                         let last_right = right.last().unwrap().clone();
-                        let union = self.make_union_from_same_base(
-                            &format!("{}_un", name),
-                            vec![last_left, last_right],
-                            output_cols,
-                        );
 
+                        // both sides may share reused filter nodes, which we only return once
                         pred_nodes.extend(left.clone());
-                        pred_nodes.extend(right.clone());
-                        pred_nodes.push(union);
+                        for n in right {
+                            if !pred_nodes.iter().any(|pn| Rc::ptr_eq(pn, &n)) {
+                                pred_nodes.push(n);
+                            }
+                        }
+
+                        // if both sides ended up as the same filter chain, there is nothing to
+                        // union
+                        if !Rc::ptr_eq(&last_left, &last_right) {
+                            debug!(self.log, "Creating union node for `or` predicate");
+
+                            let union = self.make_union_from_same_base(
+                                &format!("{}_un", name),
+                                vec![last_left, last_right],
+                                output_cols,
+                            );
+                            pred_nodes.push(union);
+                        }
                     }
                     _ => unreachable!("LogicalOp operator is {:?}", ct.operator),
                 }
             }
-            ComparisonOp(ref ct) => {
+            ComparisonOp(_) => {
                 // currently, we only support filter-like
                 // comparison operations, no nested-selections
-                let f = self.make_filter_node(&format!("{}_f{}", name, nc), parent, ct);
+                let f = self.make_filter_node(&format!("{}_f{}", name, nc), parent, ce)?;
 
                 pred_nodes.push(f);
             }
             Bracketed(ref inner) => {
-                pred_nodes.extend(self.make_predicate_nodes(name, parent, &*inner, nc));
+                pred_nodes.extend(self.make_predicate_nodes(name, parent, &*inner, nc)?);
             }
             NegationOp(_) => unreachable!("negation should have been removed earlier"),
             Base(_) => unreachable!("dangling base predicate"),
-            Arithmetic(_) => unimplemented!(),
+            Arithmetic(_) => {
+                return Err(MirConversionError::NestedCondition(format!(
+                    "arithmetic predicate {:?}",
+                    ce
+                )))
+            }
         }
 
-        pred_nodes
+        Ok(pred_nodes)
     }
 
     fn predicates_above_group_by<'a>(
@@ -1524,7 +3728,7 @@ impl SqlToMirConverter {
         over_col: Column,
         parent: MirNodeRef,
         created_predicates: &mut Vec<&'a ConditionExpression>,
-    ) -> Vec<MirNodeRef> {
+    ) -> Result<Vec<MirNodeRef>, MirConversionError> {
         let mut predicates_above_group_by_nodes = Vec::new();
         let mut prev_node = parent.clone();
 
@@ -1536,7 +3740,7 @@ impl SqlToMirConverter {
                     prev_node.clone(),
                     ce,
                     0,
-                );
+                )?;
                 assert!(!mpns.is_empty());
                 prev_node = mpns.last().unwrap().clone();
                 predicates_above_group_by_nodes.extend(mpns);
@@ -1544,7 +3748,7 @@ impl SqlToMirConverter {
             }
         }
 
-        predicates_above_group_by_nodes
+        Ok(predicates_above_group_by_nodes)
     }
 
     fn make_value_project_node(
@@ -1553,7 +3757,7 @@ impl SqlToMirConverter {
         prev_node: Option<MirNodeRef>,
         node_count: usize,
         universe: &str,
-    ) -> Option<MirNodeRef> {
+    ) -> Result<Option<MirNodeRef>, MirConversionError> {
         let arith_and_lit_columns_needed =
             value_columns_needed_for_predicates(&qg.columns, &qg.global_predicates);
 
@@ -1588,17 +3792,24 @@ impl SqlToMirConverter {
 
             let passthru_cols: Vec<_> = parent.borrow().columns().to_vec();
             let projected = self.make_project_node(
-                &format!("q_{:x}_n{}{}", qg.signature().hash, node_count, universe),
+                &format!(
+                    "{}q_{:x}_n{}{}",
+                    self.name_prefix,
+                    qg.signature().hash,
+                    node_count,
+                    universe
+                ),
                 parent.clone(),
                 passthru_cols.iter().collect(),
                 projected_arithmetic,
                 projected_literals,
+                vec![],
                 false,
-            );
+            )?;
 
-            Some(projected)
+            Ok(Some(projected))
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -1611,6 +3822,7 @@ impl SqlToMirConverter {
         qg: &QueryGraph,
         has_leaf: bool,
         universe: UniverseId,
+        materialization: Option<MaterializationMode>,
     ) -> Result<
         (
             bool,
@@ -1618,16 +3830,26 @@ impl SqlToMirConverter {
             Option<HashMap<(String, Option<String>), String>>,
             String,
         ),
-        String,
+        MirConversionError,
     > {
         // TODO: make this take &self!
+        use crate::controller::sql::mir::grouped::check_group_by;
         use crate::controller::sql::mir::grouped::make_grouped;
         use crate::controller::sql::mir::grouped::make_predicates_above_grouped;
         use crate::controller::sql::mir::join::make_joins;
 
+        if self.strict_group_by {
+            check_group_by(qg)?;
+        }
+
         let mut nodes_added: Vec<MirNodeRef>;
         let mut new_node_count = 0;
 
+        // filters are only shared within a single query's lowering
+        self.filter_nodes.borrow_mut().clear();
+        let read_time_limit = self.read_time_limits.contains(name) && has_leaf;
+        let ordered_distinct = self.ordered_distincts.contains(name) && has_leaf;
+
         let (uid, _) = universe.clone();
 
         let uformat = if uid == "global".into() {
@@ -1660,13 +3882,156 @@ impl SqlToMirConverter {
                 node_for_rel.insert(*rel, base_for_rel);
             }
 
+            // 0b. Check that all columns the query refers to exist, since a misspelled column would
+            //     otherwise only surface as a panic once we look it up in the dataflow nodes
+            for rel in &sorted_rels {
+                if *rel == "computed_columns" {
+                    continue;
+                }
+                let qgn = &qg.relations[*rel];
+                let referenced = qgn.columns.iter().map(Column::from).chain(
+                    qgn.predicates
+                        .iter()
+                        .chain(qgn.on_predicates.iter())
+                        .flat_map(predicate_columns),
+                );
+                for c in referenced {
+                    let table = c.table.clone().unwrap_or_else(|| String::from(*rel));
+                    let n = match node_for_rel.get(table.as_str()) {
+                        Some(n) => n,
+                        None => continue,
+                    };
+                    let available: Vec<String> = n
+                        .borrow()
+                        .columns()
+                        .iter()
+                        .map(|c| c.name.clone())
+                        .collect();
+                    if !available.contains(&c.name) {
+                        return Err(MirConversionError::UnknownColumn {
+                            column: format!("{}.{}", table, c.name),
+                            available: close_matches(&c.name, &available),
+                        });
+                    }
+                }
+            }
+
+            // 1. Filters from ON clauses apply to the join inputs, rather than to the join output
+            let mut on_predicate_nodes: Vec<MirNodeRef> = Vec::new();
+            for rel in &sorted_rels {
+                let qgn = &qg.relations[*rel];
+                for (i, p) in fuse_simple_conjunctions(qgn.on_predicates.iter().collect())
+                    .iter()
+                    .enumerate()
+                {
+                    let fns = self.make_predicate_nodes(
+                        &format!(
+                            "{}q_{:x}_n{}_on{}{}",
+                            self.name_prefix,
+                            qg.signature().hash,
+                            new_node_count,
+                            i,
+                            uformat
+                        ),
+                        node_for_rel[*rel].clone(),
+                        p,
+                        0,
+                    )?;
+
+                    assert!(!fns.is_empty());
+                    new_node_count += fns.len();
+                    node_for_rel.insert(*rel, fns.last().unwrap().clone());
+                    on_predicate_nodes.extend(fns);
+                }
+            }
+
+            // Predicates that refer to the alias of an aggregation can only be evaluated on the
+            // grouped nodes' output, as if they had been given in a HAVING clause, so they never
+            // move above them. As in SQL, a column of one of the query's relations takes
+            // precedence over an alias of the same name, so only unqualified columns that no
+            // relation has refer to an aggregation.
+            let aggregate_aliases: Vec<&str> = match qg.relations.get("computed_columns") {
+                None => vec![],
+                Some(cc) => cc
+                    .columns
+                    .iter()
+                    .map(|c| c.alias.as_ref().unwrap_or(&c.name).as_str())
+                    .collect(),
+            };
+            let relation_columns: HashSet<String> = base_nodes
+                .iter()
+                .flat_map(|n| {
+                    let n = n.borrow();
+                    n.columns()
+                        .iter()
+                        .map(|c| c.name.clone())
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            let references_aggregate = |p: &ConditionExpression| {
+                predicate_columns(p).iter().any(|c| {
+                    c.table.is_none()
+                        && aggregate_aliases.contains(&c.name.as_str())
+                        && !relation_columns.contains(&c.name)
+                })
+            };
+            // 1a. Predicates that only concern a single relation are pushed below the joins, so
+            //     that the joins see fewer rows. Predicates on the nullable side of a LEFT JOIN
+            //     must stay above it, since they also filter out the NULL-extended rows.
+            let has_joins = qg.edges.values().any(|e| match *e {
+                QueryGraphEdge::Join(_) | QueryGraphEdge::LeftJoin(_) => true,
+                QueryGraphEdge::GroupBy(_) => false,
+            });
+            let nullable_rels: HashSet<&str> = qg
+                .edges
+                .iter()
+                .filter_map(|(&(_, ref dst), e)| match *e {
+                    QueryGraphEdge::LeftJoin(_) => Some(dst.as_str()),
+                    _ => None,
+                })
+                .collect();
+            let mut pushed_predicates: Vec<&ConditionExpression> = Vec::new();
+            let mut pushdown_nodes: Vec<MirNodeRef> = Vec::new();
+            for rel in &sorted_rels {
+                if !has_joins || *rel == "computed_columns" || nullable_rels.contains(rel) {
+                    continue;
+                }
+
+                let local: Vec<&ConditionExpression> = qg.relations[*rel]
+                    .predicates
+                    .iter()
+                    .filter(|p| !references_aggregate(p))
+                    .collect();
+                pushed_predicates.extend(local.iter().cloned());
+                for (i, p) in fuse_simple_conjunctions(local).iter().enumerate() {
+                    let fns = self.make_predicate_nodes(
+                        &format!(
+                            "{}q_{:x}_n{}_pd{}{}",
+                            self.name_prefix,
+                            qg.signature().hash,
+                            new_node_count,
+                            i,
+                            uformat
+                        ),
+                        node_for_rel[*rel].clone(),
+                        p,
+                        0,
+                    )?;
+
+                    assert!(!fns.is_empty());
+                    new_node_count += fns.len();
+                    node_for_rel.insert(*rel, fns.last().unwrap().clone());
+                    pushdown_nodes.extend(fns);
+                }
+            }
+
             let join_nodes = make_joins(
                 self,
-                &format!("q_{:x}{}", qg.signature().hash, uformat),
+                &format!("{}q_{:x}{}", self.name_prefix, qg.signature().hash, uformat),
                 qg,
                 &node_for_rel,
                 new_node_count,
-            );
+            )?;
 
             new_node_count += join_nodes.len();
 
@@ -1689,7 +4054,11 @@ impl SqlToMirConverter {
                 }
 
                 let qgn = &qg.relations[*rel];
-                for pred in &qgn.predicates {
+                for pred in qgn
+                    .predicates
+                    .iter()
+                    .filter(|p| !references_aggregate(p) && !pushed_predicates.contains(p))
+                {
                     let cols = predicate_columns(pred);
 
                     for col in cols {
@@ -1697,7 +4066,11 @@ impl SqlToMirConverter {
                     }
                 }
 
-                for pred in &qg.global_predicates {
+                for pred in qg
+                    .global_predicates
+                    .iter()
+                    .filter(|p| !references_aggregate(p))
+                {
                     let cols = predicate_columns(pred);
 
                     for col in cols {
@@ -1713,13 +4086,13 @@ impl SqlToMirConverter {
             let (created_predicates, predicates_above_group_by_nodes) =
                 make_predicates_above_grouped(
                     self,
-                    &format!("q_{:x}{}", qg.signature().hash, uformat),
+                    &format!("{}q_{:x}{}", self.name_prefix, qg.signature().hash, uformat),
                     &qg,
                     &node_for_rel,
                     new_node_count,
                     &column_to_predicates,
                     &mut prev_node,
-                );
+                )?;
 
             new_node_count += predicates_above_group_by_nodes.len();
 
@@ -1733,9 +4106,9 @@ impl SqlToMirConverter {
 
             let mut ancestors = self.universe.member_of.iter().fold(
                 Ok(vec![]),
-                |acc: Result<_, String>, (gname, gids)| {
+                |acc: Result<_, MirConversionError>, (gname, gids)| {
                     acc.and_then(|mut acc| {
-                        let group_views: Result<Vec<_>, String> = gids
+                        let group_views: Result<Vec<_>, MirConversionError> = gids
                             .iter()
                             .filter_map(|gid| {
                                 // This is a little annoying, but because of the way we name universe queries,
@@ -1764,6 +4137,8 @@ impl SqlToMirConverter {
 
             nodes_added = base_nodes
                 .into_iter()
+                .chain(on_predicate_nodes.into_iter())
+                .chain(pushdown_nodes.into_iter())
                 .chain(join_nodes.into_iter())
                 .chain(predicates_above_group_by_nodes.into_iter())
                 .chain(policy_nodes.into_iter())
@@ -1778,13 +4153,13 @@ impl SqlToMirConverter {
                 // 3. Add function and grouped nodes
                 let mut func_nodes: Vec<MirNodeRef> = make_grouped(
                     self,
-                    &format!("q_{:x}{}", qg.signature().hash, uformat),
+                    &format!("{}q_{:x}{}", self.name_prefix, qg.signature().hash, uformat),
                     &qg,
                     &node_for_rel,
                     new_node_count,
                     &mut prev_node,
                     false,
-                );
+                )?;
 
                 new_node_count += func_nodes.len();
 
@@ -1805,12 +4180,19 @@ impl SqlToMirConverter {
                     // the following conditional is required to avoid "empty" nodes (without any
                     // projected columns) that are required as inputs to joins
                     if !qgn.predicates.is_empty() {
-                        // add a predicate chain for each query graph node's predicates
-                        for (i, ref p) in qgn.predicates.iter().enumerate() {
-                            if created_predicates.contains(p) {
-                                continue;
-                            }
-
+                        // add a predicate chain for each query graph node's predicates; simple
+                        // comparisons are fused so that they share a single filter node
+                        let predicates = fuse_simple_conjunctions(
+                            qgn.predicates
+                                .iter()
+                                .filter(|p| {
+                                    !created_predicates.contains(p)
+                                        && !pushed_predicates.contains(p)
+                                        && !references_aggregate(p)
+                                })
+                                .collect(),
+                        );
+                        for (i, p) in predicates.iter().enumerate() {
                             let parent = match prev_node {
                                 None => node_for_rel[rel].clone(),
                                 Some(pn) => pn,
@@ -1818,7 +4200,8 @@ impl SqlToMirConverter {
 
                             let fns = self.make_predicate_nodes(
                                 &format!(
-                                    "q_{:x}_n{}_p{}{}",
+                                    "{}q_{:x}_n{}_p{}{}",
+                                    self.name_prefix,
                                     qg.signature().hash,
                                     new_node_count,
                                     i,
@@ -1827,7 +4210,7 @@ impl SqlToMirConverter {
                                 parent,
                                 p,
                                 0,
-                            );
+                            )?;
 
                             assert!(!fns.is_empty());
                             new_node_count += fns.len();
@@ -1842,15 +4225,25 @@ impl SqlToMirConverter {
                 // 5. Determine literals and arithmetic expressions that global predicates depend
                 //    on and add them here; remembering that we've already added them-
                 if let Some(projected) =
-                    self.make_value_project_node(&qg, prev_node.clone(), new_node_count, &uformat)
+                    self.make_value_project_node(&qg, prev_node.clone(), new_node_count, &uformat)?
                 {
                     new_node_count += 1;
                     nodes_added.push(projected.clone());
                     prev_node = Some(projected);
                 }
 
-                // 5. Global predicates
-                for (i, ref p) in qg.global_predicates.iter().enumerate() {
+                // 5. Global predicates, and predicates on aggregation outputs
+                let aggregate_predicates = sorted_rels
+                    .iter()
+                    .filter(|rel| **rel != "computed_columns")
+                    .flat_map(|rel| qg.relations[*rel].predicates.iter())
+                    .filter(|p| references_aggregate(p));
+                for (i, ref p) in qg
+                    .global_predicates
+                    .iter()
+                    .chain(aggregate_predicates)
+                    .enumerate()
+                {
                     if created_predicates.contains(p) {
                         continue;
                     }
@@ -1862,7 +4255,8 @@ impl SqlToMirConverter {
 
                     let fns = self.make_predicate_nodes(
                         &format!(
-                            "q_{:x}_n{}_{}{}",
+                            "{}q_{:x}_n{}_{}{}",
+                            self.name_prefix,
                             qg.signature().hash,
                             new_node_count,
                             num_local_predicates + i,
@@ -1871,7 +4265,7 @@ impl SqlToMirConverter {
                         parent,
                         p,
                         0,
-                    );
+                    )?;
 
                     assert!(!fns.is_empty());
                     new_node_count += fns.len();
@@ -1888,25 +4282,74 @@ impl SqlToMirConverter {
                     node_for_rel[sorted_rels.last().unwrap()].clone()
                 };
 
-                // 7. Potentially insert TopK node below the final node
+                // 7. Potentially insert a Distinct node below the final node. This must come
+                // before any TopK, since the limit applies to the deduplicated rows.
+                if st.distinct {
+                    let mut group_by: Vec<Column> = qg
+                        .columns
+                        .iter()
+                        .filter_map(|oc| match *oc {
+                            OutputColumn::Data(ref c) => Some(Column::from(c)),
+                            OutputColumn::Arithmetic(_) | OutputColumn::Literal(_) => None,
+                        })
+                        .collect();
+                    // parameter columns end up in the leaf, so rows must be distinct per key
+                    for pc in qg.parameters() {
+                        let pc = Column::from(pc);
+                        if !group_by.contains(&pc) {
+                            group_by.push(pc);
+                        }
+                    }
+
+                    let distinct_node = self.make_distinct_node(
+                        &format!(
+                            "{}q_{:x}_n{}{}",
+                            self.name_prefix,
+                            qg.signature().hash,
+                            new_node_count,
+                            uformat
+                        ),
+                        final_node,
+                        group_by.iter().collect(),
+                        ordered_distinct,
+                    );
+                    func_nodes.push(distinct_node.clone());
+                    final_node = distinct_node;
+                    new_node_count += 1;
+                }
+
+                // 8. Potentially insert TopK node below the final node
                 // XXX(malte): this adds a bogokey if there are no parameter columns to do the TopK
                 // over, but we could end up in a stick place if we reconcile/combine multiple
                 // queries (due to security universes or due to compound select queries) that do
                 // not all have the bogokey!
                 if let Some(ref limit) = st.limit {
+                    // readers rank the rows of each key to pick the first ones themselves
+                    if read_time_limit && st.order.is_none() {
+                        return Err(MirConversionError::UnsupportedParameters(format!(
+                            "Query \"{}\" takes its limit at read time, which needs an ORDER BY",
+                            name
+                        )));
+                    }
                     let group_by = if qg.parameters().is_empty() {
                         // need to add another projection to introduce a bogokey to group by
                         let cols: Vec<_> = final_node.borrow().columns().to_vec();
-                        let table =
-                            format!("q_{:x}_n{}{}", qg.signature().hash, new_node_count, uformat);
+                        let table = format!(
+                            "{}q_{:x}_n{}{}",
+                            self.name_prefix,
+                            qg.signature().hash,
+                            new_node_count,
+                            uformat
+                        );
                         let bogo_project = self.make_project_node(
                             &table,
                             final_node.clone(),
                             cols.iter().collect(),
                             vec![],
                             vec![("bogokey".into(), DataType::from(0 as i32))],
+                            vec![],
                             false,
-                        );
+                        )?;
                         new_node_count += 1;
                         nodes_added.push(bogo_project.clone());
                         final_node = bogo_project;
@@ -1917,11 +4360,18 @@ impl SqlToMirConverter {
                     };
 
                     let topk_node = self.make_topk_node(
-                        &format!("q_{:x}_n{}{}", qg.signature().hash, new_node_count, uformat),
+                        &format!(
+                            "{}q_{:x}_n{}{}",
+                            self.name_prefix,
+                            qg.signature().hash,
+                            new_node_count,
+                            uformat
+                        ),
                         final_node,
                         group_by.iter().collect(),
                         &st.order,
                         limit,
+                        read_time_limit,
                     );
                     func_nodes.push(topk_node.clone());
                     final_node = topk_node;
@@ -1943,12 +4393,12 @@ impl SqlToMirConverter {
                 }
 
                 let (nodes, tables, union_base_node_name) = self.reconcile(
-                    &format!("q_{:x}{}", qg.signature().hash, uformat),
+                    &format!("{}q_{:x}{}", self.name_prefix, qg.signature().hash, uformat),
                     &qg,
                     &ancestors,
                     new_node_count,
                     sec_round,
-                );
+                )?;
 
                 if sec_round {
                     table_mapping = tables;
@@ -1963,7 +4413,7 @@ impl SqlToMirConverter {
             };
 
             let final_node_cols: Vec<Column> = final_node.borrow().columns().to_vec();
-            // 8. Generate leaf views that expose the query result
+            // 9. Generate leaf views that expose the query result
             let mut projected_columns: Vec<Column> = if universe.1.is_none() {
                 qg.columns
                     .iter()
@@ -1981,13 +4431,56 @@ impl SqlToMirConverter {
                 final_node_cols.to_vec()
             };
 
+            // a parameter compared against a function of its column keys the leaf on a column
+            // that computes the function instead, so the column itself need not be projected
+            let computed_params = match self.computed_parameters.get(name) {
+                Some(cps) if has_leaf => cps.clone(),
+                _ => vec![],
+            };
+            for &(ref param, _, _) in &computed_params {
+                if !qg
+                    .parameters()
+                    .into_iter()
+                    .any(|pc| Column::from(pc) == *param)
+                {
+                    return Err(MirConversionError::UnsupportedParameters(format!(
+                        "Query \"{}\" has no parameter {} to compute a key from",
+                        name, param.name
+                    )));
+                }
+            }
+            let computed_param = |pc: &Column| {
+                computed_params
+                    .iter()
+                    .find(|&&(ref param, _, _)| param == pc)
+                    .map(|&(_, ref n, _)| Column::new(None, n))
+            };
+
             for pc in qg.parameters() {
                 let pc = Column::from(pc);
-                if !projected_columns.contains(&pc) {
+                if computed_param(&pc).is_none() && !projected_columns.contains(&pc) {
                     projected_columns.push(pc);
                 }
             }
 
+            // a group's column policies hide columns from its views; this includes the parameter
+            // columns added above, which the view would otherwise expose as its key
+            if universe.1.is_some() {
+                if let Some(pc) = qg
+                    .parameters()
+                    .into_iter()
+                    .map(Column::from)
+                    .find(|pc| !self.universe.allows_column(pc))
+                {
+                    return Err(MirConversionError::UnsupportedParameters(format!(
+                        "Query \"{}\" looks up rows by column \"{}\", which a column policy \
+                         hides",
+                        name, pc.name
+                    )));
+                }
+                projected_columns.retain(|c| self.universe.allows_column(c));
+            }
+
             // We may already have added some of the arithmetic and literal columns
             let (_, already_computed): (Vec<_>, Vec<_>) =
                 value_columns_needed_for_predicates(&qg.columns, &qg.global_predicates)
@@ -2026,8 +4519,17 @@ impl SqlToMirConverter {
                 })
                 .collect();
 
-            // if this query does not have any parameters, we must add a bogokey
-            let has_bogokey = if has_leaf && qg.parameters().is_empty() {
+            let unkeyed = materialization == Some(MaterializationMode::Unkeyed);
+            if has_leaf && unkeyed && !qg.parameters().is_empty() {
+                return Err(MirConversionError::UnsupportedParameters(format!(
+                    "Query \"{}\" has parameters, so its view cannot be unkeyed",
+                    name
+                )));
+            }
+
+            // if this query does not have any parameters, we must add a bogokey, unless its view
+            // is read in its entirety rather than by key
+            let has_bogokey = if has_leaf && qg.parameters().is_empty() && !unkeyed {
                 // only add the bogokey if we haven't already added it prior to a TopK above
                 if !projected_columns.contains(&Column::new(None, "bogokey")) {
                     projected_literals.push(("bogokey".into(), DataType::from(0 as i32)));
@@ -2038,7 +4540,13 @@ impl SqlToMirConverter {
             };
 
             let ident = if has_leaf {
-                format!("q_{:x}_n{}{}", qg.signature().hash, new_node_count, uformat)
+                format!(
+                    "{}q_{:x}_n{}{}",
+                    self.name_prefix,
+                    qg.signature().hash,
+                    new_node_count,
+                    uformat
+                )
             } else {
                 String::from(name)
             };
@@ -2049,11 +4557,30 @@ impl SqlToMirConverter {
                 projected_columns.iter().collect(),
                 projected_arithmetic,
                 projected_literals,
+                computed_params
+                    .iter()
+                    .map(|&(_, ref n, ref f)| (n.clone(), f.clone()))
+                    .collect(),
                 !has_leaf,
-            );
+            )?;
 
             nodes_added.push(leaf_project_node.clone());
 
+            // the reader ranks rows by the ORDER BY columns, so it needs to see them
+            if read_time_limit && st.limit.is_some() {
+                let leaf_columns = leaf_project_node.borrow().columns().to_vec();
+                for (c, _) in &st.order.as_ref().unwrap().columns {
+                    let c = Column::from(c);
+                    if !leaf_columns.contains(&c) {
+                        return Err(MirConversionError::UnsupportedParameters(format!(
+                            "Query \"{}\" takes its limit at read time, so it must select the \
+                             column \"{}\" it is ordered by",
+                            name, c.name
+                        )));
+                    }
+                }
+            }
+
             if has_leaf {
                 // We are supposed to add a `MaterializedLeaf` node keyed on the query
                 // parameters. For purely internal views (e.g., subqueries), this is not set.
@@ -2068,12 +4595,46 @@ impl SqlToMirConverter {
                     })
                     .collect();
 
-                let query_params = if has_bogokey {
+                let query_params: Vec<Column> = if has_bogokey {
                     vec![Column::new(None, "bogokey")]
                 } else {
-                    qg.parameters().into_iter().map(Column::from).collect()
+                    qg.parameters()
+                        .into_iter()
+                        .map(|pc| {
+                            let pc = Column::from(pc);
+                            computed_param(&pc).unwrap_or(pc)
+                        })
+                        .collect()
+                };
+
+                // a range lookup needs an ordered key, which we only support on a single column
+                let ranged = !qg.range_parameters().is_empty();
+                if ranged && query_params.len() != 1 {
+                    return Err(MirConversionError::UnsupportedParameters(format!(
+                        "Query \"{}\" compares a parameter by range, but range lookups are only \
+                         supported on a single parameter column",
+                        name
+                    )));
+                }
+
+                // a column compared with a parameter on one side only (`x > ?`) is looked up by a
+                // single value, beyond which all keys match
+                let open_range = if ranged {
+                    qg.open_range_parameter().map(|(_, op)| op.clone())
+                } else {
+                    None
                 };
 
+                // a set of values is read as one key per value, which only makes sense when the
+                // leaf is keyed on that column alone
+                if !qg.set_parameters().is_empty() && (query_params.len() != 1 || ranged) {
+                    return Err(MirConversionError::UnsupportedParameters(format!(
+                        "Query \"{}\" compares a parameter against a list of placeholders, but \
+                         set lookups are only supported on a single parameter column",
+                        name
+                    )));
+                }
+
                 let leaf_node = MirNode::new(
                     name,
                     self.schema_version,
@@ -2081,6 +4642,9 @@ impl SqlToMirConverter {
                     MirNodeType::Leaf {
                         node: leaf_project_node.clone(),
                         keys: query_params,
+                        ranged,
+                        open_range,
+                        materialization,
                     },
                     vec![leaf_project_node.clone()],
                     vec![],
@@ -2093,7 +4657,14 @@ impl SqlToMirConverter {
                 "Added final MIR node for query named \"{}\"", name
             );
         }
+        if let Some(LoweringHook(ref hook)) = self.post_lowering_hook {
+            hook(&mut nodes_added);
+        }
+
         // finally, we output all the nodes we generated
         Ok((sec_round, nodes_added, table_mapping, union_base_name))
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -1,4 +1,4 @@
-use crate::controller::sql::mir::SqlToMirConverter;
+use crate::controller::sql::mir::{MirConversionError, SqlToMirConverter};
 use mir::node::{MirNode, MirNodeType};
 use mir::MirNodeRef;
 
@@ -8,7 +8,7 @@ pub(super) fn make_rewrite_nodes(
     prev_node: MirNodeRef,
     table: &str,
     node_count: usize,
-) -> Result<Vec<MirNodeRef>, String> {
+) -> Result<Vec<MirNodeRef>, MirConversionError> {
     let mut nodes = Vec::new();
     let rewrite_policies = match mir_converter
         .universe
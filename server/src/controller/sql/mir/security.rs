@@ -1,5 +1,5 @@
 use crate::controller::sql::mir::rewrite::make_rewrite_nodes;
-use crate::controller::sql::mir::SqlToMirConverter;
+use crate::controller::sql::mir::{MirConversionError, SqlToMirConverter};
 use crate::controller::sql::query_graph::QueryGraph;
 use crate::controller::sql::query_signature::Signature;
 use crate::controller::sql::UniverseId;
@@ -14,18 +14,21 @@ pub trait SecurityBoundary {
         ancestors: &[MirNodeRef],
         node_count: usize,
         sec: bool,
-    ) -> (
-        Vec<MirNodeRef>,
-        Option<HashMap<(String, Option<String>), String>>,
-        String,
-    );
+    ) -> Result<
+        (
+            Vec<MirNodeRef>,
+            Option<HashMap<(String, Option<String>), String>>,
+            String,
+        ),
+        MirConversionError,
+    >;
 
     fn make_security_boundary(
         &self,
         universe: UniverseId,
         node_for_rel: &mut HashMap<&str, MirNodeRef>,
         prev_node: Option<MirNodeRef>,
-    ) -> Result<(Vec<MirNodeRef>, Vec<MirNodeRef>), String>;
+    ) -> Result<(Vec<MirNodeRef>, Vec<MirNodeRef>), MirConversionError>;
 }
 
 impl SecurityBoundary for SqlToMirConverter {
@@ -36,11 +39,14 @@ impl SecurityBoundary for SqlToMirConverter {
         ancestors: &[MirNodeRef],
         node_count: usize,
         sec: bool,
-    ) -> (
-        Vec<MirNodeRef>,
-        Option<HashMap<(String, Option<String>), String>>,
-        String,
-    ) {
+    ) -> Result<
+        (
+            Vec<MirNodeRef>,
+            Option<HashMap<(String, Option<String>), String>>,
+            String,
+        ),
+        MirConversionError,
+    > {
         use crate::controller::sql::mir::grouped::make_grouped;
 
         let mut nodes_added = Vec::new();
@@ -79,10 +85,10 @@ impl SecurityBoundary for SqlToMirConverter {
                     node_count,
                     &mut Some(node.clone()),
                     true,
-                );
+                )?;
 
                 nodes_added.extend(grouped);
-                (nodes_added, mapping, n)
+                Ok((nodes_added, mapping, n))
             }
             None => {
                 panic!("union not computed correctly");
@@ -96,7 +102,7 @@ impl SecurityBoundary for SqlToMirConverter {
         universe: UniverseId,
         node_for_rel: &mut HashMap<&str, MirNodeRef>,
         prev_node: Option<MirNodeRef>,
-    ) -> Result<(Vec<MirNodeRef>, Vec<MirNodeRef>), String> {
+    ) -> Result<(Vec<MirNodeRef>, Vec<MirNodeRef>), MirConversionError> {
         let mut security_nodes: Vec<MirNodeRef> = Vec::new();
         let mut last_security_nodes: Vec<MirNodeRef> = Vec::new();
         let mut prev_node = prev_node.unwrap().clone();
@@ -141,7 +147,7 @@ fn make_security_nodes(
     table: &str,
     prev_node: &MirNodeRef,
     node_for_rel: HashMap<&str, MirNodeRef>,
-) -> Result<(Vec<MirNodeRef>, Vec<MirNodeRef>), String> {
+) -> Result<(Vec<MirNodeRef>, Vec<MirNodeRef>), MirConversionError> {
     let policies = match mir_converter
         .universe
         .row_policies
@@ -213,11 +219,16 @@ fn make_security_nodes(
 
             for pred in &qgn.predicates {
                 let new_nodes = mir_converter.make_predicate_nodes(
-                    &format!("sp_{:x}_n{:x}", qg.signature().hash, node_count),
+                    &format!(
+                        "{}sp_{:x}_n{:x}",
+                        mir_converter.name_prefix,
+                        qg.signature().hash,
+                        node_count
+                    ),
                     prev_node.expect("empty previous node"),
                     pred,
                     0,
-                );
+                )?;
 
                 prev_node = Some(
                     new_nodes
@@ -235,11 +246,11 @@ fn make_security_nodes(
 
         let join_nodes = make_joins(
             mir_converter,
-            &format!("sp_{:x}", qg.signature().hash),
+            &format!("{}sp_{:x}", mir_converter.name_prefix, qg.signature().hash),
             qg,
             &local_node_for_rel,
             node_count,
-        );
+        )?;
 
         node_count += join_nodes.len();
 
@@ -250,7 +261,7 @@ fn make_security_nodes(
 
         let rewrite_nodes = make_rewrite_nodes(
             mir_converter,
-            &format!("sp_{:x}", qg.signature().hash),
+            &format!("{}sp_{:x}", mir_converter.name_prefix, qg.signature().hash),
             prev_node,
             table,
             node_count,
@@ -0,0 +1,2067 @@
+use super::*;
+use crate::controller::sql::query_graph::to_query_graph;
+use nom_sql::parser as sql_parser;
+use nom_sql::SqlQuery;
+
+/// Parses `q`, which must be a `SELECT` query.
+fn parse_select(q: &str) -> SelectStatement {
+    match sql_parser::parse_query(q).unwrap() {
+        SqlQuery::Select(st) => st,
+        _ => unreachable!(),
+    }
+}
+
+/// Creates a converter with a base for each of the `CREATE TABLE` statements in `tables`.
+fn converter_with_bases(tables: &[&str]) -> SqlToMirConverter {
+    let mut converter = SqlToMirConverter::default();
+    for ct in tables {
+        let ct = sql_parser::parse_query(ct).unwrap();
+        let name = match ct {
+            SqlQuery::CreateTable(ref ct) => ct.table.name.clone(),
+            _ => unreachable!(),
+        };
+        converter.named_base_to_mir(&name, &ct);
+    }
+    converter
+}
+
+/// Converts the `SELECT` query `q` into the view `name` of the global universe.
+fn convert(
+    converter: &mut SqlToMirConverter,
+    name: &str,
+    q: &str,
+) -> Result<MirQuery, MirConversionError> {
+    let st = parse_select(q);
+    let qg = to_query_graph(&st).unwrap();
+    converter
+        .named_query_to_mir(name, &st, &qg, true, ("global".into(), None), None)
+        .map(|(_, mq, _, _)| mq)
+}
+
+#[test]
+fn it_runs_post_lowering_hook() {
+    let mut converter =
+        converter_with_bases(&["CREATE TABLE post (id int, author int, content text);"]);
+
+    // tag every node that the query added (i.e., all but the reused base)
+    converter.set_post_lowering_hook(std::sync::Arc::new(|nodes: &mut Vec<MirNodeRef>| {
+        for n in nodes.iter() {
+            let mut n = n.borrow_mut();
+            if !n.ancestors().is_empty() {
+                n.name = format!("{}_hooked", n.name);
+            }
+        }
+    }));
+
+    let mq = convert(
+        &mut converter,
+        "posts",
+        "SELECT post.id, post.content FROM post WHERE post.author = ?;",
+    )
+    .unwrap();
+
+    assert_eq!(mq.leaf.borrow().name(), "posts_hooked");
+    assert_eq!(mq.roots.len(), 1);
+    assert_eq!(mq.roots[0].borrow().name(), "post");
+    assert!(converter.get_view("posts_hooked").is_ok());
+}
+
+#[test]
+fn it_prefixes_generated_node_names() {
+    fn generated_names(prefix: &str) -> HashSet<String> {
+        let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int, b int);"]);
+        converter.set_name_prefix(prefix);
+        let mq = convert(
+            &mut converter,
+            "q",
+            "SELECT t.id, COUNT(t.a) AS c FROM t WHERE t.a = 1 AND t.b = ? GROUP BY t.id;",
+        )
+        .unwrap();
+
+        // everything between the (reused) base and the leaf has a generated name
+        let mut names = HashSet::new();
+        let mut queue: Vec<_> = mq.leaf.borrow().ancestors().to_vec();
+        while let Some(n) = queue.pop() {
+            let n = n.borrow();
+            if !n.ancestors().is_empty() {
+                names.insert(String::from(n.name()));
+                queue.extend(n.ancestors().iter().cloned());
+            }
+        }
+        names
+    }
+
+    let a = generated_names("tenant_a_");
+    let b = generated_names("tenant_b_");
+    assert!(!a.is_empty());
+    assert!(a.iter().all(|n| n.starts_with("tenant_a_q_")));
+    assert!(b.iter().all(|n| n.starts_with("tenant_b_q_")));
+    assert!(a.is_disjoint(&b));
+}
+
+#[test]
+fn it_groups_join_output_by_columns_of_either_table() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE a (id int, region text);",
+        "CREATE TABLE b (id int, aid int, amount int);",
+    ]);
+
+    fn find_aggregation(n: &MirNodeRef) -> Option<MirNodeRef> {
+        if let MirNodeType::Aggregation { .. } = n.borrow().inner {
+            return Some(n.clone());
+        }
+        n.borrow().ancestors().iter().find_map(find_aggregation)
+    }
+
+    // with and without an explicit GROUP BY, the sum is grouped by the region from `a`
+    for (name, q) in &[
+        (
+            "explicit",
+            "SELECT a.region, SUM(b.amount) AS total FROM a JOIN b ON a.id = b.aid \
+             GROUP BY a.region;",
+        ),
+        (
+            "implied",
+            "SELECT a.region, SUM(b.amount) AS total FROM a JOIN b ON a.id = b.aid;",
+        ),
+    ] {
+        let mq = convert(&mut converter, name, q).unwrap();
+
+        let agg = find_aggregation(&mq.leaf).unwrap();
+        let agg = agg.borrow();
+        match agg.inner {
+            MirNodeType::Aggregation { ref group_by, .. } => {
+                assert_eq!(group_by, &vec![Column::new(Some("a"), "region")]);
+            }
+            _ => unreachable!(),
+        }
+        match agg.ancestors()[0].borrow().inner {
+            MirNodeType::Join { .. } => (),
+            ref n => panic!("aggregation should read from the join, not {:?}", n),
+        }
+    }
+}
+
+#[test]
+fn it_computes_counts_and_sums_over_a_group_in_one_node() {
+    use dataflow::ops::grouped::aggregate::Aggregation;
+
+    let mut converter =
+        converter_with_bases(&["CREATE TABLE votes (aid int, uid int, weight int);"]);
+
+    convert(
+        &mut converter,
+        "tally",
+        "SELECT votes.aid, COUNT(votes.uid) AS n, SUM(votes.weight) AS total FROM votes \
+         WHERE votes.aid = ? GROUP BY votes.aid;",
+    )
+    .unwrap();
+
+    let grouped = converter.find_nodes("tally", |n| match *n {
+        MirNodeType::Aggregation { .. }
+        | MirNodeType::MultiAggregation { .. }
+        | MirNodeType::Join { .. } => true,
+        _ => false,
+    });
+    assert_eq!(grouped.len(), 1);
+    let node = grouped[0].borrow();
+    match node.inner {
+        MirNodeType::MultiAggregation {
+            ref group_by,
+            ref aggregates,
+        } => {
+            assert_eq!(group_by, &vec![Column::new(Some("votes"), "aid")]);
+            assert_eq!(
+                aggregates,
+                &vec![
+                    (Column::new(Some("votes"), "uid"), Aggregation::COUNT),
+                    (Column::new(Some("votes"), "weight"), Aggregation::SUM),
+                ]
+            );
+        }
+        ref n => panic!("expected a multi-aggregation, got {:?}", n),
+    }
+    let names: Vec<_> = node.columns().iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["aid", "n", "total"]);
+}
+
+#[test]
+fn it_estimates_node_count_without_lowering() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE a (id int, region text);",
+        "CREATE TABLE b (id int, aid int, amount int);",
+    ]);
+
+    let st = parse_select(
+        "SELECT a.region, SUM(b.amount) AS total FROM a JOIN b ON a.id = b.aid \
+         GROUP BY a.region ORDER BY total DESC LIMIT 3;",
+    );
+    let qg = to_query_graph(&st).unwrap();
+
+    let estimate = converter.estimate_node_count(&st, &qg, true).unwrap();
+    // the estimate must not register any nodes
+    assert!(converter.current.get("q").is_none());
+    assert_eq!(converter.nodes.len(), 2);
+
+    converter
+        .named_query_to_mir("q", &st, &qg, true, ("global".into(), None), None)
+        .unwrap();
+    // all new nodes get registered next to the two bases
+    let lowered = converter.nodes.len() - 2;
+    // at least a join, an aggregation, a top-k, a projection and a leaf
+    assert!(estimate >= 5);
+    assert_eq!(estimate, lowered);
+
+    // queries that cannot be lowered report why rather than looking free
+    let st = parse_select("SELECT a.nope FROM a;");
+    let qg = to_query_graph(&st).unwrap();
+    let err = converter.estimate_node_count(&st, &qg, true).unwrap_err();
+    assert_eq!(err.code(), "UNKNOWN_COLUMN");
+}
+
+#[test]
+fn it_explains_queries() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE a (id int, region text);",
+        "CREATE TABLE b (id int, aid int, amount int);",
+    ]);
+
+    convert(
+        &mut converter,
+        "q",
+        "SELECT a.region, SUM(b.amount) AS total FROM a JOIN b ON a.id = b.aid \
+         GROUP BY a.region ORDER BY total DESC LIMIT 3;",
+    )
+    .unwrap();
+
+    let plan = converter.explain("q").unwrap();
+    let lines: Vec<_> = plan.lines().collect();
+    // the plan starts at the leaf, and ancestors are indented below their children
+    assert!(lines[0].starts_with("Leaf "));
+    assert!(lines[1].starts_with("  "));
+    for op in &["Leaf", "Project", "TopK", "Aggregation", "Join", "Base"] {
+        assert!(
+            lines.iter().any(|l| l.trim_start().starts_with(op)),
+            "no {} in plan:\n{}",
+            op,
+            plan
+        );
+    }
+    assert!(plan.contains("k: 3"));
+    assert!(plan.contains("id:aid"));
+    // the bases existed before the query, so the query reuses them
+    assert!(lines
+        .iter()
+        .filter(|l| l.trim_start().starts_with("Base"))
+        .all(|l| l.ends_with("(reused)")));
+
+    assert_eq!(converter.explain("nonexistent"), None);
+}
+
+#[test]
+fn it_emits_grouped_columns_in_select_order() {
+    let mut converter = converter_with_bases(&["CREATE TABLE emp (id int, dept int, salary int);"]);
+
+    // the aggregation emits its group column first, but the SELECT list puts it last
+    let mq = convert(
+        &mut converter,
+        "q",
+        "SELECT SUM(emp.salary) AS total, emp.dept FROM emp GROUP BY emp.dept;",
+    )
+    .unwrap();
+
+    let leaf = mq.leaf.borrow();
+    let names: Vec<&str> = leaf.columns().iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["total", "dept", "bogokey"]);
+}
+
+#[test]
+fn it_pushes_single_relation_filters_below_joins() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE a (id int, region text);",
+        "CREATE TABLE b (id int, aid int, amount int);",
+    ]);
+
+    fn find_join(n: &MirNodeRef) -> Option<MirNodeRef> {
+        match n.borrow().inner {
+            MirNodeType::Join { .. } | MirNodeType::LeftJoin { .. } => return Some(n.clone()),
+            _ => (),
+        }
+        n.borrow().ancestors().iter().find_map(find_join)
+    }
+    let is_filter = |n: &MirNodeRef| match n.borrow().inner {
+        MirNodeType::Filter { .. } => true,
+        _ => false,
+    };
+
+    for (name, q, pushed) in &[
+        (
+            "inner",
+            "SELECT a.id, b.amount FROM a JOIN b ON a.id = b.aid WHERE a.region = 'east';",
+            true,
+        ),
+        // filtering the nullable side must also drop the NULL-extended rows
+        (
+            "left",
+            "SELECT a.id, b.amount FROM a LEFT JOIN b ON a.id = b.aid WHERE b.amount = 1;",
+            false,
+        ),
+    ] {
+        let mq = convert(&mut converter, name, q).unwrap();
+
+        let join = find_join(&mq.leaf).unwrap();
+        let join = join.borrow();
+        let filter_below = join.ancestors().iter().any(|a| is_filter(a));
+        let filter_above = is_filter(&join.children()[0]);
+        assert_eq!(filter_below, *pushed);
+        assert_eq!(filter_above, !*pushed);
+    }
+}
+
+#[test]
+fn it_shares_identical_filters() {
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int, b int, c int);"]);
+
+    // both sides of the OR start with the same `t.a = 1` filter over the base
+    let mq = convert(
+        &mut converter,
+        "q",
+        "SELECT t.id FROM t \
+         WHERE (t.a = 1 AND (t.b = 2 OR t.b = 3)) OR (t.a = 1 AND (t.c = 4 OR t.c = 5));",
+    )
+    .unwrap();
+
+    assert_eq!(mq.roots.len(), 1);
+    let base_filters: Vec<_> = mq.roots[0]
+        .borrow()
+        .children()
+        .iter()
+        .filter(|c| match c.borrow().inner {
+            MirNodeType::Filter { .. } => true,
+            _ => false,
+        })
+        .cloned()
+        .collect();
+    assert_eq!(base_filters.len(), 1);
+    // and the shared filter feeds both inner ORs
+    let shared = base_filters[0].borrow();
+    assert_eq!(shared.children().len(), 4);
+}
+
+#[test]
+fn it_treats_both_inequality_spellings_alike() {
+    fn filter_conditions(q: &str) -> Vec<Vec<(usize, FilterCondition)>> {
+        let mut converter =
+            converter_with_bases(&["CREATE TABLE t (id int, a int, b text, c int);"]);
+        let mq = convert(&mut converter, "q", q).unwrap();
+
+        let mut conditions = Vec::new();
+        let mut n = mq.leaf.clone();
+        loop {
+            if let MirNodeType::Filter { conditions: ref c } = n.borrow().inner {
+                conditions.push(c.clone());
+            }
+            let next = match n.borrow().ancestors().first() {
+                Some(a) => a.clone(),
+                None => break,
+            };
+            n = next;
+        }
+        conditions
+    }
+
+    let ne = filter_conditions("SELECT t.id FROM t WHERE (t.a != 1 AND t.b != 'x') AND t.c != 3;");
+    let lg = filter_conditions("SELECT t.id FROM t WHERE (t.a <> 1 AND t.b <> 'x') AND t.c <> 3;");
+    let mixed =
+        filter_conditions("SELECT t.id FROM t WHERE (t.a <> 1 AND t.b != 'x') AND t.c <> 3;");
+    assert_eq!(ne, lg);
+    assert_eq!(ne, mixed);
+
+    // the bracketed conjunction is evaluated by the same filter node as the rest
+    assert_eq!(ne.len(), 1);
+    assert_eq!(ne[0].len(), 3);
+    assert!(ne[0].iter().all(|(_, c)| match *c {
+        FilterCondition::Comparison(Operator::NotEqual, _) => true,
+        _ => false,
+    }));
+}
+
+#[test]
+fn it_keys_derived_leaf_on_computed_expression() {
+    use nom_sql::{FieldDefinitionExpression, FieldValueExpression};
+
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+    let mq = convert(
+        &mut converter,
+        "q",
+        "SELECT t.id, t.a FROM t WHERE t.a = ?;",
+    )
+    .unwrap();
+
+    // bucket rows by id, e.g., to spread lookups across shards
+    let bucket = match sql_parser::parse_query("SELECT t.id / 100 AS bucket FROM t;").unwrap() {
+        SqlQuery::Select(mut st) => match st.fields.remove(0) {
+            FieldDefinitionExpression::Value(FieldValueExpression::Arithmetic(ae)) => ae,
+            f => unreachable!("{:?}", f),
+        },
+        _ => unreachable!(),
+    };
+    let leaf_project = mq.leaf.borrow().ancestors()[0].clone();
+    let derived = converter
+        .add_leaf_below(
+            leaf_project,
+            "q_by_bucket",
+            &[],
+            vec![(String::from("bucket"), bucket.clone())],
+            None,
+            None,
+        )
+        .unwrap();
+
+    let leaf = derived.leaf.borrow();
+    let names: Vec<_> = leaf.columns().iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["id", "a", "bucket"]);
+    match leaf.inner {
+        MirNodeType::Leaf { ref keys, .. } => {
+            assert_eq!(keys, &vec![Column::new(None, "bucket")]);
+        }
+        _ => unreachable!(),
+    }
+    let reproject = leaf.ancestors()[0].borrow();
+    match reproject.inner {
+        MirNodeType::Project {
+            ref emit,
+            ref arithmetic,
+            ..
+        } => {
+            assert_eq!(emit.len(), 2);
+            assert_eq!(arithmetic, &vec![(String::from("bucket"), bucket)]);
+        }
+        _ => panic!("expected a reprojection, got {:?}", reproject),
+    }
+}
+
+#[test]
+fn it_records_materialization_mode_on_leaves() {
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+    let st = parse_select("SELECT t.id, t.a FROM t WHERE t.id = ?;");
+    let qg = to_query_graph(&st).unwrap();
+
+    let leaf_mode = |mq: &MirQuery| match mq.leaf.borrow().inner {
+        MirNodeType::Leaf {
+            materialization, ..
+        } => materialization,
+        _ => unreachable!(),
+    };
+
+    for (name, mode) in &[("full", Some(MaterializationMode::Full)), ("default", None)] {
+        let (_, mq, _, _) = converter
+            .named_query_to_mir(name, &st, &qg, true, ("global".into(), None), *mode)
+            .unwrap();
+        assert_eq!(leaf_mode(&mq), *mode);
+    }
+
+    // leaves added below an existing query record their mode, too
+    let prior = converter.nodes[&(String::from("default"), 0)]
+        .borrow()
+        .ancestors()[0]
+        .clone();
+    let mq = converter
+        .add_leaf_below(
+            prior.clone(),
+            "by_a",
+            &[Column::new(Some("t"), "a")],
+            vec![],
+            None,
+            Some(MaterializationMode::Full),
+        )
+        .unwrap();
+    assert_eq!(leaf_mode(&mq), Some(MaterializationMode::Full));
+}
+
+#[test]
+fn it_rejects_unknown_leaf_projection_columns() {
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, amount int);"]);
+    let mq = convert(
+        &mut converter,
+        "q",
+        "SELECT t.id, t.amount FROM t WHERE t.id = ?;",
+    )
+    .unwrap();
+    let prior = mq.leaf.borrow().ancestors()[0].clone();
+
+    match converter.add_leaf_below(
+        prior.clone(),
+        "typo",
+        &[Column::new(Some("t"), "id")],
+        vec![],
+        Some(vec![Column::new(Some("t"), "amuont")]),
+        None,
+    ) {
+        Err(MirConversionError::UnknownColumn { column, available }) => {
+            assert_eq!(column, "amuont");
+            assert_eq!(available, vec![String::from("amount")]);
+        }
+        r => panic!("expected an unknown column error, got {:?}", r),
+    }
+    // no leaf was registered for the failed view
+    assert!(converter.get_view("typo").is_err());
+
+    let mq = converter
+        .add_leaf_below(
+            prior,
+            "amounts",
+            &[Column::new(Some("t"), "id")],
+            vec![],
+            Some(vec![Column::new(Some("t"), "amount")]),
+            None,
+        )
+        .unwrap();
+    let names: Vec<_> = mq
+        .leaf
+        .borrow()
+        .columns()
+        .iter()
+        .map(|c| c.name.clone())
+        .collect();
+    assert_eq!(names, vec!["amount", "id"]);
+}
+
+#[test]
+fn it_marks_read_time_limits_on_topk() {
+    fn convert_top(
+        converter: &mut SqlToMirConverter,
+        name: &str,
+        q: &str,
+    ) -> Result<MirQuery, MirConversionError> {
+        converter.set_read_time_limit(name);
+        convert(converter, name, q)
+    }
+
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+
+    let q = "SELECT t.id, t.a FROM t WHERE t.id = ? ORDER BY t.a LIMIT 5;";
+    convert_top(&mut converter, "top", q).unwrap();
+    let topks = converter.find_nodes("top", |t| match *t {
+        MirNodeType::TopK {
+            parameterized_k: true,
+            k: 5,
+            ..
+        } => true,
+        _ => false,
+    });
+    assert_eq!(topks.len(), 1);
+
+    // without an order, or without the ordering column, readers cannot rank the rows
+    for (name, q) in &[
+        (
+            "unordered",
+            "SELECT t.id, t.a FROM t WHERE t.id = ? LIMIT 5;",
+        ),
+        (
+            "hidden",
+            "SELECT t.id FROM t WHERE t.id = ? ORDER BY t.a LIMIT 5;",
+        ),
+    ] {
+        let err = convert_top(&mut converter, name, q).unwrap_err();
+        assert_eq!(err.code(), "UNSUPPORTED_PARAMETERS");
+    }
+}
+
+#[test]
+fn it_builds_unkeyed_leaves_without_bogokey() {
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+
+    let st = parse_select("SELECT t.id, t.a FROM t;");
+    let qg = to_query_graph(&st).unwrap();
+    let (_, mq, _, _) = converter
+        .named_query_to_mir(
+            "everything",
+            &st,
+            &qg,
+            true,
+            ("global".into(), None),
+            Some(MaterializationMode::Unkeyed),
+        )
+        .unwrap();
+    let leaf = mq.leaf.borrow();
+    match leaf.inner {
+        MirNodeType::Leaf { ref keys, .. } => assert!(keys.is_empty()),
+        _ => unreachable!(),
+    }
+    let names: Vec<_> = leaf.columns().iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["id", "a"]);
+
+    // a query with parameters must be read by key
+    let st = parse_select("SELECT t.id, t.a FROM t WHERE t.id = ?;");
+    let qg = to_query_graph(&st).unwrap();
+    let err = converter
+        .named_query_to_mir(
+            "by_id",
+            &st,
+            &qg,
+            true,
+            ("global".into(), None),
+            Some(MaterializationMode::Unkeyed),
+        )
+        .unwrap_err();
+    assert_eq!(err.code(), "UNSUPPORTED_PARAMETERS");
+}
+
+#[test]
+fn it_keys_leaf_on_placeholder_list() {
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+
+    // the list's values are given at read time, so they key the leaf rather than a filter
+    let st = parse_select("SELECT t.id, t.a FROM t WHERE t.a IN (?, ?, ?);");
+    let qg = to_query_graph(&st).unwrap();
+    assert_eq!(qg.set_parameters(), vec![&Column::new(Some("t"), "a")]);
+    let (_, mq, _, _) = converter
+        .named_query_to_mir("by_a", &st, &qg, true, ("global".into(), None), None)
+        .unwrap();
+    match mq.leaf.borrow().inner {
+        MirNodeType::Leaf {
+            ref keys, ranged, ..
+        } => {
+            assert_eq!(keys, &vec![Column::new(Some("t"), "a")]);
+            assert!(!ranged);
+        }
+        _ => unreachable!(),
+    }
+    let filters = converter.find_nodes("by_a", |n| match *n {
+        MirNodeType::Filter { .. } => true,
+        _ => false,
+    });
+    assert!(filters.is_empty());
+
+    // a set of values can't be combined with another parameter
+    let q = "SELECT t.id, t.a FROM t WHERE t.a IN (?, ?) AND t.id = ?;";
+    let err = convert(&mut converter, "by_a_and_id", q).unwrap_err();
+    assert_eq!(err.code(), "UNSUPPORTED_PARAMETERS");
+}
+
+#[test]
+fn it_attaches_leaf_directly_when_parent_matches() {
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+    let st = parse_select("SELECT t.id, t.a FROM t WHERE t.a = 1;");
+    let qg = to_query_graph(&st).unwrap();
+
+    let leaf_parent_name = |mq: &MirQuery| {
+        let leaf = mq.leaf.borrow();
+        let parent = leaf.ancestors()[0].borrow();
+        match parent.inner {
+            MirNodeType::Reuse { ref node } => String::from(node.borrow().name()),
+            _ => String::from(parent.name()),
+        }
+    };
+
+    // an internal view has no reader yet, so a leaf can read from its projection directly
+    let (_, internal, _, _) = converter
+        .named_query_to_mir("internal", &st, &qg, false, ("global".into(), None), None)
+        .unwrap();
+    let mq = converter
+        .add_leaf_below(internal.leaf.clone(), "direct", &[], vec![], None, None)
+        .unwrap();
+    assert_eq!(leaf_parent_name(&mq), "internal");
+
+    // but once the projection is read from, the next leaf needs an identity node of its own
+    let mq = converter
+        .add_leaf_below(internal.leaf.clone(), "indirect", &[], vec![], None, None)
+        .unwrap();
+    assert_eq!(leaf_parent_name(&mq), "indirect_id");
+
+    // a leaf below that identity node skips it, and gets an identity node right below the
+    // projection instead of one stacked onto the first identity node
+    let indirect_id = mq.leaf.borrow().ancestors()[0].clone();
+    let mq = converter
+        .add_leaf_below(indirect_id, "third", &[], vec![], None, None)
+        .unwrap();
+    assert_eq!(leaf_parent_name(&mq), "third_id");
+    let third_id = mq.leaf.borrow().ancestors()[0].clone();
+    let third_id_parent = third_id.borrow().ancestors()[0].clone();
+    assert_eq!(
+        mir::query::reuse_target(&third_id_parent).borrow().name(),
+        "internal"
+    );
+}
+
+#[test]
+fn it_lowers_empty_in_list_to_rejecting_filter() {
+    let mut converter = SqlToMirConverter::default();
+    let ct = sql_parser::parse_query("CREATE TABLE t (id int, a int);").unwrap();
+    let base = converter.named_base_to_mir("t", &ct).leaf;
+
+    let cond = ConditionTree {
+        operator: Operator::In,
+        left: Box::new(ConditionExpression::Base(ConditionBase::Field(
+            nom_sql::Column::from("t.a"),
+        ))),
+        right: Box::new(ConditionExpression::Base(ConditionBase::LiteralList(
+            vec![],
+        ))),
+    };
+    let mut columns = base.borrow().columns().to_vec();
+    let conditions = converter.to_conditions(&cond, &mut columns, &base).unwrap();
+    assert_eq!(conditions, vec![(1, FilterCondition::In(vec![]))]);
+}
+
+#[test]
+fn it_places_in_filters_on_the_last_column_of_wide_tables() {
+    let mut converter = SqlToMirConverter::default();
+    let cols: Vec<_> = (0..16).map(|i| format!("c{} int", i)).collect();
+    let ct = sql_parser::parse_query(&format!("CREATE TABLE t ({});", cols.join(", "))).unwrap();
+    let base = converter.named_base_to_mir("t", &ct).leaf;
+    let parent = converter.get_view("t").unwrap();
+
+    let in_list = |column: &str, values: Vec<i64>| ConditionTree {
+        operator: Operator::In,
+        left: Box::new(ConditionExpression::Base(ConditionBase::Field(
+            nom_sql::Column::from(column),
+        ))),
+        right: Box::new(ConditionExpression::Base(ConditionBase::LiteralList(
+            values.into_iter().map(Literal::Integer).collect(),
+        ))),
+    };
+    let values = |vs: &[i64]| vs.iter().map(|&v| DataType::from(v)).collect::<Vec<_>>();
+
+    // both on the base itself and through the reuse node that queries see
+    for n in &[base, parent] {
+        let mut columns = n.borrow().columns().to_vec();
+        let conditions = converter
+            .to_conditions(&in_list("t.c15", vec![1, 2, 3]), &mut columns, n)
+            .unwrap();
+        assert_eq!(
+            conditions,
+            vec![(15, FilterCondition::In(values(&[1, 2, 3])))]
+        );
+        assert_eq!(columns.len(), 16);
+
+        // a conjunction keeps each list on its own column
+        let both = ConditionTree {
+            operator: Operator::And,
+            left: Box::new(ConditionExpression::ComparisonOp(in_list("t.c0", vec![7]))),
+            right: Box::new(ConditionExpression::ComparisonOp(in_list(
+                "t.c14",
+                vec![8, 9],
+            ))),
+        };
+        let mut columns = n.borrow().columns().to_vec();
+        let conditions = converter
+            .logical_op_to_conditions(&both, &mut columns, n)
+            .unwrap();
+        assert!(conditions.contains(&(0, FilterCondition::In(values(&[7])))));
+        assert!(conditions.contains(&(14, FilterCondition::In(values(&[8, 9])))));
+    }
+}
+
+#[test]
+fn it_coerces_literals_to_column_types() {
+    use dataflow::ops::filter::Value;
+
+    let mut converter =
+        converter_with_bases(&["CREATE TABLE t (id int, price float, code varchar(8));"]);
+
+    let mut filter_on = |name: &str, q: &str| {
+        convert(&mut converter, name, q).unwrap();
+        let filters = converter.find_nodes(name, |t| match *t {
+            MirNodeType::Filter { .. } => true,
+            _ => false,
+        });
+        assert_eq!(filters.len(), 1);
+        let f = filters[0].borrow();
+        match f.inner {
+            MirNodeType::Filter { ref conditions } => conditions[0].1.clone(),
+            _ => unreachable!(),
+        }
+    };
+
+    // an integer literal matches the float stored for the same number
+    let cond = filter_on("by_price", "SELECT t.id FROM t WHERE t.price = 3;");
+    assert_eq!(
+        cond,
+        FilterCondition::Comparison(Operator::Equal, Value::Constant(DataType::from(3.0)))
+    );
+    match cond {
+        FilterCondition::Comparison(_, Value::Constant(ref v)) => {
+            assert_eq!(*v, DataType::from(3.0));
+            assert_ne!(*v, DataType::from(3));
+        }
+        _ => unreachable!(),
+    }
+    // as are the members of IN lists
+    let cond = filter_on("by_prices", "SELECT t.id FROM t WHERE t.price IN (3, 4);");
+    assert_eq!(
+        cond,
+        FilterCondition::In(vec![DataType::from(3.0), DataType::from(4.0)])
+    );
+
+    let cond = filter_on("by_code", "SELECT t.id FROM t WHERE t.code = 7;");
+    assert_eq!(
+        cond,
+        FilterCondition::Comparison(Operator::Equal, Value::Constant("7".into()))
+    );
+    let cond = filter_on("by_id", "SELECT t.code FROM t WHERE t.id = '42';");
+    assert_eq!(
+        cond,
+        FilterCondition::Comparison(Operator::Equal, Value::Constant(DataType::from(42i64)))
+    );
+    // strings that are not numbers are compared as they are
+    let cond = filter_on("by_id_text", "SELECT t.code FROM t WHERE t.id = 'x';");
+    assert_eq!(
+        cond,
+        FilterCondition::Comparison(Operator::Equal, Value::Constant("x".into()))
+    );
+}
+
+#[test]
+fn it_applies_column_collations() {
+    use dataflow::ops::filter::Value;
+
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE t (id int, name varchar(255) COLLATE utf8mb4_general_ci, code text);",
+    ]);
+
+    let mut node_on = |name: &str, q: &str, kind: &str| {
+        convert(&mut converter, name, q).unwrap();
+        let nodes = converter.find_nodes(name, |t| t.operator_name() == kind);
+        assert_eq!(nodes.len(), 1);
+        nodes[0].clone()
+    };
+    let condition = |n: MirNodeRef| {
+        let n = n.borrow();
+        match n.inner {
+            MirNodeType::Filter { ref conditions } => conditions[0].1.clone(),
+            _ => unreachable!(),
+        }
+    };
+
+    let n = node_on(
+        "by_name",
+        "SELECT t.id FROM t WHERE t.name = 'José';",
+        "Filter",
+    );
+    assert_eq!(
+        condition(n),
+        FilterCondition::CollatedComparison {
+            operator: Operator::Equal,
+            value: "José".into(),
+            collation: Collation::CaseInsensitive,
+        }
+    );
+    // columns without a COLLATE clause compare by bytes, as before
+    let n = node_on(
+        "by_code",
+        "SELECT t.id FROM t WHERE t.code = 'x';",
+        "Filter",
+    );
+    assert_eq!(
+        condition(n),
+        FilterCondition::Comparison(Operator::Equal, Value::Constant("x".into()))
+    );
+
+    let n = node_on(
+        "ordered",
+        "SELECT t.id, t.name FROM t ORDER BY t.name LIMIT 3;",
+        "TopK",
+    );
+    match n.borrow().inner {
+        MirNodeType::TopK { ref order, .. } => {
+            assert_eq!(order.as_ref().unwrap()[0].3, Collation::CaseInsensitive)
+        }
+        _ => unreachable!(),
+    };
+}
+
+#[test]
+fn it_keeps_only_the_tightest_bounds() {
+    use dataflow::ops::filter::Value;
+
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+    convert(
+        &mut converter,
+        "q",
+        "SELECT t.id, t.a FROM t WHERE t.a > 5 AND t.a > 10 AND t.id < 3;",
+    )
+    .unwrap();
+    let filters = converter.find_nodes("q", |t| match *t {
+        MirNodeType::Filter { .. } => true,
+        _ => false,
+    });
+    assert_eq!(filters.len(), 1);
+    match filters[0].borrow().inner {
+        MirNodeType::Filter { ref conditions } => assert_eq!(
+            conditions,
+            &vec![
+                (
+                    1,
+                    FilterCondition::Comparison(
+                        Operator::Greater,
+                        Value::Constant(DataType::from(10i64))
+                    )
+                ),
+                (
+                    0,
+                    FilterCondition::Comparison(
+                        Operator::Less,
+                        Value::Constant(DataType::from(3i64))
+                    )
+                ),
+            ]
+        ),
+        _ => unreachable!(),
+    }
+
+    let cond = |op: Operator, v: i64| {
+        (
+            0,
+            FilterCondition::Comparison(op, Value::Constant(DataType::from(v))),
+        )
+    };
+    // a strict bound is tighter than a non-strict one on the same constant
+    assert_eq!(
+        tighten_ranges(vec![
+            cond(Operator::GreaterOrEqual, 10),
+            cond(Operator::Greater, 10)
+        ]),
+        vec![cond(Operator::Greater, 10)]
+    );
+    // upper bounds tighten downwards, and lower and upper bounds do not affect each other
+    assert_eq!(
+        tighten_ranges(vec![
+            cond(Operator::Less, 7),
+            cond(Operator::Greater, 1),
+            cond(Operator::LessOrEqual, 4)
+        ]),
+        vec![cond(Operator::Greater, 1), cond(Operator::LessOrEqual, 4)]
+    );
+}
+
+#[test]
+fn it_rejects_filters_on_nodes_without_columns() {
+    let converter = SqlToMirConverter::default();
+    let empty = MirNode::new("empty", 0, vec![], MirNodeType::Identity, vec![], vec![]);
+
+    let cond = ConditionTree {
+        operator: Operator::Equal,
+        left: Box::new(ConditionExpression::Base(ConditionBase::Field(
+            nom_sql::Column::from("t.id"),
+        ))),
+        right: Box::new(ConditionExpression::Base(ConditionBase::Literal(
+            Literal::Integer(1),
+        ))),
+    };
+    let err = converter
+        .to_conditions(&cond, &mut vec![], &empty)
+        .unwrap_err();
+    assert_eq!(err.code(), "EMPTY_FILTER_INPUT");
+    assert!(err.to_string().contains("\"empty\""));
+}
+
+#[test]
+fn it_lowers_like_to_pattern_filter() {
+    let mut converter = SqlToMirConverter::default();
+    let ct = sql_parser::parse_query("CREATE TABLE t (id int, path text);").unwrap();
+    let base = converter.named_base_to_mir("t", &ct).leaf;
+
+    let cond = ConditionTree {
+        operator: Operator::NotLike,
+        left: Box::new(ConditionExpression::Base(ConditionBase::Field(
+            nom_sql::Column::from("t.path"),
+        ))),
+        right: Box::new(ConditionExpression::Base(ConditionBase::Literal(
+            Literal::String("a_%".into()),
+        ))),
+    };
+    let mut columns = base.borrow().columns().to_vec();
+    let conditions = converter.to_conditions(&cond, &mut columns, &base).unwrap();
+    assert_eq!(
+        conditions,
+        vec![(
+            1,
+            FilterCondition::Like {
+                pattern: "a_%".into(),
+                escape: None,
+                negated: true,
+            }
+        )]
+    );
+}
+
+#[test]
+fn it_reports_unsupported_features_by_variant() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE a (id int, x int);",
+        "CREATE TABLE b (id int, aid int);",
+    ]);
+
+    let mut lower = |name: &str, q: &str| convert(&mut converter, name, q).map(|_| ());
+    match lower("unknown", "SELECT c.id FROM c;") {
+        Err(MirConversionError::UnknownView(_)) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+    match lower("avg", "SELECT AVG(a.x) AS avg_x FROM a;") {
+        Err(MirConversionError::UnsupportedAggregation(_)) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+    match lower("theta", "SELECT a.id, b.id FROM a JOIN b ON a.id > b.aid;") {
+        Err(MirConversionError::UnsupportedJoinType(_)) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+
+    match converter.compound_query_to_mir(
+        "except",
+        vec![],
+        CompoundSelectOperator::Except,
+        &None,
+        &None,
+        true,
+    ) {
+        Err(MirConversionError::UnsupportedCompoundOp(_)) => (),
+        r => panic!("unexpected result {:?}", r.map(|mq| mq.name)),
+    }
+
+    let base = converter.get_view("a").unwrap();
+    let cond = ConditionTree {
+        operator: Operator::Equal,
+        left: Box::new(ConditionExpression::Base(ConditionBase::Literal(
+            Literal::Integer(1),
+        ))),
+        right: Box::new(ConditionExpression::Base(ConditionBase::Field(
+            nom_sql::Column::from("a.x"),
+        ))),
+    };
+    let mut columns = base.borrow().columns().to_vec();
+    let err = converter
+        .to_conditions(&cond, &mut columns, &base)
+        .unwrap_err();
+    assert_eq!(err.code(), "NESTED_CONDITION");
+}
+
+#[test]
+fn it_suggests_close_matches_for_unknown_columns() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE users (id int, name varchar(40), nickname varchar(40), age int);",
+    ]);
+
+    let mut lower = |name: &str, q: &str| convert(&mut converter, name, q).map(|_| ());
+
+    let err = lower(
+        "misspelled",
+        "SELECT users.nmae FROM users WHERE users.id = ?;",
+    )
+    .unwrap_err();
+    assert_eq!(
+        err,
+        MirConversionError::UnknownColumn {
+            column: String::from("users.nmae"),
+            available: vec![String::from("name")],
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "UNKNOWN_COLUMN: column \"users.nmae\" does not exist; did you mean one of: name?"
+    );
+
+    // columns that are only filtered on are checked, too
+    match lower(
+        "filtered",
+        "SELECT users.id FROM users WHERE users.agee > 18 AND users.id = ?;",
+    ) {
+        Err(MirConversionError::UnknownColumn { column, available }) => {
+            assert_eq!(column, "users.agee");
+            assert_eq!(available, vec![String::from("age")]);
+        }
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn it_orders_joins_by_cardinality_hints() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE a (id int, x int);",
+        "CREATE TABLE b (id int, aid int);",
+        "CREATE TABLE c (id int, bid int);",
+    ]);
+    let q = "SELECT a.x, c.id FROM a JOIN b ON a.id = b.aid JOIN c ON b.id = c.bid \
+             WHERE a.id = ?;";
+
+    // the names of the two relations that the first join of the chain joins
+    let first_join = |converter: &mut SqlToMirConverter, name: &str| {
+        let mq = convert(converter, name, q).unwrap();
+        let is_join = |n: &MirNodeRef| match n.borrow().inner {
+            MirNodeType::Join { .. } => true,
+            _ => false,
+        };
+        let mut stack = vec![mq.leaf.clone()];
+        while let Some(n) = stack.pop() {
+            let ancestors = n.borrow().ancestors().to_vec();
+            if is_join(&n) && !ancestors.iter().any(is_join) {
+                let mut rels: Vec<_> = ancestors
+                    .iter()
+                    .map(|a| a.borrow().name().to_owned())
+                    .collect();
+                rels.sort();
+                return rels;
+            }
+            stack.extend(ancestors);
+        }
+        unreachable!("query has no joins");
+    };
+
+    // without hints, the joins follow the query
+    assert_eq!(first_join(&mut converter, "unhinted"), vec!["a", "b"]);
+
+    // with c the smallest relation, the chain starts by joining it to b instead
+    converter.set_cardinality_hints(
+        vec![("a", 1000), ("b", 100), ("c", 10)]
+            .into_iter()
+            .map(|(r, n)| (String::from(r), n))
+            .collect(),
+    );
+    assert_eq!(first_join(&mut converter, "hinted"), vec!["b", "c"]);
+}
+
+#[test]
+fn it_reuses_join_nodes_across_queries() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE customers (id int, name text);",
+        "CREATE TABLE orders (id int, cid int, amount int);",
+    ]);
+
+    let mut add = |name: &str, q: &str| {
+        convert(&mut converter, name, q).unwrap();
+    };
+    add(
+        "names",
+        "SELECT orders.id, customers.name FROM orders \
+         JOIN customers ON orders.cid = customers.id WHERE orders.id = ?;",
+    );
+    add(
+        "amounts",
+        "SELECT customers.name, orders.amount FROM orders \
+         JOIN customers ON orders.cid = customers.id WHERE customers.id = ?;",
+    );
+    add(
+        "reversed",
+        "SELECT orders.id, customers.name FROM customers \
+         JOIN orders ON customers.id = orders.cid WHERE orders.id = ?;",
+    );
+
+    let joins = |query: &str| {
+        converter.find_nodes(query, |n| match *n {
+            MirNodeType::Join { .. } => true,
+            _ => false,
+        })
+    };
+    let (names, amounts, reversed) = (joins("names"), joins("amounts"), joins("reversed"));
+    assert_eq!(names.len(), 1);
+    assert_eq!(amounts.len(), 1);
+    assert_eq!(reversed.len(), 1);
+
+    // both queries share the join of orders and customers on the customer id
+    assert!(Rc::ptr_eq(&names[0], &amounts[0]));
+    // joining the other way around yields different columns, so it gets its own node
+    assert!(!Rc::ptr_eq(&names[0], &reversed[0]));
+}
+
+#[test]
+fn it_only_reuses_join_nodes_with_node_sharing() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE customers (id int, name text);",
+        "CREATE TABLE orders (id int, cid int, amount int);",
+    ]);
+
+    let add = |converter: &mut SqlToMirConverter, name: &str, key: &str| {
+        let q = format!(
+            "SELECT orders.id, customers.name FROM orders \
+             JOIN customers ON orders.cid = customers.id WHERE {} = ?;",
+            key
+        );
+        convert(converter, name, &q).unwrap()
+    };
+    let join = |converter: &SqlToMirConverter, query: &str| {
+        let joins = converter.find_nodes(query, |n| match *n {
+            MirNodeType::Join { .. } => true,
+            _ => false,
+        });
+        assert_eq!(joins.len(), 1);
+        joins[0].clone()
+    };
+
+    converter.set_node_sharing(false);
+    add(&mut converter, "by_order", "orders.id");
+    add(&mut converter, "by_customer", "customers.id");
+    assert!(!Rc::ptr_eq(
+        &join(&converter, "by_order"),
+        &join(&converter, "by_customer")
+    ));
+
+    converter.set_node_sharing(true);
+    let by_amount = add(&mut converter, "by_amount", "orders.amount");
+    add(&mut converter, "by_name", "customers.name");
+    let shared = join(&converter, "by_amount");
+    assert!(Rc::ptr_eq(&shared, &join(&converter, "by_name")));
+
+    // removing the query that created the join keeps it around for the one that reuses it
+    converter.remove_query("by_amount", &by_amount);
+    add(&mut converter, "by_cid", "orders.cid");
+    assert!(Rc::ptr_eq(&shared, &join(&converter, "by_cid")));
+}
+
+#[test]
+fn it_reuses_aggregations_across_queries() {
+    let mut converter = converter_with_bases(&["CREATE TABLE emp (id int, dept int, salary int);"]);
+
+    let mut add = |name: &str, q: &str| convert(&mut converter, name, q).unwrap();
+    let by_dept = add(
+        "by_dept",
+        "SELECT emp.dept, COUNT(emp.id) AS n FROM emp GROUP BY emp.dept;",
+    );
+    let for_dept = add(
+        "for_dept",
+        "SELECT emp.dept, COUNT(emp.id) AS n FROM emp WHERE emp.dept = ? GROUP BY emp.dept;",
+    );
+    let totals = add(
+        "totals",
+        "SELECT emp.dept, SUM(emp.salary) AS n FROM emp GROUP BY emp.dept;",
+    );
+
+    let aggregations = |query: &str| {
+        converter.find_nodes(query, |n| match *n {
+            MirNodeType::Aggregation { .. } => true,
+            _ => false,
+        })
+    };
+    let (by_dept_agg, for_dept_agg, totals_agg) = (
+        aggregations("by_dept"),
+        aggregations("for_dept"),
+        aggregations("totals"),
+    );
+    assert_eq!(by_dept_agg.len(), 1);
+    assert_eq!(for_dept_agg.len(), 1);
+    assert_eq!(totals_agg.len(), 1);
+
+    // both counts share one aggregation node, but are keyed differently at their own leaves
+    assert!(Rc::ptr_eq(&by_dept_agg[0], &for_dept_agg[0]));
+    assert!(!Rc::ptr_eq(&by_dept.leaf, &for_dept.leaf));
+    // summing is a different aggregation, so it gets its own node
+    assert!(!Rc::ptr_eq(&by_dept_agg[0], &totals_agg[0]));
+}
+
+#[test]
+fn it_only_reuses_aggregations_with_node_sharing() {
+    let mut converter = converter_with_bases(&["CREATE TABLE emp (id int, dept int, salary int);"]);
+
+    let add = |converter: &mut SqlToMirConverter, name: &str, filter: &str| {
+        let q = format!(
+            "SELECT emp.dept, COUNT(emp.id) AS n FROM emp {} GROUP BY emp.dept;",
+            filter
+        );
+        convert(converter, name, &q).unwrap()
+    };
+    let aggregation = |converter: &SqlToMirConverter, query: &str| {
+        let aggregations = converter.find_nodes(query, |n| match *n {
+            MirNodeType::Aggregation { .. } => true,
+            _ => false,
+        });
+        assert_eq!(aggregations.len(), 1);
+        aggregations[0].clone()
+    };
+
+    converter.set_node_sharing(false);
+    add(&mut converter, "unshared", "");
+    add(&mut converter, "unshared_for_dept", "WHERE emp.dept = ?");
+    assert!(!Rc::ptr_eq(
+        &aggregation(&converter, "unshared"),
+        &aggregation(&converter, "unshared_for_dept")
+    ));
+
+    converter.set_node_sharing(true);
+    let by_dept = add(&mut converter, "by_dept", "");
+    add(&mut converter, "for_dept", "WHERE emp.dept = ?");
+    let shared = aggregation(&converter, "by_dept");
+    assert!(Rc::ptr_eq(&shared, &aggregation(&converter, "for_dept")));
+
+    // removing the query that created the aggregation keeps it around for the one that
+    // reuses it
+    converter.remove_query("by_dept", &by_dept);
+    add(&mut converter, "again", "");
+    assert!(Rc::ptr_eq(&shared, &aggregation(&converter, "again")));
+}
+
+#[test]
+fn it_adds_sorted_leaves_over_unordered_views() {
+    use nom_sql::OrderType;
+
+    let mut converter = converter_with_bases(&["CREATE TABLE items (id int, price int);"]);
+    let order = |col: &str| OrderClause {
+        columns: vec![(nom_sql::Column::from(col), OrderType::OrderDescending)],
+    };
+    let page = |limit, offset| LimitClause { limit, offset };
+
+    let mq = converter
+        .add_sorted_leaf("items", "priciest", order("price"), page(2, 0))
+        .unwrap();
+    // the base has no key to page within, so the whole base is paged through a bogokey
+    match mq.leaf.borrow().inner {
+        MirNodeType::Leaf { ref keys, .. } => {
+            assert_eq!(keys, &vec![Column::new(None, "bogokey")])
+        }
+        _ => unreachable!(),
+    }
+    let topk = mq.leaf.borrow().ancestors()[0].clone();
+    match topk.borrow().inner {
+        MirNodeType::TopK {
+            ref order,
+            ref group_by,
+            k,
+            offset,
+            ..
+        } => {
+            let order = order.as_ref().unwrap();
+            assert_eq!(order.len(), 1);
+            assert_eq!(order[0].0, Column::new(Some("items"), "price"));
+            assert_eq!(group_by, &vec![Column::new(None, "bogokey")]);
+            assert_eq!((k, offset), (2, 0));
+        }
+        _ => unreachable!(),
+    }
+    assert_eq!(
+        topk.borrow().ancestors()[0].borrow().name(),
+        "priciest_bogokey"
+    );
+    let v = converter.current["priciest"];
+    assert!(Rc::ptr_eq(
+        &converter.nodes[&("priciest".into(), v)],
+        &mq.leaf
+    ));
+
+    // a view is paged within each of its keys, and the sorted leaf is keyed like it
+    convert(
+        &mut converter,
+        "by_id",
+        "SELECT items.id, items.price FROM items WHERE items.id = ?;",
+    )
+    .unwrap();
+    let mq = converter
+        .add_sorted_leaf("by_id", "by_id_sorted", order("price"), page(1, 0))
+        .unwrap();
+    match mq.leaf.borrow().inner {
+        MirNodeType::Leaf { ref keys, .. } => {
+            assert_eq!(keys, &vec![Column::new(Some("items"), "id")])
+        }
+        _ => unreachable!(),
+    }
+
+    assert_eq!(
+        converter
+            .add_sorted_leaf("items", "priciest", order("price"), page(2, 0))
+            .unwrap_err()
+            .code(),
+        "UNSUPPORTED_PARAMETERS"
+    );
+    assert_eq!(
+        converter
+            .add_sorted_leaf("items", "later", order("price"), page(2, 2))
+            .unwrap_err()
+            .code(),
+        "UNSUPPORTED_PARAMETERS"
+    );
+    assert_eq!(
+        converter
+            .add_sorted_leaf("items", "bad", order("weight"), page(2, 0))
+            .unwrap_err()
+            .code(),
+        "UNKNOWN_COLUMN"
+    );
+    assert_eq!(
+        converter
+            .add_sorted_leaf("nonexistent", "bad", order("price"), page(2, 0))
+            .unwrap_err()
+            .code(),
+        "UNKNOWN_VIEW"
+    );
+}
+
+#[test]
+fn it_updates_topk_in_place() {
+    let mut converter = converter_with_bases(&["CREATE TABLE items (id int, price int);"]);
+    let top = "SELECT items.id, items.price FROM items ORDER BY items.price DESC LIMIT 3;";
+    convert(&mut converter, "top", top).unwrap();
+    convert(
+        &mut converter,
+        "all",
+        "SELECT items.id, items.price FROM items;",
+    )
+    .unwrap();
+    let order = parse_select(top).order;
+
+    let old_topk = converter.find_nodes("top", |t| t.operator_name() == "TopK")[0].clone();
+    let above = old_topk.borrow().ancestors()[0].clone();
+    let leaf_columns = |c: &SqlToMirConverter| {
+        c.nodes[&("top".into(), c.current["top"])]
+            .borrow()
+            .columns()
+            .to_vec()
+    };
+    let old_columns = leaf_columns(&converter);
+
+    let page = |limit, offset| LimitClause { limit, offset };
+    let mq = converter
+        .update_topk("top", order.clone(), page(5, 0))
+        .unwrap();
+    let v = converter.current["top"];
+    assert!(Rc::ptr_eq(&converter.nodes[&("top".into(), v)], &mq.leaf));
+    assert_eq!(leaf_columns(&converter), old_columns);
+
+    // only the limit changed, and the new TopK reads from the old one's parent
+    let topk = converter.find_nodes("top", |t| t.operator_name() == "TopK")[0].clone();
+    assert!(!Rc::ptr_eq(&topk, &old_topk));
+    match topk.borrow().inner {
+        MirNodeType::TopK {
+            ref order,
+            ref group_by,
+            k,
+            ..
+        } => {
+            let order = order.as_ref().unwrap();
+            assert_eq!(order.len(), 1);
+            assert_eq!(order[0].0, Column::new(Some("items"), "price"));
+            assert_eq!(group_by, &vec![Column::new(None, "bogokey")]);
+            assert_eq!(k, 5);
+        }
+        _ => unreachable!(),
+    }
+    match mq.roots[0].borrow().inner {
+        MirNodeType::Reuse { ref node } => assert!(Rc::ptr_eq(node, &above)),
+        _ => unreachable!(),
+    }
+    assert!(Rc::ptr_eq(&topk.borrow().ancestors()[0], &mq.roots[0]));
+
+    let code = |r: Result<MirQuery, MirConversionError>| r.unwrap_err().code();
+    assert_eq!(
+        code(converter.update_topk("top", order, page(5, 5))),
+        "UNSUPPORTED_PARAMETERS"
+    );
+    let by_weight = OrderClause {
+        columns: vec![(
+            nom_sql::Column::from("items.weight"),
+            nom_sql::OrderType::OrderAscending,
+        )],
+    };
+    assert_eq!(
+        code(converter.update_topk("top", Some(by_weight), page(5, 0))),
+        "UNKNOWN_COLUMN"
+    );
+    assert_eq!(
+        code(converter.update_topk("all", None, page(5, 0))),
+        "UNSUPPORTED_PARAMETERS"
+    );
+    assert_eq!(
+        code(converter.update_topk("nonexistent", None, page(5, 0))),
+        "UNKNOWN_VIEW"
+    );
+}
+
+#[test]
+fn it_rekeys_bogokey_leaves() {
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+    let mq = convert(&mut converter, "q", "SELECT t.id, t.a FROM t;").unwrap();
+    let above = mq.leaf.borrow().ancestors()[0].clone();
+    match mq.leaf.borrow().inner {
+        MirNodeType::Leaf { ref keys, .. } => assert_eq!(keys[0].name, "bogokey"),
+        _ => unreachable!(),
+    }
+
+    let key = Column::new(Some("t"), "a");
+    let rekeyed = converter.rekey_leaf("q", &[key.clone()]).unwrap();
+    match rekeyed.leaf.borrow().inner {
+        MirNodeType::Leaf { ref keys, .. } => assert_eq!(keys, &vec![key]),
+        _ => unreachable!(),
+    }
+    // the new leaf reads from the old leaf's parent rather than from a copy of it
+    match rekeyed.roots[0].borrow().inner {
+        MirNodeType::Reuse { ref node } => assert!(Rc::ptr_eq(node, &above)),
+        _ => unreachable!(),
+    }
+    let v = converter.current["q"];
+    assert!(Rc::ptr_eq(
+        &converter.nodes[&("q".into(), v)],
+        &rekeyed.leaf
+    ));
+
+    // keys given by column name alone refer to the view's columns, and re-keying again reads
+    // from the original parent rather than from the identity node of the earlier re-key
+    let again = converter
+        .rekey_leaf("q", &[Column::new(None, "id")])
+        .unwrap();
+    match again.leaf.borrow().inner {
+        MirNodeType::Leaf { ref keys, .. } => assert_eq!(keys, &vec![Column::new(Some("t"), "id")]),
+        _ => unreachable!(),
+    }
+    match again.roots[0].borrow().inner {
+        MirNodeType::Reuse { ref node } => assert!(Rc::ptr_eq(node, &above)),
+        _ => unreachable!(),
+    }
+
+    let err = converter
+        .rekey_leaf("q", &[Column::new(Some("t"), "b")])
+        .unwrap_err();
+    assert_eq!(err.code(), "UNKNOWN_COLUMN");
+    assert_eq!(
+        converter.rekey_leaf("nonexistent", &[]).unwrap_err().code(),
+        "UNKNOWN_VIEW"
+    );
+}
+
+#[test]
+fn it_reports_view_schemas() {
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+    convert(
+        &mut converter,
+        "q",
+        "SELECT t.id, t.a AS value, t.a * 2 AS twice FROM t WHERE t.id = ?;",
+    )
+    .unwrap();
+
+    let schema = converter.view_schema("q").unwrap();
+    assert_eq!(
+        schema.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+        vec!["id", "value", "twice"]
+    );
+    assert!(schema
+        .iter()
+        .all(|c| c.table == Some("q".into()) && c.aliases.is_empty()));
+
+    // base tables have no leaf to read a schema from
+    assert_eq!(converter.view_schema("t"), None);
+    assert_eq!(converter.view_schema("nonexistent"), None);
+}
+
+#[test]
+fn it_finds_nodes_by_type() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE a (id int, x int);",
+        "CREATE TABLE b (id int, aid int);",
+        "CREATE TABLE c (id int, bid int);",
+    ]);
+    convert(
+        &mut converter,
+        "q",
+        "SELECT a.x, c.id FROM a JOIN b ON a.id = b.aid JOIN c ON b.id = c.bid \
+         WHERE a.id = ?;",
+    )
+    .unwrap();
+
+    let joins = converter.find_nodes("q", |t| match *t {
+        MirNodeType::Join { .. } | MirNodeType::LeftJoin { .. } => true,
+        _ => false,
+    });
+    assert_eq!(joins.len(), 2);
+
+    // the query reads the base tables through reuse nodes, which are looked through
+    let mut bases: Vec<_> = converter
+        .find_nodes("q", |t| match *t {
+            MirNodeType::Base { .. } => true,
+            _ => false,
+        })
+        .iter()
+        .map(|n| n.borrow().name().to_owned())
+        .collect();
+    bases.sort();
+    assert_eq!(bases, vec!["a", "b", "c"]);
+    assert!(converter
+        .find_nodes("q", |t| match *t {
+            MirNodeType::Reuse { .. } => true,
+            _ => false,
+        })
+        .is_empty());
+
+    assert!(converter.find_nodes("nonexistent", |_| true).is_empty());
+}
+
+#[test]
+fn it_keeps_declared_left_joins() {
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE a (id int, x int);",
+        "CREATE TABLE b (id int, aid int);",
+    ]);
+    let mq = convert(
+        &mut converter,
+        "q",
+        "SELECT a.x, b.id FROM a LEFT JOIN b ON a.id = b.aid WHERE a.id = ?;",
+    )
+    .unwrap();
+
+    let mut joins = Vec::new();
+    let mut stack = vec![mq.leaf.clone()];
+    while let Some(n) = stack.pop() {
+        match n.borrow().inner {
+            MirNodeType::Join { .. } | MirNodeType::LeftJoin { .. } => joins.push(n.clone()),
+            _ => (),
+        }
+        stack.extend(n.borrow().ancestors().iter().cloned());
+    }
+    assert_eq!(joins.len(), 1);
+    assert!(join::check_join_type(&joins[0], &JoinType::Left).is_ok());
+
+    // an inner join standing in for the declared left join is caught
+    match join::check_join_type(&joins[0], &JoinType::Inner) {
+        Err(MirConversionError::Inconsistency(_)) => (),
+        r => panic!("expected an inconsistency, got {:?}", r),
+    }
+    let a = converter.get_view("a").unwrap();
+    let b = converter.get_view("b").unwrap();
+    let inner = converter
+        .make_join_node(
+            "downgraded",
+            &ConditionTree {
+                operator: Operator::Equal,
+                left: Box::new(ConditionExpression::Base(ConditionBase::Field(
+                    nom_sql::Column::from("a.id"),
+                ))),
+                right: Box::new(ConditionExpression::Base(ConditionBase::Field(
+                    nom_sql::Column::from("b.aid"),
+                ))),
+            },
+            a,
+            b,
+            JoinType::Inner,
+        )
+        .unwrap();
+    assert!(join::check_join_type(&inner, &JoinType::Left).is_err());
+}
+
+#[test]
+fn it_orders_union_columns_like_the_first_ancestor() {
+    let mut converter = SqlToMirConverter::default();
+    for q in &[
+        "CREATE TABLE a (x int, y int, z int);",
+        "CREATE TABLE b (z int, x int, y int);",
+    ] {
+        let ct = sql_parser::parse_query(q).unwrap();
+        let name = match ct {
+            SqlQuery::CreateTable(ref ctq) => ctq.table.name.clone(),
+            _ => unreachable!(),
+        };
+        converter.named_base_to_mir(&name, &ct);
+    }
+    let a = converter.get_view("a").unwrap();
+    let b = converter.get_view("b").unwrap();
+
+    let names = |cs: &[Column]| cs.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+    for _ in 0..10 {
+        let union = converter.make_union_node("u", &[a.clone(), b.clone()]);
+        let union = union.borrow();
+        assert_eq!(names(union.columns()), vec!["x", "y", "z"]);
+        match union.inner {
+            MirNodeType::Union { ref emit } => {
+                // the second ancestor's columns are lined up with the first's by name
+                assert_eq!(names(&emit[1]), vec!["x", "y", "z"]);
+                assert_eq!(emit[1][0].table, Some(String::from("b")));
+            }
+            ref n => panic!("expected a union, got {:?}", n),
+        }
+    }
+}
+
+#[test]
+fn it_resolves_union_order_columns_by_name() {
+    use nom_sql::OrderType;
+
+    let mut converter = converter_with_bases(&[
+        "CREATE TABLE a (x int, y int);",
+        "CREATE TABLE b (x int, y int);",
+    ]);
+
+    let mut branches = Vec::new();
+    for (i, q) in ["SELECT a.x, a.y FROM a;", "SELECT b.x, b.y FROM b;"]
+        .iter()
+        .enumerate()
+    {
+        let st = parse_select(q);
+        let qg = to_query_graph(&st).unwrap();
+        let (_, mq, _, _) = converter
+            .named_query_to_mir(
+                &format!("u_csq_{}", i),
+                &st,
+                &qg,
+                false,
+                ("global".into(), None),
+                None,
+            )
+            .unwrap();
+        branches.push(mq);
+    }
+
+    let order_by = |c: &str| {
+        Some(OrderClause {
+            columns: vec![(nom_sql::Column::from(c), OrderType::OrderDescending)],
+        })
+    };
+    let limit = Some(LimitClause {
+        limit: 3,
+        offset: 0,
+    });
+
+    // the order column names the second branch's table, but resolves to the union's column
+    let mq = converter
+        .compound_query_to_mir(
+            "u",
+            branches.iter().collect(),
+            CompoundSelectOperator::Union,
+            &order_by("b.x"),
+            &limit,
+            true,
+        )
+        .unwrap();
+    let topk = mq.leaf.borrow().ancestors()[0].clone();
+    match topk.borrow().inner {
+        MirNodeType::TopK {
+            order: Some(ref order),
+            ..
+        } => assert_eq!(order[0].0, Column::new(Some("u_csq_0"), "x")),
+        ref n => panic!("expected a TopK, got {:?}", n),
+    };
+
+    match converter.compound_query_to_mir(
+        "v",
+        branches.iter().collect(),
+        CompoundSelectOperator::Union,
+        &order_by("z"),
+        &limit,
+        true,
+    ) {
+        Err(MirConversionError::InvalidExpression(_)) => (),
+        r => panic!("unexpected result {:?}", r.map(|mq| mq.name)),
+    }
+}
+
+#[test]
+fn it_folds_constant_arithmetic_into_literals() {
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+
+    let mut lower = |name: &str, q: &str| convert(&mut converter, name, q);
+
+    let mq = lower(
+        "folded",
+        "SELECT t.id, 1 + 2 AS three FROM t WHERE t.id = ?;",
+    )
+    .unwrap();
+    let leaf = mq.leaf.borrow();
+    match leaf.ancestors()[0].borrow().inner {
+        MirNodeType::Project {
+            ref arithmetic,
+            ref literals,
+            ..
+        } => {
+            assert!(arithmetic.is_empty());
+            assert_eq!(literals, &vec![(String::from("three"), DataType::from(3))]);
+        }
+        ref n => panic!("expected a projection, got {:?}", n),
+    }
+
+    match lower("boom", "SELECT t.id, 1 / 0 AS boom FROM t WHERE t.id = ?;") {
+        Err(MirConversionError::InvalidExpression(_)) => (),
+        r => panic!("unexpected result {:?}", r.map(|mq| mq.name)),
+    }
+}
+
+#[test]
+fn it_keeps_columns_shadowed_by_computed_columns_distinct() {
+    let mut converter = converter_with_bases(&["CREATE TABLE t (id int, a int);"]);
+    let parent = converter.get_view("t").unwrap();
+    let (id, a) = (Column::new(Some("t"), "id"), Column::new(Some("t"), "a"));
+
+    // SELECT t.id, t.a, t.a + 1 AS a FROM t, as a view that other queries read from
+    let plus_one = ArithmeticExpression::new(
+        ArithmeticOperator::Add,
+        ArithmeticBase::Column(nom_sql::Column::from("t.a")),
+        ArithmeticBase::Scalar(Literal::Integer(1)),
+        None,
+    );
+    let n = converter
+        .make_project_node(
+            "v",
+            parent,
+            vec![&id, &a],
+            vec![("a".into(), plus_one)],
+            vec![],
+            vec![],
+            true,
+        )
+        .unwrap();
+    let n = n.borrow();
+
+    // the view's column `a` is the computed one, while the base column remains reachable
+    assert_eq!(
+        n.columns(),
+        &[
+            Column::new(Some("v"), "id"),
+            Column::new(Some("t"), "a"),
+            Column::new(Some("v"), "a"),
+        ]
+    );
+    assert_eq!(
+        n.column_id_for_column(&Column::new(Some("v"), "a"), None),
+        2
+    );
+    assert_eq!(n.column_id_for_column(&a, None), 1);
+    match n.inner {
+        MirNodeType::Project {
+            ref emit,
+            ref arithmetic,
+            ..
+        } => {
+            assert_eq!(emit, &vec![id.clone(), a.clone()]);
+            assert_eq!(arithmetic.len(), 1);
+        }
+        ref n => panic!("expected a projection, got {:?}", n),
+    }
+}
+
+#[test]
+fn it_lowers_in_join_like_equi_join() {
+    let mut converter = SqlToMirConverter::default();
+    let ct = sql_parser::parse_query("CREATE TABLE a (id int, x int);").unwrap();
+    let a = converter.named_base_to_mir("a", &ct).leaf;
+    let ct = sql_parser::parse_query("CREATE TABLE sq (y int);").unwrap();
+    let sq = converter.named_base_to_mir("sq", &ct).leaf;
+
+    let join_on = |operator| {
+        let jp = ConditionTree {
+            operator,
+            left: Box::new(ConditionExpression::Base(ConditionBase::Field(
+                nom_sql::Column::from("a.x"),
+            ))),
+            right: Box::new(ConditionExpression::Base(ConditionBase::Field(
+                nom_sql::Column::from("sq.y"),
+            ))),
+        };
+        let jn = converter
+            .make_join_node("j", &jp, a.clone(), sq.clone(), JoinType::Inner)
+            .unwrap();
+        let jn = jn.borrow();
+        match jn.inner {
+            MirNodeType::Join {
+                ref on_left,
+                ref on_right,
+                ..
+            } => (on_left.clone(), on_right.clone(), jn.columns().to_vec()),
+            _ => unreachable!(),
+        }
+    };
+
+    let (on_left, on_right, columns) = join_on(Operator::In);
+    assert_eq!(on_left, vec![Column::from("a.x")]);
+    assert_eq!(on_right, vec![Column::from("sq.y")]);
+    assert_eq!((on_left, on_right, columns), join_on(Operator::Equal));
+}
+
+#[test]
+fn it_adds_implicit_rowid_to_keyless_base() {
+    let mut converter = SqlToMirConverter::default();
+    let ct = sql_parser::parse_query("CREATE TABLE log (msg text, level int);").unwrap();
+    let mq = converter.named_base_to_mir("log", &ct);
+    match mq.leaf.borrow().inner {
+        MirNodeType::Base {
+            ref keys,
+            implicit_rowid,
+            ..
+        } => {
+            assert!(keys.is_empty());
+            assert!(!implicit_rowid);
+        }
+        _ => unreachable!(),
+    }
+
+    let mut converter = SqlToMirConverter::default();
+    converter.enable_implicit_rowid();
+    let mq = converter.named_base_to_mir("log", &ct);
+    let leaf = mq.leaf.borrow();
+    let names: Vec<_> = leaf.columns().iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["msg", "level", "rowid"]);
+    match leaf.inner {
+        MirNodeType::Base {
+            ref column_specs,
+            ref keys,
+            implicit_rowid,
+            ..
+        } => {
+            assert!(implicit_rowid);
+            assert_eq!(keys.len(), 1);
+            assert_eq!(keys[0].name, "rowid");
+            assert!(column_specs[2]
+                .0
+                .constraints
+                .contains(&ColumnConstraint::AutoIncrement));
+        }
+        _ => unreachable!(),
+    }
+
+    // tables with a primary key are unaffected
+    let ct = sql_parser::parse_query("CREATE TABLE users (id int, PRIMARY KEY(id));").unwrap();
+    let mq = converter.named_base_to_mir("users", &ct);
+    match mq.leaf.borrow().inner {
+        MirNodeType::Base {
+            ref keys,
+            implicit_rowid,
+            ..
+        } => {
+            assert_eq!(keys.len(), 1);
+            assert_eq!(keys[0].name, "id");
+            assert!(!implicit_rowid);
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn it_rejects_columns_neither_grouped_nor_aggregated() {
+    let ct = sql_parser::parse_query("CREATE TABLE emp (id int, dept int, salary int);").unwrap();
+    let ungrouped = "SELECT emp.id, emp.dept, SUM(emp.salary) AS total FROM emp \
+                     GROUP BY emp.dept;";
+
+    // by default, the query is lowered anyway
+    let mut converter = SqlToMirConverter::default();
+    converter.named_base_to_mir("emp", &ct);
+    assert!(convert(&mut converter, "relaxed", ungrouped).is_ok());
+
+    let mut converter = SqlToMirConverter::default();
+    converter.enable_strict_group_by();
+    converter.named_base_to_mir("emp", &ct);
+    let err = convert(&mut converter, "strict", ungrouped)
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("INVALID_GROUP_BY"));
+    assert!(err.contains("\"emp.id\""));
+
+    // grouped columns, aggregates, and parameters are all fine
+    for (name, q) in &[
+        (
+            "grouped",
+            "SELECT emp.dept AS d, SUM(emp.salary) AS total FROM emp GROUP BY emp.dept;",
+        ),
+        (
+            "parameter",
+            "SELECT emp.id, SUM(emp.salary) AS total FROM emp WHERE emp.id = ? \
+             GROUP BY emp.dept;",
+        ),
+        (
+            "implied",
+            "SELECT emp.id, SUM(emp.salary) AS total FROM emp;",
+        ),
+    ] {
+        assert!(convert(&mut converter, name, q).is_ok(), "{}", name);
+    }
+}
+
+#[test]
+fn it_records_not_null_columns_on_base() {
+    let mut converter = SqlToMirConverter::default();
+    let ct = sql_parser::parse_query(
+        "CREATE TABLE users (id int NOT NULL, name varchar(40) NOT NULL, bio text, \
+         PRIMARY KEY(id));",
+    )
+    .unwrap();
+    let mq = converter.named_base_to_mir("users", &ct);
+    let names: Vec<_> = mq
+        .leaf
+        .borrow()
+        .not_null_columns()
+        .iter()
+        .map(|c| c.name.clone())
+        .collect();
+    assert_eq!(names, vec!["id", "name"]);
+}
+
+#[test]
+fn it_adapts_base_for_added_column_with_default() {
+    let mut converter = converter_with_bases(&["CREATE TABLE users (id int, name varchar(40));"]);
+
+    converter.upgrade_schema(1);
+    let ct = sql_parser::parse_query(
+        "CREATE TABLE users (id int, name varchar(40), age int DEFAULT 18);",
+    )
+    .unwrap();
+    let mq = converter.named_base_to_mir("users", &ct);
+
+    // the added column's spec, which the base is adapted with, carries its default, so
+    // that rows written before the change surface it
+    let leaf = mq.leaf.borrow();
+    match leaf.inner {
+        MirNodeType::Base {
+            adapted_over: Some(ref bna),
+            ..
+        } => {
+            assert_eq!(bna.columns_added.len(), 1);
+            assert_eq!(bna.columns_added[0].column.name, "age");
+            assert!(bna.columns_added[0]
+                .constraints
+                .contains(&ColumnConstraint::DefaultValue(Literal::Integer(18))));
+        }
+        _ => panic!("expected an adapted base node"),
+    }
+}
+
+#[test]
+fn it_makes_new_base_when_only_keys_change() {
+    let mut converter =
+        converter_with_bases(&["CREATE TABLE users (id int, email varchar(40), PRIMARY KEY(id));"]);
+
+    // the same schema and keys reuse the existing base
+    converter.upgrade_schema(1);
+    let mq = converter.named_base_to_mir("users", &ct);
+    match mq.leaf.borrow().inner {
+        MirNodeType::Reuse { .. } => (),
+        _ => panic!("expected the base to be reused"),
+    }
+
+    // the same columns keyed on another column need a new base with that key
+    converter.upgrade_schema(2);
+    let ct = sql_parser::parse_query(
+        "CREATE TABLE users (id int, email varchar(40), PRIMARY KEY(email));",
+    )
+    .unwrap();
+    let mq = converter.named_base_to_mir("users", &ct);
+    let leaf = mq.leaf.borrow();
+    assert_eq!(leaf.from_version, 2);
+    match leaf.inner {
+        MirNodeType::Base {
+            ref keys,
+            adapted_over: None,
+            ..
+        } => {
+            assert_eq!(keys.len(), 1);
+            assert_eq!(keys[0].name, "email");
+        }
+        _ => panic!("expected a new base node"),
+    }
+}
+
+#[test]
+fn it_round_trips_base_schema_history() {
+    let mut converter = SqlToMirConverter::default();
+    for (version, ct) in &[
+        (0, "CREATE TABLE a (id int, b int);"),
+        (1, "CREATE TABLE a (id int, b int, c text);"),
+    ] {
+        if *version > 0 {
+            converter.upgrade_schema(*version);
+        }
+        let ct = sql_parser::parse_query(ct).unwrap();
+        converter.named_base_to_mir("a", &ct);
+    }
+    assert_eq!(converter.base_schemas["a"].len(), 2);
+
+    let state = converter.export_state();
+    let state: SerializedConverterState =
+        serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+
+    let mut restored = SqlToMirConverter::default();
+    restored.import_state(state);
+    assert_eq!(restored.base_schemas, converter.base_schemas);
+    assert_eq!(restored.current, converter.current);
+    assert_eq!(restored.schema_version, 1);
+    assert_eq!(restored.export_state(), converter.export_state());
+}
@@ -6,25 +6,31 @@ mod query_utils;
 mod reuse;
 pub(super) mod security;
 
+pub(super) use self::query_utils::parse_inline_views;
+
 use self::mir::SqlToMirConverter;
 use self::query_graph::{to_query_graph, QueryGraph};
 use self::query_signature::Signature;
+use self::query_utils::{add_group_size, cube_to_union, rollup_to_union};
 use self::reuse::ReuseConfig;
 use super::mir_to_flow::mir_query_to_flow_parts;
 use crate::controller::Migration;
-use crate::ReuseConfigType;
+use crate::{PostLoweringHook, ReuseConfigType};
+use ::mir::node::MaterializationMode;
 use ::mir::query::{MirQuery, QueryFlowParts};
 use ::mir::reuse as mir_reuse;
 use ::mir::Column;
 use ::mir::MirNodeRef;
 use dataflow::prelude::DataType;
 use nom_sql::parser as sql_parser;
-use nom_sql::{ArithmeticBase, CreateTableStatement, SqlQuery};
+use nom_sql::{ArithmeticBase, CreateTableStatement, Literal, SqlQuery};
 use nom_sql::{CompoundSelectOperator, CompoundSelectStatement, SelectStatement};
+use nom_sql::{LimitClause, OrderClause};
+use noria::SerializedConverterState;
 use petgraph::graph::NodeIndex;
 
 use slog;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str;
 use std::vec::Vec;
 
@@ -65,6 +71,25 @@ pub(crate) struct SqlIncorporator {
     /// Active universes mapped to the group they belong to.
     /// If an user universe, mapped to None.
     universes: HashMap<Option<DataType>, Vec<UniverseId>>,
+
+    /// How the views of named queries are materialized, if not as the planner chooses
+    materializations: HashMap<String, MaterializationMode>,
+
+    /// Parameters of named queries that are bound to a constant rather than supplied by readers
+    bound_parameters: HashMap<String, Vec<(nom_sql::Column, Literal)>>,
+
+    /// Named queries whose `GROUP BY` columns are rolled up into subtotals
+    rollups: HashSet<String>,
+
+    /// Named queries whose `GROUP BY` columns form a cube of subtotals
+    cubes: HashSet<String>,
+
+    /// Named grouped queries whose rows carry the number of rows in their group
+    group_sizes: HashSet<String>,
+
+    /// Named queries whose leaf was re-keyed or re-sorted after they were added, and so no longer
+    /// matches the query's query graph and statement
+    altered_leaves: HashSet<String>,
 }
 
 impl Default for SqlIncorporator {
@@ -87,6 +112,13 @@ impl Default for SqlIncorporator {
 
             reuse_type: ReuseConfigType::Finkelstein,
             universes: HashMap::default(),
+
+            materializations: HashMap::default(),
+            bound_parameters: HashMap::default(),
+            rollups: HashSet::default(),
+            cubes: HashSet::default(),
+            group_sizes: HashSet::default(),
+            altered_leaves: HashSet::default(),
         }
     }
 }
@@ -106,14 +138,123 @@ impl SqlIncorporator {
     #[allow(unused)]
     pub(super) fn disable_reuse(&mut self) {
         self.reuse_type = ReuseConfigType::NoReuse;
+        self.mir_converter.set_node_sharing(false);
     }
 
     /// Disable node reuse for future migrations.
     #[allow(unused)]
     pub(super) fn enable_reuse(&mut self, reuse_type: ReuseConfigType) {
+        self.mir_converter
+            .set_node_sharing(reuse_type != ReuseConfigType::NoReuse);
         self.reuse_type = reuse_type;
     }
 
+    /// Synthesize a rowid key column for base tables created without a primary key.
+    pub(super) fn enable_implicit_rowid(&mut self) {
+        self.mir_converter.enable_implicit_rowid();
+    }
+
+    /// Reject queries that select columns they neither group by nor aggregate.
+    pub(super) fn enable_strict_group_by(&mut self) {
+        self.mir_converter.enable_strict_group_by();
+    }
+
+    /// Prepend `prefix` to the names of all MIR nodes generated from now on.
+    pub(super) fn set_name_prefix(&mut self, prefix: &str) {
+        self.mir_converter.set_name_prefix(prefix);
+    }
+
+    /// Set estimates of the number of rows in each relation, so that queries added from now on
+    /// join smaller relations first.
+    pub(super) fn set_cardinality_hints(&mut self, hints: HashMap<String, usize>) {
+        self.mir_converter.set_cardinality_hints(hints);
+    }
+
+    /// Run `hook` over the MIR nodes generated for each query added from now on.
+    pub(super) fn set_post_lowering_hook(&mut self, hook: PostLoweringHook) {
+        self.mir_converter.set_post_lowering_hook(hook);
+    }
+
+    /// Materialize the view of the query `query_name`, once added, as `mode` says.
+    pub(super) fn set_materialization(&mut self, query_name: &str, mode: MaterializationMode) {
+        self.materializations.insert(String::from(query_name), mode);
+    }
+
+    /// Let readers of the query `query_name`, once added, ask for any number of its first rows
+    /// per key, up to the query's LIMIT.
+    pub(super) fn set_read_time_limit(&mut self, query_name: &str) {
+        self.mir_converter.set_read_time_limit(query_name);
+    }
+
+    /// Have readers of the `SELECT DISTINCT` query `query_name`, once added, return each key's
+    /// rows ordered by the selected columns.
+    pub(super) fn set_ordered_distinct(&mut self, query_name: &str) {
+        self.mir_converter.set_ordered_distinct(query_name);
+    }
+
+    /// Let the branches of the `UNION` query `query_name`, once added, select fewer columns than
+    /// its widest branch; the columns a branch lacks are `NULL` in its rows.
+    pub(super) fn set_padded_union(&mut self, query_name: &str) {
+        self.mir_converter.set_padded_union(query_name);
+    }
+
+    /// Have the grouped query `query_name`, once added, select an additional `group_size` column
+    /// with the number of rows in each group, as if it also selected `COUNT(*)`.
+    pub(super) fn set_group_size(&mut self, query_name: &str) {
+        self.group_sizes.insert(String::from(query_name));
+    }
+
+    /// Key the view of the query `query_name`, once added, on the SQL function `function` (one of
+    /// `LOWER` and `UPPER`) of its parameter column `param`, computed into a column named `name`,
+    /// as if the query compared the function with the parameter (e.g., `WHERE LOWER(users.email)
+    /// = ?`). The view is then looked up by the function's value.
+    pub(super) fn set_computed_parameter(
+        &mut self,
+        query_name: &str,
+        param: &str,
+        name: &str,
+        function: &str,
+    ) -> Result<(), String> {
+        use self::mir::ScalarFunction;
+        use nom_sql::ColumnOrLiteral;
+
+        let arg = ColumnOrLiteral::Column(nom_sql::Column::from(param));
+        let function = match function.to_lowercase().as_str() {
+            "lower" => ScalarFunction::Lower(arg),
+            "upper" => ScalarFunction::Upper(arg),
+            _ => return Err(format!("unsupported function {} for a parameter", function)),
+        };
+        self.mir_converter
+            .set_computed_parameter(query_name, Column::from(param), name, function);
+        Ok(())
+    }
+
+    /// Treat the `GROUP BY` of the query `query_name`, once added, as `GROUP BY ROLLUP(...)`: on
+    /// top of its usual rows, the view holds a subtotal row for every prefix of the grouping
+    /// columns and a grand total row, with `NULL` in place of the rolled-up columns.
+    pub(super) fn set_rollup(&mut self, query_name: &str) {
+        self.rollups.insert(String::from(query_name));
+        self.mir_converter.set_padded_union(query_name);
+    }
+
+    /// Treat the `GROUP BY` of the query `query_name`, once added, as `GROUP BY CUBE(...)`: on top
+    /// of its usual rows, the view holds a subtotal row for every subset of the (at most three)
+    /// grouping columns, with `NULL` in place of the columns left out.
+    pub(super) fn set_cube(&mut self, query_name: &str) {
+        self.cubes.insert(String::from(query_name));
+        self.mir_converter.set_padded_union(query_name);
+    }
+
+    /// Bind the parameter `column` (as in `table.column = ?`) of the query `query_name`, once
+    /// added, to `value`. The view is then pre-filtered on `value` and keyed on the query's other
+    /// parameters only, or on the bogokey if it has none left.
+    pub(super) fn bind_parameter(&mut self, query_name: &str, column: &str, value: Literal) {
+        self.bound_parameters
+            .entry(String::from(query_name))
+            .or_insert_with(Vec::new)
+            .push((nom_sql::Column::from(column), value));
+    }
+
     /// Incorporates a single query into via the flow graph migration in `mig`. The `query`
     /// argument is a string that holds a parameterized SQL query, and the `name` argument supplies
     /// an optional name for the query. If no `name` is specified, the table name is used in the
@@ -174,6 +315,114 @@ impl SqlIncorporator {
         }
     }
 
+    /// Returns the names of all base tables that the query `query_name` reads from.
+    pub(super) fn base_dependencies(&self, query_name: &str) -> HashSet<String> {
+        self.mir_converter.base_dependencies(query_name)
+    }
+
+    /// Returns a textual plan of the query `query_name`, one line per MIR node.
+    pub(super) fn explain(&self, query_name: &str) -> Option<String> {
+        self.mir_converter.explain(query_name)
+    }
+
+    /// Returns the names of the columns that the view of the query `query_name` exposes, in
+    /// order.
+    pub(super) fn view_columns(&self, query_name: &str) -> Option<Vec<String>> {
+        self.mir_converter
+            .view_schema(query_name)
+            .map(|cols| cols.into_iter().map(|c| c.name).collect())
+    }
+
+    /// Returns the names of the MIR nodes of the query `query_name` whose operator is `operator`
+    /// (e.g., "Join" or "TopK"), in the order a walk up from the query's leaf first visits them.
+    pub(super) fn find_nodes(&self, query_name: &str, operator: &str) -> Vec<String> {
+        self.mir_converter
+            .find_nodes(query_name, |t| t.operator_name() == operator)
+            .iter()
+            .map(|n| n.borrow().name().to_owned())
+            .collect()
+    }
+
+    /// Estimates how many dataflow nodes adding the `SELECT` query `query` to the global universe
+    /// would create, without adding it. Nodes that the query could share with existing queries
+    /// are counted as well, so the estimate is an upper bound.
+    pub(super) fn estimate_node_count(&self, query: &str) -> Result<usize, String> {
+        use self::passes::alias_removal::AliasRemoval;
+        use self::passes::count_star_rewrite::CountStarRewrite;
+        use self::passes::implied_tables::ImpliedTableExpansion;
+        use self::passes::negation_removal::NegationRemoval;
+        use self::passes::star_expansion::StarExpansion;
+        use self::passes::subqueries::SubQueries;
+        use self::query_utils::ReferredTables;
+
+        let mut q = sql_parser::parse_query(query).map_err(String::from)?;
+        match q {
+            SqlQuery::Select(_) => (),
+            _ => return Err(String::from("can only estimate the size of SELECT queries")),
+        }
+        // subqueries would be added as views of their own, which an estimate must not do
+        if !q.extract_subqueries().is_empty() {
+            return Err(String::from(
+                "cannot estimate the size of queries with subqueries",
+            ));
+        }
+        for t in &q.referred_tables() {
+            if !self.view_schemas.contains_key(&t.name) {
+                return Err(format!("query refers to unknown table \"{}\"", t.name));
+            }
+        }
+
+        // the same rewrite passes as for queries that are added
+        let q = q
+            .expand_table_aliases(&HashMap::new())
+            .remove_negation()
+            .expand_stars(&self.view_schemas)
+            .expand_implied_tables(&self.view_schemas)
+            .rewrite_count_star(&self.view_schemas);
+        let st = match q {
+            SqlQuery::Select(st) => st,
+            _ => unreachable!(),
+        };
+        let qg = to_query_graph(&st)?;
+        Ok(self.mir_converter.estimate_node_count(&st, &qg, true)?)
+    }
+
+    /// Returns the schema evolution state of the converter, to be restored with `import_state`.
+    pub(super) fn export_state(&self) -> SerializedConverterState {
+        self.mir_converter.export_state()
+    }
+
+    /// Replaces the converter's schema evolution state with one taken by `export_state`.
+    pub(super) fn import_state(&mut self, state: SerializedConverterState) {
+        self.mir_converter.import_state(state);
+    }
+
+    /// Adds the query `query_name` to the universe of the migration in `mig`, lowering it again
+    /// from its parsed form. Returns `None` if the query or the universe is unknown, or if the
+    /// query already exists in the universe.
+    pub(super) fn reinstantiate_for_universe(
+        &mut self,
+        query_name: &str,
+        mut mig: &mut Migration,
+    ) -> Result<Option<QueryFlowParts>, String> {
+        let universe = mig.universe();
+        let mut mir = match self
+            .mir_converter
+            .reinstantiate_for_universe(query_name, universe.clone())
+        {
+            Some(mir) => mir,
+            None => return Ok(None),
+        };
+
+        trace!(self.log, "Reinstantiated MIR: {}", mir);
+
+        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None)?;
+
+        self.register_query(&mir.name, None, &mir, universe);
+
+        Ok(Some(qfp))
+    }
+
     pub(super) fn is_leaf_address(&self, ni: NodeIndex) -> bool {
         self.leaf_addresses.values().any(|nn| *nn == ni)
     }
@@ -190,20 +439,30 @@ impl SqlIncorporator {
         query_name: &str,
         universe: UniverseId,
         st: &SelectStatement,
-    ) -> (QueryGraph, QueryGraphReuse) {
+    ) -> Result<(QueryGraph, QueryGraphReuse), String> {
         debug!(self.log, "Making QG for \"{}\"", query_name);
         trace!(self.log, "Query \"{}\": {:#?}", query_name, st);
 
-        let mut qg = match to_query_graph(st) {
-            Ok(qg) => qg,
-            Err(e) => panic!(e),
-        };
+        let mut qg = to_query_graph(st)?;
+        if let Some(bound) = self.bound_parameters.get(query_name) {
+            for (column, value) in bound {
+                if !qg.bind_parameter(column, value.clone()) {
+                    warn!(
+                        self.log,
+                        "Query \"{}\" has no parameter {} to bind", query_name, column.name
+                    );
+                }
+            }
+        }
 
         trace!(self.log, "QG for \"{}\": {:#?}", query_name, qg);
 
-        // if reuse is disabled, we're done
-        if self.reuse_type == ReuseConfigType::NoReuse {
-            return (qg, QueryGraphReuse::None);
+        // if reuse is disabled, we're done; the same goes for views keyed on computed parameters,
+        // as other queries' readers are keyed on the plain parameter columns
+        if self.reuse_type == ReuseConfigType::NoReuse
+            || self.mir_converter.has_computed_parameters(query_name)
+        {
+            return Ok((qg, QueryGraphReuse::None));
         }
 
         // Do we already have this exact query or a subset of it in the same universe?
@@ -221,6 +480,7 @@ impl SqlIncorporator {
                 if existing_qg.signature() == qg.signature()
                     && existing_qg.parameters() == qg.parameters()
                     && existing_qg.exact_hash() == qg.exact_hash()
+                    && !self.altered_leaves.contains(&mir_query.name)
                 {
                     // we already have this exact query, down to the exact same reader key columns
                     // in exactly the same order
@@ -238,7 +498,7 @@ impl SqlIncorporator {
                         existing_qg,
                     );
 
-                    return (qg, QueryGraphReuse::ExactMatch(mir_query.leaf.clone()));
+                    return Ok((qg, QueryGraphReuse::ExactMatch(mir_query.leaf.clone())));
                 } else if existing_qg.signature() == qg.signature()
                     && existing_qg.parameters() != qg.parameters()
                 {
@@ -262,6 +522,11 @@ impl SqlIncorporator {
                                 predicates_match = false;
                             }
                         }
+                        if existing_qg.relations.contains_key(r)
+                            && existing_qg.relations[r].on_predicates != n.on_predicates
+                        {
+                            predicates_match = false;
+                        }
                     }
 
                     // if any of our columns are grouped expressions, we can't reuse here, since
@@ -284,7 +549,14 @@ impl SqlIncorporator {
                         OutputColumn::Data(ref dc) => dc.function.is_none(),
                     });
 
-                    if predicates_match && no_grouped_columns {
+                    // likewise, a DISTINCT query deduplicates per parameter value, so a reader on
+                    // different parameters would see incorrectly collapsed rows. Readers looked up
+                    // by range need their leaf built from scratch, too.
+                    if predicates_match
+                        && no_grouped_columns
+                        && !qg.distinct
+                        && qg.range_parameters().is_empty()
+                    {
                         // QGs are identical, except for parameters (or their order)
                         info!(
                             self.log,
@@ -331,10 +603,10 @@ impl SqlIncorporator {
                                     Some(project_columns)
                                 }
                             };
-                            return (
+                            return Ok((
                                 qg,
                                 QueryGraphReuse::ReaderOntoExisting(mn, project_columns, params),
-                            );
+                            ));
                         }
                     }
                 }
@@ -372,12 +644,12 @@ impl SqlIncorporator {
                 mir_queries.extend(mqs);
             }
 
-            return (qg, QueryGraphReuse::ExtendExisting(mir_queries));
+            return Ok((qg, QueryGraphReuse::ExtendExisting(mir_queries)));
         } else {
             info!(self.log, "No reuse opportunity, adding fresh query");
         }
 
-        (qg, QueryGraphReuse::None)
+        Ok((qg, QueryGraphReuse::None))
     }
 
     fn add_leaf_to_existing_query(
@@ -387,25 +659,138 @@ impl SqlIncorporator {
         final_query_node: MirNodeRef,
         project_columns: Option<Vec<Column>>,
         mut mig: &mut Migration,
-    ) -> QueryFlowParts {
+    ) -> Result<QueryFlowParts, String> {
         trace!(self.log, "Adding a new leaf below: {:?}", final_query_node);
 
         let mut mir = self.mir_converter.add_leaf_below(
             final_query_node,
             query_name,
             params,
+            vec![],
             project_columns,
-        );
+            self.materializations.get(query_name).cloned(),
+        )?;
 
         trace!(self.log, "Reused leaf node MIR: {}", mir);
 
         // push it into the flow graph using the migration in `mig`, and obtain `QueryFlowParts`.
         // Note that we don't need to optimize the MIR here, because the query is trivial.
-        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None);
+        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None)?;
 
         self.register_query(query_name, None, &mir, mig.universe());
 
-        qfp
+        Ok(qfp)
+    }
+
+    /// Replace the reader of the view `query_name` with one keyed on `key_columns`, which reads
+    /// from the same node as the old one. The old reader is left for the caller to remove.
+    pub(super) fn rekey_leaf(
+        &mut self,
+        query_name: &str,
+        key_columns: &[Column],
+        mut mig: &mut Migration,
+    ) -> Result<QueryFlowParts, String> {
+        let mut mir = self.mir_converter.rekey_leaf(query_name, key_columns)?;
+
+        trace!(self.log, "Re-keyed leaf MIR: {}", mir);
+
+        // as for leaves added to existing queries, there is nothing to optimize here
+        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None)?;
+
+        match self.named_queries.get(query_name) {
+            Some(&qg_hash) => {
+                // the query's query graph still has the old parameters, so queries that match it
+                // exactly must not take the re-keyed reader for their own
+                self.mir_queries.insert((qg_hash, mig.universe()), mir);
+                self.altered_leaves.insert(query_name.to_owned());
+            }
+            None => {
+                self.base_mir_queries.insert(query_name.to_owned(), mir);
+            }
+        }
+        self.leaf_addresses
+            .insert(query_name.to_owned(), qfp.query_leaf);
+
+        Ok(qfp)
+    }
+
+    /// Replace the `ORDER BY` and `LIMIT` of the query `query_name` with `new_order` and
+    /// `new_limit`, rebuilding only its TopK and what lies above it.
+    pub(super) fn update_topk(
+        &mut self,
+        query_name: &str,
+        new_order: Option<OrderClause>,
+        new_limit: LimitClause,
+        mut mig: &mut Migration,
+    ) -> Result<QueryFlowParts, String> {
+        let qg_hash = *self
+            .named_queries
+            .get(query_name)
+            .ok_or_else(|| format!("no query named \"{}\"", query_name))?;
+        let mut mir = self
+            .mir_converter
+            .update_topk(query_name, new_order, new_limit)?;
+
+        trace!(self.log, "Updated TopK MIR: {}", mir);
+
+        // as for leaves added to existing queries, there is nothing to optimize here
+        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None)?;
+
+        // queries that match the original statement must not take the re-sorted reader
+        self.mir_queries.insert((qg_hash, mig.universe()), mir);
+        self.altered_leaves.insert(query_name.to_owned());
+        self.leaf_addresses
+            .insert(query_name.to_owned(), qfp.query_leaf);
+
+        Ok(qfp)
+    }
+
+    /// Add a secondary index over base table `base_name`, i.e., a view that returns the base's
+    /// rows looked up by `key_columns` rather than by its primary key.
+    pub(super) fn add_secondary_index(
+        &mut self,
+        base_name: &str,
+        key_columns: &[Column],
+        mut mig: &mut Migration,
+    ) -> Result<QueryFlowParts, String> {
+        let mut mir = self
+            .mir_converter
+            .add_secondary_index(base_name, key_columns)?;
+
+        trace!(self.log, "Secondary index MIR: {}", mir);
+
+        // as for leaves added to existing queries, there is nothing to optimize here
+        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None)?;
+
+        self.register_query(&mir.name, None, &mir, mig.universe());
+        self.leaf_addresses.insert(mir.name.clone(), qfp.query_leaf);
+
+        Ok(qfp)
+    }
+
+    /// Add the view `name`, which reads the existing view (or base) `base_view` sorted by `order`
+    /// and cut down to `page` rows within each of the view's keys.
+    pub(super) fn add_sorted_leaf(
+        &mut self,
+        base_view: &str,
+        name: &str,
+        order: OrderClause,
+        page: LimitClause,
+        mut mig: &mut Migration,
+    ) -> Result<QueryFlowParts, String> {
+        let mut mir = self
+            .mir_converter
+            .add_sorted_leaf(base_view, name, order, page)?;
+
+        trace!(self.log, "Sorted leaf MIR: {}", mir);
+
+        // as for leaves added to existing queries, there is nothing to optimize here
+        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None)?;
+
+        self.register_query(&mir.name, None, &mir, mig.universe());
+        self.leaf_addresses.insert(mir.name.clone(), qfp.query_leaf);
+
+        Ok(qfp)
     }
 
     fn add_base_via_mir(
@@ -413,7 +798,7 @@ impl SqlIncorporator {
         query_name: &str,
         query: &SqlQuery,
         mut mig: &mut Migration,
-    ) -> QueryFlowParts {
+    ) -> Result<QueryFlowParts, String> {
         // first, compute the MIR representation of the SQL query
         let mut mir = self.mir_converter.named_base_to_mir(query_name, query);
 
@@ -422,7 +807,7 @@ impl SqlIncorporator {
         // no optimization, because standalone base nodes can't be optimized
 
         // push it into the flow graph using the migration in `mig`, and obtain `QueryFlowParts`
-        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None);
+        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None)?;
 
         // remember the schema in case we need it later
         // on base table schema change, we will overwrite the existing schema here.
@@ -436,7 +821,7 @@ impl SqlIncorporator {
 
         self.register_query(query_name, None, &mir, mig.universe());
 
-        qfp
+        Ok(qfp)
     }
 
     fn add_compound_query(
@@ -465,9 +850,9 @@ impl SqlIncorporator {
             &query.order,
             &query.limit,
             is_leaf,
-        );
+        )?;
 
-        let qfp = mir_query_to_flow_parts(&mut combined_mir_query, &mut mig, None);
+        let qfp = mir_query_to_flow_parts(&mut combined_mir_query, &mut mig, None)?;
 
         self.register_query(query_name, None, &combined_mir_query, mig.universe());
 
@@ -483,7 +868,7 @@ impl SqlIncorporator {
         is_leaf: bool,
         mig: &mut Migration,
     ) -> Result<(QueryFlowParts, Option<MirQuery>), String> {
-        let (qg, reuse) = self.consider_query_graph(&query_name, mig.universe(), sq);
+        let (qg, reuse) = self.consider_query_graph(&query_name, mig.universe(), sq)?;
         Ok(match reuse {
             QueryGraphReuse::ExactMatch(mn) => {
                 let flow_node = mn.borrow().flow_node.as_ref().unwrap().address();
@@ -500,8 +885,13 @@ impl SqlIncorporator {
                 (qfp, None)
             }
             QueryGraphReuse::ReaderOntoExisting(mn, project_columns, params) => {
-                let qfp =
-                    self.add_leaf_to_existing_query(&query_name, &params, mn, project_columns, mig);
+                let qfp = self.add_leaf_to_existing_query(
+                    &query_name,
+                    &params,
+                    mn,
+                    project_columns,
+                    mig,
+                )?;
                 (qfp, None)
             }
             QueryGraphReuse::None => {
@@ -529,6 +919,7 @@ impl SqlIncorporator {
             &qg,
             is_leaf,
             universe.clone(),
+            self.materializations.get(query_name).cloned(),
         )?;
 
         trace!(
@@ -558,7 +949,7 @@ impl SqlIncorporator {
         }
 
         // push it into the flow graph using the migration in `mig`, and obtain `QueryFlowParts`
-        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None);
+        let qfp = mir_query_to_flow_parts(&mut mir, &mut mig, None)?;
 
         // register local state
         self.register_query(query_name, Some(qg), &mir, universe);
@@ -571,6 +962,7 @@ impl SqlIncorporator {
             .leaf_addresses
             .remove(query_name)
             .expect("tried to remove unknown query");
+        self.altered_leaves.remove(query_name);
 
         let qg_hash = self
             .named_queries
@@ -685,9 +1077,15 @@ impl SqlIncorporator {
 
         // no QG-level reuse possible, so we'll build a new query.
         // first, compute the MIR representation of the SQL query
-        let (sec, new_query_mir, table_mapping, base_name) = self
-            .mir_converter
-            .named_query_to_mir(query_name, query, &qg, is_leaf, universe.clone())?;
+        let (sec, new_query_mir, table_mapping, base_name) =
+            self.mir_converter.named_query_to_mir(
+                query_name,
+                query,
+                &qg,
+                is_leaf,
+                universe.clone(),
+                self.materializations.get(query_name).cloned(),
+            )?;
 
         trace!(
             self.log,
@@ -742,7 +1140,7 @@ impl SqlIncorporator {
         );
 
         let qfp =
-            mir_query_to_flow_parts(&mut post_reuse_opt_mir, &mut mig, table_mapping.as_ref());
+            mir_query_to_flow_parts(&mut post_reuse_opt_mir, &mut mig, table_mapping.as_ref())?;
 
         info!(
             self.log,
@@ -791,6 +1189,7 @@ impl SqlIncorporator {
         // flattens out the query by replacing subqueries for references
         // to existing views in the graph
         let mut fq = q.clone();
+        let mut semi_joined = Vec::new();
         for sq in fq.extract_subqueries() {
             use self::passes::subqueries::{
                 field_with_table_name, query_from_condition_base, Subquery,
@@ -800,22 +1199,29 @@ impl SqlIncorporator {
                 Subquery::InComparison(cond_base) => {
                     let (sq, column) = query_from_condition_base(&cond_base);
 
-                    let qfp = self
-                        .add_parsed_query(sq, None, false, mig)
-                        .expect("failed to add subquery");
+                    // the subquery's view is joined with the other tables on the compared
+                    // column, which makes for a semi-join as long as the view never has the
+                    // same value twice; otherwise, rows would appear once per match.
+                    let sq = match sq {
+                        SqlQuery::Select(mut st) => {
+                            st.distinct = true;
+                            SqlQuery::Select(st)
+                        }
+                        sq => sq,
+                    };
+                    let qfp = self.add_parsed_query(sq, None, false, mig)?;
                     *cond_base = field_with_table_name(qfp.name.clone(), column);
+                    semi_joined.push(Table::from(qfp.name.as_str()));
                 }
                 Subquery::InJoin(join_right_side) => {
                     *join_right_side = match *join_right_side {
                         JoinRightSide::NestedSelect(ref ns, ref alias) => {
-                            let qfp = self
-                                .add_parsed_query(
-                                    SqlQuery::Select((**ns).clone()),
-                                    alias.clone(),
-                                    false,
-                                    mig,
-                                )
-                                .expect("failed to add subquery in join");
+                            let qfp = self.add_parsed_query(
+                                SqlQuery::Select((**ns).clone()),
+                                alias.clone(),
+                                false,
+                                mig,
+                            )?;
                             JoinRightSide::Table(Table {
                                 name: qfp.name.clone(),
                                 alias: None,
@@ -826,6 +1232,28 @@ impl SqlIncorporator {
                 }
             }
         }
+        if let SqlQuery::Select(ref mut st) = fq {
+            use nom_sql::FieldDefinitionExpression;
+
+            // `*` must not pick up the columns of semi-joined subquery views, so we pin it to
+            // the tables the query named itself before adding them
+            if !semi_joined.is_empty() {
+                let tables: Vec<_> = st.tables.iter().map(|t| t.name.clone()).collect();
+                st.fields = st
+                    .fields
+                    .drain(..)
+                    .flat_map(|f| match f {
+                        FieldDefinitionExpression::All => tables
+                            .iter()
+                            .cloned()
+                            .map(FieldDefinitionExpression::AllInTable)
+                            .collect(),
+                        f => vec![f],
+                    })
+                    .collect();
+            }
+            st.tables.extend(semi_joined);
+        }
 
         // Check that all tables mentioned in the query exist.
         // This must happen before the rewrite passes are applied because some of them rely on
@@ -889,6 +1317,24 @@ impl SqlIncorporator {
             }
         };
 
+        let q = match q {
+            SqlQuery::Select(mut sq) if self.group_sizes.contains(&query_name) => {
+                add_group_size(&mut sq)?;
+                SqlQuery::Select(sq)
+            }
+            q => q,
+        };
+        // nom-sql cannot parse `ROLLUP` or `CUBE`, so such queries arrive as plain grouped selects
+        let q = match q {
+            SqlQuery::Select(sq) if self.rollups.contains(&query_name) => {
+                SqlQuery::CompoundSelect(rollup_to_union(&sq)?)
+            }
+            SqlQuery::Select(sq) if self.cubes.contains(&query_name) => {
+                SqlQuery::CompoundSelect(cube_to_union(&sq)?)
+            }
+            q => q,
+        };
+
         let q = self.rewrite_query(q, mig)?;
 
         // TODO(larat): extend existing should handle policy nodes
@@ -903,7 +1349,7 @@ impl SqlIncorporator {
                     .unwrap()
             }
             SqlQuery::Select(sq) => self.add_select_query(&query_name, &sq, is_leaf, mig)?.0,
-            ref q @ SqlQuery::CreateTable { .. } => self.add_base_via_mir(&query_name, &q, mig),
+            ref q @ SqlQuery::CreateTable { .. } => self.add_base_via_mir(&query_name, &q, mig)?,
             q => panic!("unhandled query type in recipe: {:?}", q),
         };
 
@@ -958,6 +1404,15 @@ impl<'a> ToFlowParts for &'a str {
         name: Option<String>,
         mig: &mut Migration,
     ) -> Result<QueryFlowParts, String> {
+        // common table expressions and derived tables (subqueries in FROM) become views without
+        // readers of their own, which the query then selects from like from any other view
+        if let Some((views, q)) = parse_inline_views(name.as_ref().map(String::as_str), self)? {
+            for (view, v) in views {
+                inc.add_parsed_query(v, Some(view), false, mig)?;
+            }
+            return inc.add_parsed_query(q, name, true, mig);
+        }
+
         // try parsing the incoming SQL
         let parsed_query = sql_parser::parse_query(self);
 
@@ -978,6 +1433,7 @@ mod tests {
     use nom_sql::{
         CaseWhenExpression, Column, ColumnOrLiteral, FunctionArguments, FunctionExpression, Literal,
     };
+    use std::collections::HashSet;
 
     /// Helper to grab a reference to a named view.
     fn get_node<'a>(inc: &SqlIncorporator, mig: &'a Migration, name: &str) -> &'a Node {
@@ -1057,6 +1513,32 @@ mod tests {
         .await;
     }
 
+    #[tokio::test(threaded_scheduler)]
+    async fn it_incorporates_unique_keys() {
+        // set up graph
+        let mut g = integration::start_simple("it_incorporates_unique_keys").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE users (id int, email varchar(255), name varchar(40), \
+                     PRIMARY KEY (id), UNIQUE KEY email_key (email));",
+                    None,
+                    mig
+                )
+                .is_ok());
+            // Should have source and "users" base table node
+            assert_eq!(mig.graph().node_count(), 2);
+            let users = get_node(&inc, mig, "users");
+            assert!(users.is_base());
+            // the primary key and the unique key are both recorded on the base
+            let base = users.get_base().unwrap();
+            assert_eq!(base.key(), Some(&[0][..]));
+            assert_eq!(base.unique_keys(), &[vec![1]]);
+        })
+        .await;
+    }
+
     #[tokio::test(threaded_scheduler)]
     async fn it_incorporates_simple_join() {
         // set up graph
@@ -1154,90 +1636,478 @@ mod tests {
     }
 
     #[tokio::test(threaded_scheduler)]
-    async fn it_incorporates_aggregation() {
+    async fn it_fuses_conjunctive_selections() {
         // set up graph
-        let mut g = integration::start_simple("it_incorporates_aggregation").await;
+        let mut g = integration::start_simple("it_fuses_conjunctive_selections").await;
         g.migrate(|mig| {
             let mut inc = SqlIncorporator::default();
-            // Establish a base write types
+            // Establish a base write type
             assert!(inc
-                .add_query("CREATE TABLE votes (aid int, userid int);", None, mig)
+                .add_query(
+                    "CREATE TABLE users (id int, name varchar(40), age int);",
+                    None,
+                    mig
+                )
                 .is_ok());
             // Should have source and "users" base table node
             assert_eq!(mig.graph().node_count(), 2);
-            assert_eq!(get_node(&inc, mig, "votes").name(), "votes");
-            assert_eq!(get_node(&inc, mig, "votes").fields(), &["aid", "userid"]);
-            assert!(get_node(&inc, mig, "votes").is_base());
 
-            // Try a simple COUNT function
             let res = inc.add_query(
-                "SELECT COUNT(votes.userid) AS votes \
-                 FROM votes GROUP BY votes.aid;",
+                "SELECT users.name FROM users WHERE users.id = 1 AND users.age = 2;",
                 None,
                 mig,
             );
             assert!(res.is_ok());
-            // added the aggregation and the edge view, and a reader
+            // should have added a single filter node, a projection and a reader
             assert_eq!(mig.graph().node_count(), 5);
-            // check aggregation view
-            let f = Box::new(FunctionExpression::Count(
-                FunctionArguments::Column(Column::from("votes.userid")),
-                false,
-            ));
+
             let qid = query_id_hash(
-                &["computed_columns", "votes"],
-                &[&Column::from("votes.aid")],
-                &[&Column {
-                    name: String::from("votes"),
-                    alias: Some(String::from("votes")),
-                    table: None,
-                    function: Some(f),
-                }],
+                &["users"],
+                &[&Column::from("users.age"), &Column::from("users.id")],
+                &[&Column::from("users.name")],
             );
-            let agg_view = get_node(&inc, mig, &format!("q_{:x}_n0", qid));
-            assert_eq!(agg_view.fields(), &["aid", "votes"]);
-            assert_eq!(agg_view.description(true), "|*| γ[0]");
-            // check edge view
-            let edge_view = get_node(&inc, mig, &res.unwrap().name);
-            assert_eq!(edge_view.fields(), &["votes", "bogokey"]);
-            assert_eq!(edge_view.description(true), "π[1, lit: 0]");
-        })
-        .await;
-    }
-
-    #[tokio::test(threaded_scheduler)]
-    async fn it_does_not_reuse_if_disabled() {
-        // set up graph
-        let mut g = integration::start_simple("it_does_not_reuse_if_disabled").await;
-        g.migrate(|mig| {
-            let mut inc = SqlIncorporator::default();
-            inc.disable_reuse();
-            assert!(inc
-                .add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
-                .is_ok());
-            let res = inc.add_query("SELECT id, name FROM users WHERE users.id = 42;", None, mig);
-            assert!(res.is_ok());
-            let leaf = res.unwrap().query_leaf;
-
-            // Add the same query again; this should NOT reuse here.
-            let ncount = mig.graph().node_count();
-            let res = inc.add_query("SELECT name, id FROM users WHERE users.id = 42;", None, mig);
-            assert!(res.is_ok());
-            // should have added nodes for this query, too
-            let qfp = res.unwrap();
-            assert_eq!(qfp.new_nodes.len(), 2);
-            // expect three new nodes: filter, project, reader
-            assert_eq!(mig.graph().node_count(), ncount + 3);
-            // should have ended up with a different leaf node
-            assert_ne!(qfp.query_leaf, leaf);
+            // both conditions are checked by the same filter node
+            let filter = get_node(&inc, mig, &format!("q_{:x}_n0_p0_f0", qid));
+            assert_eq!(filter.fields(), &["id", "name", "age"]);
+            assert_eq!(filter.description(true), "σ[f0 = 1, f2 = 2]");
+            // leaf view node
+            let edge = get_node(&inc, mig, &res.unwrap().name);
+            assert_eq!(edge.fields(), &["name", "bogokey"]);
+            assert_eq!(edge.description(true), "π[1, lit: 0]");
         })
         .await;
     }
 
     #[tokio::test(threaded_scheduler)]
-    async fn it_orders_parameter_columns() {
+    async fn it_fuses_in_lists_with_comparisons() {
         // set up graph
-        let mut g = integration::start_simple("it_orders_parameter_columns").await;
+        let mut g = integration::start_simple("it_fuses_in_lists_with_comparisons").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            // Establish a base write type
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE users (id int, name varchar(40), age int);",
+                    None,
+                    mig
+                )
+                .is_ok());
+            assert_eq!(mig.graph().node_count(), 2);
+
+            let res = inc.add_query(
+                "SELECT users.id FROM users WHERE users.name IN ('a', 'b') AND users.age > 18;",
+                None,
+                mig,
+            );
+            assert!(res.is_ok());
+            // should have added a single filter node, a projection and a reader
+            assert_eq!(mig.graph().node_count(), 5);
+
+            let qid = query_id_hash(
+                &["users"],
+                &[&Column::from("users.name"), &Column::from("users.age")],
+                &[&Column::from("users.id")],
+            );
+            // the IN list and the range comparison are checked by the same filter node
+            let filter = get_node(&inc, mig, &format!("q_{:x}_n0_p0_f0", qid));
+            assert_eq!(filter.fields(), &["id", "name", "age"]);
+            assert_eq!(
+                filter.description(true),
+                "σ[f1 IN (\"a\", \"b\"), f2 \\> 18]"
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_short_circuits_unsatisfiable_selections() {
+        // set up graph
+        let mut g = integration::start_simple("it_short_circuits_unsatisfiable_selections").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            // Establish a base write type
+            assert!(inc
+                .add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
+                .is_ok());
+
+            // users.id can't be both 1 and 2
+            let res = inc.add_query(
+                "SELECT users.name FROM users WHERE users.id = 1 AND users.id = 2;",
+                None,
+                mig,
+            );
+            assert!(res.is_ok());
+
+            let qid = query_id_hash(
+                &["users"],
+                &[&Column::from("users.id"), &Column::from("users.id")],
+                &[&Column::from("users.name")],
+            );
+            // the filter node lets no records through
+            let filter = get_node(&inc, mig, &format!("q_{:x}_n0_p0_f0", qid));
+            assert_eq!(filter.fields(), &["id", "name"]);
+            assert_eq!(filter.description(true), "σ[f0 IN ()]");
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_incorporates_aggregation() {
+        // set up graph
+        let mut g = integration::start_simple("it_incorporates_aggregation").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            // Establish a base write types
+            assert!(inc
+                .add_query("CREATE TABLE votes (aid int, userid int);", None, mig)
+                .is_ok());
+            // Should have source and "users" base table node
+            assert_eq!(mig.graph().node_count(), 2);
+            assert_eq!(get_node(&inc, mig, "votes").name(), "votes");
+            assert_eq!(get_node(&inc, mig, "votes").fields(), &["aid", "userid"]);
+            assert!(get_node(&inc, mig, "votes").is_base());
+
+            // Try a simple COUNT function
+            let res = inc.add_query(
+                "SELECT COUNT(votes.userid) AS votes \
+                 FROM votes GROUP BY votes.aid;",
+                None,
+                mig,
+            );
+            assert!(res.is_ok());
+            // added the aggregation and the edge view, and a reader
+            assert_eq!(mig.graph().node_count(), 5);
+            // check aggregation view
+            let f = Box::new(FunctionExpression::Count(
+                FunctionArguments::Column(Column::from("votes.userid")),
+                false,
+            ));
+            let qid = query_id_hash(
+                &["computed_columns", "votes"],
+                &[&Column::from("votes.aid")],
+                &[&Column {
+                    name: String::from("votes"),
+                    alias: Some(String::from("votes")),
+                    table: None,
+                    function: Some(f),
+                }],
+            );
+            let agg_view = get_node(&inc, mig, &format!("q_{:x}_n0", qid));
+            assert_eq!(agg_view.fields(), &["aid", "votes"]);
+            assert_eq!(agg_view.description(true), "|*| γ[0]");
+            // check edge view
+            let edge_view = get_node(&inc, mig, &res.unwrap().name);
+            assert_eq!(edge_view.fields(), &["votes", "bogokey"]);
+            assert_eq!(edge_view.description(true), "π[1, lit: 0]");
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_computes_base_dependencies() {
+        // set up graph
+        let mut g = integration::start_simple("it_computes_base_dependencies").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
+                .is_ok());
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE articles (id int, author int, title varchar(255));",
+                    None,
+                    mig
+                )
+                .is_ok());
+            assert!(inc
+                .add_query("CREATE TABLE votes (aid int, userid int);", None, mig)
+                .is_ok());
+            let res = inc.add_query(
+                "SELECT users.name, articles.title \
+                 FROM articles, users \
+                 WHERE users.id = articles.author;",
+                None,
+                mig,
+            );
+            assert!(res.is_ok());
+            // both sides of the join, but not the unrelated table
+            let deps = inc.base_dependencies(&res.unwrap().name);
+            let expected: HashSet<String> = vec!["users".into(), "articles".into()]
+                .into_iter()
+                .collect();
+            assert_eq!(deps, expected);
+            // unknown queries have no dependencies
+            assert!(inc.base_dependencies("nonexistent").is_empty());
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_filters_on_aggregate_alias_after_aggregation() {
+        // set up graph
+        let mut g =
+            integration::start_simple("it_filters_on_aggregate_alias_after_aggregation").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE votes (aid int, userid int);", None, mig)
+                .is_ok());
+            let parent_of = |na| {
+                let parents: Vec<_> = mig
+                    .graph()
+                    .neighbors_directed(na, petgraph::EdgeDirection::Incoming)
+                    .collect();
+                assert_eq!(parents.len(), 1);
+                parents[0]
+            };
+
+            // the WHERE clause refers to the count, so the filter sits between the aggregation
+            // and the leaf
+            let res = inc.add_query(
+                "SELECT votes.aid, COUNT(votes.userid) AS votecount \
+                 FROM votes WHERE votecount > 5 GROUP BY votes.aid;",
+                None,
+                mig,
+            );
+            assert!(res.is_ok());
+            let leaf = inc.get_flow_node_address(&res.unwrap().name, 0).unwrap();
+            let filter = parent_of(leaf);
+            assert_eq!(mig.graph()[filter].description(true), "σ[f1 \\> 5]");
+            let agg = parent_of(filter);
+            assert_eq!(mig.graph()[agg].description(true), "|*| γ[0]");
+
+            // as in SQL, a column of the table takes precedence over an alias of the same name,
+            // whether or not it is qualified, so the filter applies to the votes before counting
+            for q in &[
+                "SELECT votes.aid, COUNT(votes.userid) AS userid \
+                 FROM votes WHERE votes.userid > 5 GROUP BY votes.aid;",
+                "SELECT votes.aid, COUNT(votes.userid) AS userid \
+                 FROM votes WHERE userid > 5 GROUP BY votes.aid;",
+            ] {
+                let res = inc.add_query(q, None, mig);
+                assert!(res.is_ok(), "{}", q);
+                let leaf = inc.get_flow_node_address(&res.unwrap().name, 0).unwrap();
+                let agg = parent_of(leaf);
+                assert_eq!(mig.graph()[agg].description(true), "|*| γ[0]");
+                let filter = parent_of(agg);
+                assert_eq!(mig.graph()[filter].description(true), "σ[f1 \\> 5]");
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_does_not_reuse_if_disabled() {
+        // set up graph
+        let mut g = integration::start_simple("it_does_not_reuse_if_disabled").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            inc.disable_reuse();
+            assert!(inc
+                .add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
+                .is_ok());
+            let res = inc.add_query("SELECT id, name FROM users WHERE users.id = 42;", None, mig);
+            assert!(res.is_ok());
+            let leaf = res.unwrap().query_leaf;
+
+            // Add the same query again; this should NOT reuse here.
+            let ncount = mig.graph().node_count();
+            let res = inc.add_query("SELECT name, id FROM users WHERE users.id = 42;", None, mig);
+            assert!(res.is_ok());
+            // should have added nodes for this query, too
+            let qfp = res.unwrap();
+            assert_eq!(qfp.new_nodes.len(), 2);
+            // expect three new nodes: filter, project, reader
+            assert_eq!(mig.graph().node_count(), ncount + 3);
+            // should have ended up with a different leaf node
+            assert_ne!(qfp.query_leaf, leaf);
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_reads_base_through_secondary_index() {
+        let mut g = integration::start_simple("it_reads_base_through_secondary_index").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE users (id int, name varchar(40), PRIMARY KEY (id));",
+                    None,
+                    mig
+                )
+                .is_ok());
+
+            let qfp = inc
+                .add_secondary_index("users", &[::mir::Column::new(None, "name")], mig)
+                .unwrap();
+            assert_eq!(qfp.name, "users_by_name");
+            // the index is keyed on the name column, not on the primary key
+            let n = get_reader(&inc, mig, &qfp.name);
+            n.with_reader(|r| assert_eq!(r.key().unwrap(), &[1]))
+                .unwrap();
+        })
+        .await;
+
+        let mut users = g.table("users").await.unwrap();
+        users.insert(vec![1.into(), "alice".into()]).await.unwrap();
+        users.insert(vec![2.into(), "bob".into()]).await.unwrap();
+        users.insert(vec![3.into(), "bob".into()]).await.unwrap();
+        integration::sleep().await;
+
+        let mut by_name = g.view("users_by_name").await.unwrap();
+        let mut rows: Vec<Vec<DataType>> =
+            by_name.lookup(&["bob".into()], true).await.unwrap().into();
+        rows.sort();
+        assert_eq!(
+            rows,
+            vec![vec![2.into(), "bob".into()], vec![3.into(), "bob".into()]]
+        );
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_reads_topk_with_limit_given_at_read_time() {
+        let mut g = integration::start_simple("it_reads_topk_with_limit_given_at_read_time").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE scores (id int, g int, score int);", None, mig)
+                .is_ok());
+
+            inc.set_read_time_limit("best");
+            let qfp = inc
+                .add_query(
+                    "SELECT scores.id, scores.score FROM scores WHERE scores.g = ? \
+                     ORDER BY scores.score DESC LIMIT 10;",
+                    Some("best".into()),
+                    mig,
+                )
+                .unwrap();
+            let n = get_reader(&inc, mig, &qfp.name);
+            n.with_reader(|r| assert!(r.order().is_some())).unwrap();
+        })
+        .await;
+
+        let mut scores = g.table("scores").await.unwrap();
+        for (id, score) in &[(1, 50), (2, 80), (3, 20), (4, 70), (5, 60)] {
+            scores
+                .insert(vec![(*id).into(), 1.into(), (*score).into()])
+                .await
+                .unwrap();
+        }
+        scores
+            .insert(vec![6.into(), 2.into(), 90.into()])
+            .await
+            .unwrap();
+        integration::sleep().await;
+
+        // the same view serves different limits, always in the query's order
+        let mut best = g.view("best").await.unwrap();
+        let ids = |rows: Vec<Vec<DataType>>| -> Vec<DataType> {
+            rows.into_iter().map(|r| r[0].clone()).collect()
+        };
+        let top2 = ids(best.lookup_top(&[1.into()], 2).await.unwrap().into());
+        assert_eq!(top2, vec![2.into(), 4.into()]);
+        let top4 = ids(best.lookup_top(&[1.into()], 4).await.unwrap().into());
+        assert_eq!(top4, vec![2.into(), 4.into(), 5.into(), 1.into()]);
+        let other = ids(best.lookup_top(&[2.into()], 4).await.unwrap().into());
+        assert_eq!(other, vec![6.into()]);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_reads_ordered_distinct_rows() {
+        let mut g = integration::start_simple("it_reads_ordered_distinct_rows").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE pairs (a int, b int);", None, mig)
+                .is_ok());
+
+            inc.set_ordered_distinct("distinct_pairs");
+            let qfp = inc
+                .add_query(
+                    "SELECT DISTINCT pairs.a, pairs.b FROM pairs;",
+                    Some("distinct_pairs".into()),
+                    mig,
+                )
+                .unwrap();
+            let n = get_reader(&inc, mig, &qfp.name);
+            n.with_reader(|r| assert!(r.order().is_some())).unwrap();
+        })
+        .await;
+
+        let mut pairs = g.table("pairs").await.unwrap();
+        for (a, b) in &[(2, 1), (1, 2), (2, 1), (1, 1), (3, 0), (1, 2)] {
+            pairs.insert(vec![(*a).into(), (*b).into()]).await.unwrap();
+        }
+        integration::sleep().await;
+
+        // rows come back ordered by the distinct columns, not in the order they were inserted
+        let mut view = g.view("distinct_pairs").await.unwrap();
+        let rows: Vec<Vec<DataType>> = view.lookup(&[0.into()], true).await.unwrap().into();
+        let rows: Vec<_> = rows
+            .into_iter()
+            .map(|r| (r[0].clone(), r[1].clone()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (1.into(), 1.into()),
+                (1.into(), 2.into()),
+                (2.into(), 1.into()),
+                (3.into(), 0.into()),
+            ]
+        );
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_binds_parameters_to_literals() {
+        let mut g = integration::start_simple("it_binds_parameters_to_literals").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE orders (id int, region text, uid int);",
+                    None,
+                    mig
+                )
+                .is_ok());
+
+            inc.bind_parameter("us_orders", "orders.region", Literal::String("us".into()));
+            let qfp = inc
+                .add_query(
+                    "SELECT orders.id FROM orders WHERE orders.region = ? AND orders.uid = ?;",
+                    Some("us_orders".into()),
+                    mig,
+                )
+                .unwrap();
+            // readers only supply the user, since the region is baked into the view
+            let n = get_reader(&inc, mig, &qfp.name);
+            n.with_reader(|r| assert_eq!(r.key().map(<[usize]>::len), Some(1)))
+                .unwrap();
+        })
+        .await;
+
+        let mut orders = g.table("orders").await.unwrap();
+        for (id, region, uid) in &[(1, "us", 7), (2, "eu", 7), (3, "us", 7), (4, "us", 8)] {
+            orders
+                .insert(vec![(*id).into(), (*region).into(), (*uid).into()])
+                .await
+                .unwrap();
+        }
+        integration::sleep().await;
+
+        let mut view = g.view("us_orders").await.unwrap();
+        let rows: Vec<Vec<DataType>> = view.lookup(&[7.into()], true).await.unwrap().into();
+        let mut ids: Vec<_> = rows.into_iter().map(|r| r[0].clone()).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1.into(), 3.into()]);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_orders_parameter_columns() {
+        // set up graph
+        let mut g = integration::start_simple("it_orders_parameter_columns").await;
         g.migrate(|mig| {
             let mut inc = SqlIncorporator::default();
             assert!(inc
@@ -2258,6 +3128,266 @@ mod tests {
         .await;
     }
 
+    #[tokio::test(threaded_scheduler)]
+    async fn it_unions_join_and_aggregation_branches_by_position() {
+        let mut g =
+            integration::start_simple("it_unions_join_and_aggregation_branches_by_position").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
+                .is_ok());
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE orders (id int, uid int, amount int);",
+                    None,
+                    mig
+                )
+                .is_ok());
+
+            // the branches' columns are named differently, but line up by position
+            let res = inc.add_query(
+                "SELECT users.id, orders.amount FROM users \
+                 JOIN orders ON users.id = orders.uid \
+                 UNION \
+                 SELECT orders.uid, SUM(orders.amount) AS total FROM orders \
+                 GROUP BY orders.uid;",
+                Some("amounts".into()),
+                mig,
+            );
+            assert!(res.is_ok(), "{:?}", res);
+
+            let union_view = get_node(&inc, mig, "amounts_union");
+            assert_eq!(union_view.fields(), &["id", "amount"]);
+            assert!(union_view.description(true).ends_with(":[0, 1]"));
+            assert_eq!(union_view.description(true).matches(":[0, 1]").count(), 2);
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_pads_narrower_union_branches_with_nulls() {
+        let mut g = integration::start_simple("it_pads_narrower_union_branches_with_nulls").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
+                .is_ok());
+
+            inc.set_padded_union("padded");
+            let res = inc.add_query(
+                "SELECT users.id, users.name FROM users WHERE users.id = 32 \
+                 UNION \
+                 SELECT users.id FROM users WHERE users.id = 42;",
+                Some("padded".into()),
+                mig,
+            );
+            assert!(res.is_ok(), "{:?}", res);
+
+            let union_view = get_node(&inc, mig, "padded_union");
+            assert_eq!(union_view.fields(), &["id", "name"]);
+            // the narrower branch passes through a projection that adds a NULL name
+            let pad = get_node(&inc, mig, "padded_union_pad_1");
+            assert_eq!(pad.fields(), &["id", "name"]);
+            assert!(pad.description(true).ends_with("lit: NULL]"));
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_rolls_up_group_by_columns() {
+        let mut g = integration::start_simple("it_rolls_up_group_by_columns").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE sales (region varchar(10), product varchar(10), amount int);",
+                    None,
+                    mig
+                )
+                .is_ok());
+
+            inc.set_rollup("totals");
+            let res = inc.add_query(
+                "SELECT sales.region, sales.product, SUM(sales.amount) AS total FROM sales \
+                 GROUP BY sales.region, sales.product;",
+                Some("totals".into()),
+                mig,
+            );
+            assert!(res.is_ok(), "{:?}", res);
+
+            // one branch per grouping: (region, product), (region) and the grand total
+            let union_view = get_node(&inc, mig, "totals_union");
+            assert_eq!(union_view.fields(), &["region", "product", "total"]);
+            let subtotals = get_node(&inc, mig, "totals_union_pad_1");
+            assert_eq!(subtotals.fields(), &["region", "total", "product"]);
+            assert!(subtotals.description(true).ends_with("lit: NULL]"));
+            let grand_total = get_node(&inc, mig, "totals_union_pad_2");
+            assert_eq!(grand_total.fields(), &["total", "region", "product"]);
+            assert!(grand_total
+                .description(true)
+                .ends_with("lit: NULL, lit: NULL]"));
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_cubes_group_by_columns() {
+        let mut g = integration::start_simple("it_cubes_group_by_columns").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE sales (region varchar(10), product varchar(10), amount int);",
+                    None,
+                    mig
+                )
+                .is_ok());
+
+            inc.set_cube("totals");
+            let res = inc.add_query(
+                "SELECT sales.region, sales.product, SUM(sales.amount) AS total FROM sales \
+                 GROUP BY sales.region, sales.product;",
+                Some("totals".into()),
+                mig,
+            );
+            assert!(res.is_ok(), "{:?}", res);
+
+            // one branch per subset: (region, product), (region), (product) and the grand total
+            let union_view = get_node(&inc, mig, "totals_union");
+            assert_eq!(union_view.fields(), &["region", "product", "total"]);
+            let by_region = get_node(&inc, mig, "totals_union_pad_1");
+            assert_eq!(by_region.fields(), &["region", "total", "product"]);
+            assert!(by_region.description(true).ends_with("lit: NULL]"));
+            let by_product = get_node(&inc, mig, "totals_union_pad_2");
+            assert_eq!(by_product.fields(), &["product", "total", "region"]);
+            assert!(by_product.description(true).ends_with("lit: NULL]"));
+            let grand_total = get_node(&inc, mig, "totals_union_pad_3");
+            assert_eq!(grand_total.fields(), &["total", "region", "product"]);
+            assert!(grand_total
+                .description(true)
+                .ends_with("lit: NULL, lit: NULL]"));
+
+            // every subset of four columns would take sixteen branches
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE visits (a int, b int, c int, d int, n int);",
+                    None,
+                    mig
+                )
+                .is_ok());
+            inc.set_cube("too_wide");
+            assert!(inc
+                .add_query(
+                    "SELECT visits.a, visits.b, visits.c, visits.d, SUM(visits.n) AS n \
+                     FROM visits GROUP BY visits.a, visits.b, visits.c, visits.d;",
+                    Some("too_wide".into()),
+                    mig,
+                )
+                .is_err());
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_keys_views_on_computed_parameters() {
+        let mut g = integration::start_simple("it_keys_views_on_computed_parameters").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE users (id int, email varchar(255));",
+                    None,
+                    mig
+                )
+                .is_ok());
+
+            // stands in for `WHERE LOWER(users.email) = ?`
+            inc.set_computed_parameter("by_email", "users.email", "email_lower", "LOWER")
+                .unwrap();
+            assert!(inc
+                .set_computed_parameter("by_email", "users.email", "email_len", "LENGTH")
+                .is_err());
+            let res = inc.add_query(
+                "SELECT users.id FROM users WHERE users.email = ?;",
+                Some("by_email".into()),
+                mig,
+            );
+            assert!(res.is_ok(), "{:?}", res);
+        })
+        .await;
+
+        let mut users = g.table("users").await.unwrap();
+        users
+            .insert(vec![1.into(), "Alice@Example.com".into()])
+            .await
+            .unwrap();
+        users
+            .insert(vec![2.into(), "bob@example.com".into()])
+            .await
+            .unwrap();
+        integration::sleep().await;
+
+        // the view is keyed on the lower-cased email, whatever case it was written in
+        let mut by_email = g.view("by_email").await.unwrap();
+        let rows: Vec<Vec<DataType>> = by_email
+            .lookup(&["alice@example.com".into()], true)
+            .await
+            .unwrap()
+            .into();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], 1.into());
+        let rows: Vec<Vec<DataType>> = by_email
+            .lookup(&["Alice@Example.com".into()], true)
+            .await
+            .unwrap()
+            .into();
+        assert!(rows.is_empty());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_annotates_groups_with_their_size() {
+        let mut g = integration::start_simple("it_annotates_groups_with_their_size").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query(
+                    "CREATE TABLE emp (id int, dept int, salary int);",
+                    None,
+                    mig
+                )
+                .is_ok());
+
+            inc.set_group_size("payroll");
+            let res = inc.add_query(
+                "SELECT emp.dept, SUM(emp.salary) AS total FROM emp \
+                 WHERE emp.dept = ? GROUP BY emp.dept;",
+                Some("payroll".into()),
+                mig,
+            );
+            assert!(res.is_ok(), "{:?}", res);
+        })
+        .await;
+
+        let mut emp = g.table("emp").await.unwrap();
+        for (id, dept, salary) in vec![(1, 1, 10), (2, 1, 20), (3, 2, 5)] {
+            emp.insert(vec![id.into(), dept.into(), salary.into()])
+                .await
+                .unwrap();
+        }
+        integration::sleep().await;
+
+        let mut payroll = g.view("payroll").await.unwrap();
+        let rows = payroll.lookup(&[1.into()], true).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        let row = rows.iter().next().unwrap();
+        assert_eq!(row["total"], 30.into());
+        assert_eq!(row["group_size"], 2.into());
+        let rows = payroll.lookup(&[2.into()], true).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows.iter().next().unwrap()["group_size"], 1.into());
+    }
+
     #[tokio::test(threaded_scheduler)]
     async fn it_distinguishes_predicates() {
         // set up graph
@@ -2332,4 +3462,160 @@ mod tests {
         })
         .await;
     }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_incorporates_common_table_expressions() {
+        let mut g = integration::start_simple("it_incorporates_common_table_expressions").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE users (id int, name varchar(40));", None, mig)
+                .is_ok());
+
+            let res = inc.add_query(
+                "WITH recent AS (SELECT users.id, users.name FROM users WHERE users.id > 10) \
+                 SELECT recent.name FROM recent WHERE recent.id = ?;",
+                Some("recent_names".into()),
+                mig,
+            );
+            assert!(res.is_ok(), "{:?}", res);
+
+            // the CTE is lowered once, as a view of its own without a reader, named after the query
+            let recent = get_node(&inc, mig, "recent_names_recent");
+            assert_eq!(recent.fields(), &["id", "name"]);
+            assert!(mig
+                .graph()
+                .neighbors_directed(recent.global_addr(), petgraph::EdgeDirection::Outgoing)
+                .all(|n| !mig.graph()[n].is_reader()));
+            assert!(inc.get_flow_node_address("recent", 0).is_none());
+
+            // a CTE named like a base table shadows it only within its own query
+            let res = inc.add_query(
+                "WITH users AS (SELECT users.id, users.name FROM users WHERE users.id < 10) \
+                 SELECT users.name FROM users WHERE users.id = ?;",
+                Some("old_names".into()),
+                mig,
+            );
+            assert!(res.is_ok(), "{:?}", res);
+            assert_eq!(get_node(&inc, mig, "users").fields(), &["id", "name"]);
+        })
+        .await;
+
+        let mut users = g.table("users").await.unwrap();
+        users.insert(vec![5.into(), "old".into()]).await.unwrap();
+        users.insert(vec![11.into(), "new".into()]).await.unwrap();
+        integration::sleep().await;
+
+        let mut view = g.view("recent_names").await.unwrap();
+        let rows: Vec<Vec<DataType>> = view.lookup(&[11.into()], true).await.unwrap().into();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], "new".into());
+        let rows: Vec<Vec<DataType>> = view.lookup(&[5.into()], true).await.unwrap().into();
+        assert!(rows.is_empty());
+
+        let mut view = g.view("old_names").await.unwrap();
+        let rows: Vec<Vec<DataType>> = view.lookup(&[5.into()], true).await.unwrap().into();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0], "old".into());
+        let rows: Vec<Vec<DataType>> = view.lookup(&[11.into()], true).await.unwrap().into();
+        assert!(rows.is_empty());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_aggregates_over_derived_tables() {
+        let mut g = integration::start_simple("it_aggregates_over_derived_tables").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE emp (id int, dept int);", None, mig)
+                .is_ok());
+
+            // the average number of employees per department; the converter does not support
+            // `AVG`, so the query computes its sum and count instead
+            let res = inc.add_query(
+                "SELECT SUM(per_dept.cnt) AS total, COUNT(per_dept.cnt) AS depts \
+                 FROM (SELECT emp.dept, COUNT(*) AS cnt FROM emp GROUP BY emp.dept) AS per_dept;",
+                Some("dept_sizes".into()),
+                mig,
+            );
+            assert!(res.is_ok(), "{:?}", res);
+
+            // the subquery is lowered once, as a view of its own without a reader, named after the
+            // query
+            let per_dept = get_node(&inc, mig, "dept_sizes_per_dept");
+            assert_eq!(per_dept.fields(), &["dept", "cnt"]);
+            assert!(mig
+                .graph()
+                .neighbors_directed(per_dept.global_addr(), petgraph::EdgeDirection::Outgoing)
+                .all(|n| !mig.graph()[n].is_reader()));
+            assert!(inc.get_flow_node_address("per_dept", 0).is_none());
+        })
+        .await;
+
+        let mut emp = g.table("emp").await.unwrap();
+        for (id, dept) in vec![(1, 1), (2, 1), (3, 1), (4, 2), (5, 3), (6, 3)] {
+            emp.insert(vec![id.into(), dept.into()]).await.unwrap();
+        }
+        integration::sleep().await;
+
+        let mut view = g.view("dept_sizes").await.unwrap();
+        let rows: Vec<Vec<DataType>> = view.lookup(&[0.into()], true).await.unwrap().into();
+        assert_eq!(rows.len(), 1);
+        // six employees in three departments, i.e., two per department on average
+        assert_eq!(rows[0][0], 6.into());
+        assert_eq!(rows[0][1], 3.into());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_rejects_joins_on_several_conditions() {
+        let mut g = integration::start_simple("it_rejects_joins_on_several_conditions").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE a (id int, x int);", None, mig)
+                .is_ok());
+            assert!(inc
+                .add_query("CREATE TABLE b (aid int, y int);", None, mig)
+                .is_ok());
+            let ncount = mig.graph().node_count();
+
+            // only a single comparison may join two tables
+            for q in &[
+                "SELECT a.id FROM a JOIN b ON a.id = b.aid AND a.x = b.y;",
+                "SELECT a.id FROM a JOIN b ON a.id = b.aid OR a.x = b.y;",
+            ] {
+                let res = inc.add_query(q, None, mig);
+                assert!(res.is_err(), "{:?}", res);
+            }
+            assert_eq!(mig.graph().node_count(), ncount);
+        })
+        .await;
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn it_reports_errors_in_subqueries() {
+        let mut g = integration::start_simple("it_reports_errors_in_subqueries").await;
+        g.migrate(|mig| {
+            let mut inc = SqlIncorporator::default();
+            assert!(inc
+                .add_query("CREATE TABLE a (id int, x int);", None, mig)
+                .is_ok());
+            assert!(inc
+                .add_query("CREATE TABLE b (aid int, y int);", None, mig)
+                .is_ok());
+
+            // the subqueries join on two conditions, which is not supported
+            for q in &[
+                "SELECT a.id FROM a WHERE a.id IN \
+                 (SELECT b.aid FROM b JOIN a ON a.id = b.aid AND a.x = b.y);",
+                "SELECT a.id FROM a JOIN \
+                 (SELECT b.aid FROM b JOIN a ON a.id = b.aid AND a.x = b.y) AS s \
+                 ON a.id = s.aid;",
+            ] {
+                let res = inc.add_query(q, None, mig);
+                assert!(res.is_err(), "{:?}", res);
+            }
+        })
+        .await;
+    }
 }
@@ -139,8 +139,19 @@ pub struct JoinRef {
 pub struct QueryGraphNode {
     pub rel_name: String,
     pub predicates: Vec<ConditionExpression>,
+    /// Predicates on this relation from the `ON` clause of the join that adds it. These must be
+    /// applied *before* the join, so that a `LEFT JOIN` still emits unmatched left-hand rows.
+    pub on_predicates: Vec<ConditionExpression>,
     pub columns: Vec<Column>,
     pub parameters: Vec<Column>,
+    /// The subset of `parameters` that are compared against their placeholders with a range
+    /// operator (`<`, `<=`, `>`, `>=`) rather than by equality.
+    pub range_parameters: Vec<Column>,
+    /// Each comparison of a `range_parameters` column against its placeholder, with its operator.
+    pub range_operators: Vec<(Column, Operator)>,
+    /// The subset of `parameters` that are compared against a list of placeholders
+    /// (`x IN (?, ?)`), and are thus looked up by a set of values.
+    pub set_parameters: Vec<Column>,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq)]
@@ -164,6 +175,8 @@ pub struct QueryGraph {
     pub join_order: Vec<JoinRef>,
     /// Global predicates (not associated with a particular relation)
     pub global_predicates: Vec<ConditionExpression>,
+    /// Whether the query deduplicates its output rows (`SELECT DISTINCT`).
+    pub distinct: bool,
 }
 
 impl QueryGraph {
@@ -174,6 +187,7 @@ impl QueryGraph {
             columns: Vec::new(),
             join_order: Vec::new(),
             global_predicates: Vec::new(),
+            distinct: false,
         }
     }
 
@@ -188,6 +202,68 @@ impl QueryGraph {
             })
     }
 
+    /// Returns the parameter columns that the query's leaf is looked up by range on.
+    pub fn range_parameters<'a>(&'a self) -> Vec<&'a Column> {
+        self.relations
+            .values()
+            .fold(Vec::new(), |mut acc: Vec<&'a Column>, qgn| {
+                acc.extend(qgn.range_parameters.iter());
+                acc
+            })
+    }
+
+    /// Returns the range parameter column and the operator it is compared with if the query bounds
+    /// that column on one side only (as in `x > ?`), so that its leaf is looked up by the single
+    /// value beyond which all keys match.
+    pub fn open_range_parameter(&self) -> Option<(&Column, &Operator)> {
+        let comparisons: Vec<_> = self
+            .relations
+            .values()
+            .flat_map(|qgn| qgn.range_operators.iter())
+            .collect();
+        if comparisons.len() == 1 {
+            Some((&comparisons[0].0, &comparisons[0].1))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the parameter columns that the query's leaf is looked up by a set of values on.
+    pub fn set_parameters<'a>(&'a self) -> Vec<&'a Column> {
+        self.relations
+            .values()
+            .fold(Vec::new(), |mut acc: Vec<&'a Column>, qgn| {
+                acc.extend(qgn.set_parameters.iter());
+                acc
+            })
+    }
+
+    /// Binds the equality parameter `column` to the constant `value`. Readers then no longer
+    /// supply it as part of the key; instead, the view only contains rows whose `column` is
+    /// `value`, as if the query had compared the column against `value` in the first place.
+    /// Returns `false`, leaving the query unchanged, if `column` is not such a parameter.
+    pub fn bind_parameter(&mut self, column: &Column, value: Literal) -> bool {
+        let qgn = match self.relations.values_mut().find(|qgn| {
+            qgn.parameters.contains(column)
+                && !qgn.range_parameters.contains(column)
+                && !qgn.set_parameters.contains(column)
+        }) {
+            Some(qgn) => qgn,
+            None => return false,
+        };
+
+        qgn.parameters.retain(|c| c != column);
+        qgn.predicates
+            .push(ConditionExpression::ComparisonOp(ConditionTree {
+                operator: Operator::Equal,
+                left: Box::new(ConditionExpression::Base(ConditionBase::Field(
+                    column.clone(),
+                ))),
+                right: Box::new(ConditionExpression::Base(ConditionBase::Literal(value))),
+            }));
+        true
+    }
+
     pub fn exact_hash(&self) -> u64 {
         use std::collections::hash_map::DefaultHasher;
 
@@ -215,6 +291,7 @@ impl Hash for QueryGraph {
         self.columns.hash(state);
         self.join_order.hash(state);
         self.global_predicates.hash(state);
+        self.distinct.hash(state);
     }
 }
 
@@ -255,7 +332,7 @@ fn classify_conditionals(
     local: &mut HashMap<String, Vec<ConditionExpression>>,
     join: &mut Vec<ConditionTree>,
     global: &mut Vec<ConditionExpression>,
-    params: &mut Vec<Column>,
+    params: &mut Vec<(Column, Operator)>,
 ) {
     // Handling OR and AND expressions requires some care as there are some corner cases.
     //    a) we don't support OR expressions with predicates with placeholder parameters,
@@ -404,11 +481,19 @@ fn classify_conditionals(
                         // right-hand side is a placeholder, so this must be a query parameter
                         ConditionBase::Literal(Literal::Placeholder) => {
                             if let ConditionBase::Field(ref lf) = *l {
-                                params.push(lf.clone());
+                                params.push((lf.clone(), ct.operator.clone()));
+                            }
+                        }
+                        // right-hand side is a list of placeholders, so this is a query parameter
+                        // that is given a set of values at lookup time
+                        ConditionBase::LiteralList(ref ll) if is_placeholder_list(ll) => {
+                            if let ConditionBase::Field(ref lf) = *l {
+                                params.push((lf.clone(), Operator::In));
                             }
                         }
-                        // right-hand side is a non-placeholder literal, so this is a predicate
-                        ConditionBase::Literal(_) => {
+                        // right-hand side is a non-placeholder literal (or a list of them), so this
+                        // is a predicate
+                        ConditionBase::Literal(_) | ConditionBase::LiteralList(_) => {
                             if let ConditionBase::Field(ref lf) = *l {
                                 // we assume that implied table names have previously been expanded
                                 // and thus all non-computed columns carry table names
@@ -422,7 +507,6 @@ fn classify_conditionals(
                                 }
                             }
                         }
-                        ConditionBase::LiteralList(_) => (),
                         ConditionBase::NestedSelect(_) => unimplemented!(),
                     }
                 };
@@ -455,9 +539,57 @@ fn classify_conditionals(
     }
 }
 
+/// Whether `ll` is a non-empty list of placeholders only, such as the `(?, ?)` in `x IN (?, ?)`.
+fn is_placeholder_list(ll: &[Literal]) -> bool {
+    !ll.is_empty() && ll.iter().all(|l| *l == Literal::Placeholder)
+}
+
+/// Splits the `ON` clause of a join that adds `right_table` into the join predicate and a set of
+/// comparisons between columns of `right_table` and literals. Fails unless exactly one join
+/// predicate remains.
+fn split_on_clause(
+    cond: &ConditionExpression,
+    right_table: &str,
+) -> Result<(ConditionExpression, Vec<ConditionExpression>), String> {
+    let (filters, join): (Vec<_>, Vec<_>) = split_conjunctions(vec![cond.clone()])
+        .into_iter()
+        .partition(|ce| match *ce {
+            ConditionExpression::ComparisonOp(ref ct) => match (&*ct.left, &*ct.right) {
+                (
+                    ConditionExpression::Base(ConditionBase::Field(_)),
+                    ConditionExpression::Base(ConditionBase::Literal(Literal::Placeholder)),
+                ) => false,
+                (
+                    ConditionExpression::Base(ConditionBase::Field(_)),
+                    ConditionExpression::Base(ConditionBase::LiteralList(ref ll)),
+                ) if is_placeholder_list(ll) => false,
+                (
+                    ConditionExpression::Base(ConditionBase::Field(ref f)),
+                    ConditionExpression::Base(ConditionBase::Literal(_)),
+                )
+                | (
+                    ConditionExpression::Base(ConditionBase::Field(ref f)),
+                    ConditionExpression::Base(ConditionBase::LiteralList(_)),
+                ) => f.table.as_ref().map(String::as_str) == Some(right_table),
+                _ => false,
+            },
+            _ => false,
+        });
+
+    // TODO: we only support a single join predicate per ON clause
+    if join.len() != 1 {
+        return Err(format!(
+            "join condition for {} is not a single comparison: {}",
+            right_table, cond
+        ));
+    }
+    Ok((join.into_iter().next().unwrap(), filters))
+}
+
 #[allow(clippy::cognitive_complexity)]
 pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
     let mut qg = QueryGraph::new();
+    qg.distinct = st.distinct;
 
     // a handy closure for making new relation nodes
     let new_node =
@@ -465,6 +597,7 @@ pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
             QueryGraphNode {
                 rel_name: rel.clone(),
                 predicates: preds,
+                on_predicates: Vec::new(),
                 columns: st
                     .fields
                     .iter()
@@ -499,6 +632,9 @@ pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
                     })
                     .collect(),
                 parameters: Vec::new(),
+                range_parameters: Vec::new(),
+                range_operators: Vec::new(),
+                set_parameters: Vec::new(),
             }
         };
 
@@ -546,6 +682,16 @@ pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
                     JoinConstraint::On(ref cond) => {
                         use crate::controller::sql::query_utils::ReferredTables;
 
+                        // the ON clause may also filter the joined table; split those filters
+                        // off, as they must be applied before the join
+                        let (cond, on_predicates) = split_on_clause(cond, &table.name)?;
+                        qg.relations
+                            .get_mut(&table.name)
+                            .unwrap()
+                            .on_predicates
+                            .extend(on_predicates);
+                        let cond = &cond;
+
                         // find all distinct tables mentioned in the condition
                         // conditions for now.
                         let mut tables_mentioned: Vec<String> =
@@ -596,7 +742,12 @@ pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
                                     ct.clone()
                                 }
                             }
-                            _ => panic!("join condition is not a comparison!"),
+                            ref c => {
+                                return Err(format!(
+                                    "join condition for {} is not a comparison: {}",
+                                    table.name, c
+                                ))
+                            }
                         }
                     }
                     JoinConstraint::Using(ref cols) => {
@@ -692,7 +843,7 @@ pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
         //    node for this query. Such columns will be carried all the way through the operators
         //    implementing the query (unlike in a traditional query plan, where the predicates on
         //    parameters might be evaluated sooner).
-        for column in query_parameters.into_iter() {
+        for (column, operator) in query_parameters.into_iter() {
             match column.table {
                 None => panic!("each parameter's column must have an associated table!"),
                 Some(ref table) => {
@@ -700,6 +851,28 @@ pub fn to_query_graph(st: &SelectStatement) -> Result<QueryGraph, String> {
                     if !rel.columns.contains(&column) {
                         rel.columns.push(column.clone());
                     }
+                    let is_range = match operator {
+                        Operator::Less
+                        | Operator::LessOrEqual
+                        | Operator::Greater
+                        | Operator::GreaterOrEqual => true,
+                        _ => false,
+                    };
+                    if is_range {
+                        rel.range_operators.push((column.clone(), operator.clone()));
+                        // both bounds of a range are given at lookup time, so a column that is
+                        // bounded on both sides (`a >= ? AND a <= ?`) is still only a single key
+                        if rel.range_parameters.contains(&column) {
+                            continue;
+                        }
+                        rel.range_parameters.push(column.clone());
+                    } else if operator == Operator::In {
+                        // every placeholder in the list stands for another value of the same key
+                        if rel.set_parameters.contains(&column) {
+                            continue;
+                        }
+                        rel.set_parameters.push(column.clone());
+                    }
                     // the parameter column is included in the projected columns of the output, but
                     // we also separately register it as a parameter so that we can set keys
                     // correctly on the leaf view
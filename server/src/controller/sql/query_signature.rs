@@ -102,7 +102,7 @@ impl Signature for QueryGraph {
         let mut attrs = HashSet::<&Column>::new();
         let mut attrs_vec = Vec::<&Column>::new();
         for n in self.relations.values() {
-            for p in &n.predicates {
+            for p in n.predicates.iter().chain(n.on_predicates.iter()) {
                 match *p {
                     ComparisonOp(ref ct) | LogicalOp(ref ct) => {
                         for c in &ct.contained_columns() {
@@ -163,6 +163,12 @@ impl Signature for QueryGraph {
             c.hash(&mut hasher);
         }
 
+        // DISTINCT changes the rows a query returns, so two queries that differ only in it must
+        // not share a signature.
+        if self.distinct {
+            self.distinct.hash(&mut hasher);
+        }
+
         QuerySignature {
             relations: rels,
             attributes: attrs,
@@ -1,4 +1,10 @@
-use nom_sql::{ConditionBase, ConditionExpression, SqlQuery, Table};
+use nom_sql::parser as sql_parser;
+use nom_sql::{
+    Column, CompoundSelectOperator, CompoundSelectStatement, FieldDefinitionExpression,
+    FunctionExpression, GroupByClause, SelectStatement,
+};
+use nom_sql::{ConditionBase, ConditionExpression, JoinRightSide, SqlQuery, Table};
+use std::collections::HashMap;
 
 pub trait ReferredTables {
     fn referred_tables(&self) -> Vec<Table>;
@@ -52,3 +58,344 @@ impl ReferredTables for ConditionExpression {
         tables
     }
 }
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Whether `s` starts with the keyword `kw` (in any case) as a word of its own.
+fn keyword(s: &str, kw: &str) -> bool {
+    s.get(..kw.len())
+        .map_or(false, |w| w.eq_ignore_ascii_case(kw))
+        && !s[kw.len()..].starts_with(is_identifier_char)
+}
+
+/// Returns the index of the parenthesis that closes the one `s` starts with, skipping over quoted
+/// strings.
+fn closing_paren(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') | (None, '`') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits a `WITH name AS (definition)[, ...] query` statement, which nom-sql does not parse, into
+/// the name and definition of each of its common table expressions, in order, and the main query.
+/// A query without a `WITH` clause is returned as is.
+fn split_ctes(query: &str) -> Result<(Vec<(&str, &str)>, &str), String> {
+    let mut rest = query.trim_start();
+    if !keyword(rest, "with") {
+        return Ok((vec![], query));
+    }
+    rest = rest[4..].trim_start();
+    if keyword(rest, "recursive") {
+        return Err(String::from(
+            "recursive common table expressions are not supported",
+        ));
+    }
+
+    let mut ctes = Vec::new();
+    loop {
+        let name_len = rest
+            .find(|c: char| !is_identifier_char(c))
+            .unwrap_or_else(|| rest.len());
+        let name = &rest[..name_len];
+        rest = rest[name_len..].trim_start();
+        if name.is_empty() || !keyword(rest, "as") {
+            return Err(format!(
+                "expected `name AS (...)` in WITH clause: {}",
+                query
+            ));
+        }
+        rest = rest[2..].trim_start();
+        if !rest.starts_with('(') {
+            return Err(format!("expected parenthesized definition of {}", name));
+        }
+
+        let end = closing_paren(rest)
+            .ok_or_else(|| format!("unbalanced parentheses in definition of {}", name))?;
+        ctes.push((name, rest[1..end].trim()));
+
+        rest = rest[end + 1..].trim_start();
+        if rest.starts_with(',') {
+            rest = rest[1..].trim_start();
+        } else {
+            break;
+        }
+    }
+    Ok((ctes, rest))
+}
+
+/// Splits the derived tables out of `query`, i.e., the subqueries it selects from as in
+/// `FROM (SELECT ...) AS name`, which nom-sql does not parse. Returns the name and definition of
+/// each derived table, with those nested in another's definition before it, and the query
+/// selecting from the derived tables by name instead.
+fn split_derived_tables(query: &str) -> Result<(Vec<(String, String)>, String), String> {
+    let mut derived = Vec::new();
+    let mut rewritten = String::with_capacity(query.len());
+    let mut rest = query;
+    while let Some(open) = find_derived_table(rest) {
+        let close = open
+            + closing_paren(&rest[open..])
+                .ok_or_else(|| format!("unbalanced parentheses in subquery: {}", query))?;
+        let definition = rest[open + 1..close].trim();
+
+        let mut after = rest[close + 1..].trim_start();
+        if keyword(after, "as") {
+            after = after[2..].trim_start();
+        }
+        let alias_len = after
+            .find(|c: char| !is_identifier_char(c))
+            .unwrap_or_else(|| after.len());
+        if alias_len == 0 {
+            return Err(format!("subquery in FROM needs an alias: {}", definition));
+        }
+        let alias = &after[..alias_len];
+
+        let (nested, definition) = split_derived_tables(definition)?;
+        derived.extend(nested);
+        derived.push((String::from(alias), definition));
+
+        rewritten.push_str(&rest[..open]);
+        rewritten.push_str(alias);
+        rest = &after[alias_len..];
+    }
+    rewritten.push_str(rest);
+    Ok((derived, rewritten))
+}
+
+/// Returns the index of the opening parenthesis of the first subquery that `query` selects from,
+/// if any, skipping over quoted strings.
+fn find_derived_table(query: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in query.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'') | (None, '"') | (None, '`') => quote = Some(c),
+            (None, _) if !is_identifier_char(prev) && keyword(&query[i..], "from") => {
+                let after = query[i + 4..].trim_start();
+                if after.starts_with('(') && keyword(after[1..].trim_start(), "select") {
+                    return Some(query.len() - after.len());
+                }
+            }
+            _ => {}
+        }
+        prev = c;
+    }
+    None
+}
+
+/// Parses the statement `query` of the query `name` if it defines views inline, i.e., has common
+/// table expressions or derived tables. Returns those views, each parsed and in the order in which
+/// they depend on each other, followed by the parsed main query, or `None` if there are none.
+///
+/// Inline views are named `{name}_{view}`, so they neither collide with the views of other
+/// queries nor shadow base tables, and the queries select from them under these names, aliased to
+/// the original ones. Only named queries can thus define views inline.
+pub(in crate::controller) fn parse_inline_views(
+    name: Option<&str>,
+    query: &str,
+) -> Result<Option<(Vec<(String, SqlQuery)>, SqlQuery)>, String> {
+    let (ctes, main) = split_ctes(query)?;
+    let mut definitions = Vec::new();
+    for (cte_name, definition) in ctes {
+        let (derived, definition) = split_derived_tables(definition)?;
+        definitions.extend(derived);
+        definitions.push((String::from(cte_name), definition));
+    }
+    let (derived, main) = split_derived_tables(main)?;
+    definitions.extend(derived);
+    if definitions.is_empty() {
+        return Ok(None);
+    }
+
+    let name = name.ok_or_else(|| format!("queries with inline views must be named: {}", query))?;
+    let mut scoped = HashMap::new();
+    let mut views = Vec::with_capacity(definitions.len());
+    for (view, definition) in definitions {
+        let mut parsed =
+            sql_parser::parse_query(&format!("{};", definition)).map_err(String::from)?;
+        select_from_scoped_views(&mut parsed, &scoped);
+        let scoped_name = format!("{}_{}", name, view);
+        views.push((scoped_name.clone(), parsed));
+        scoped.insert(view, scoped_name);
+    }
+    let mut parsed = sql_parser::parse_query(&main).map_err(String::from)?;
+    select_from_scoped_views(&mut parsed, &scoped);
+    Ok(Some((views, parsed)))
+}
+
+/// Renames the tables `q` selects from that are inline views to their names in `scoped`, keeping
+/// the names used in the query as aliases.
+fn select_from_scoped_views(q: &mut SqlQuery, scoped: &HashMap<String, String>) {
+    fn rename(t: &mut Table, scoped: &HashMap<String, String>) {
+        if let Some(scoped_name) = scoped.get(&t.name) {
+            if t.alias.is_none() {
+                t.alias = Some(t.name.clone());
+            }
+            t.name = scoped_name.clone();
+        }
+    }
+    fn rename_in_select(st: &mut SelectStatement, scoped: &HashMap<String, String>) {
+        for t in &mut st.tables {
+            rename(t, scoped);
+        }
+        for jc in &mut st.join {
+            match jc.right {
+                JoinRightSide::Table(ref mut t) => rename(t, scoped),
+                JoinRightSide::Tables(ref mut ts) => {
+                    for t in ts {
+                        rename(t, scoped);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    match *q {
+        SqlQuery::Select(ref mut st) => rename_in_select(st, scoped),
+        SqlQuery::CompoundSelect(ref mut csq) => {
+            for (_, st) in &mut csq.selects {
+                rename_in_select(st, scoped);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Expands a query grouped by `ROLLUP(c1, ..., cn)`, given as the same query grouped by plain
+/// `c1, ..., cn`, into the union of its aggregation grouped by every prefix of those columns, from
+/// all of them down to none (the grand total). Each branch leaves out the columns it rolls up, for
+/// a padded union to fill in with `NULL`.
+pub(super) fn rollup_to_union(st: &SelectStatement) -> Result<CompoundSelectStatement, String> {
+    let group_by = grouping_columns(st, "ROLLUP")?;
+    let sets = (0..=group_by.len())
+        .rev()
+        .map(|k| group_by[..k].to_vec())
+        .collect();
+    grouping_sets_to_union(st, sets)
+}
+
+/// Expands a query grouped by `CUBE(c1, ..., cn)`, given as the same query grouped by plain
+/// `c1, ..., cn`, into the union of its aggregation grouped by every subset of those columns, from
+/// all of them down to none (the grand total). As with `ROLLUP`, each branch leaves out the
+/// columns it does not group by. The 2^n branches are each maintained separately, so at most
+/// three columns are supported.
+pub(super) fn cube_to_union(st: &SelectStatement) -> Result<CompoundSelectStatement, String> {
+    let group_by = grouping_columns(st, "CUBE")?;
+    let n = group_by.len();
+    if n > 3 {
+        return Err(format!(
+            "CUBE over {} columns is not supported; at most 3 are",
+            n
+        ));
+    }
+    // the i-th column is in the subset `mask` if its i-th highest bit is set, so that the first
+    // branch groups by all columns; the others then run from larger groupings to smaller ones
+    let mut sets: Vec<Vec<Column>> = (0..1usize << n)
+        .rev()
+        .map(|mask| {
+            group_by
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| mask & (1 << (n - 1 - i)) != 0)
+                .map(|(_, c)| c.clone())
+                .collect()
+        })
+        .collect();
+    sets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    grouping_sets_to_union(st, sets)
+}
+
+/// The `GROUP BY` columns of `st`, which `modifier` (`ROLLUP` or `CUBE`) groups over.
+fn grouping_columns<'a>(st: &'a SelectStatement, modifier: &str) -> Result<&'a [Column], String> {
+    let group_by = match st.group_by {
+        Some(ref gb) => gb,
+        None => return Err(format!("{} requires GROUP BY columns", modifier)),
+    };
+    if group_by.having.is_some() {
+        return Err(format!("HAVING is not supported with {}", modifier));
+    }
+    Ok(&group_by.columns)
+}
+
+/// The union of the aggregation of `st` grouped by each of `sets` in turn, the first of which
+/// holds all of its `GROUP BY` columns. Each branch leaves out the columns it does not group by.
+fn grouping_sets_to_union(
+    st: &SelectStatement,
+    sets: Vec<Vec<Column>>,
+) -> Result<CompoundSelectStatement, String> {
+    let group_by = &st.group_by.as_ref().unwrap().columns;
+    let selects = sets
+        .into_iter()
+        .enumerate()
+        .map(|(i, kept)| {
+            let mut branch = st.clone();
+            branch.fields.retain(|f| match *f {
+                FieldDefinitionExpression::Col(ref c) if c.function.is_none() => {
+                    !group_by.iter().any(|g| {
+                        !kept.contains(g)
+                            && g.name == c.name
+                            && (g.table.is_none() || c.table.is_none() || g.table == c.table)
+                    })
+                }
+                _ => true,
+            });
+            branch.group_by = if kept.is_empty() {
+                None
+            } else {
+                Some(GroupByClause {
+                    columns: kept,
+                    having: None,
+                })
+            };
+            branch.order = None;
+            branch.limit = None;
+
+            let op = if i == 0 {
+                None
+            } else {
+                Some(CompoundSelectOperator::Union)
+            };
+            (op, branch)
+        })
+        .collect();
+
+    Ok(CompoundSelectStatement {
+        selects,
+        order: st.order.clone(),
+        limit: st.limit.clone(),
+    })
+}
+
+/// Adds a `group_size` column to the grouped query `st` that holds the number of rows in each
+/// group, i.e., the `COUNT(*)` over the query's `GROUP BY` columns.
+pub(super) fn add_group_size(st: &mut SelectStatement) -> Result<(), String> {
+    if st.group_by.is_none() {
+        return Err(String::from("only grouped queries have group sizes"));
+    }
+    st.fields.push(FieldDefinitionExpression::Col(Column {
+        name: String::from("group_size"),
+        alias: None,
+        table: None,
+        function: Some(Box::new(FunctionExpression::CountStar)),
+    }));
+    Ok(())
+}
@@ -96,6 +96,11 @@ impl Finkelstein {
         for (name, ex_qgn) in &existing_qg.relations {
             let new_qgn = &new_qg.relations[name];
 
+            // ON clause predicates filter the inputs to joins, so they must match exactly
+            if ex_qgn.on_predicates != new_qgn.on_predicates {
+                return None;
+            }
+
             // iterate over predicates and ensure that each matching
             // one on the existing QG is implied by the new one
             for ep in &ex_qgn.predicates {
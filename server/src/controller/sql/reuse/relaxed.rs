@@ -138,6 +138,12 @@ impl Relaxed {
             }
             let new_qgn = &new_qg.relations[name];
 
+            // ON clause predicates filter the inputs to joins, so we can't reuse anything from a
+            // query that filters them differently
+            if ex_qgn.on_predicates != new_qgn.on_predicates {
+                return None;
+            }
+
             // iterate over predicates and ensure that each
             // matching one on the existing QG is implied by the new one
             for ep in &ex_qgn.predicates {
@@ -1,7 +1,9 @@
+use crate::controller::security::policy::Policy;
 use crate::controller::security::SecurityConfig;
 use crate::controller::sql::query_graph::{to_query_graph, QueryGraph};
-use crate::controller::sql::{QueryFlowParts, SqlIncorporator};
+use crate::controller::sql::{QueryFlowParts, SqlIncorporator, UniverseId};
 use crate::controller::Migration;
+use ::mir::Column;
 use dataflow::prelude::DataType;
 use nom_sql::parser as sql_parser;
 use nom_sql::SqlQuery;
@@ -14,6 +16,7 @@ pub(super) struct Universe {
     pub(super) member_of: HashMap<String, Vec<DataType>>,
     pub(super) row_policies: HashMap<String, Vec<QueryGraph>>,
     pub(super) rewrite_policies: HashMap<String, Vec<RewritePolicy>>,
+    pub(super) hidden_columns: HashMap<String, Vec<String>>,
 }
 
 impl Default for Universe {
@@ -24,10 +27,35 @@ impl Default for Universe {
             member_of: HashMap::default(),
             row_policies: HashMap::default(),
             rewrite_policies: HashMap::default(),
+            hidden_columns: HashMap::default(),
         }
     }
 }
 
+impl Universe {
+    /// The id of this universe, along with its group if it is a group universe.
+    pub(super) fn id(&self) -> UniverseId {
+        (self.id.clone(), self.from_group.clone())
+    }
+
+    /// Returns true if views created in this universe may expose column `c`, i.e., if no column
+    /// policy hides it or any of its aliases. A column without a table may come from any table,
+    /// so it is hidden if a policy hides a column of that name in any table.
+    pub(super) fn allows_column(&self, c: &Column) -> bool {
+        let hidden = match c.table {
+            Some(ref t) => self
+                .hidden_columns
+                .get(t)
+                .map_or(false, |hidden| hidden.contains(&c.name)),
+            None => self
+                .hidden_columns
+                .values()
+                .any(|hidden| hidden.contains(&c.name)),
+        };
+        !hidden && c.aliases.iter().all(|a| self.allows_column(a))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub(super) struct RewritePolicy {
     pub(super) value: String,
@@ -73,6 +101,7 @@ impl Multiverse for SqlIncorporator {
             member_of: universe_groups,
             row_policies: HashMap::new(),
             rewrite_policies: HashMap::new(),
+            hidden_columns: HashMap::new(),
         };
 
         // Create the UserContext base node.
@@ -105,6 +134,15 @@ impl Multiverse for SqlIncorporator {
         // e.g. if they reference UserContext.
         let mut row_policies_qg: HashMap<String, Vec<QueryGraph>> = HashMap::new();
         for policy in universe_policies {
+            if let Policy::Hide(ref p) = *policy {
+                universe
+                    .hidden_columns
+                    .entry(p.table.clone())
+                    .or_insert_with(Vec::new)
+                    .extend(p.columns.iter().cloned());
+                continue;
+            }
+
             if !policy.is_row_policy() {
                 let qfp = self
                     .add_parsed_query(policy.predicate(), None, false, mig)
@@ -185,3 +223,137 @@ impl Multiverse for SqlIncorporator {
             .unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::sql::mir::SqlToMirConverter;
+
+    #[test]
+    fn it_hides_columns_in_group_universes() {
+        let mut converter = SqlToMirConverter::default();
+        let ct = sql_parser::parse_query(
+            "CREATE TABLE post (id int, author int, content text, notes text);",
+        )
+        .unwrap();
+        converter.named_base_to_mir("post", &ct);
+
+        // a group universe whose members must not see the notes column
+        let mut universe = Universe {
+            id: "ta".into(),
+            from_group: Some("tas".into()),
+            ..Universe::default()
+        };
+        universe
+            .hidden_columns
+            .insert(String::from("post"), vec![String::from("notes")]);
+        converter.set_universe(universe);
+
+        let convert = |converter: &mut SqlToMirConverter, q: &str| {
+            let st = match sql_parser::parse_query(q).unwrap() {
+                SqlQuery::Select(st) => st,
+                _ => unreachable!(),
+            };
+            let qg = to_query_graph(&st).unwrap();
+            converter.named_query_to_mir(
+                "q",
+                &st,
+                &qg,
+                true,
+                ("ta".into(), Some("tas".into())),
+                None,
+            )
+        };
+
+        let (_, mq, _, _) = convert(
+            &mut converter,
+            "SELECT post.id, post.content FROM post WHERE post.author = ?;",
+        )
+        .unwrap();
+        let leaf_columns: Vec<String> = mq
+            .leaf
+            .borrow()
+            .columns()
+            .iter()
+            .map(|c| c.name.clone())
+            .collect();
+        assert_eq!(leaf_columns, vec!["id", "author", "content"]);
+
+        // looking up by a hidden column would expose it as the view's key
+        let err = convert(
+            &mut converter,
+            "SELECT post.id, post.content FROM post WHERE post.notes = ?;",
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "UNSUPPORTED_PARAMETERS");
+    }
+
+    #[test]
+    fn it_reinstantiates_queries_for_universes() {
+        let mut converter = SqlToMirConverter::default();
+        let ct = sql_parser::parse_query("CREATE TABLE post (id int, author int, content text);")
+            .unwrap();
+        converter.named_base_to_mir("post", &ct);
+
+        let st = match sql_parser::parse_query(
+            "SELECT post.id, post.content FROM post WHERE post.author = ?;",
+        )
+        .unwrap()
+        {
+            SqlQuery::Select(st) => st,
+            _ => unreachable!(),
+        };
+        let qg = to_query_graph(&st).unwrap();
+        let (_, mq, _, _) = converter
+            .named_query_to_mir("posts", &st, &qg, true, ("global".into(), None), None)
+            .unwrap();
+
+        for uid in &["1", "2"] {
+            converter.set_universe(Universe {
+                id: (*uid).into(),
+                ..Universe::default()
+            });
+        }
+        converter.clear_universe();
+
+        let mut leaves = vec![mq.leaf.borrow().name().to_owned()];
+        for uid in &["1", "2"] {
+            let mq = converter
+                .reinstantiate_for_universe("posts", ((*uid).into(), None))
+                .unwrap();
+            assert_eq!(mq.name, format!("posts_u{}", uid));
+            leaves.push(mq.leaf.borrow().name().to_owned());
+        }
+        assert_eq!(leaves, vec!["posts", "posts_u1", "posts_u2"]);
+
+        // a query is only instantiated once per universe
+        assert!(converter
+            .reinstantiate_for_universe("posts", ("1".into(), None))
+            .is_none());
+        // queries that were never converted and universes that were never set up are unknown
+        assert!(converter
+            .reinstantiate_for_universe("nonexistent", ("1".into(), None))
+            .is_none());
+        assert!(converter
+            .reinstantiate_for_universe("posts", ("3".into(), None))
+            .is_none());
+    }
+
+    #[test]
+    fn it_hides_unqualified_and_aliased_columns() {
+        let mut universe = Universe::default();
+        universe
+            .hidden_columns
+            .insert(String::from("post"), vec![String::from("notes")]);
+
+        assert!(universe.allows_column(&Column::new(Some("post"), "content")));
+        assert!(!universe.allows_column(&Column::new(Some("post"), "notes")));
+        // without a table, the column may be the hidden one
+        assert!(!universe.allows_column(&Column::new(None, "notes")));
+        assert!(universe.allows_column(&Column::new(None, "content")));
+        // renaming a hidden column does not expose it
+        let mut renamed = Column::new(Some("q"), "remarks");
+        renamed.aliases.push(Column::new(Some("post"), "notes"));
+        assert!(!universe.allows_column(&renamed));
+    }
+}
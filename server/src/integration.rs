@@ -10,6 +10,7 @@ use dataflow::ops::project::Project;
 use dataflow::ops::union::Union;
 use dataflow::{DurabilityMode, PersistenceParameters};
 use noria::consensus::LocalAuthority;
+use noria::error::PrewarmError;
 use noria::DataType;
 
 use std::collections::HashMap;
@@ -67,7 +68,7 @@ fn get_settle_time() -> Duration {
 
 // Sleeps for either DEFAULT_SETTLE_TIME_MS milliseconds, or
 // for the value given through the SETTLE_TIME environment variable.
-async fn sleep() {
+pub(crate) async fn sleep() {
     tokio::time::delay_for(get_settle_time()).await;
 }
 
@@ -622,6 +623,67 @@ async fn it_works_w_partial_mat_below_empty() {
     assert_eq!(cq.len().await.unwrap(), 1);
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn it_prewarms_partial_views() {
+    let mut g = start_simple("it_prewarms_partial_views").await;
+    g.migrate(|mig| {
+        let a = mig.add_base("a", &["a", "b"], Base::default());
+        let b = mig.add_base("b", &["a", "b"], Base::default());
+        let mut emits = HashMap::new();
+        emits.insert(a, vec![0, 1]);
+        emits.insert(b, vec![0, 1]);
+        let u = Union::new(emits);
+        let c = mig.add_ingredient("c", &["a", "b"], u);
+        mig.maintain_anonymous(c, &[0]);
+    })
+    .await;
+
+    let mut muta = g.table("a").await.unwrap();
+    muta.insert(vec![1.into(), 1.into()]).await.unwrap();
+    muta.insert(vec![2.into(), 2.into()]).await.unwrap();
+    muta.insert(vec![3.into(), 3.into()]).await.unwrap();
+    sleep().await;
+
+    let mut cq = g.view("c").await.unwrap();
+    assert_eq!(cq.len().await.unwrap(), 0);
+
+    // keys must match the arity of the reader's key, and the controller's error makes it back
+    // to the client
+    let has_cause = |err: &failure::Error, cause: PrewarmError| {
+        let cause = cause.to_string();
+        err.iter_chain().any(|e| e.to_string().ends_with(&cause))
+    };
+    let err = g
+        .prewarm_keys("c", vec![vec![1.into(), 1.into()]])
+        .await
+        .unwrap_err();
+    assert!(
+        has_cause(&err, PrewarmError::WrongKeyColumnCount(1, 2)),
+        "{:?}",
+        err
+    );
+    let err = g.prewarm_keys("nonexistent", vec![]).await.unwrap_err();
+    assert!(
+        has_cause(&err, PrewarmError::NoSuchView("nonexistent".into())),
+        "{:?}",
+        err
+    );
+
+    g.prewarm_keys("c", vec![vec![1.into()], vec![2.into()]])
+        .await
+        .unwrap();
+    sleep().await;
+
+    // the prewarmed keys are now materialized without anyone having read them
+    assert_eq!(cq.len().await.unwrap(), 2);
+    let res = cq.lookup(&[1.into()], false).await.unwrap();
+    assert_eq!(res.len(), 1);
+    assert!(res
+        .iter()
+        .any(|r| r == &vec![DataType::from(1), DataType::from(1)]));
+    assert_eq!(cq.len().await.unwrap(), 2);
+}
+
 #[tokio::test(threaded_scheduler)]
 async fn it_works_deletion() {
     // set up graph
@@ -809,6 +871,50 @@ async fn it_works_with_double_query_through() {
     assert_eq!(empty.len(), 0);
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn it_works_with_left_join_filter_in_on_clause() {
+    let mut g = start_simple("it_works_with_left_join_filter_in_on_clause").await;
+    let sql = "
+        CREATE TABLE Article (aid int, title varchar(255), PRIMARY KEY(aid));
+        CREATE TABLE Comment (cid int, aid int, approved int, PRIMARY KEY(cid));
+        QUERY ApprovedComments: SELECT Article.aid, Article.title, Comment.cid \
+            FROM Article \
+            LEFT JOIN Comment ON Article.aid = Comment.aid AND Comment.approved = 1 \
+            WHERE Article.aid = ?;
+    ";
+
+    g.install_recipe(sql).await.unwrap();
+    let mut article = g.table("Article").await.unwrap();
+    let mut comment = g.table("Comment").await.unwrap();
+    let mut getter = g.view("ApprovedComments").await.unwrap();
+
+    article.insert(vec![1.into(), "a".into()]).await.unwrap();
+    article.insert(vec![2.into(), "b".into()]).await.unwrap();
+    comment
+        .insert(vec![10.into(), 1.into(), 1.into()])
+        .await
+        .unwrap();
+    comment
+        .insert(vec![11.into(), 1.into(), 0.into()])
+        .await
+        .unwrap();
+    comment
+        .insert(vec![12.into(), 2.into(), 0.into()])
+        .await
+        .unwrap();
+    sleep().await;
+
+    // only the approved comment is joined
+    let rs = getter.lookup(&[1.into()], true).await.unwrap();
+    assert_eq!(rs.len(), 1);
+    assert_eq!(rs[0], vec![1.into(), "a".into(), 10.into()]);
+
+    // an article without approved comments is still returned
+    let rs = getter.lookup(&[2.into()], true).await.unwrap();
+    assert_eq!(rs.len(), 1);
+    assert_eq!(rs[0], vec![2.into(), "b".into(), DataType::None]);
+}
+
 #[tokio::test(threaded_scheduler)]
 async fn it_works_with_reads_before_writes() {
     let mut g = start_simple("it_works_with_reads_before_writes").await;
@@ -1327,6 +1433,292 @@ async fn it_works_with_function_arithmetic() {
     assert_eq!(result[0][0], DataType::from(max_price * 2));
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn it_works_with_filtered_and_unfiltered_counts() {
+    let mut g = start_simple("it_works_with_filtered_and_unfiltered_counts").await;
+    let sql = "
+        CREATE TABLE votes (aid int, uid int, status varchar(10));
+        QUERY counts: SELECT votes.aid, COUNT(votes.uid) AS total, \
+                      COUNT(CASE WHEN votes.status = 'active' THEN votes.uid END) AS active \
+                      FROM votes WHERE votes.aid = ? GROUP BY votes.aid;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut mutator = g.table("votes").await.unwrap();
+    let mut getter = g.view("counts").await.unwrap();
+    let rows = vec![
+        (1, 10, "active"),
+        (1, 11, "inactive"),
+        (1, 12, "active"),
+        (2, 13, "inactive"),
+    ];
+    for (aid, uid, status) in rows {
+        mutator
+            .insert(vec![aid.into(), uid.into(), status.into()])
+            .await
+            .unwrap();
+    }
+
+    // Let writes propagate:
+    sleep().await;
+
+    // the filtered count only sees active votes, while the other count sees all of them
+    let result = getter.lookup(&[1.into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][1], 3.into());
+    assert_eq!(result[0][2], 2.into());
+
+    let result = getter.lookup(&[2.into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][1], 1.into());
+    assert_eq!(result[0][2], 0.into());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_works_with_aggregation_over_join_grouped_across_tables() {
+    let mut g = start_simple("it_works_with_aggregation_over_join_grouped_across_tables").await;
+    let sql = "
+        CREATE TABLE stores (id int, region varchar(40), PRIMARY KEY(id));
+        CREATE TABLE sales (id int, store int, amount int, PRIMARY KEY(id));
+        QUERY by_region: SELECT stores.region, SUM(sales.amount) AS total \
+                         FROM stores JOIN sales ON stores.id = sales.store \
+                         WHERE stores.region = ? GROUP BY stores.region;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut stores = g.table("stores").await.unwrap();
+    let mut sales = g.table("sales").await.unwrap();
+    let mut getter = g.view("by_region").await.unwrap();
+    for (id, region) in vec![(1, "east"), (2, "east"), (3, "west")] {
+        stores.insert(vec![id.into(), region.into()]).await.unwrap();
+    }
+    for (id, store, amount) in vec![(1, 1, 5), (2, 2, 7), (3, 3, 11), (4, 1, 2)] {
+        sales
+            .insert(vec![id.into(), store.into(), amount.into()])
+            .await
+            .unwrap();
+    }
+
+    // Let writes propagate:
+    sleep().await;
+
+    // sales from both eastern stores are summed up together
+    let result = getter.lookup(&["east".into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][1], 14.into());
+
+    let result = getter.lookup(&["west".into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][1], 11.into());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_works_with_aggregate_before_group_column() {
+    let mut g = start_simple("it_works_with_aggregate_before_group_column").await;
+    let sql = "
+        CREATE TABLE emp (id int, dept int, salary int, PRIMARY KEY(id));
+        QUERY payroll: SELECT SUM(emp.salary) AS total, emp.dept FROM emp \
+                       WHERE emp.dept = ? GROUP BY emp.dept;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut emp = g.table("emp").await.unwrap();
+    let mut getter = g.view("payroll").await.unwrap();
+    assert_eq!(getter.columns(), &["total", "dept"]);
+    for (id, dept, salary) in vec![(1, 1, 100), (2, 1, 50), (3, 2, 70)] {
+        emp.insert(vec![id.into(), dept.into(), salary.into()])
+            .await
+            .unwrap();
+    }
+
+    // Let writes propagate:
+    sleep().await;
+
+    // columns come out in SELECT order: the sum first, then the group column
+    let result = getter.lookup(&[1.into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][0], 150.into());
+    assert_eq!(result[0][1], 1.into());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_works_with_global_aggregate_over_empty_table() {
+    let mut g = start_simple("it_works_with_global_aggregate_over_empty_table").await;
+    let sql = "
+        CREATE TABLE t (id int, x int, PRIMARY KEY(id));
+        QUERY n: SELECT COUNT(*) AS n FROM t;
+        QUERY total: SELECT SUM(t.x) AS total FROM t;
+        QUERY both: SELECT COUNT(*) AS n, SUM(t.x) AS total FROM t;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut t = g.table("t").await.unwrap();
+    let mut n = g.view("n").await.unwrap();
+    let mut total = g.view("total").await.unwrap();
+    let mut both = g.view("both").await.unwrap();
+
+    // without any rows, there is still a single aggregate row
+    let result = n.lookup(&[0.into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][0], 0.into());
+    let result = total.lookup(&[0.into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][0], 0.into());
+    let result = both.lookup(&[0.into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][0], 0.into());
+    assert_eq!(result[0][1], 0.into());
+
+    for (id, x) in vec![(1, 10), (2, 32)] {
+        t.insert(vec![id.into(), x.into()]).await.unwrap();
+    }
+
+    // Let writes propagate:
+    sleep().await;
+
+    let result = n.lookup(&[0.into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][0], 2.into());
+    let result = total.lookup(&[0.into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][0], 42.into());
+    let result = both.lookup(&[0.into()], true).await.unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0][0], 2.into());
+    assert_eq!(result[0][1], 42.into());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_works_with_in_subquery_as_semi_join() {
+    let mut g = start_simple("it_works_with_in_subquery_as_semi_join").await;
+    let sql = "
+        CREATE TABLE parent (id int, name varchar(40), PRIMARY KEY(id));
+        CREATE TABLE child (cid int, pid int, age int, PRIMARY KEY(cid));
+        QUERY with_children: SELECT parent.id, parent.name FROM parent \
+                             WHERE parent.id IN (SELECT child.pid FROM child WHERE child.age > 1);
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut parents = g.table("parent").await.unwrap();
+    let mut children = g.table("child").await.unwrap();
+    let mut getter = g.view("with_children").await.unwrap();
+    for (id, name) in vec![(1, "a"), (2, "b"), (3, "c")] {
+        parents.insert(vec![id.into(), name.into()]).await.unwrap();
+    }
+    // parent 1 has two children, parent 2 only has one that the subquery filters out
+    for (cid, pid, age) in vec![(10, 1, 5), (11, 1, 7), (12, 2, 0), (13, 3, 2)] {
+        children
+            .insert(vec![cid.into(), pid.into(), age.into()])
+            .await
+            .unwrap();
+    }
+
+    // Let writes propagate:
+    sleep().await;
+
+    // each parent shows up once, no matter how many children it has
+    let mut result = getter.lookup(&[0.into()], true).await.unwrap();
+    result.sort();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0][0], 1.into());
+    assert_eq!(result[0][1], "a".into());
+    assert_eq!(result[1][0], 3.into());
+    assert_eq!(result[1][1], "c".into());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_works_with_range_parameters() {
+    let mut g = start_simple_unsharded("it_works_with_range_parameters").await;
+    let sql = "
+        CREATE TABLE players (id int, score int, PRIMARY KEY(id));
+        QUERY in_range: SELECT players.id, players.score FROM players \
+                        WHERE players.score >= ? AND players.score <= ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut players = g.table("players").await.unwrap();
+    let mut getter = g.view("in_range").await.unwrap();
+    for (id, score) in vec![(1, 50), (2, 10), (3, 30), (4, 20)] {
+        players.insert(vec![id.into(), score.into()]).await.unwrap();
+    }
+
+    // Let writes propagate:
+    sleep().await;
+
+    // with a single shard, rows come back ordered by score
+    let result = getter.lookup_range(15.into(), 30.into()).await.unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0][0], 4.into());
+    assert_eq!(result[0][1], 20.into());
+    assert_eq!(result[1][0], 3.into());
+    assert_eq!(result[1][1], 30.into());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_works_with_open_range_parameters() {
+    let mut g = start_simple_unsharded("it_works_with_open_range_parameters").await;
+    let sql = "
+        CREATE TABLE players (id int, score int, PRIMARY KEY(id));
+        QUERY above: SELECT players.id, players.score FROM players WHERE players.score > ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut players = g.table("players").await.unwrap();
+    let mut getter = g.view("above").await.unwrap();
+    for (id, score) in vec![(1, 50), (2, 10), (3, 30), (4, 20)] {
+        players.insert(vec![id.into(), score.into()]).await.unwrap();
+    }
+
+    // Let writes propagate:
+    sleep().await;
+
+    // a lookup gives the threshold, and returns all rows above it ordered by score
+    let result = getter.lookup(&[20.into()], true).await.unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0][0], 3.into());
+    assert_eq!(result[0][1], 30.into());
+    assert_eq!(result[1][0], 1.into());
+    assert_eq!(result[1][1], 50.into());
+
+    let result = getter.lookup(&[50.into()], true).await.unwrap();
+    assert!(result.is_empty());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_works_with_placeholder_in_lists() {
+    let mut g = start_simple_unsharded("it_works_with_placeholder_in_lists").await;
+    let sql = "
+        CREATE TABLE players (id int, team int, PRIMARY KEY(id));
+        QUERY on_teams: SELECT players.id, players.team FROM players \
+                        WHERE players.team IN (?, ?);
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut players = g.table("players").await.unwrap();
+    let mut getter = g.view("on_teams").await.unwrap();
+    for (id, team) in vec![(1, 1), (2, 2), (3, 3), (4, 1)] {
+        players.insert(vec![id.into(), team.into()]).await.unwrap();
+    }
+
+    // Let writes propagate:
+    sleep().await;
+
+    let result = getter
+        .lookup_any(&[1.into(), 3.into()], true)
+        .await
+        .unwrap();
+    let mut ids: Vec<DataType> = result.iter().map(|r| r["id"].clone()).collect();
+    ids.sort();
+    assert_eq!(ids, vec![1.into(), 3.into(), 4.into()]);
+
+    // repeated values don't repeat rows
+    let result = getter
+        .lookup_any(&[2.into(), 2.into()], true)
+        .await
+        .unwrap();
+    assert_eq!(result.len(), 1);
+}
+
 #[tokio::test(threaded_scheduler)]
 async fn votes() {
     // set up graph
@@ -1535,6 +1927,46 @@ async fn simple_migration() {
     );
 }
 
+#[tokio::test(threaded_scheduler)]
+async fn rename_column() {
+    let id: DataType = "x".into();
+
+    // set up graph
+    let mut g = start_simple("rename_column").await;
+    let a = g
+        .migrate(|mig| {
+            let a = mig.add_base("a", &["a", "b"], Base::default());
+            mig.maintain_anonymous(a, &[0]);
+            a
+        })
+        .await;
+    let mut aq = g.view("a").await.unwrap();
+    let mut muta = g.table("a").await.unwrap();
+
+    // send a value on a
+    muta.insert(vec![id.clone(), "y".into()]).await.unwrap();
+    sleep().await;
+
+    // rename the second column of a
+    g.migrate(move |mig| {
+        mig.rename_column(a, 1, "c");
+    })
+    .await;
+    sleep().await;
+
+    // the table now exposes the new name
+    let mut muta = g.table("a").await.unwrap();
+    assert_eq!(muta.columns(), &["a", "c"]);
+
+    // and both the existing and new data are still there
+    muta.insert(vec![id.clone(), "z".into()]).await.unwrap();
+    sleep().await;
+    let res = aq.lookup(&[id.clone()], true).await.unwrap();
+    assert_eq!(res.len(), 2);
+    assert!(res.contains(&vec![id.clone(), "y".into()]));
+    assert!(res.contains(&vec![id.clone(), "z".into()]));
+}
+
 #[tokio::test(threaded_scheduler)]
 async fn add_columns() {
     let id: DataType = "x".into();
@@ -2959,3 +3391,698 @@ async fn correct_nested_view_schema() {
     ];
     assert_eq!(q.schema(), Some(&expected_schema[..]));
 }
+
+#[tokio::test(threaded_scheduler)]
+async fn it_works_with_select_distinct() {
+    let mut g = start_simple("it_works_with_select_distinct").await;
+    let sql = "
+        CREATE TABLE users (id int, city varchar(255), state varchar(255), PRIMARY KEY(id));
+        QUERY cities: SELECT DISTINCT city FROM users WHERE users.state = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut mutator = g.table("users").await.unwrap();
+    let mut getter = g.view("cities").await.unwrap();
+
+    let rows = vec![(1, "Boston"), (2, "Boston"), (3, "Cambridge")];
+    for (id, city) in rows {
+        mutator
+            .insert(vec![id.into(), city.into(), "MA".into()])
+            .await
+            .unwrap();
+    }
+
+    // Let writes propagate:
+    sleep().await;
+
+    // the two Boston rows must have been collapsed into one
+    let mut result = getter.lookup(&["MA".into()], true).await.unwrap();
+    result.sort();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0][0], "Boston".into());
+    assert_eq!(result[1][0], "Cambridge".into());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_reads_top_rows_of_recipe_query() {
+    let mut g = start_simple("it_reads_top_rows_of_recipe_query").await;
+    let sql = "
+        CREATE TABLE scores (id int, g int, score int, PRIMARY KEY(id));
+        QUERY TOP best: SELECT scores.id, scores.score FROM scores WHERE scores.g = ? \
+                        ORDER BY scores.score DESC LIMIT 10;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut mutator = g.table("scores").await.unwrap();
+    let mut getter = g.view("best").await.unwrap();
+
+    for (id, score) in &[(1, 50), (2, 80), (3, 20)] {
+        mutator
+            .insert(vec![(*id).into(), 1.into(), (*score).into()])
+            .await
+            .unwrap();
+    }
+
+    // Let writes propagate:
+    sleep().await;
+
+    let result = getter.lookup_top(&[1.into()], 2).await.unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0][0], 2.into());
+    assert_eq!(result[1][0], 1.into());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_reports_base_dependencies_of_views() {
+    let mut g = start_simple("it_reports_base_dependencies_of_views").await;
+    let sql = "
+        CREATE TABLE users (id int, name varchar(40), PRIMARY KEY(id));
+        CREATE TABLE articles (id int, author int, title varchar(255), PRIMARY KEY(id));
+        CREATE TABLE votes (aid int, uid int);
+        QUERY titles: SELECT users.name, articles.title FROM articles, users \
+                      WHERE users.id = articles.author AND users.id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    // both sides of the join, but not the unrelated table
+    let deps = g.base_dependencies("titles").await.unwrap().unwrap();
+    let mut deps: Vec<_> = deps.into_iter().collect();
+    deps.sort();
+    assert_eq!(deps, vec!["articles".to_owned(), "users".to_owned()]);
+
+    assert_eq!(g.base_dependencies("nonexistent").await.unwrap(), None);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_explains_views() {
+    let mut g = start_simple("it_explains_views").await;
+    let sql = "
+        CREATE TABLE articles (id int, author int, title varchar(255), PRIMARY KEY(id));
+        QUERY by_author: SELECT articles.id, articles.title FROM articles \
+                         WHERE articles.author = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let plan = g.explain("by_author").await.unwrap().unwrap();
+    let lines: Vec<_> = plan.lines().collect();
+    assert!(lines[0].starts_with("Leaf "));
+    assert!(lines.last().unwrap().trim_start().starts_with("Base "));
+
+    assert_eq!(g.explain("nonexistent").await.unwrap(), None);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_orders_joins_by_cardinality_hints() {
+    let mut g = start_simple("it_orders_joins_by_cardinality_hints").await;
+    let hints = vec![("a", 1000), ("b", 100), ("c", 10)]
+        .into_iter()
+        .map(|(r, n)| (String::from(r), n))
+        .collect();
+    g.set_cardinality_hints(hints).await.unwrap();
+    let sql = "
+        CREATE TABLE a (id int, x int, PRIMARY KEY(id));
+        CREATE TABLE b (id int, aid int, PRIMARY KEY(id));
+        CREATE TABLE c (id int, bid int, PRIMARY KEY(id));
+        QUERY abc: SELECT a.x, c.id FROM a JOIN b ON a.id = b.aid JOIN c ON b.id = c.bid \
+                   WHERE a.id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    // the join furthest from the leaf comes first in the chain, and joins b to c
+    let plan = g.explain("abc").await.unwrap().unwrap();
+    let first_join = plan
+        .lines()
+        .filter(|l| l.trim_start().starts_with("Join "))
+        .max_by_key(|l| l.len() - l.trim_start().len())
+        .unwrap();
+    assert!(first_join.contains("bid"), "{}", plan);
+
+    let mut a = g.table("a").await.unwrap();
+    let mut b = g.table("b").await.unwrap();
+    let mut c = g.table("c").await.unwrap();
+    a.insert(vec![1.into(), 42.into()]).await.unwrap();
+    b.insert(vec![2.into(), 1.into()]).await.unwrap();
+    c.insert(vec![3.into(), 2.into()]).await.unwrap();
+    sleep().await;
+
+    let mut abc = g.view("abc").await.unwrap();
+    assert_eq!(
+        abc.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![42.into(), 3.into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_rekeys_views() {
+    let mut g = start_simple("it_rekeys_views").await;
+    let sql = "
+        CREATE TABLE t (id int, a int, PRIMARY KEY(id));
+        QUERY positive: SELECT t.id, t.a FROM t WHERE t.id = ? AND t.a > 0;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let filters = g.find_nodes("positive", "Filter").await.unwrap().unwrap();
+
+    let mut t = g.table("t").await.unwrap();
+    t.insert(vec![1.into(), 10.into()]).await.unwrap();
+    t.insert(vec![2.into(), 20.into()]).await.unwrap();
+    t.insert(vec![3.into(), 0.into()]).await.unwrap();
+    sleep().await;
+
+    // look the view up by a rather than by id
+    g.rekey_view("positive", &["a"]).await.unwrap();
+    // the view keeps its other operators
+    assert_eq!(
+        g.find_nodes("positive", "Filter").await.unwrap().unwrap(),
+        filters
+    );
+    let mut positive = g.view("positive").await.unwrap();
+    assert_eq!(
+        positive.lookup(&[20.into()], true).await.unwrap(),
+        vec![vec![2.into(), 20.into()]]
+    );
+    assert!(positive.lookup(&[0.into()], true).await.unwrap().is_empty());
+
+    // writes after the re-key reach the new reader
+    t.insert(vec![4.into(), 20.into()]).await.unwrap();
+    sleep().await;
+    let mut rows: Vec<Vec<DataType>> = positive.lookup(&[20.into()], true).await.unwrap().into();
+    rows.sort();
+    assert_eq!(
+        rows,
+        vec![vec![2.into(), 20.into()], vec![4.into(), 20.into()]]
+    );
+
+    assert!(g.rekey_view("positive", &["nope"]).await.is_err());
+    assert!(g.rekey_view("nonexistent", &["a"]).await.is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_reads_base_through_secondary_index() {
+    let mut g = start_simple("it_reads_base_through_secondary_index").await;
+    g.install_recipe("CREATE TABLE users (id int, name varchar(40), PRIMARY KEY (id));")
+        .await
+        .unwrap();
+    assert_eq!(
+        g.add_secondary_index("users", &["name"]).await.unwrap(),
+        "users_by_name"
+    );
+    // the same index cannot be added twice, and only existing columns can be indexed
+    assert!(g.add_secondary_index("users", &["name"]).await.is_err());
+    assert!(g.add_secondary_index("users", &["email"]).await.is_err());
+
+    let mut users = g.table("users").await.unwrap();
+    users.insert(vec![1.into(), "alice".into()]).await.unwrap();
+    users.insert(vec![2.into(), "bob".into()]).await.unwrap();
+    users.insert(vec![3.into(), "bob".into()]).await.unwrap();
+    sleep().await;
+
+    let mut by_name = g.view("users_by_name").await.unwrap();
+    let mut rows: Vec<Vec<DataType>> = by_name.lookup(&["bob".into()], true).await.unwrap().into();
+    rows.sort();
+    assert_eq!(
+        rows,
+        vec![vec![2.into(), "bob".into()], vec![3.into(), "bob".into()]]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_pages_unordered_views() {
+    use nom_sql::{LimitClause, OrderClause, OrderType};
+
+    let mut g = start_simple("it_pages_unordered_views").await;
+    g.install_recipe("CREATE TABLE items (id int, price int, PRIMARY KEY(id));")
+        .await
+        .unwrap();
+    let order = OrderClause {
+        columns: vec![("price".into(), OrderType::OrderDescending)],
+    };
+    g.add_sorted_view(
+        "items",
+        "priciest",
+        order.clone(),
+        LimitClause {
+            limit: 2,
+            offset: 0,
+        },
+    )
+    .await
+    .unwrap();
+    // TopK operators cannot skip rows, so pages past the first are refused
+    assert!(g
+        .add_sorted_view(
+            "items",
+            "later",
+            order,
+            LimitClause {
+                limit: 2,
+                offset: 2,
+            },
+        )
+        .await
+        .is_err());
+
+    let mut items = g.table("items").await.unwrap();
+    for &(id, price) in &[(1, 10), (2, 40), (3, 20), (4, 30)] {
+        items.insert(vec![id.into(), price.into()]).await.unwrap();
+    }
+    sleep().await;
+
+    let mut priciest = g.view("priciest").await.unwrap();
+    let rows: Vec<Vec<DataType>> = priciest.lookup(&[0.into()], true).await.unwrap().into();
+    let mut ids: Vec<_> = rows.into_iter().map(|r| r[0].clone()).collect();
+    ids.sort();
+    assert_eq!(ids, vec![2.into(), 4.into()]);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_updates_view_limits_in_place() {
+    use nom_sql::{LimitClause, OrderClause, OrderType};
+
+    let mut g = start_simple("it_updates_view_limits_in_place").await;
+    let sql = "
+        CREATE TABLE items (id int, price int, PRIMARY KEY(id));
+        QUERY priciest: SELECT items.id, items.price FROM items \
+                        ORDER BY items.price DESC LIMIT 2;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut items = g.table("items").await.unwrap();
+    for &(id, price) in &[(1, 10), (2, 40), (3, 20), (4, 30)] {
+        items.insert(vec![id.into(), price.into()]).await.unwrap();
+    }
+    sleep().await;
+
+    let before = g.explain("priciest").await.unwrap().unwrap();
+    let order = OrderClause {
+        columns: vec![("items.price".into(), OrderType::OrderDescending)],
+    };
+    g.update_topk(
+        "priciest",
+        Some(order),
+        LimitClause {
+            limit: 3,
+            offset: 0,
+        },
+    )
+    .await
+    .unwrap();
+    sleep().await;
+
+    // the nodes below the TopK are shared with the old version of the view
+    let after = g.explain("priciest").await.unwrap().unwrap();
+    let below_topk = |explain: &str| -> Vec<String> {
+        explain
+            .lines()
+            .skip_while(|l| !l.trim_start().starts_with("TopK"))
+            .skip(1)
+            .map(|l| l.trim_end_matches(" (reused)").to_owned())
+            .collect()
+    };
+    assert!(!below_topk(&before).is_empty());
+    assert_eq!(below_topk(&after), below_topk(&before));
+
+    let mut priciest = g.view("priciest").await.unwrap();
+    let rows: Vec<Vec<DataType>> = priciest.lookup(&[0.into()], true).await.unwrap().into();
+    let mut ids: Vec<_> = rows.into_iter().map(|r| r[0].clone()).collect();
+    ids.sort();
+    assert_eq!(ids, vec![2.into(), 3.into(), 4.into()]);
+
+    assert!(g
+        .update_topk(
+            "nonexistent",
+            None,
+            LimitClause {
+                limit: 3,
+                offset: 0,
+            },
+        )
+        .await
+        .is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_exports_and_imports_converter_state() {
+    let mut g = start_simple("it_exports_converter_state").await;
+    g.install_recipe("CREATE TABLE a (id int, b int, PRIMARY KEY(id));")
+        .await
+        .unwrap();
+    let state = g.export_converter_state().await.unwrap();
+    assert_eq!(state.base_schemas["a"].len(), 1);
+    assert_eq!(state.base_schemas["a"][0].1.len(), 2);
+    assert!(state.current.contains_key("a"));
+
+    // a fresh instance picks up the schema history of the first one
+    let mut restored = start_simple("it_imports_converter_state").await;
+    restored
+        .import_converter_state(state.clone())
+        .await
+        .unwrap();
+    assert_eq!(restored.export_converter_state().await.unwrap(), state);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_estimates_node_count() {
+    let mut g = start_simple("it_estimates_node_count").await;
+    let sql = "
+        CREATE TABLE a (id int, region text, PRIMARY KEY(id));
+        CREATE TABLE b (id int, aid int, amount int, PRIMARY KEY(id));
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let query = "SELECT a.region, SUM(b.amount) AS total FROM a JOIN b ON a.id = b.aid \
+                 GROUP BY a.region ORDER BY total DESC LIMIT 3;";
+    let estimate = g.estimate_node_count(query).await.unwrap();
+    // at least a join, an aggregation, a top-k, a projection and a reader
+    assert!(estimate >= 5);
+    // the estimate adds nothing to the graph
+    assert_eq!(g.outputs().await.unwrap().len(), 0);
+
+    // queries that could not be added fail rather than looking free
+    assert!(g.estimate_node_count("SELECT c.id FROM c;").await.is_err());
+    assert!(g
+        .estimate_node_count("SELECT a.nope FROM a;")
+        .await
+        .is_err());
+    assert!(g
+        .estimate_node_count("INSERT INTO a VALUES (1, 'x');")
+        .await
+        .is_err());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_reports_view_columns() {
+    let mut g = start_simple("it_reports_view_columns").await;
+    let sql = "
+        CREATE TABLE t (id int, a int, PRIMARY KEY(id));
+        QUERY q: SELECT t.id, t.a AS value, t.a * 2 AS twice FROM t WHERE t.id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    assert_eq!(
+        g.view_columns("q").await.unwrap(),
+        Some(vec!["id".into(), "value".into(), "twice".into()])
+    );
+    // the view's columns are known without reading from it, and match what it returns
+    let mut t = g.table("t").await.unwrap();
+    t.insert(vec![1.into(), 21.into()]).await.unwrap();
+    sleep().await;
+    let mut q = g.view("q").await.unwrap();
+    assert_eq!(q.columns(), &["id", "value", "twice"]);
+    assert_eq!(
+        q.lookup(&[1.into()], true).await.unwrap(),
+        vec![vec![1.into(), 21.into(), 42.into()]]
+    );
+
+    assert_eq!(g.view_columns("t").await.unwrap(), None);
+    assert_eq!(g.view_columns("nonexistent").await.unwrap(), None);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_binds_parameters_to_literals() {
+    let mut g = start_simple("it_binds_parameters_to_literals").await;
+    g.bind_parameter(
+        "us_orders",
+        "orders.region",
+        nom_sql::Literal::String("us".into()),
+    )
+    .await
+    .unwrap();
+    let sql = "
+        CREATE TABLE orders (id int, region text, uid int, PRIMARY KEY(id));
+        QUERY us_orders: SELECT orders.id FROM orders \
+                         WHERE orders.region = ? AND orders.uid = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut orders = g.table("orders").await.unwrap();
+    for &(id, region, uid) in &[(1, "us", 7), (2, "eu", 7), (3, "us", 7), (4, "us", 8)] {
+        orders
+            .insert(vec![id.into(), region.into(), uid.into()])
+            .await
+            .unwrap();
+    }
+    sleep().await;
+
+    // readers only supply the user, since the region is baked into the view
+    let mut view = g.view("us_orders").await.unwrap();
+    let rows: Vec<Vec<DataType>> = view.lookup(&[7.into()], true).await.unwrap().into();
+    let mut ids: Vec<_> = rows.into_iter().map(|r| r[0].clone()).collect();
+    ids.sort();
+    assert_eq!(ids, vec![1.into(), 3.into()]);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_looks_up_views_by_computed_parameters() {
+    let mut g = start_simple("it_looks_up_views_by_computed_parameters").await;
+    // stands in for `WHERE LOWER(users.email) = ?`, which nom-sql does not parse
+    g.set_computed_parameter("by_email", "users.email", "email_lower", "LOWER")
+        .await
+        .unwrap();
+    assert!(g
+        .set_computed_parameter("by_email", "users.email", "email_len", "LENGTH")
+        .await
+        .is_err());
+    let sql = "
+        CREATE TABLE users (id int, email varchar(255), PRIMARY KEY(id));
+        QUERY by_email: SELECT users.id FROM users WHERE users.email = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    let mut users = g.table("users").await.unwrap();
+    users
+        .insert(vec![1.into(), "Alice@Example.com".into()])
+        .await
+        .unwrap();
+    users
+        .insert(vec![2.into(), "bob@example.com".into()])
+        .await
+        .unwrap();
+    sleep().await;
+
+    // however the email was written, it is found by its lower-cased form
+    let mut by_email = g.view("by_email").await.unwrap();
+    let rows: Vec<Vec<DataType>> = by_email
+        .lookup(&["alice@example.com".into()], true)
+        .await
+        .unwrap()
+        .into();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0][0], 1.into());
+    let rows: Vec<Vec<DataType>> = by_email
+        .lookup(&["Alice@Example.com".into()], true)
+        .await
+        .unwrap()
+        .into();
+    assert!(rows.is_empty());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_finds_nodes_by_operator() {
+    let mut g = start_simple("it_finds_nodes_by_operator").await;
+    let sql = "
+        CREATE TABLE a (id int, x int, PRIMARY KEY(id));
+        CREATE TABLE b (id int, aid int, PRIMARY KEY(id));
+        CREATE TABLE c (id int, bid int, PRIMARY KEY(id));
+        QUERY abc: SELECT a.x, c.id FROM a JOIN b ON a.id = b.aid JOIN c ON b.id = c.bid \
+                   WHERE a.id = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    assert_eq!(g.find_nodes("abc", "Join").await.unwrap().unwrap().len(), 2);
+    let mut bases = g.find_nodes("abc", "Base").await.unwrap().unwrap();
+    bases.sort();
+    assert_eq!(bases, vec!["a", "b", "c"]);
+    assert!(g
+        .find_nodes("abc", "Aggregation")
+        .await
+        .unwrap()
+        .unwrap()
+        .is_empty());
+
+    assert_eq!(g.find_nodes("nonexistent", "Join").await.unwrap(), None);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_reinstantiates_views_for_universes() {
+    let mut g = start_simple("it_reinstantiates_views_for_universes").await;
+    let policies = r#"{
+        "policies": [
+            { "table": "posts", "predicate": "WHERE UserContext.id = posts.author" }
+        ]
+    }"#;
+    g.set_security_config(policies.to_owned()).await.unwrap();
+    g.install_recipe("CREATE TABLE posts (id int, author int, content text, PRIMARY KEY(id));")
+        .await
+        .unwrap();
+
+    let mut user = HashMap::new();
+    user.insert(String::from("id"), DataType::from(1));
+    g.create_universe(user.clone()).await.unwrap();
+
+    // a view added after the universe was created only exists globally until reinstantiated
+    g.extend_recipe("QUERY post: SELECT posts.id, posts.content FROM posts WHERE posts.id = ?;")
+        .await
+        .unwrap();
+    assert!(g.view("post_u1").await.is_err());
+    assert_eq!(
+        g.reinstantiate_for_universe("post", user.clone())
+            .await
+            .unwrap(),
+        Some(String::from("post_u1"))
+    );
+
+    let mut posts = g.table("posts").await.unwrap();
+    posts
+        .insert(vec![1.into(), 1.into(), "mine".into()])
+        .await
+        .unwrap();
+    posts
+        .insert(vec![2.into(), 2.into(), "theirs".into()])
+        .await
+        .unwrap();
+    sleep().await;
+
+    // the universe's copy of the view enforces the universe's policies
+    let mut post = g.view("post_u1").await.unwrap();
+    assert_eq!(post.lookup(&[1.into()], true).await.unwrap().len(), 1);
+    assert!(post.lookup(&[2.into()], true).await.unwrap().is_empty());
+
+    assert_eq!(
+        g.reinstantiate_for_universe("post", user.clone())
+            .await
+            .unwrap(),
+        None
+    );
+    assert_eq!(
+        g.reinstantiate_for_universe("nonexistent", user)
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_prefixes_generated_node_names() {
+    let mut g = start_simple("it_prefixes_generated_node_names").await;
+    g.set_name_prefix("tenant_a_").await.unwrap();
+    let sql = "
+        CREATE TABLE articles (id int, author int, title varchar(255), PRIMARY KEY(id));
+        QUERY by_author: SELECT articles.id, articles.title FROM articles \
+                         WHERE articles.author = ? AND articles.id > 1;
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    // the base and the view keep their names, the nodes in between are prefixed
+    let plan = g.explain("by_author").await.unwrap().unwrap();
+    let lines: Vec<_> = plan.lines().collect();
+    assert!(lines[0].starts_with("Leaf by_author"));
+    let (base, generated) = lines[1..].split_last().unwrap();
+    assert!(base.trim_start().starts_with("Base articles"));
+    for l in generated {
+        let name = l.split_whitespace().nth(1).unwrap();
+        assert!(name.starts_with("tenant_a_q_"), "{} is not prefixed", name);
+    }
+
+    let mut articles = g.table("articles").await.unwrap();
+    articles
+        .insert(vec![2.into(), 1.into(), "a".into()])
+        .await
+        .unwrap();
+    sleep().await;
+
+    let mut by_author = g.view("by_author").await.unwrap();
+    assert_eq!(by_author.lookup(&[1.into()], true).await.unwrap().len(), 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_runs_post_lowering_hook_on_recipe_queries() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let lowered = Arc::new(AtomicUsize::new(0));
+    let mut builder = Builder::default();
+    builder.set_sharding(Some(DEFAULT_SHARDING));
+    builder.set_persistence(get_persistence_params(
+        "it_runs_post_lowering_hook_on_recipe_queries",
+    ));
+    let counter = lowered.clone();
+    builder.set_post_lowering_hook(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    let mut g = builder.start_local().await.unwrap().0;
+
+    let sql = "
+        CREATE TABLE articles (id int, author int, title varchar(255), PRIMARY KEY(id));
+        QUERY by_author: SELECT articles.id, articles.title FROM articles \
+                         WHERE articles.author = ?;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    assert_eq!(lowered.load(Ordering::SeqCst), 1);
+
+    let mut articles = g.table("articles").await.unwrap();
+    articles
+        .insert(vec![1.into(), 2.into(), "hello".into()])
+        .await
+        .unwrap();
+    sleep().await;
+    let mut by_author = g.view("by_author").await.unwrap();
+    assert_eq!(by_author.lookup(&[2.into()], true).await.unwrap().len(), 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_matches_integers_written_to_float_columns() {
+    let mut g = start_simple("it_matches_integers_written_to_float_columns").await;
+    let sql = "
+        CREATE TABLE items (id int, price float, PRIMARY KEY(id));
+        QUERY at_three: SELECT items.id, items.price FROM items WHERE items.price = 3;
+        QUERY at_either: SELECT items.id FROM items WHERE items.price IN (3, 4);
+    ";
+    g.install_recipe(sql).await.unwrap();
+
+    // prices written as integers are stored as floats
+    let mut items = g.table("items").await.unwrap();
+    items.insert(vec![1.into(), 3.into()]).await.unwrap();
+    items.insert(vec![2.into(), 3.5.into()]).await.unwrap();
+    items.insert(vec![3.into(), 4.into()]).await.unwrap();
+    sleep().await;
+
+    let mut at_three = g.view("at_three").await.unwrap();
+    let rows: Vec<Vec<DataType>> = at_three.lookup(&[0.into()], true).await.unwrap().into();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0][0], 1.into());
+    assert_eq!(rows[0][1], 3.0.into());
+
+    let mut at_either = g.view("at_either").await.unwrap();
+    let rows: Vec<Vec<DataType>> = at_either.lookup(&[0.into()], true).await.unwrap().into();
+    let mut ids: Vec<_> = rows.into_iter().map(|r| r[0].clone()).collect();
+    ids.sort();
+    assert_eq!(ids, vec![1.into(), 3.into()]);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn it_places_nulls_last_in_ascending_topk() {
+    let mut g = start_simple("it_places_nulls_last_in_ascending_topk").await;
+    let sql = "
+        CREATE TABLE scores (id int, score int, PRIMARY KEY(id));
+        QUERY lowest: SELECT scores.id FROM scores ORDER BY scores.score ASC LIMIT 2;
+        QUERY highest: SELECT scores.id FROM scores ORDER BY scores.score DESC LIMIT 2;
+    ";
+    g.install_recipe(sql).await.unwrap();
+    let mut scores = g.table("scores").await.unwrap();
+    scores.insert(vec![1.into(), DataType::None]).await.unwrap();
+    for &(id, score) in &[(2, 5), (3, 7), (4, 9)] {
+        scores.insert(vec![id.into(), score.into()]).await.unwrap();
+    }
+    sleep().await;
+
+    // NULL sorts above every other value, so only descending order keeps it
+    let ids = |rows: Vec<Vec<DataType>>| {
+        let mut ids: Vec<_> = rows.into_iter().map(|r| r[0].clone()).collect();
+        ids.sort();
+        ids
+    };
+    let mut lowest = g.view("lowest").await.unwrap();
+    let rows = lowest.lookup(&[0.into()], true).await.unwrap().into();
+    assert_eq!(ids(rows), vec![2.into(), 3.into()]);
+    let mut highest = g.view("highest").await.unwrap();
+    let rows = highest.lookup(&[0.into()], true).await.unwrap().into();
+    assert_eq!(ids(rows), vec![1.into(), 4.into()]);
+}
@@ -389,8 +389,14 @@ pub mod manual {
     pub use crate::controller::migrate::Migration;
     pub use dataflow::node::special::Base;
     pub use dataflow::ops;
+    pub use mir::node::{MirNode, MirNodeType};
+    pub use mir::MirNodeRef;
 }
 
+/// A function that gets to inspect and rewrite the MIR nodes generated for each query, including
+/// the query's leaf, before they are added to the data-flow graph.
+pub type PostLoweringHook = std::sync::Arc<dyn Fn(&mut Vec<mir::MirNodeRef>) + Send + Sync>;
+
 use dataflow::DomainConfig;
 use std::time;
 
@@ -405,6 +411,8 @@ pub(crate) struct Config {
     pub(crate) healthcheck_every: time::Duration,
     pub(crate) quorum: usize,
     pub(crate) reuse: ReuseConfigType,
+    pub(crate) implicit_rowid: bool,
+    pub(crate) strict_group_by: bool,
     pub(crate) threads: Option<usize>,
 }
 impl Default for Config {
@@ -425,6 +433,8 @@ impl Default for Config {
             healthcheck_every: time::Duration::from_secs(10),
             quorum: 1,
             reuse: ReuseConfigType::Finkelstein,
+            implicit_rowid: false,
+            strict_group_by: false,
             #[cfg(any(debug_assertions, test))]
             threads: Some(2),
             #[cfg(not(any(debug_assertions, test)))]
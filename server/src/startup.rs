@@ -24,6 +24,7 @@ use tokio::sync::mpsc::UnboundedSender;
 
 use crate::handle::Handle;
 use crate::Config;
+use crate::PostLoweringHook;
 
 #[allow(clippy::large_enum_variant)]
 pub(crate) enum Event {
@@ -71,6 +72,7 @@ pub(super) async fn start_instance<A: Authority + 'static>(
     memory_limit: Option<usize>,
     memory_check_frequency: Option<time::Duration>,
     log: slog::Logger,
+    post_lowering_hook: Option<PostLoweringHook>,
 ) -> Result<(Handle<A>, impl Future<Output = ()> + Unpin + Send), failure::Error> {
     let (trigger, valve) = Valve::new();
     let (alive, done) = tokio::sync::mpsc::channel(1);
@@ -167,6 +169,7 @@ pub(super) async fn start_instance<A: Authority + 'static>(
         log.clone(),
         authority.clone(),
         tx.clone(),
+        post_lowering_hook,
     ));
     tokio::spawn(crate::worker::main(
         alive.clone(),
@@ -207,7 +207,9 @@ fn handle_message(
                         ret.push(SerializedReadReplyBatch::empty());
                         return false;
                     }
-                    let rs = reader.try_find_and(key, |rs| serialize(rs)).map(|r| r.0);
+                    let rs = reader
+                        .try_find_sorted_and(key, |rs| serialize(rs))
+                        .map(|r| r.0);
                     match rs {
                         Ok(Some(rs)) => {
                             // immediate hit!
@@ -289,6 +291,61 @@ fn handle_message(
                 }
             }
         }
+        ReadQuery::Range {
+            target,
+            lower,
+            upper,
+        } => {
+            let rows = READERS.with(|readers_cache| {
+                let mut readers_cache = readers_cache.borrow_mut();
+                let reader = readers_cache.entry(target).or_insert_with(|| {
+                    let readers = s.lock().unwrap();
+                    readers.get(&target).unwrap().clone()
+                });
+
+                reader.try_find_range_and(&lower, &upper, |rs| serialize(rs.collect::<Vec<_>>()))
+            });
+
+            Either::Right(future::ready(Ok(Tagged {
+                tag,
+                v: ReadReply::Normal(rows.map(|rs| vec![rs])),
+            })))
+        }
+        ReadQuery::Top { target, key, k } => {
+            let rows = READERS.with(|readers_cache| {
+                let mut readers_cache = readers_cache.borrow_mut();
+                let reader = readers_cache.entry(target).or_insert_with(|| {
+                    let readers = s.lock().unwrap();
+                    readers.get(&target).unwrap().clone()
+                });
+
+                // readers that rank their rows are fully materialized, so there are no holes
+                reader
+                    .try_find_top_and(&key, k, |rs| serialize(rs))
+                    .map(|r| r.0.unwrap_or_else(SerializedReadReplyBatch::empty))
+            });
+
+            Either::Right(future::ready(Ok(Tagged {
+                tag,
+                v: ReadReply::Normal(rows.map(|rs| vec![rs])),
+            })))
+        }
+        ReadQuery::All { target } => {
+            let rows = READERS.with(|readers_cache| {
+                let mut readers_cache = readers_cache.borrow_mut();
+                let reader = readers_cache.entry(target).or_insert_with(|| {
+                    let readers = s.lock().unwrap();
+                    readers.get(&target).unwrap().clone()
+                });
+
+                reader.try_find_all_and(|rs| serialize(rs.collect::<Vec<_>>()))
+            });
+
+            Either::Right(future::ready(Ok(Tagged {
+                tag,
+                v: ReadReply::Normal(rows.map(|rs| vec![rs])),
+            })))
+        }
         ReadQuery::Size { target } => {
             let size = READERS.with(|readers_cache| {
                 let mut readers_cache = readers_cache.borrow_mut();
@@ -364,7 +421,10 @@ impl BlockingRead {
 
             while let Some(read_i) = self.pending.pop() {
                 let key = self.keys.pop().expect("pending.len() == keys.len()");
-                match reader.try_find_and(&key, |rs| serialize(rs)).map(|r| r.0) {
+                match reader
+                    .try_find_sorted_and(&key, |rs| serialize(rs))
+                    .map(|r| r.0)
+                {
                     Ok(Some(rs)) => {
                         read[read_i] = rs;
                     }